@@ -27,10 +27,10 @@ async fn main() {
     let home = dirs::home_dir().unwrap();
     let cfg = fs::File::open(home.join(".docker/config.json"));
     if let Ok(fp) = cfg {
-        let creds = dkregistry::get_credentials(io::BufReader::new(fp), &registry);
+        let creds = dkregistry::get_credentials(io::BufReader::new(fp), registry);
         if let Ok(user_pass) = creds {
-            user = user_pass.0;
-            password = user_pass.1;
+            user = user_pass.0.map(Into::into);
+            password = user_pass.1.map(Into::into);
         } else {
             println!("[{}] no credentials found in config.json", registry);
         }
@@ -58,11 +58,11 @@ async fn run(
     user: Option<String>,
     passwd: Option<String>,
 ) -> Result<(), dkregistry::errors::Error> {
-    let mut client = dkregistry::v2::Client::configure()
-        .registry(&dkr_ref.registry())
+    let client = dkregistry::v2::Client::configure()
+        .registry(dkr_ref.registry())
         .insecure_registry(false)
-        .username(user)
-        .password(passwd)
+        .username(user.map(Into::into))
+        .password(passwd.map(Into::into))
         .build()?;
 
     let image = dkr_ref.repository();
@@ -70,7 +70,7 @@ async fn run(
     let version = dkr_ref.version();
 
     client.authenticate(&[&login_scope]).await?;
-    let manifest = client.get_manifest(&image, &version, None).await?;
+    let manifest = client.get_manifest(image, &version, None).await?;
 
     if let Manifest::S1Signed(s1s) = manifest {
         let labels = s1s.get_labels(0);
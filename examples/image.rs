@@ -50,8 +50,8 @@ async fn main() -> Result<(), boxed::Box<dyn error::Error>> {
     if let Ok(fp) = cfg {
         let creds = dkregistry::get_credentials(io::BufReader::new(fp), &registry);
         if let Ok(user_pass) = creds {
-            user = user_pass.0;
-            password = user_pass.1;
+            user = user_pass.0.map(Into::into);
+            password = user_pass.1.map(Into::into);
         } else {
             println!("[{}] no credentials found in config.json", registry);
         }
@@ -66,7 +66,7 @@ async fn main() -> Result<(), boxed::Box<dyn error::Error>> {
         }
     };
 
-    let res = run(&registry, &image, &version, user, password, &path).await;
+    let res = run(&registry, &image, &version, user, password, path).await;
 
     if let Err(e) = res {
         println!("[{}] {}", registry, e);
@@ -89,24 +89,24 @@ async fn run(
         .filter(Some("trace"), log::LevelFilter::Trace)
         .try_init()?;
 
-    let mut client = dkregistry::v2::Client::configure()
+    let client = dkregistry::v2::Client::configure()
         .registry(registry)
         .insecure_registry(false)
-        .username(user)
-        .password(passwd)
+        .username(user.map(Into::into))
+        .password(passwd.map(Into::into))
         .build()?;
 
     let login_scope = format!("repository:{}:pull", image);
 
     client.authenticate(&[&login_scope]).await?;
-    let manifest = client.get_manifest(&image, &version, None).await?;
+    let manifest = client.get_manifest(image, version, None).await?;
     let layers_digests = manifest.layers_digests(None)?.collect::<Vec<_>>();
 
     println!("{} -> got {} layer(s)", &image, layers_digests.len(),);
 
     let blob_futures = layers_digests
         .iter()
-        .map(|layer_digest| client.get_blob(&image, &layer_digest, None))
+        .map(|layer_digest| client.get_blob(image, *layer_digest, None))
         .collect::<Vec<_>>();
 
     let blobs = try_join_all(blob_futures).await?;
@@ -114,7 +114,7 @@ async fn run(
     println!("Downloaded {} layers", blobs.len());
 
     // TODO: use async io
-    std::fs::create_dir(&path).unwrap();
+    std::fs::create_dir(path).unwrap();
     let can_path = path.canonicalize().unwrap();
 
     println!("Unpacking layers to {:?}", &can_path);
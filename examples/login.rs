@@ -46,14 +46,13 @@ async fn run(
         .filter(Some("trace"), log::LevelFilter::Trace)
         .try_init()?;
 
-    let mut client = dkregistry::v2::Client::configure()
+    let client = dkregistry::v2::Client::configure()
         .registry(host)
         .insecure_registry(false)
-        .username(user)
-        .password(passwd)
+        .username(user.map(Into::into))
+        .password(passwd.map(Into::into))
         .build()?;
 
-    client.authenticate(&[&login_scope]).await?;
-    client.is_auth().await?;
+    client.login(&[&login_scope]).await?;
     Ok(())
 }
@@ -48,11 +48,11 @@ async fn run(
         .filter(Some("trace"), log::LevelFilter::Trace)
         .try_init()?;
 
-    let mut client = dkregistry::v2::Client::configure()
+    let client = dkregistry::v2::Client::configure()
         .registry(host)
         .insecure_registry(false)
-        .username(user)
-        .password(passwd)
+        .username(user.map(Into::into))
+        .password(passwd.map(Into::into))
         .build()?;
 
     let login_scope = format!("repository:{}:pull", image);
@@ -60,7 +60,7 @@ async fn run(
     client.authenticate(&[&login_scope]).await?;
 
     client
-        .get_tags(&image, Some(7))
+        .get_tags(image, Some(7))
         .collect::<Vec<_>>()
         .await
         .into_iter()
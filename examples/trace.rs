@@ -6,6 +6,7 @@ extern crate tokio;
 mod common;
 
 use dkregistry::reference;
+use futures::TryStreamExt;
 use std::str::FromStr;
 use std::{boxed, env, error, fs, io};
 
@@ -25,10 +26,10 @@ async fn main() {
     let home = dirs::home_dir().unwrap();
     let cfg = fs::File::open(home.join(".docker/config.json"));
     if let Ok(fp) = cfg {
-        let creds = dkregistry::get_credentials(io::BufReader::new(fp), &registry);
+        let creds = dkregistry::get_credentials(io::BufReader::new(fp), registry);
         if let Ok(user_pass) = creds {
-            user = user_pass.0;
-            password = user_pass.1;
+            user = user_pass.0.map(Into::into);
+            password = user_pass.1.map(Into::into);
         } else {
             println!("[{}] no credentials found in config.json", registry);
         }
@@ -64,23 +65,23 @@ async fn run(
     let image = dkr_ref.repository();
     let version = dkr_ref.version();
 
-    let mut client = dkregistry::v2::Client::configure()
-        .registry(&dkr_ref.registry())
+    let client = dkregistry::v2::Client::configure()
+        .registry(dkr_ref.registry())
         .insecure_registry(false)
-        .username(user)
-        .password(passwd)
+        .username(user.map(Into::into))
+        .password(passwd.map(Into::into))
         .build()?;
 
     let login_scope = "";
 
-    client.authenticate(&[&login_scope]).await?;
-    let manifest = client.get_manifest(&image, &version, None).await?;
+    client.authenticate(&[login_scope]).await?;
+    let manifest = client.get_manifest(image, &version, None).await?;
 
     let layers_digests = manifest.layers_digests(None)?.collect::<Vec<_>>();
     println!("{} -> got {} layer(s)", &image, layers_digests.len(),);
 
-    for layer_digest in &layers_digests {
-        let blob = client.get_blob(&image, &layer_digest, None).await?;
+    let mut blobs = std::pin::pin!(client.prefetch_layers(image, &manifest, None, 4, None));
+    while let Some((layer_digest, blob)) = blobs.try_next().await? {
         println!("Layer {}, got {} bytes.\n", layer_digest, blob.len());
     }
 
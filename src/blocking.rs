@@ -0,0 +1,184 @@
+//! Blocking facade over the asynchronous `v2::Client`.
+//!
+//! Each call drives the asynchronous implementation to completion on a runtime
+//! owned by the client, in the same way as `reqwest::blocking`:
+//!
+//! ```rust,no_run
+//! # fn run() -> dkregistry::errors::Result<()> {
+//! let client = dkregistry::v2::Client::configure()
+//!     .registry("quay.io")
+//!     .build()?;
+//! let client = dkregistry::blocking::Client::new(client)?;
+//! client.authenticate(["repository:coreos/etcd:pull"])?;
+//! let manifest = client.get_manifest("coreos/etcd", "v3.1.0", None)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Like `reqwest::blocking`, the methods of this client must not be called from within
+//! an asynchronous runtime, as blocking there panics.
+
+use std::convert::{TryFrom, TryInto};
+use std::io::Write;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::stream::{StreamExt, TryStreamExt};
+
+use crate::errors::{Error, Result};
+use crate::mediatypes::MediaTypes;
+use crate::reference::Version;
+use crate::v2::manifest::Manifest;
+use crate::v2::RegistryInfo;
+
+/// A blocking client, wrapping a `v2::Client` and the runtime it runs on.
+///
+/// Clones share the runtime, as well as the authentication of the wrapped client.
+#[derive(Clone, Debug)]
+pub struct Client {
+    inner: crate::v2::Client,
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+impl Client {
+    /// Wrap an asynchronous client, starting the runtime it is driven on.
+    pub fn new(client: crate::v2::Client) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            inner: client,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// The wrapped asynchronous client.
+    pub fn inner(&self) -> &crate::v2::Client {
+        &self.inner
+    }
+
+    /// Run a future of the asynchronous client to completion.
+    ///
+    /// This gives access to the methods without a blocking counterpart.
+    pub fn block_on<F: std::future::Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Blocking version of `v2::Client::is_v2_supported`.
+    pub fn is_v2_supported(&self) -> Result<bool> {
+        self.block_on(self.inner.is_v2_supported())
+    }
+
+    /// Blocking version of `v2::Client::ping`.
+    pub fn ping(&self) -> Result<RegistryInfo> {
+        self.block_on(self.inner.ping())
+    }
+
+    /// Blocking version of `v2::Client::authenticate`.
+    pub fn authenticate<I, S>(&self, scopes: I) -> Result<Vec<String>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.block_on(self.inner.authenticate(scopes))
+    }
+
+    /// Blocking version of `v2::Client::get_catalog`, collecting all repositories.
+    pub fn get_catalog(&self, paginate: Option<u32>) -> Result<Vec<String>> {
+        self.block_on(self.inner.get_catalog(paginate).try_collect())
+    }
+
+    /// Blocking version of `v2::Client::get_tags`, collecting all tags.
+    pub fn get_tags(&self, name: &str, paginate: Option<u32>) -> Result<Vec<String>> {
+        self.block_on(self.inner.get_tags(name, paginate).try_collect())
+    }
+
+    /// Blocking version of `v2::Client::get_manifest`.
+    pub fn get_manifest<R>(&self, name: &str, reference: R, ns: Option<&str>) -> Result<Manifest>
+    where
+        R: TryInto<Version>,
+        Error: From<R::Error>,
+    {
+        self.block_on(self.inner.get_manifest(name, reference, ns))
+    }
+
+    /// Blocking version of `v2::Client::get_manifest_and_ref`.
+    pub fn get_manifest_and_ref(
+        &self,
+        name: &str,
+        reference: &str,
+        ns: Option<&str>,
+    ) -> Result<(Manifest, Option<String>)> {
+        self.block_on(self.inner.get_manifest_and_ref(name, reference, ns))
+    }
+
+    /// Blocking version of `v2::Client::has_manifest`.
+    pub fn has_manifest(
+        &self,
+        name: &str,
+        reference: &str,
+        ns: Option<&str>,
+        mediatypes: Option<&[&str]>,
+    ) -> Result<Option<MediaTypes>> {
+        self.block_on(self.inner.has_manifest(name, reference, ns, mediatypes))
+    }
+
+    /// Blocking version of `v2::Client::put_manifest`.
+    pub fn put_manifest(
+        &self,
+        name: &str,
+        reference: &str,
+        media_type: &MediaTypes,
+        manifest: Bytes,
+    ) -> Result<String> {
+        self.block_on(
+            self.inner
+                .put_manifest(name, reference, media_type, manifest),
+        )
+    }
+
+    /// Blocking version of `v2::Client::has_blob`.
+    pub fn has_blob(&self, name: &str, digest: &str, ns: Option<&str>) -> Result<bool> {
+        self.block_on(self.inner.has_blob(name, digest, ns))
+    }
+
+    /// Blocking version of `v2::Client::get_blob`.
+    pub fn get_blob(&self, name: &str, digest: &str, ns: Option<&str>) -> Result<Vec<u8>> {
+        self.block_on(self.inner.get_blob(name, digest, ns))
+    }
+
+    /// Blocking version of `v2::Client::download_blob_to`, writing the blob as it is
+    /// downloaded, without buffering it in memory.
+    ///
+    /// Returns the number of bytes written. The digest is verified once the whole blob has
+    /// been written, whatever `Config::verify_digests` says, and the writer is flushed.
+    /// On error, whatever was written is left in the writer.
+    pub fn download_blob_to<W: Write>(
+        &self,
+        name: &str,
+        digest: &str,
+        mut writer: W,
+        ns: Option<&str>,
+    ) -> Result<u64> {
+        let client = self.inner.verifying_digests();
+        self.block_on(async {
+            let mut stream = client.get_blob_stream(name, digest, ns).await?;
+            let mut written = 0;
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk?;
+                writer.write_all(&chunk)?;
+                written += chunk.len() as u64;
+            }
+            writer.flush()?;
+            Ok(written)
+        })
+    }
+}
+
+impl TryFrom<crate::v2::Client> for Client {
+    type Error = Error;
+
+    fn try_from(client: crate::v2::Client) -> Result<Self> {
+        Self::new(client)
+    }
+}
@@ -0,0 +1,122 @@
+//! Client for docker credential helpers.
+//!
+//! Helpers are `docker-credential-<name>` binaries implementing the protocol at
+//! <https://github.com/docker/docker-credential-helpers>.
+
+use crate::errors::{Error, Result};
+use compact_str::CompactString;
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Message printed by helpers which do not hold credentials for a server.
+const CREDENTIALS_NOT_FOUND: &str = "credentials not found in native keychain";
+
+#[derive(Debug, Deserialize)]
+struct HelperCredentials {
+    #[serde(rename = "Username")]
+    username: CompactString,
+    #[serde(rename = "Secret")]
+    secret: CompactString,
+}
+
+/// Get the credentials for `server_url` from the credential helper `helper`.
+pub(crate) fn get(
+    helper: &str,
+    server_url: &str,
+) -> Result<(Option<CompactString>, Option<CompactString>)> {
+    run(format!("docker-credential-{}", helper).as_ref(), server_url)
+}
+
+fn run(
+    program: &OsStr,
+    server_url: &str,
+) -> Result<(Option<CompactString>, Option<CompactString>)> {
+    let helper_error = |message: String| Error::CredentialHelper {
+        helper: program.to_string_lossy().into_owned(),
+        message,
+    };
+
+    trace!("Running credential helper {:?} for {}", program, server_url);
+    let mut child = Command::new(program)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| helper_error(e.to_string()))?;
+    // Helpers without credentials for the server may exit before reading it: the
+    // outcome is then told by their exit status and output.
+    let written = child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(server_url.as_bytes());
+    match written {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(helper_error(e.to_string())),
+        _ => {}
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| helper_error(e.to_string()))?;
+
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if message == CREDENTIALS_NOT_FOUND {
+            return Err(Error::AuthInfoMissing(server_url.to_string()));
+        }
+        return Err(helper_error(message));
+    }
+
+    let creds: HelperCredentials = serde_json::from_slice(&output.stdout)?;
+    let up = match (creds.username.as_str(), creds.secret.as_str()) {
+        ("", "") => (None, None),
+        ("", _) => (None, Some(creds.secret)),
+        (_, "") => (Some(creds.username), None),
+        (_, _) => (Some(creds.username), Some(creds.secret)),
+    };
+    Ok(up)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    fn fake_helper(name: &str, script: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("docker-credential-{}-{}", name, std::process::id()));
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn helper_credentials_are_parsed() -> Result<()> {
+        let helper = fake_helper(
+            "ok",
+            r#"read server; echo "{\"ServerURL\":\"$server\",\"Username\":\"user\",\"Secret\":\"secret\"}""#,
+        );
+        let up = run(helper.as_os_str(), "registry.example.com");
+        std::fs::remove_file(&helper).unwrap();
+
+        assert_eq!(up?, (Some("user".into()), Some("secret".into())));
+        Ok(())
+    }
+
+    #[test]
+    fn helper_without_credentials_reports_missing() {
+        let helper = fake_helper(
+            "missing",
+            &format!("echo '{}'; exit 1", CREDENTIALS_NOT_FOUND),
+        );
+        let res = run(helper.as_os_str(), "registry.example.com");
+        std::fs::remove_file(&helper).unwrap();
+
+        match res {
+            Err(Error::AuthInfoMissing(index)) => assert_eq!(index, "registry.example.com"),
+            res => panic!("expected AuthInfoMissing, got {:?}", res),
+        }
+    }
+}
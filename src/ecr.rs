@@ -0,0 +1,397 @@
+//! Credentials for Amazon ECR registries.
+//!
+//! ECR registries don't issue tokens through the usual bearer flow. Instead, AWS credentials
+//! are exchanged for a username and password through the `GetAuthorizationToken` action
+//! of the ECR API, which are then used as basic credentials against the registry:
+//!
+//! ```rust,no_run
+//! # async fn run() -> dkregistry::errors::Result<()> {
+//! use dkregistry::ecr::{self, AwsCredentials};
+//!
+//! let credentials = AwsCredentials::from_env().expect("AWS credentials in the environment");
+//! let config = dkregistry::v2::Client::configure()
+//!     .registry("123456789012.dkr.ecr.us-east-1.amazonaws.com");
+//! let authorization = ecr::get_authorization_token(&config, "us-east-1", &credentials).await?;
+//! let client = config
+//!     .username(Some(authorization.username().into()))
+//!     .password(Some(authorization.password().into()))
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Requests to the ECR API are signed with AWS Signature Version 4, without depending
+//! on the AWS SDK. They are sent with the HTTP settings of the registry configuration,
+//! such as its proxy, root certificates and timeouts.
+
+use std::time::{Duration, SystemTime};
+
+use base64::engine::Engine;
+use compact_str::CompactString;
+use reqwest::{header, StatusCode, Url};
+use sha2::{Digest, Sha256};
+
+use crate::errors::{Error, Result};
+use crate::v2::Config;
+
+/// Target of the `GetAuthorizationToken` action.
+const GET_AUTHORIZATION_TOKEN: &str = "AmazonEC2ContainerRegistry_V20150921.GetAuthorizationToken";
+
+/// Content type of requests to the ECR API.
+const CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+/// AWS credentials to sign requests to the ECR API with.
+#[derive(Clone)]
+pub struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl std::fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AwsCredentials {
+    /// Credentials from an access key, with the session token of temporary credentials if any.
+    pub fn new(access_key_id: &str, secret_access_key: &str, session_token: Option<&str>) -> Self {
+        Self {
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.to_string(),
+            session_token: session_token.map(str::to_string),
+        }
+    }
+
+    /// Credentials from the `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN` environment variables, if the first two are set.
+    pub fn from_env() -> Option<Self> {
+        let access_key_id = std::env::var("AWS_ACCESS_KEY_ID").ok()?;
+        let secret_access_key = std::env::var("AWS_SECRET_ACCESS_KEY").ok()?;
+        let session_token = std::env::var("AWS_SESSION_TOKEN").ok();
+        Some(Self::new(
+            &access_key_id,
+            &secret_access_key,
+            session_token.as_deref(),
+        ))
+    }
+}
+
+/// Registry credentials obtained from the ECR API.
+#[derive(Clone)]
+pub struct EcrAuthorization {
+    username: CompactString,
+    password: CompactString,
+    proxy_endpoint: Option<String>,
+    expires_at: Option<SystemTime>,
+}
+
+impl std::fmt::Debug for EcrAuthorization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EcrAuthorization")
+            .field("username", &self.username)
+            .field("proxy_endpoint", &self.proxy_endpoint)
+            .field("expires_at", &self.expires_at)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EcrAuthorization {
+    /// Username to authenticate to the registry with, usually `AWS`.
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// Password to authenticate to the registry with.
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    /// URL of the registry the credentials are for, such as
+    /// `https://123456789012.dkr.ecr.us-east-1.amazonaws.com`.
+    pub fn proxy_endpoint(&self) -> Option<&str> {
+        self.proxy_endpoint.as_deref()
+    }
+
+    /// When the credentials expire, usually 12 hours after they were issued.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GetAuthorizationTokenResponse {
+    #[serde(rename = "authorizationData")]
+    authorization_data: Vec<AuthorizationData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthorizationData {
+    #[serde(rename = "authorizationToken")]
+    authorization_token: String,
+    #[serde(rename = "expiresAt")]
+    expires_at: Option<f64>,
+    #[serde(rename = "proxyEndpoint")]
+    proxy_endpoint: Option<String>,
+}
+
+/// Error body of the AWS JSON protocol.
+#[derive(Debug, Deserialize)]
+struct AwsError {
+    #[serde(rename = "__type")]
+    error_type: Option<String>,
+    #[serde(alias = "Message")]
+    message: Option<String>,
+}
+
+/// Get registry credentials for the default registry of the account in `region`.
+///
+/// The ECR API is requested with the HTTP settings of `config`, such as its proxy.
+pub async fn get_authorization_token(
+    config: &Config,
+    region: &str,
+    credentials: &AwsCredentials,
+) -> Result<EcrAuthorization> {
+    let endpoint = Url::parse(&format!("https://api.ecr.{}.amazonaws.com/", region))?;
+    get_authorization_token_from(config, &endpoint, region, credentials).await
+}
+
+/// Get registry credentials from the ECR API at `endpoint`, such as a VPC endpoint.
+pub async fn get_authorization_token_from(
+    config: &Config,
+    endpoint: &Url,
+    region: &str,
+    credentials: &AwsCredentials,
+) -> Result<EcrAuthorization> {
+    let body = b"{}";
+    let host = match (endpoint.host_str(), endpoint.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => return Err(Error::Ecr(format!("invalid endpoint '{}'", endpoint))),
+    };
+    let signed = sign(
+        credentials,
+        region,
+        &host,
+        endpoint.path(),
+        body,
+        SystemTime::now(),
+    );
+
+    let mut request = config
+        .auxiliary_http_client()?
+        .post(endpoint.clone())
+        .header(header::CONTENT_TYPE, CONTENT_TYPE)
+        .header("x-amz-target", GET_AUTHORIZATION_TOKEN)
+        .header("x-amz-date", &signed.amz_date)
+        .header(header::AUTHORIZATION, &signed.authorization)
+        .body(&body[..]);
+    if let Some(token) = &credentials.session_token {
+        request = request.header("x-amz-security-token", token);
+    }
+    let resp = request.send().await?;
+
+    let status = resp.status();
+    trace!("ECR GetAuthorizationToken status: {:?}", status);
+    if status != StatusCode::OK {
+        let message = match resp.json::<AwsError>().await {
+            Ok(AwsError {
+                error_type,
+                message,
+            }) => format!(
+                "{}: {}",
+                error_type.as_deref().unwrap_or("error"),
+                message.as_deref().unwrap_or_default()
+            ),
+            Err(_) => format!("unexpected HTTP status {}", status),
+        };
+        return Err(Error::Ecr(message));
+    }
+
+    let data = resp
+        .json::<GetAuthorizationTokenResponse>()
+        .await?
+        .authorization_data
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::Ecr("no authorization data in response".into()))?;
+    let token = base64::engine::general_purpose::STANDARD.decode(&data.authorization_token)?;
+    let token = String::from_utf8(token)
+        .map_err(|_| Error::Ecr("authorization token is not UTF-8".into()))?;
+    let (username, password) = token
+        .split_once(':')
+        .ok_or_else(|| Error::Ecr("authorization token is not 'user:password'".into()))?;
+
+    Ok(EcrAuthorization {
+        username: username.into(),
+        password: password.into(),
+        proxy_endpoint: data.proxy_endpoint,
+        expires_at: data
+            .expires_at
+            .filter(|t| t.is_finite() && *t >= 0.0)
+            .map(|t| SystemTime::UNIX_EPOCH + Duration::from_secs_f64(t)),
+    })
+}
+
+/// Headers of a request signed with AWS Signature Version 4.
+struct Signed {
+    amz_date: String,
+    authorization: String,
+}
+
+/// Sign a `GetAuthorizationToken` request with AWS Signature Version 4.
+fn sign(
+    credentials: &AwsCredentials,
+    region: &str,
+    host: &str,
+    path: &str,
+    body: &[u8],
+    now: SystemTime,
+) -> Signed {
+    // `2024-01-02T03:04:05Z` to `20240102T030405Z`.
+    let amz_date: String = humantime::format_rfc3339_seconds(now)
+        .to_string()
+        .chars()
+        .filter(|c| *c != '-' && *c != ':')
+        .collect();
+    let date = &amz_date[..8];
+
+    let mut headers = vec![
+        ("content-type", CONTENT_TYPE),
+        ("host", host),
+        ("x-amz-date", &amz_date),
+        ("x-amz-target", GET_AUTHORIZATION_TOKEN),
+    ];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token));
+    }
+    headers.sort();
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let path = if path.is_empty() { "/" } else { path };
+    let canonical_request = format!(
+        "POST\n{}\n\n{}\n{}\n{:x}",
+        path,
+        canonical_headers,
+        signed_headers,
+        Sha256::digest(body)
+    );
+
+    let scope = format!("{}/{}/ecr/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+        amz_date,
+        scope,
+        Sha256::digest(canonical_request.as_bytes())
+    );
+    let key = signing_key(&credentials.secret_access_key, date, region, "ecr");
+    let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+    Signed {
+        authorization: format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature
+        ),
+        amz_date,
+    }
+}
+
+/// Derive the key signing requests to `service` in `region` on `date`.
+fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac_sha256(
+        format!("AWS4{}", secret_access_key).as_bytes(),
+        date.as_bytes(),
+    );
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+/// HMAC-SHA256, as described in RFC 2104.
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // RFC 4231, test case 6, with a key larger than a block.
+        assert_eq!(
+            hex(&hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn test_signing_key() {
+        // From the AWS Signature Version 4 documentation.
+        assert_eq!(
+            hex(&signing_key(
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+                "20120215",
+                "us-east-1",
+                "iam"
+            )),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn test_sign() {
+        let credentials = AwsCredentials::new("AKIDEXAMPLE", "secret", Some("session"));
+        let now = humantime::parse_rfc3339("2024-01-02T03:04:05Z").unwrap();
+        let signed = sign(
+            &credentials,
+            "us-east-1",
+            "api.ecr.us-east-1.amazonaws.com",
+            "/",
+            b"{}",
+            now,
+        );
+        assert_eq!(signed.amz_date, "20240102T030405Z");
+        assert!(signed.authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20240102/us-east-1/ecr/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date;x-amz-security-token;x-amz-target, \
+             Signature="
+        ));
+    }
+}
@@ -11,6 +11,10 @@ pub enum Error {
     Json(#[from] serde_json::Error),
     #[error("http transport error: {0}")]
     Reqwest(#[from] reqwest::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid root certificate: {0}")]
+    Certificate(String),
     #[error("URI parse error")]
     Uri(#[from] url::ParseError),
     #[error("input is not UTF-8")]
@@ -19,20 +23,57 @@ pub enum Error {
     StrumParse(#[from] strum::ParseError),
     #[error("authentication information missing for index {0}")]
     AuthInfoMissing(String),
+    #[cfg(feature = "credential-helpers")]
+    #[error("credential helper {helper} failed: {message}")]
+    CredentialHelper { helper: String, message: String },
+    #[cfg(feature = "ecr")]
+    #[error("ECR authorization failed: {0}")]
+    Ecr(String),
+    #[cfg(feature = "gcr")]
+    #[error("Google authorization failed: {0}")]
+    Gcr(String),
+    #[cfg(feature = "schema1-signatures")]
+    #[error("invalid manifest signature: {0}")]
+    InvalidManifestSignature(String),
+    #[cfg(feature = "decompress")]
+    #[error("failed to decompress blob")]
+    Decompress(#[source] std::io::Error),
     #[error("unknown media type {0:?}")]
     UnknownMimeType(mime::Mime),
     #[error("unknown media type {0:?}")]
     UnsupportedMediaType(crate::mediatypes::MediaTypes),
+    #[error("invalid media type '{0}'")]
+    InvalidMediaType(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    /// The registry confirmed with `MANIFEST_UNKNOWN` that the manifest doesn't exist,
+    /// rather than merely answering `404 Not Found`.
+    #[error("manifest '{reference}' unknown in repository '{name}'")]
+    ManifestUnknown { name: String, reference: String },
+    /// Every mirror failed, then the registry itself, with the error of each.
+    #[error("all mirrors and the registry failed: {}", display_mirror_errors(.0))]
+    Mirrors(Vec<(String, Error)>),
+    #[error("ambiguous digest prefix '{prefix}', matching {}", .candidates.join(", "))]
+    AmbiguousDigest {
+        prefix: String,
+        candidates: Vec<String>,
+    },
     #[error("mime parse error")]
     MimeParse(#[from] mime::FromStrError),
     #[error("missing authentication header {0}")]
     MissingAuthHeader(&'static str),
+    #[error("missing header {0}")]
+    MissingHeader(&'static str),
     #[error("unexpected HTTP status {0}")]
     UnexpectedHttpStatus(http::StatusCode),
     #[error("invalid auth token '{0}'")]
     InvalidAuthToken(arcstr::ArcStr),
+    #[error("unauthorized: request failed with status {0}")]
+    Unauthorized(http::StatusCode),
     #[error("API V2 not supported")]
     V2NotSupported,
+    #[error("{0} is not a registry: missing Docker-Distribution-Api-Version header")]
+    NotARegistry(String),
     #[error("obtained token is invalid")]
     LoginReturnedBadToken,
     #[error("www-authenticate header parse error")]
@@ -42,25 +83,255 @@ pub enum Error {
     #[error("request failed with status {status}")]
     Server { status: http::StatusCode },
     #[error("content digest error")]
-    ContentDigestParse(#[from] crate::v2::ContentDigestError),
+    ContentDigestParse(crate::v2::ContentDigestError),
+    #[error("unsupported digest algorithm {0}")]
+    UnsupportedDigestAlgorithm(String),
+    #[error("invalid digest: {0}")]
+    InvalidDigest(String),
+    #[error("digest mismatch: expected '{expected}', got '{actual}'")]
+    DigestMismatch { expected: String, actual: String },
+    #[error("size mismatch: expected {expected} bytes, got {actual}")]
+    SizeMismatch { expected: u64, actual: u64 },
+    #[error("streamed upload body cannot be verified before it is sent")]
+    UnverifiableUploadBody,
+    #[error("upload location '{0}' is not on the registry")]
+    ForeignUploadLocation(String),
+    #[error("invalid upload range '{0}'")]
+    InvalidUploadRange(String),
+    #[error("too many redirects, at most {0} are followed")]
+    TooManyRedirects(usize),
+    #[error("listing truncated after {0} pages")]
+    PaginationTruncated(usize),
+    #[error("unexpected Content-Range {0:?} in partial response")]
+    UnexpectedContentRange(Option<String>),
+    #[error("registry storage quota exceeded")]
+    QuotaExceeded,
+    /// The registry answered `429 Too Many Requests`, with the delay to wait
+    /// given by its `Retry-After` header, if any.
+    #[error("rate limited by the registry")]
+    RateLimited {
+        retry_after: Option<std::time::Duration>,
+    },
+    #[error("registry error with status {status}: {}", display_registry_errors(.errors))]
+    Registry {
+        status: http::StatusCode,
+        errors: Vec<RegistryError>,
+    },
+    #[error("manifest exceeds the maximum size of {0} bytes")]
+    ManifestTooLarge(usize),
+    #[error("blob of {size} bytes exceeds the maximum size of {limit} bytes, use get_blob_stream to download it")]
+    BlobTooLarge { size: u64, limit: u64 },
     #[error("no header Content-Type given and no workaround to apply")]
     MediaTypeSniff,
     #[error("manifest error")]
     Manifest(#[from] crate::v2::manifest::ManifestError),
+    #[error("tag or digest is invalid")]
+    VersionParse(#[from] crate::reference::VersionParseError),
     #[error("reference is invalid")]
     ReferenceParse(#[from] crate::reference::ReferenceParseError),
+    #[error("invalid registry '{0}'")]
+    InvalidRegistry(String),
+    #[error("invalid auth realm '{0}': expected an http(s) URL")]
+    InvalidAuthRealm(String),
+    #[error("invalid repository name '{0}'")]
+    InvalidName(String),
+    #[error("login failed: {cause}")]
+    LoginFailed {
+        #[source]
+        cause: Box<Error>,
+    },
     #[error("requested operation requires that credentials are available")]
     NoCredentials,
+    #[error("operation cancelled")]
+    Cancelled,
+    #[error("registry does not support the referrers API")]
+    ReferrersUnsupported,
+    #[error("authentication challenge failed with '{error}' for scope {scope:?}")]
+    AuthChallenge {
+        error: String,
+        scope: Option<String>,
+    },
+    /// The token endpoint timed out or sent a response too large to be a token.
+    #[error("token endpoint error: {message}")]
+    AuthEndpointError {
+        message: String,
+        #[source]
+        source: Option<reqwest::Error>,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// An error reported by a registry in the body of a failed response.
+///
+/// Format is described at <https://distribution.github.io/distribution/spec/api/#errors>.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct RegistryError {
+    code: String,
+    #[serde(default)]
+    message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    detail: Option<serde_json::Value>,
+}
+
+impl RegistryError {
+    /// Error code, such as `MANIFEST_UNKNOWN`.
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// Human-readable message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Unstructured detail about the error, if any.
+    pub fn detail(&self) -> Option<&serde_json::Value> {
+        self.detail.as_ref().filter(|detail| !detail.is_null())
+    }
+}
+
+impl std::fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+fn display_mirror_errors(errors: &[(String, Error)]) -> String {
+    errors
+        .iter()
+        .map(|(registry, e)| format!("{}: {}", registry, e))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn display_registry_errors(errors: &[RegistryError]) -> String {
+    errors
+        .iter()
+        .map(RegistryError::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Error {
+    /// Whether the error was caused by a request timing out.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Error::Reqwest(e)
+            | Error::AuthEndpointError {
+                source: Some(e), ..
+            } => e.is_timeout(),
+            _ => false,
+        }
+    }
+
+    /// Status of the response the error was built from, if any.
+    fn status(&self) -> Option<http::StatusCode> {
+        match self {
+            Error::Reqwest(e) => e.status(),
+            Error::Client { status }
+            | Error::Server { status }
+            | Error::Registry { status, .. }
+            | Error::UnexpectedHttpStatus(status)
+            | Error::Unauthorized(status) => Some(*status),
+            Error::ManifestUnknown { .. } => Some(http::StatusCode::NOT_FOUND),
+            _ => None,
+        }
+    }
+
+    /// Whether the same request may succeed if retried later.
+    ///
+    /// This is the case of rate limiting, server errors, and failures to connect or
+    /// to get a response in time.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            Error::RateLimited { .. } => true,
+            Error::Reqwest(e) if e.is_connect() || e.is_timeout() => true,
+            Error::AuthEndpointError { .. } => self.is_timeout(),
+            Error::LoginFailed { cause } => cause.is_retriable(),
+            Error::Mirrors(errors) => errors.iter().all(|(_, e)| e.is_retriable()),
+            e => e
+                .status()
+                .is_some_and(|s| s.is_server_error() || crate::v2::is_retryable_status(s)),
+        }
+    }
+
+    /// Whether the error is caused by missing, invalid or insufficient credentials.
+    pub fn is_auth(&self) -> bool {
+        match self {
+            Error::AuthInfoMissing(_)
+            | Error::InvalidAuthToken(_)
+            | Error::LoginReturnedBadToken
+            | Error::LoginFailed { .. }
+            | Error::NoCredentials
+            | Error::Www(_)
+            | Error::AuthChallenge { .. } => true,
+            e => matches!(
+                e.status(),
+                Some(http::StatusCode::UNAUTHORIZED | http::StatusCode::FORBIDDEN)
+            ),
+        }
+    }
+
+    /// Whether the requested repository, manifest, blob or tag doesn't exist.
+    ///
+    /// With mirrors, this is the case when it exists on none of them nor on the registry.
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::NotFound(_) => true,
+            Error::Mirrors(errors) => errors.iter().all(|(_, e)| e.is_not_found()),
+            e => e.status() == Some(http::StatusCode::NOT_FOUND),
+        }
+    }
+}
+
+impl From<std::convert::Infallible> for Error {
+    fn from(e: std::convert::Infallible) -> Self {
+        match e {}
+    }
+}
+
+impl From<crate::v2::ContentDigestError> for Error {
+    fn from(e: crate::v2::ContentDigestError) -> Self {
+        match e {
+            crate::v2::ContentDigestError::AlgorithmUnknown(algorithm) => {
+                Error::UnsupportedDigestAlgorithm(algorithm)
+            }
+            crate::v2::ContentDigestError::Verify { expected, got } => Error::DigestMismatch {
+                expected,
+                actual: got,
+            },
+            crate::v2::ContentDigestError::BadDigest(reason) => Error::InvalidDigest(reason),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use test_case::test_case;
+
     #[test]
     fn test_error_bounds() {
         fn check_bounds<T: Send + Sync + 'static>() {}
         check_bounds::<Error>();
     }
+
+    #[test_case(Error::RateLimited { retry_after: None } => (true, false, false); "rate limited")]
+    #[test_case(Error::Server { status: http::StatusCode::BAD_GATEWAY } => (true, false, false); "server error")]
+    #[test_case(Error::UnexpectedHttpStatus(http::StatusCode::SERVICE_UNAVAILABLE) => (true, false, false); "unexpected server status")]
+    #[test_case(Error::Unauthorized(http::StatusCode::FORBIDDEN) => (false, true, false); "forbidden")]
+    #[test_case(Error::NoCredentials => (false, true, false); "no credentials")]
+    #[test_case(Error::LoginFailed { cause: Box::new(Error::Server { status: http::StatusCode::INTERNAL_SERVER_ERROR }) } => (true, true, false); "login failed on server error")]
+    #[test_case(Error::Client { status: http::StatusCode::NOT_FOUND } => (false, false, true); "client not found")]
+    #[test_case(Error::Registry { status: http::StatusCode::NOT_FOUND, errors: vec![] } => (false, false, true); "registry not found")]
+    #[test_case(Error::NotFound("sha256:abcd".into()) => (false, false, true); "not found")]
+    #[test_case(Error::ManifestUnknown { name: "repo".into(), reference: "latest".into() } => (false, false, true); "manifest unknown")]
+    #[test_case(Error::Mirrors(vec![("mirror".into(), Error::NotFound("sha256:abcd".into())), ("registry".into(), Error::Client { status: http::StatusCode::NOT_FOUND })]) => (false, false, true); "not found on mirrors")]
+    #[test_case(Error::Mirrors(vec![("mirror".into(), Error::QuotaExceeded), ("registry".into(), Error::Client { status: http::StatusCode::NOT_FOUND })]) => (false, false, false); "mirror failed")]
+    #[test_case(Error::Client { status: http::StatusCode::BAD_REQUEST } => (false, false, false); "bad request")]
+    #[test_case(Error::QuotaExceeded => (false, false, false); "quota exceeded")]
+    fn test_classification(e: Error) -> (bool, bool, bool) {
+        (e.is_retriable(), e.is_auth(), e.is_not_found())
+    }
 }
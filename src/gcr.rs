@@ -0,0 +1,195 @@
+//! Credentials for Google Container Registry and Artifact Registry.
+//!
+//! Google registries accept an OAuth2 access token as the password of the
+//! `oauth2accesstoken` user, which `Config::gcr_access_token` sets up:
+//!
+//! ```rust,no_run
+//! # async fn run() -> dkregistry::errors::Result<()> {
+//! let config = dkregistry::v2::Client::configure().registry("us-docker.pkg.dev");
+//! let token = dkregistry::gcr::application_default_access_token(&config).await?;
+//! let client = config
+//!     .gcr_access_token(token.token())
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Access tokens can be obtained from Application Default Credentials, either user
+//! credentials as set up by `gcloud auth application-default login`, or the metadata
+//! server of Google Cloud instances. Service account keys need signing, which is not
+//! supported: use an access token obtained elsewhere for those.
+//!
+//! Tokens are requested with the HTTP settings of the registry configuration, such as
+//! its proxy, root certificates and timeouts.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use reqwest::StatusCode;
+
+use crate::errors::{Error, Result};
+use crate::v2::Config;
+
+/// Username to authenticate to Google registries with an access token.
+pub const ACCESS_TOKEN_USERNAME: &str = "oauth2accesstoken";
+
+/// Endpoint refreshing user credentials, unless the credentials file names another.
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// Host of the metadata server, unless overridden by `GCE_METADATA_HOST`.
+const DEFAULT_METADATA_HOST: &str = "metadata.google.internal";
+
+/// Timeout of requests to the metadata server, which is unreachable outside Google Cloud.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// An OAuth2 access token.
+#[derive(Clone)]
+pub struct AccessToken {
+    token: String,
+    expires_at: Option<SystemTime>,
+}
+
+impl std::fmt::Debug for AccessToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessToken")
+            .field("expires_at", &self.expires_at)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AccessToken {
+    /// The token, to use as password of the `oauth2accesstoken` user.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// When the token expires, if known.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+    }
+}
+
+/// Application Default Credentials file.
+#[derive(Deserialize)]
+struct CredentialsFile {
+    #[serde(rename = "type")]
+    credentials_type: String,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    refresh_token: Option<String>,
+    token_uri: Option<String>,
+}
+
+/// Response of the token endpoint and of the metadata server.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+impl TokenResponse {
+    fn into_access_token(self, now: SystemTime) -> AccessToken {
+        AccessToken {
+            token: self.access_token,
+            expires_at: self.expires_in.map(|s| now + Duration::from_secs(s)),
+        }
+    }
+}
+
+/// Get an access token from Application Default Credentials.
+///
+/// Credentials are looked up in the file named by `GOOGLE_APPLICATION_CREDENTIALS`,
+/// then in the file written by `gcloud auth application-default login`,
+/// and finally from the metadata server.
+pub async fn application_default_access_token(config: &Config) -> Result<AccessToken> {
+    if let Some(path) = std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS") {
+        return access_token_from_file(config, Path::new(&path)).await;
+    }
+    if let Some(path) = well_known_credentials_file().filter(|p| p.is_file()) {
+        return access_token_from_file(config, &path).await;
+    }
+    access_token_from_metadata_server(config).await
+}
+
+/// Path of the file written by `gcloud auth application-default login`.
+fn well_known_credentials_file() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("CLOUDSDK_CONFIG") {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(std::env::var_os("APPDATA")?).join("gcloud"),
+        None => PathBuf::from(std::env::var_os("HOME")?)
+            .join(".config")
+            .join("gcloud"),
+    };
+    Some(config_dir.join("application_default_credentials.json"))
+}
+
+/// Get an access token from an Application Default Credentials file of user credentials.
+pub async fn access_token_from_file(config: &Config, path: &Path) -> Result<AccessToken> {
+    let file: CredentialsFile = serde_json::from_slice(&std::fs::read(path)?)?;
+    let (client_id, client_secret, refresh_token) = match file.credentials_type.as_str() {
+        "authorized_user" => match (file.client_id, file.client_secret, file.refresh_token) {
+            (Some(id), Some(secret), Some(token)) => (id, secret, token),
+            _ => return Err(Error::Gcr("incomplete user credentials".into())),
+        },
+        other => {
+            return Err(Error::Gcr(format!(
+                "unsupported credentials type '{}'",
+                other
+            )))
+        }
+    };
+
+    let token_uri = file.token_uri.as_deref().unwrap_or(DEFAULT_TOKEN_URI);
+    trace!("Refreshing Google user credentials at {}", token_uri);
+    let resp = config
+        .auxiliary_http_client()?
+        .post(token_uri)
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", &client_id),
+            ("client_secret", &client_secret),
+            ("refresh_token", &refresh_token),
+        ])
+        .send()
+        .await?;
+    token_response(resp).await
+}
+
+/// Get an access token for the default service account of a Google Cloud instance
+/// from its metadata server, at the host set in `GCE_METADATA_HOST` if any.
+///
+/// The request times out after a few seconds, whatever `Config::timeout` says, so that
+/// a missing metadata server fails fast.
+pub async fn access_token_from_metadata_server(config: &Config) -> Result<AccessToken> {
+    let host =
+        std::env::var("GCE_METADATA_HOST").unwrap_or_else(|_| DEFAULT_METADATA_HOST.to_string());
+    let url = format!(
+        "http://{}/computeMetadata/v1/instance/service-accounts/default/token",
+        host
+    );
+    trace!("Getting access token from metadata server {}", host);
+    let resp = config
+        .auxiliary_http_client()?
+        .get(url)
+        .header("Metadata-Flavor", "Google")
+        .timeout(METADATA_TIMEOUT)
+        .send()
+        .await?;
+    token_response(resp).await
+}
+
+async fn token_response(resp: reqwest::Response) -> Result<AccessToken> {
+    let status = resp.status();
+    if status != StatusCode::OK {
+        let body = resp.text().await.unwrap_or_default();
+        return Err(Error::Gcr(format!(
+            "token request failed with status {}: {}",
+            status,
+            body.trim()
+        )));
+    }
+    Ok(resp
+        .json::<TokenResponse>()
+        .await?
+        .into_access_token(SystemTime::now()))
+}
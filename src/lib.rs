@@ -40,7 +40,15 @@ extern crate log;
 #[macro_use]
 extern crate strum_macros;
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "credential-helpers")]
+mod credential_helper;
+#[cfg(feature = "ecr")]
+pub mod ecr;
 pub mod errors;
+#[cfg(feature = "gcr")]
+pub mod gcr;
 pub mod mediatypes;
 pub mod reference;
 pub mod render;
@@ -55,12 +63,17 @@ use std::collections::HashMap;
 use std::io::Read;
 
 /// Default User-Agent client identity.
-pub static USER_AGENT: ArcStr = arcstr::literal!("camallo-dkregistry/0.0");
+pub static USER_AGENT: ArcStr =
+    arcstr::literal!(concat!("dkregistry-rs/", env!("CARGO_PKG_VERSION")));
 
 /// Get registry credentials from a JSON config reader.
 ///
 /// This is a convenience decoder for docker-client credentials
 /// typically stored under `~/.docker/config.json`.
+///
+/// With the `credential-helpers` feature, registries without inline credentials
+/// are resolved through the helper configured in `credHelpers` for this registry,
+/// or else through the global `credsStore`.
 pub fn get_credentials<T: Read>(
     reader: T,
     index: &str,
@@ -71,9 +84,9 @@ pub fn get_credentials<T: Read>(
         "docker.io" | "registry-1.docker.io" => "https://index.docker.io/v1/",
         other => other,
     };
-    let auth = match map.auths.get(real_index) {
+    let auth = match map.auths.get(real_index).filter(|x| !x.auth.is_empty()) {
         Some(x) => base64::engine::general_purpose::STANDARD.decode(x.auth.as_str())?,
-        None => return Err(Error::AuthInfoMissing(real_index.to_string())),
+        None => return get_helper_credentials(&map, index, real_index),
     };
     let s = CompactString::from_utf8(auth)?;
     let mut creds = s.splitn(2, ':');
@@ -83,16 +96,52 @@ pub fn get_credentials<T: Read>(
         (Some(u), Some(p)) => (Some(u.to_compact_string()), Some(p.to_compact_string())),
         (_, _) => (None, None),
     };
-    trace!("Found credentials for user={:?} on {}", up.0, index);
+    trace!("Found credentials for {}", index);
     Ok(up)
 }
 
+#[cfg(feature = "credential-helpers")]
+fn get_helper_credentials(
+    map: &Auths,
+    index: &str,
+    real_index: &str,
+) -> Result<(Option<CompactString>, Option<CompactString>)> {
+    let helper = map
+        .cred_helpers
+        .get(index)
+        .or_else(|| map.cred_helpers.get(real_index))
+        .or(map.creds_store.as_ref())
+        .ok_or_else(|| Error::AuthInfoMissing(real_index.to_string()))?;
+    let up = credential_helper::get(helper, real_index)?;
+    trace!("Found credentials on {} via helper {}", index, helper);
+    Ok(up)
+}
+
+#[cfg(not(feature = "credential-helpers"))]
+fn get_helper_credentials(
+    _map: &Auths,
+    _index: &str,
+    real_index: &str,
+) -> Result<(Option<CompactString>, Option<CompactString>)> {
+    Err(Error::AuthInfoMissing(real_index.to_string()))
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct Auths {
+    #[serde(default)]
     auths: HashMap<String, AuthObj>,
+    #[serde(rename = "credsStore", skip_serializing_if = "Option::is_none")]
+    creds_store: Option<String>,
+    #[serde(
+        rename = "credHelpers",
+        default,
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    cred_helpers: HashMap<String, String>,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 struct AuthObj {
+    #[serde(default)]
     auth: String,
 }
@@ -3,6 +3,7 @@
 use crate::errors::Result;
 use serde_with::DeserializeFromStr;
 use serde_with::SerializeDisplay;
+use std::str::FromStr;
 use strum::EnumProperty;
 
 // For schema1 types, see https://docs.docker.com/registry/spec/manifest-v2-1/
@@ -33,6 +34,10 @@ pub enum MediaTypes {
     #[strum(serialize = "application/vnd.oci.image.config.v1+json")]
     #[strum(props(Sub = "vnd.oci.image.config.v1+json"))]
     OciV1ManifestConfig,
+    /// OCI empty descriptor, used as the config of artifacts without one.
+    #[strum(serialize = "application/vnd.oci.empty.v1+json")]
+    #[strum(props(Sub = "vnd.oci.empty.v1+json"))]
+    OciV1EmptyConfig,
     /// Manifest, version 2 schema 1.
     #[strum(serialize = "application/vnd.docker.distribution.manifest.v1+json")]
     #[strum(props(Sub = "vnd.docker.distribution.manifest.v1+json"))]
@@ -53,6 +58,26 @@ pub enum MediaTypes {
     #[strum(serialize = "application/vnd.docker.image.rootfs.diff.tar.gzip")]
     #[strum(props(Sub = "vnd.docker.image.rootfs.diff.tar.gzip"))]
     ImageLayerTgz,
+    /// Foreign image layer, as a gzip-compressed tar fetched from external URLs.
+    #[strum(serialize = "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip")]
+    #[strum(props(Sub = "vnd.docker.image.rootfs.foreign.diff.tar.gzip"))]
+    ForeignImageLayerTgz,
+    /// OCI layer, as an uncompressed tar.
+    #[strum(serialize = "application/vnd.oci.image.layer.v1.tar")]
+    #[strum(props(Sub = "vnd.oci.image.layer.v1.tar"))]
+    OciV1LayerTar,
+    /// OCI layer, as a gzip-compressed tar.
+    #[strum(serialize = "application/vnd.oci.image.layer.v1.tar+gzip")]
+    #[strum(props(Sub = "vnd.oci.image.layer.v1.tar+gzip"))]
+    OciV1LayerTgz,
+    /// OCI layer, as a zstd-compressed tar.
+    #[strum(serialize = "application/vnd.oci.image.layer.v1.tar+zstd")]
+    #[strum(props(Sub = "vnd.oci.image.layer.v1.tar+zstd"))]
+    OciV1LayerTzst,
+    /// OCI non-distributable layer, as a gzip-compressed tar.
+    #[strum(serialize = "application/vnd.oci.image.layer.nondistributable.v1.tar+gzip")]
+    #[strum(props(Sub = "vnd.oci.image.layer.nondistributable.v1.tar+gzip"))]
+    OciV1NonDistributableLayerTgz,
     /// Configuration object for a container.
     #[strum(serialize = "application/vnd.docker.container.image.v1+json")]
     #[strum(props(Sub = "vnd.docker.container.image.v1+json"))]
@@ -61,14 +86,36 @@ pub enum MediaTypes {
     #[strum(serialize = "application/json")]
     #[strum(props(Sub = "json"))]
     ApplicationJson,
+    /// Any other media type, such as the `artifactType` of an OCI artifact.
+    #[strum(default)]
+    Other(String),
 }
 
+/// Media types of manifests and manifest lists, as returned by `MediaTypes::all_manifest_types`.
+static MANIFEST_TYPES: [MediaTypes; 6] = [
+    MediaTypes::ManifestV2S2,
+    MediaTypes::ManifestList,
+    MediaTypes::ManifestV2S1Signed,
+    MediaTypes::ManifestV2S1,
+    MediaTypes::OciV1Manifest,
+    MediaTypes::OciV1ManifestList,
+];
+
 impl MediaTypes {
+    /// All media types of manifests and manifest lists (or indexes), Docker and OCI.
+    pub fn all_manifest_types() -> &'static [MediaTypes] {
+        &MANIFEST_TYPES
+    }
+
     // TODO(lucab): proper error types
     pub fn from_mime(mtype: &mime::Mime) -> Result<Self> {
         match (mtype.type_(), mtype.subtype(), mtype.suffix()) {
             (mime::APPLICATION, mime::JSON, _) => Ok(MediaTypes::ApplicationJson),
             (mime::APPLICATION, subt, Some(suff)) => match (subt.as_str(), suff.as_str()) {
+                ("vnd.oci.image.index.v1", "json") => Ok(MediaTypes::OciV1ManifestList),
+                ("vnd.oci.image.manifest.v1", "json") => Ok(MediaTypes::OciV1Manifest),
+                ("vnd.oci.image.config.v1", "json") => Ok(MediaTypes::OciV1ManifestConfig),
+                ("vnd.oci.empty.v1", "json") => Ok(MediaTypes::OciV1EmptyConfig),
                 ("vnd.docker.distribution.manifest.v1", "json") => Ok(MediaTypes::ManifestV2S1),
                 ("vnd.docker.distribution.manifest.v1", "prettyjws") => {
                     Ok(MediaTypes::ManifestV2S1Signed)
@@ -79,14 +126,88 @@ impl MediaTypes {
                 }
                 ("vnd.docker.image.rootfs.diff.tar.gzip", _) => Ok(MediaTypes::ImageLayerTgz),
                 ("vnd.docker.container.image.v1", "json") => Ok(MediaTypes::ContainerConfigV1),
-                _ => Err(crate::Error::UnknownMimeType(mtype.clone())),
+                ("vnd.oci.image.layer.v1.tar", "gzip") => Ok(MediaTypes::OciV1LayerTgz),
+                ("vnd.oci.image.layer.v1.tar", "zstd") => Ok(MediaTypes::OciV1LayerTzst),
+                ("vnd.oci.image.layer.nondistributable.v1.tar", "gzip") => {
+                    Ok(MediaTypes::OciV1NonDistributableLayerTgz)
+                }
+                _ => Ok(MediaTypes::Other(mtype.essence_str().to_string())),
+            },
+            (mime::APPLICATION, subt, None) => match subt.as_str() {
+                "vnd.docker.image.rootfs.diff.tar.gzip" => Ok(MediaTypes::ImageLayerTgz),
+                "vnd.oci.image.layer.v1.tar" => Ok(MediaTypes::OciV1LayerTar),
+                "vnd.docker.image.rootfs.foreign.diff.tar.gzip" => {
+                    Ok(MediaTypes::ForeignImageLayerTgz)
+                }
+                _ => Ok(MediaTypes::Other(mtype.essence_str().to_string())),
             },
-            _ => Err(crate::Error::UnknownMimeType(mtype.clone())),
+            _ => Ok(MediaTypes::Other(mtype.essence_str().to_string())),
         }
     }
+
+    /// Parse a media type, such as a `Content-Type` header value, ignoring its parameters.
+    ///
+    /// Parameters such as `; charset=utf-8` and surrounding whitespace are stripped,
+    /// and the media type is matched case-insensitively.
+    /// Unknown media types are kept as `MediaTypes::Other`, without their parameters.
+    pub fn from_content_type(value: &str) -> Self {
+        let essence = value.split(';').next().unwrap_or_default().trim();
+        match Self::from_str(&essence.to_ascii_lowercase()) {
+            Ok(MediaTypes::Other(_)) | Err(_) => MediaTypes::Other(essence.to_string()),
+            Ok(media_type) => media_type,
+        }
+    }
+
+    /// The subtype of the media type, with its suffix, such as
+    /// `vnd.docker.distribution.manifest.v2+json`.
+    ///
+    /// For `MediaTypes::Other`, this is whatever follows the first `/`, or the whole value
+    /// if there is none.
+    pub fn subtype(&self) -> &str {
+        match self {
+            MediaTypes::Other(s) => s.split_once('/').map_or(s.as_str(), |(_, sub)| sub),
+            m => m
+                .get_str("Sub")
+                .expect("known media types should have a subtype"),
+        }
+    }
+
+    /// Whether this is the media type of an image manifest, Docker or OCI.
+    ///
+    /// Manifest lists and indexes are not image manifests, see `is_index`.
+    pub fn is_manifest(&self) -> bool {
+        matches!(
+            self,
+            MediaTypes::ManifestV2S1
+                | MediaTypes::ManifestV2S1Signed
+                | MediaTypes::ManifestV2S2
+                | MediaTypes::OciV1Manifest
+        )
+    }
+
+    /// Whether this is the media type of a manifest list or an OCI index.
+    pub fn is_index(&self) -> bool {
+        matches!(
+            self,
+            MediaTypes::ManifestList | MediaTypes::OciV1ManifestList
+        )
+    }
+
+    /// Whether this is the media type of an image config blob, including the OCI empty
+    /// descriptor.
+    pub fn is_config(&self) -> bool {
+        matches!(
+            self,
+            MediaTypes::ContainerConfigV1
+                | MediaTypes::OciV1ManifestConfig
+                | MediaTypes::OciV1EmptyConfig
+        )
+    }
+
     pub fn to_mime(&self) -> mime::Mime {
         match self {
             &MediaTypes::ApplicationJson => Ok(mime::APPLICATION_JSON),
+            MediaTypes::Other(s) => s.parse().or_else(|_| "application/star".parse()),
             m => match m.get_str("Sub") {
                 Some(s) => format!("application/{s}").parse(),
                 None => "application/star".parse(),
@@ -95,3 +216,125 @@ impl MediaTypes {
         .expect("to_mime should be always successful")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test]
+    fn test_oci_empty_config() {
+        let media_type = MediaTypes::from_str("application/vnd.oci.empty.v1+json").unwrap();
+        assert_eq!(media_type, MediaTypes::OciV1EmptyConfig);
+        assert_eq!(
+            MediaTypes::from_mime(&media_type.to_mime()).unwrap(),
+            MediaTypes::OciV1EmptyConfig
+        );
+    }
+
+    #[test]
+    fn test_oci_layer_types() {
+        for media_type in [
+            MediaTypes::OciV1LayerTar,
+            MediaTypes::OciV1LayerTgz,
+            MediaTypes::OciV1LayerTzst,
+        ] {
+            assert_eq!(
+                MediaTypes::from_str(&media_type.to_string()).unwrap(),
+                media_type
+            );
+            assert_eq!(
+                MediaTypes::from_mime(&media_type.to_mime()).unwrap(),
+                media_type
+            );
+        }
+    }
+
+    #[test]
+    fn test_other_media_type() {
+        let artifact_type = "application/vnd.example.sbom.v1+json";
+        let media_type = MediaTypes::from_str(artifact_type).unwrap();
+        assert_eq!(media_type, MediaTypes::Other(artifact_type.to_string()));
+        assert_eq!(media_type.to_string(), artifact_type);
+
+        let json = serde_json::to_string(&media_type).unwrap();
+        assert_eq!(json, format!("\"{}\"", artifact_type));
+        let parsed: MediaTypes = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, media_type);
+    }
+
+    #[test]
+    fn test_unknown_mime_round_trip() {
+        let mtype: mime::Mime = "application/vnd.example.manifest.v9+json".parse().unwrap();
+        let media_type = MediaTypes::from_mime(&mtype).unwrap();
+        assert_eq!(media_type, MediaTypes::Other(mtype.to_string()));
+        assert_eq!(media_type.to_mime(), mtype);
+        assert_eq!(media_type.to_string(), mtype.to_string());
+
+        let mtype: mime::Mime = "text/plain".parse().unwrap();
+        assert_eq!(MediaTypes::from_mime(&mtype).unwrap().to_mime(), mtype);
+    }
+
+    #[test]
+    fn test_all_manifest_types() {
+        let types = MediaTypes::all_manifest_types();
+        assert!(types.contains(&MediaTypes::OciV1ManifestList));
+        assert!(types.contains(&MediaTypes::ManifestV2S2));
+        assert!(!types.contains(&MediaTypes::OciV1ManifestConfig));
+        for media_type in types {
+            assert_eq!(
+                &MediaTypes::from_mime(&media_type.to_mime()).unwrap(),
+                media_type
+            );
+        }
+    }
+
+    #[test_case("application/vnd.docker.distribution.manifest.v2+json; charset=utf-8" => MediaTypes::ManifestV2S2; "charset")]
+    #[test_case("application/vnd.docker.distribution.manifest.v2+json;charset=utf-8" => MediaTypes::ManifestV2S2; "charset without space")]
+    #[test_case("  application/vnd.oci.image.index.v1+json  " => MediaTypes::OciV1ManifestList; "whitespace")]
+    #[test_case("application/vnd.oci.image.manifest.v1+json ; charset=\"UTF-8\"" => MediaTypes::OciV1Manifest; "quoted charset")]
+    #[test_case("Application/VND.Docker.Distribution.Manifest.List.v2+JSON" => MediaTypes::ManifestList; "mixed case")]
+    #[test_case("application/json; charset=utf-8" => MediaTypes::ApplicationJson; "json")]
+    #[test_case("application/x-unknown; charset=utf-8" => MediaTypes::Other("application/x-unknown".to_string()); "unknown")]
+    fn test_from_content_type(value: &str) -> MediaTypes {
+        MediaTypes::from_content_type(value)
+    }
+
+    #[test]
+    fn test_subtype() {
+        assert_eq!(
+            MediaTypes::ManifestV2S2.subtype(),
+            "vnd.docker.distribution.manifest.v2+json"
+        );
+        assert_eq!(MediaTypes::ApplicationJson.subtype(), "json");
+        assert_eq!(
+            MediaTypes::Other("application/vnd.example.sbom.v1+json".to_string()).subtype(),
+            "vnd.example.sbom.v1+json"
+        );
+        for media_type in MediaTypes::all_manifest_types() {
+            assert_eq!(
+                media_type.to_mime().subtype(),
+                media_type.subtype().split('+').next().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_classification() {
+        for media_type in MediaTypes::all_manifest_types() {
+            assert!(media_type.is_manifest() != media_type.is_index());
+            assert!(!media_type.is_config());
+        }
+        assert!(MediaTypes::OciV1Manifest.is_manifest());
+        assert!(MediaTypes::OciV1ManifestList.is_index());
+        assert!(MediaTypes::ContainerConfigV1.is_config());
+        assert!(MediaTypes::OciV1EmptyConfig.is_config());
+        for media_type in [
+            MediaTypes::ImageLayerTgz,
+            MediaTypes::ApplicationJson,
+            MediaTypes::Other("application/vnd.example.sbom.v1+json".to_string()),
+        ] {
+            assert!(!media_type.is_manifest() && !media_type.is_index() && !media_type.is_config());
+        }
+    }
+}
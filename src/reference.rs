@@ -17,6 +17,13 @@
 //! assert_eq!(dkref.registry(), "registry-1.docker.io");
 //! assert_eq!(dkref.repository(), "library/busybox");
 //! assert_eq!(dkref.version(), "latest");
+//!
+//! // Docker Hub references are normalized like the Docker CLI does,
+//! // while the raw input stays available.
+//! let dkref = Reference::from_str("docker.io/ubuntu")?;
+//! assert_eq!(dkref.registry(), "registry-1.docker.io");
+//! assert_eq!(dkref.repository(), "library/ubuntu");
+//! assert_eq!(dkref.to_raw_string(), "docker.io/ubuntu");
 //! #
 //! # Ok(())
 //! # };
@@ -30,11 +37,15 @@
 // https://github.com/docker/distribution/blob/v2.6.1/reference/reference.go
 
 use std::collections::VecDeque;
+use std::convert::TryFrom;
 use std::str::FromStr;
 use std::{fmt, str};
 
 pub static DEFAULT_REGISTRY: &str = "registry-1.docker.io";
-static DEFAULT_TAG: &str = "latest";
+/// Names of Docker Hub which are normalized to `DEFAULT_REGISTRY`, as the Docker CLI does.
+static DEFAULT_REGISTRY_ALIASES: &[&str] = &["docker.io", "index.docker.io"];
+/// Tag of references which have neither a tag nor a digest.
+pub static DEFAULT_TAG: &str = "latest";
 static DEFAULT_SCHEME: &str = "docker";
 
 /// Image version, either a tag or a digest.
@@ -52,6 +63,170 @@ pub enum VersionParseError {
     UnknownPrefix,
     #[error("empty string is invalid digest")]
     Empty,
+    #[error("invalid digest '{0}'")]
+    InvalidDigest(String),
+    #[error("invalid tag '{0}'")]
+    InvalidTag(String),
+}
+
+/// Check a tag against the grammar of the distribution spec.
+fn validate_tag(tag: &str) -> Result<(), VersionParseError> {
+    const TAG_REGEX: &str = "^[a-zA-Z0-9_][a-zA-Z0-9._-]{0,127}$";
+
+    if regex::Regex::new(TAG_REGEX)
+        .expect("hardcoded regex is invalid")
+        .is_match(tag)
+    {
+        Ok(())
+    } else {
+        Err(VersionParseError::InvalidTag(tag.to_string()))
+    }
+}
+
+/// Check a digest against the grammar of the distribution spec,
+/// and the expected length of the hex encoding for known algorithms.
+fn validate_digest(algorithm: &str, encoded: &str) -> Result<(), VersionParseError> {
+    const ALGORITHM_REGEX: &str = "^[a-z0-9]+(?:[.+_-][a-z0-9]+)*$";
+    const ENCODED_REGEX: &str = "^[a-zA-Z0-9=_-]+$";
+
+    let is_hex = |len: usize| {
+        encoded.len() == len
+            && encoded
+                .chars()
+                .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+    };
+    let valid = match algorithm {
+        "sha256" => is_hex(64),
+        "sha512" => is_hex(128),
+        _ => {
+            regex::Regex::new(ALGORITHM_REGEX)
+                .expect("hardcoded regex is invalid")
+                .is_match(algorithm)
+                && regex::Regex::new(ENCODED_REGEX)
+                    .expect("hardcoded regex is invalid")
+                    .is_match(encoded)
+        }
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(VersionParseError::InvalidDigest(format!(
+            "{}:{}",
+            algorithm, encoded
+        )))
+    }
+}
+
+/// A validated content digest, as `algorithm:encoded`.
+///
+/// Client methods taking a digest accept either a `Digest` or a string,
+/// which is then validated before any request is sent.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Digest {
+    algorithm: String,
+    encoded: String,
+}
+
+impl Digest {
+    /// Algorithm of the digest, e.g. `sha256`.
+    pub fn algorithm(&self) -> &str {
+        &self.algorithm
+    }
+
+    /// Encoded value of the digest, e.g. the hex of a sha256 hash.
+    pub fn encoded(&self) -> &str {
+        &self.encoded
+    }
+}
+
+impl str::FromStr for Digest {
+    type Err = VersionParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algorithm, encoded) = s
+            .split_once(':')
+            .ok_or(VersionParseError::WrongDigestFormat)?;
+        validate_digest(algorithm, encoded)?;
+        Ok(Digest {
+            algorithm: algorithm.to_string(),
+            encoded: encoded.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}:{}", self.algorithm, self.encoded)
+    }
+}
+
+impl TryFrom<&str> for Digest {
+    type Error = VersionParseError;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl TryFrom<&String> for Digest {
+    type Error = VersionParseError;
+    fn try_from(s: &String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl TryFrom<String> for Digest {
+    type Error = VersionParseError;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<&Digest> for Digest {
+    fn from(digest: &Digest) -> Self {
+        digest.clone()
+    }
+}
+
+impl From<Digest> for Version {
+    fn from(digest: Digest) -> Self {
+        Version::Digest(digest.algorithm, digest.encoded)
+    }
+}
+
+impl From<&Digest> for Version {
+    fn from(digest: &Digest) -> Self {
+        digest.clone().into()
+    }
+}
+
+impl From<&Version> for Version {
+    fn from(version: &Version) -> Self {
+        version.clone()
+    }
+}
+
+/// Parse a bare tag or digest, as in the URL of a manifest, e.g. `latest` or `sha256:...`.
+///
+/// Unlike `FromStr`, no `:` or `@` prefix is expected: tags can't contain a `:`,
+/// so anything with one is parsed as a digest.
+impl TryFrom<&str> for Version {
+    type Error = VersionParseError;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        if s.is_empty() {
+            return Err(VersionParseError::Empty);
+        }
+        match s.contains(':') {
+            true => Ok(s.parse::<Digest>()?.into()),
+            false => Ok(Version::Tag(s.to_string())),
+        }
+    }
+}
+
+impl TryFrom<&String> for Version {
+    type Error = VersionParseError;
+    fn try_from(s: &String) -> Result<Self, Self::Error> {
+        Version::try_from(s.as_str())
+    }
 }
 
 impl str::FromStr for Version {
@@ -64,6 +239,7 @@ impl str::FromStr for Version {
                 if r.len() != 2 {
                     return Err(VersionParseError::WrongDigestFormat);
                 };
+                validate_digest(r[0], r[1])?;
                 Version::Digest(r[0].to_string(), r[1].to_string())
             }
             Some(_) => return Err(VersionParseError::UnknownPrefix),
@@ -100,23 +276,28 @@ impl fmt::Display for Version {
 }
 
 /// A registry image reference.
+///
+/// A reference may carry both a tag and a digest, as in `repo:tag@sha256:...`,
+/// in which case the digest is its version. A reference with neither stands for
+/// `DEFAULT_TAG`, or the `default_tag` of the client using it.
 #[derive(Clone, Debug, Default)]
 pub struct Reference {
     raw_input: String,
     registry: String,
     repository: String,
-    version: Version,
+    version: Option<Version>,
+    tag: Option<String>,
 }
 
 impl Reference {
     pub fn new(registry: Option<String>, repository: String, version: Option<Version>) -> Self {
         let reg = registry.unwrap_or_else(|| DEFAULT_REGISTRY.to_string());
-        let ver = version.unwrap_or_else(|| Version::Tag(DEFAULT_TAG.to_string()));
         Self {
             raw_input: "".into(),
             registry: reg,
             repository,
-            version: ver,
+            version,
+            tag: None,
         }
     }
 
@@ -128,8 +309,71 @@ impl Reference {
         self.repository.as_ref()
     }
 
+    /// Return the version to fetch: the digest if any, or the tag, `DEFAULT_TAG` if none.
     pub fn version(&self) -> String {
-        self.version.to_string()
+        self.version_or(DEFAULT_TAG)
+    }
+
+    /// Return the version to fetch: the digest if any, or the tag, `default_tag` if none.
+    pub fn version_or(&self, default_tag: &str) -> String {
+        match &self.version {
+            Some(version) => version.to_string(),
+            None => default_tag.to_string(),
+        }
+    }
+
+    /// Whether the reference has a tag or a digest, rather than standing for the default tag.
+    pub fn has_version(&self) -> bool {
+        self.version.is_some()
+    }
+
+    /// Return the tag, if any.
+    pub fn tag(&self) -> Option<&str> {
+        match &self.version {
+            Some(Version::Tag(t)) => Some(t),
+            Some(Version::Digest(..)) => self.tag.as_deref(),
+            None => Some(DEFAULT_TAG),
+        }
+    }
+
+    /// Return the digest, if any, as `algorithm:hex`.
+    pub fn digest(&self) -> Option<String> {
+        match &self.version {
+            Some(version @ Version::Digest(..)) => Some(version.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Format the tag, if any, and the digest of a digest reference, e.g. `:tag@sha256:...`.
+    fn version_suffix(&self) -> String {
+        let version = self.version.clone().unwrap_or_default();
+        match &self.tag {
+            Some(tag) => format!(":{}{:?}", tag, version),
+            None => format!("{:?}", version),
+        }
+    }
+
+    /// Return the reference to the same repository at `digest`, e.g. to pin a tag.
+    ///
+    /// The tag of the reference, if any, is kept alongside the digest.
+    pub fn with_digest(mut self, digest: &str) -> Result<Self, ReferenceParseError> {
+        let digest = digest.parse::<Digest>()?;
+        self.tag = match self.version.take() {
+            Some(Version::Tag(tag)) => Some(tag),
+            _ => self.tag,
+        };
+        self.version = Some(digest.into());
+        self.raw_input = self.to_string();
+        Ok(self)
+    }
+
+    /// Return the reference to the same repository at `tag`, without any digest.
+    pub fn with_tag(mut self, tag: &str) -> Result<Self, ReferenceParseError> {
+        validate_tag(tag)?;
+        self.version = Some(Version::Tag(tag.to_string()));
+        self.tag = None;
+        self.raw_input = self.to_string();
+        Ok(self)
     }
 
     pub fn to_raw_string(&self) -> &str {
@@ -139,15 +383,24 @@ impl Reference {
     //TODO(lucab): move this to a real URL type
     pub fn to_url(&self) -> String {
         format!(
-            "{}://{}/{}{:?}",
-            DEFAULT_SCHEME, self.registry, self.repository, self.version
+            "{}://{}/{}{}",
+            DEFAULT_SCHEME,
+            self.registry,
+            self.repository,
+            self.version_suffix()
         )
     }
 }
 
 impl fmt::Display for Reference {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{}/{}{:?}", self.registry, self.repository, self.version)
+        write!(
+            f,
+            "{}/{}{}",
+            self.registry,
+            self.repository,
+            self.version_suffix()
+        )
     }
 }
 
@@ -209,22 +462,43 @@ fn parse_url(input: &str) -> Result<Reference, ReferenceParseError> {
         ([:][0-9]{1,6})?
         $
     ").expect("hardcoded regex is invalid").is_match(&first) {
+        if DEFAULT_REGISTRY_ALIASES.contains(&first.as_str()) {
+            DEFAULT_REGISTRY.to_string()
+        } else {
+            first
+        }
+    } else if regex::Regex::new("^localhost(:[0-9]{1,6})?$")
+        .expect("hardcoded regex is invalid")
+        .is_match(&first)
+    {
         first
     } else {
         components.push_front(first);
         DEFAULT_REGISTRY.to_string()
     };
 
-    // Take image name and extract tag or digest-ref, if any.
+    // Take image name and extract tag and digest-ref, if any.
     let last = components
         .pop_back()
         .ok_or(ReferenceParseError::MissingImageName)?;
-    let (image_name, version) = match (last.rfind('@'), last.rfind(':')) {
-        (Some(i), _) | (None, Some(i)) => {
+    let (name_and_tag, digest) = match last.find('@') {
+        Some(i) => {
             let s = last.split_at(i);
-            (String::from(s.0), Version::from_str(s.1)?)
+            (s.0, Some(Version::from_str(s.1)?))
         }
-        (None, None) => (last, Version::default()),
+        None => (last.as_str(), None),
+    };
+    let (image_name, tag) = match name_and_tag.rfind(':') {
+        Some(i) => {
+            let s = name_and_tag.split_at(i);
+            (String::from(s.0), Some(Version::from_str(s.1)?))
+        }
+        None => (String::from(name_and_tag), None),
+    };
+    let (version, tag) = match (digest, tag) {
+        (Some(digest), Some(Version::Tag(tag))) => (Some(digest), Some(tag)),
+        (Some(digest), _) => (Some(digest), None),
+        (None, tag) => (tag, None),
     };
     if image_name.is_empty() {
         return Err(ReferenceParseError::EmptyImageName);
@@ -266,5 +540,6 @@ fn parse_url(input: &str) -> Result<Reference, ReferenceParseError> {
         registry,
         repository,
         version,
+        tag,
     })
 }
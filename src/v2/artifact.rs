@@ -0,0 +1,166 @@
+//! Push of OCI artifacts.
+
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+
+use crate::errors::Result;
+use crate::mediatypes::MediaTypes;
+use crate::v2::manifest::Descriptor;
+use crate::v2::*;
+
+/// An OCI artifact to push with `Client::put_artifact`, such as a Helm chart or an SBOM.
+///
+/// Artifacts are pushed as OCI image manifests with an `artifactType`, so they can be
+/// listed with `Client::get_referrers` when they have a subject.
+#[derive(Clone, Debug)]
+pub struct ArtifactManifest {
+    artifact_type: String,
+    config: Option<(String, Vec<u8>)>,
+    layers: Vec<(String, Vec<u8>, BTreeMap<String, String>)>,
+    subject: Option<Descriptor>,
+    annotations: BTreeMap<String, String>,
+}
+
+/// An OCI image manifest describing an artifact.
+#[derive(Serialize)]
+struct ArtifactImageManifest<'a> {
+    #[serde(rename = "schemaVersion")]
+    schema_version: u16,
+    #[serde(rename = "mediaType")]
+    media_type: String,
+    #[serde(rename = "artifactType")]
+    artifact_type: &'a str,
+    config: Descriptor,
+    layers: Vec<Descriptor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subject: Option<&'a Descriptor>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    annotations: &'a BTreeMap<String, String>,
+}
+
+impl ArtifactManifest {
+    /// Create an artifact of the given type, without content.
+    pub fn new(artifact_type: &str) -> Self {
+        Self {
+            artifact_type: artifact_type.to_string(),
+            config: None,
+            layers: Vec::new(),
+            subject: None,
+            annotations: BTreeMap::new(),
+        }
+    }
+
+    /// Set the config blob of the artifact.
+    ///
+    /// Artifacts without one get the empty config, `{}` of type `application/vnd.oci.empty.v1+json`.
+    pub fn config(mut self, media_type: &str, data: impl Into<Vec<u8>>) -> Self {
+        self.config = Some((media_type.to_string(), data.into()));
+        self
+    }
+
+    /// Append a layer blob to the artifact.
+    pub fn layer(self, media_type: &str, data: impl Into<Vec<u8>>) -> Self {
+        self.layer_with_annotations(media_type, data, BTreeMap::new())
+    }
+
+    /// Append a layer blob to the artifact, with annotations such as
+    /// `org.opencontainers.image.title`.
+    pub fn layer_with_annotations(
+        mut self,
+        media_type: &str,
+        data: impl Into<Vec<u8>>,
+        annotations: BTreeMap<String, String>,
+    ) -> Self {
+        self.layers
+            .push((media_type.to_string(), data.into(), annotations));
+        self
+    }
+
+    /// Set the manifest the artifact refers to, making it a referrer of that manifest.
+    pub fn subject(self, media_type: &MediaTypes, digest: &str, size: u64) -> Self {
+        self.subject_descriptor(Descriptor {
+            media_type: media_type.to_string().into(),
+            size,
+            digest: digest.to_string(),
+            ..Default::default()
+        })
+    }
+
+    /// Set the manifest the artifact refers to from its full descriptor,
+    /// as returned by `ManifestSchema2Spec::subject` for example.
+    pub fn subject_descriptor(mut self, subject: Descriptor) -> Self {
+        self.subject = Some(subject);
+        self
+    }
+
+    /// Add an annotation to the manifest of the artifact.
+    pub fn annotation(mut self, key: &str, value: &str) -> Self {
+        self.annotations.insert(key.to_string(), value.to_string());
+        self
+    }
+}
+
+impl Client {
+    /// Upload a blob unless the repository already has it, returning its descriptor.
+    async fn push_artifact_blob(
+        &self,
+        name: &str,
+        media_type: &str,
+        data: Vec<u8>,
+        annotations: BTreeMap<String, String>,
+    ) -> Result<Descriptor> {
+        let digest = self.digest_algorithm.digest(&data);
+        let size = data.len() as u64;
+        if self.has_blob_here(name, &digest, None).await? {
+            trace!("Blob {} already present in {}", digest, name);
+        } else {
+            self.upload_blob(name, &digest, data).await?;
+        }
+        Ok(Descriptor {
+            media_type: media_type.into(),
+            size,
+            digest,
+            artifact_type: None,
+            annotations: Some(annotations).filter(|a| !a.is_empty()),
+        })
+    }
+
+    /// Push an OCI artifact, tagging it with `reference`.
+    ///
+    /// The config and layers are uploaded first, skipping those already in the repository,
+    /// then the OCI image manifest describing them is pushed. Returns the digest of the manifest.
+    pub async fn put_artifact(
+        &self,
+        name: &str,
+        reference: &str,
+        artifact: ArtifactManifest,
+    ) -> Result<String> {
+        let (config_type, config_data) = artifact
+            .config
+            .unwrap_or_else(|| (MediaTypes::OciV1EmptyConfig.to_string(), b"{}".to_vec()));
+        let config = self
+            .push_artifact_blob(name, &config_type, config_data, BTreeMap::new())
+            .await?;
+        let mut layers = Vec::with_capacity(artifact.layers.len());
+        for (media_type, data, annotations) in artifact.layers {
+            layers.push(
+                self.push_artifact_blob(name, &media_type, data, annotations)
+                    .await?,
+            );
+        }
+
+        let manifest = ArtifactImageManifest {
+            schema_version: 2,
+            media_type: MediaTypes::OciV1Manifest.to_string(),
+            artifact_type: &artifact.artifact_type,
+            config,
+            layers,
+            subject: artifact.subject.as_ref(),
+            annotations: &artifact.annotations,
+        };
+        let manifest = Bytes::from(serde_json::to_vec(&manifest)?);
+        self.put_manifest(name, reference, &MediaTypes::OciV1Manifest, manifest)
+            .await
+    }
+}
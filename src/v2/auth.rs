@@ -1,11 +1,35 @@
 use crate::errors::{Error, Result};
 use crate::v2::*;
 use arcstr::ArcStr;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::engine::Engine;
 use compact_str::CompactString;
 use compact_str::ToCompactString;
 use cow_utils::CowUtils;
+use futures::future::BoxFuture;
 use reqwest::{header::HeaderValue, RequestBuilder, StatusCode, Url};
 use serde::Serializer;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+/// Bearer tokens are considered stale this long before they actually expire.
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(5);
+
+/// Token lifetime in seconds to assume when the token endpoint omits `expires_in`.
+///
+/// See <https://docs.docker.com/registry/spec/auth/token/#token-response-fields>.
+const DEFAULT_TOKEN_LIFETIME: u32 = 60;
+
+/// Timeout of token requests, unless set with `Config::auth_timeout`.
+pub(crate) const DEFAULT_AUTH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum size of a token endpoint response, far above that of any token.
+const MAX_TOKEN_RESPONSE_BYTES: usize = 1024 * 1024;
+
+/// Client identifier sent to OAuth2 token endpoints.
+const OAUTH2_CLIENT_ID: &str = "dkregistry";
 
 /// Represents all supported authentication schemes and is stored by `Client`.
 #[derive(Debug, Clone)]
@@ -19,83 +43,607 @@ impl Auth {
     pub(crate) fn add_auth_headers(&self, request_builder: RequestBuilder) -> RequestBuilder {
         match self {
             Auth::Bearer(bearer_auth) => request_builder.bearer_auth(&bearer_auth.token),
-            Auth::Basic(basic_auth) => {
-                request_builder.basic_auth(&basic_auth.user, basic_auth.password.as_ref())
-            }
+            Auth::Basic(basic_auth) => request_builder.basic_auth(
+                &basic_auth.user,
+                basic_auth.password.as_ref().map(SecretString::expose),
+            ),
+        }
+    }
+}
+
+/// Kind of authentication a `Client` currently uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthKind {
+    /// No credentials are sent.
+    Anonymous,
+    /// Credentials are sent with HTTP Basic authentication.
+    Basic,
+    /// A bearer token is sent.
+    Bearer,
+}
+
+/// A secret, such as a password, whose `Debug` output is masked so it never reaches logs.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub(crate) struct SecretString(CompactString);
+
+impl SecretString {
+    pub(crate) fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<CompactString> for SecretString {
+    fn from(secret: CompactString) -> Self {
+        Self(secret)
+    }
+}
+
+impl std::fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("***")
+    }
+}
+
+/// Source of the username and password of a `Client`, called whenever they are needed.
+///
+/// Static credentials are a provider returning the same ones every time.
+#[derive(Clone)]
+pub(crate) struct CredentialProvider(Arc<dyn Fn() -> CredentialsFuture + Send + Sync>);
+
+type CredentialsFuture = BoxFuture<'static, Result<(CompactString, SecretString)>>;
+
+impl CredentialProvider {
+    pub(crate) fn new<F, Fut>(provider: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(String, String)>> + Send + 'static,
+    {
+        Self(Arc::new(move || {
+            provider()
+                .map_ok(|(user, password)| (user.into(), CompactString::from(password).into()))
+                .boxed()
+        }))
+    }
+
+    /// A provider of the same credentials every time.
+    pub(crate) fn fixed(user: CompactString, password: SecretString) -> Self {
+        Self(Arc::new(move || {
+            futures::future::ready(Ok((user.clone(), password.clone()))).boxed()
+        }))
+    }
+
+    async fn get(&self) -> Result<(CompactString, SecretString)> {
+        (self.0)().await
+    }
+}
+
+impl std::fmt::Debug for CredentialProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("CredentialProvider").finish_non_exhaustive()
+    }
+}
+
+/// Authentication of a `Client`, shared by all its clones so that it can be
+/// updated through a shared reference.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SharedAuth(Arc<RwLock<Option<Auth>>>);
+
+impl SharedAuth {
+    pub(crate) fn new(auth: Option<Auth>) -> Self {
+        Self(Arc::new(RwLock::new(auth)))
+    }
+
+    pub(crate) fn get(&self) -> Option<Auth> {
+        self.0.read().ok()?.clone()
+    }
+
+    fn set(&self, auth: Option<Auth>) {
+        if let Ok(mut current) = self.0.write() {
+            *current = auth;
         }
     }
 }
 
 /// Used for Bearer HTTP Authentication.
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[derive(Clone, Default, Deserialize, Serialize)]
 pub struct BearerAuth {
     token: ArcStr,
     expires_in: Option<u32>,
     issued_at: Option<ArcStr>,
     refresh_token: Option<ArcStr>,
+    scope: Option<ArcStr>,
+    #[serde(skip)]
+    received_at: Option<SystemTime>,
+}
+
+impl std::fmt::Debug for BearerAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BearerAuth")
+            .field("expires_in", &self.expires_in)
+            .field("issued_at", &self.issued_at)
+            .field("scope", &self.scope)
+            .field("received_at", &self.received_at)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Body of a token endpoint response.
+///
+/// OAuth2 endpoints return `access_token`, while the token flow returns `token`
+/// and possibly `access_token` too for compatibility.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    token: Option<ArcStr>,
+    access_token: Option<ArcStr>,
+    expires_in: Option<u32>,
+    issued_at: Option<ArcStr>,
+    refresh_token: Option<ArcStr>,
+    scope: Option<ArcStr>,
+}
+
+/// Claims of a JWT bearer token, as issued by the Docker token flow.
+#[derive(Debug, Deserialize)]
+struct TokenClaims {
+    access: Option<Vec<ResourceAccess>>,
+}
+
+/// Access to a resource granted by a token.
+#[derive(Debug, Deserialize)]
+struct ResourceAccess {
+    #[serde(rename = "type")]
+    resource_type: String,
+    name: String,
+    #[serde(default)]
+    actions: Vec<String>,
 }
 
 impl BearerAuth {
+    /// Bearer authentication with a token obtained elsewhere, of unknown lifetime.
+    pub(crate) fn from_token(token: ArcStr) -> Self {
+        Self {
+            token,
+            ..Default::default()
+        }
+    }
+
     async fn try_from_header_content(
         client: &Client,
         scopes: &[&str],
-        credentials: Option<(CompactString, CompactString)>,
-        bearer_header_content: WwwAuthenticateHeaderContentBearer,
+        credentials: Option<(CompactString, SecretString)>,
+        bearer_header_content: &WwwAuthenticateHeaderContentBearer,
     ) -> Result<Self> {
-        let auth_ep = bearer_header_content.auth_ep(scopes);
-        trace!("authenticate: token endpoint: {}", auth_ep);
-
-        let url = reqwest::Url::parse(&auth_ep)?;
+        let url = bearer_header_content.auth_ep(&client.base_url, scopes)?;
+        trace!("authenticate: token endpoint: {}", url);
 
         let auth_req = {
             Client {
-                auth: credentials.map(|(user, password)| {
+                auth: SharedAuth::new(credentials.map(|(user, password)| {
                     Auth::Basic(BasicAuth {
                         user,
                         password: Some(password),
                     })
-                }),
+                })),
                 ..client.clone()
             }
         }
-        .build_reqwest(Method::GET, url);
+        .build_reqwest(Method::GET, url)
+        .timeout(client.auth_timeout);
+
+        let r = auth_req.send().await.map_err(token_request_error)?;
+        Self::try_from_response(r, client.clock.now()).await
+    }
+
+    /// Obtain a token by POSTing the given OAuth2 grant to the realm.
+    ///
+    /// See <https://docs.docker.com/registry/spec/auth/oauth/>.
+    async fn try_oauth2(
+        client: &Client,
+        scopes: &[&str],
+        grant: &[(&str, &str)],
+        bearer_header_content: &WwwAuthenticateHeaderContentBearer,
+    ) -> Result<Self> {
+        let url = bearer_header_content.realm_url(&client.base_url)?;
+        trace!("authenticate: OAuth2 token endpoint: {}", url);
+
+        let scope = scopes.join(" ");
+        let mut form = grant.to_vec();
+        form.push(("client_id", OAUTH2_CLIENT_ID));
+        if let Some(service) = &bearer_header_content.service {
+            form.push(("service", service));
+        }
+        if !scope.is_empty() {
+            form.push(("scope", &scope));
+        }
+
+        let auth_req = Client {
+            auth: SharedAuth::default(),
+            ..client.clone()
+        }
+        .build_reqwest(Method::POST, url)
+        .timeout(client.auth_timeout)
+        .form(&form);
+
+        let r = auth_req.send().await.map_err(token_request_error)?;
+        Self::try_from_response(r, client.clock.now()).await
+    }
+
+    /// Obtain a token with the OAuth2 password grant.
+    async fn try_password(
+        client: &Client,
+        scopes: &[&str],
+        (user, password): (CompactString, SecretString),
+        bearer_header_content: &WwwAuthenticateHeaderContentBearer,
+    ) -> Result<Self> {
+        let grant = [
+            ("grant_type", "password"),
+            ("username", user.as_str()),
+            ("password", password.expose()),
+            ("access_type", "offline"),
+        ];
+        Self::try_oauth2(client, scopes, &grant, bearer_header_content).await
+    }
+
+    /// Obtain a new token with the OAuth2 refresh token grant.
+    async fn try_refresh(
+        client: &Client,
+        scopes: &[&str],
+        refresh_token: &str,
+        bearer_header_content: &WwwAuthenticateHeaderContentBearer,
+    ) -> Result<Self> {
+        let grant = [
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ];
+        let mut bearer_auth =
+            Self::try_oauth2(client, scopes, &grant, bearer_header_content).await?;
+        if bearer_auth.refresh_token.is_none() {
+            bearer_auth.refresh_token = Some(refresh_token.into());
+        }
+        Ok(bearer_auth)
+    }
 
-        let r = auth_req.send().await?;
+    /// Parse and validate the response of a token endpoint.
+    ///
+    /// Responses larger than `MAX_TOKEN_RESPONSE_BYTES` are rejected without being read in full.
+    async fn try_from_response(mut r: reqwest::Response, received_at: SystemTime) -> Result<Self> {
         let status = r.status();
         trace!("authenticate: got status {}", status);
         if status != StatusCode::OK {
             return Err(Error::UnexpectedHttpStatus(status));
         }
 
-        let bearer_auth = r.json::<BearerAuth>().await?;
+        let too_large = || Error::AuthEndpointError {
+            message: format!(
+                "token response exceeds the maximum size of {} bytes",
+                MAX_TOKEN_RESPONSE_BYTES
+            ),
+            source: None,
+        };
+        if r.content_length()
+            .is_some_and(|len| len > MAX_TOKEN_RESPONSE_BYTES as u64)
+        {
+            return Err(too_large());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = r.chunk().await.map_err(token_request_error)? {
+            if body.len() + chunk.len() > MAX_TOKEN_RESPONSE_BYTES {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        let token_response = serde_json::from_slice::<TokenResponse>(&body)?;
+        let bearer_auth = BearerAuth {
+            token: token_response
+                .token
+                .or(token_response.access_token)
+                .unwrap_or_default(),
+            expires_in: token_response.expires_in,
+            issued_at: token_response.issued_at,
+            refresh_token: token_response.refresh_token,
+            scope: token_response.scope,
+            received_at: Some(received_at),
+        };
 
         match bearer_auth.token.as_str() {
             "unauthenticated" | "" => return Err(Error::InvalidAuthToken(bearer_auth.token)),
             _ => {}
         };
 
-        // mask the token before logging it
-        let mut chars = bearer_auth.token.chars().enumerate();
-        let (_, first_char) = chars.next().unwrap();
-        let (mut chars_count, last_char) = chars.last().unwrap();
-        chars_count += 1;
+        trace!("authenticate: got token: {}", bearer_auth.masked_token());
+
+        Ok(bearer_auth)
+    }
 
-        trace!(
-            "authenticate: got token: {}****{{{}}}****{}",
+    /// The token masked for logs, as its first and last characters around its length.
+    fn masked_token(&self) -> ArcStr {
+        let mut chars = self.token.chars();
+        let first_char = chars.next().unwrap_or_default();
+        let last_char = chars.next_back().unwrap_or(first_char);
+        arcstr::format!(
+            "{}****{{{}}}****{}",
             first_char,
-            chars_count,
+            self.token.chars().count(),
             last_char
-        );
+        )
+    }
 
-        Ok(bearer_auth)
+    /// Set the authorization header of an already built request to this token.
+    pub(crate) fn authorize_request(&self, request: &mut reqwest::Request) -> Result<()> {
+        let value = HeaderValue::from_str(&format!("Bearer {}", self.token))
+            .map_err(|_| Error::InvalidAuthToken(self.masked_token()))?;
+        request
+            .headers_mut()
+            .insert(reqwest::header::AUTHORIZATION, value);
+        Ok(())
+    }
+
+    /// Scopes granted to this token, if known.
+    ///
+    /// They are taken from the `scope` field of the token response, or else from the
+    /// `access` claim of the token if it is a JWT, as issued by the Docker token flow.
+    /// Resources granted without any action are left out.
+    pub fn granted_scopes(&self) -> Option<Vec<String>> {
+        if let Some(scope) = &self.scope {
+            return Some(scope.split_whitespace().map(str::to_string).collect());
+        }
+
+        let mut parts = self.token.split('.');
+        let claims = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(_), Some(claims), Some(_), None) => claims,
+            _ => return None,
+        };
+        let claims = URL_SAFE_NO_PAD.decode(claims.trim_end_matches('=')).ok()?;
+        let claims: TokenClaims = serde_json::from_slice(&claims).ok()?;
+        let scopes = claims
+            .access?
+            .into_iter()
+            .filter(|access| !access.actions.is_empty())
+            .map(|access| {
+                access
+                    .actions
+                    .iter()
+                    .fold(
+                        Scope::new(&access.resource_type, &access.name),
+                        |scope, a| scope.action(a),
+                    )
+                    .to_string()
+            })
+            .collect();
+        Some(scopes)
+    }
+
+    /// Whether this token has expired.
+    ///
+    /// The lifetime of `expires_in` seconds starts at `issued_at`, or at the time the token
+    /// was received if `issued_at` is missing or lies in the future.
+    /// Tokens of unknown age are never considered expired.
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(SystemTime::now())
+    }
+
+    /// Whether this token has expired as of `now`, as in `is_expired`.
+    ///
+    /// Pass the time of a clock set with `Config::clock` to check expiry against it.
+    pub fn is_expired_at(&self, now: SystemTime) -> bool {
+        self.expires_within(now, Duration::ZERO)
+    }
+
+    /// Point in time after which this token should not be used anymore, if known.
+    fn expires_at(&self) -> Option<SystemTime> {
+        let issued_at = self
+            .issued_at
+            .as_ref()
+            .and_then(|issued_at| humantime::parse_rfc3339_weak(issued_at).ok());
+        let start = match (issued_at, self.received_at) {
+            (Some(issued_at), Some(received_at)) => issued_at.min(received_at),
+            (issued_at, received_at) => issued_at.or(received_at)?,
+        };
+        let lifetime = self.expires_in.unwrap_or(DEFAULT_TOKEN_LIFETIME);
+        Some(start + Duration::from_secs(lifetime.into()))
+    }
+
+    fn expires_within(&self, now: SystemTime, margin: Duration) -> bool {
+        self.expires_at()
+            .is_some_and(|expires_at| now + margin >= expires_at)
+    }
+}
+
+/// Report a token request timing out as `Error::AuthEndpointError`.
+fn token_request_error(e: reqwest::Error) -> Error {
+    if e.is_timeout() {
+        Error::AuthEndpointError {
+            message: "token request timed out".to_string(),
+            source: Some(e),
+        }
+    } else {
+        e.into()
     }
 }
 
+/// Cache of bearer tokens, keyed by the set of scopes they were granted for.
+///
+/// The cache is shared by all clones of a `Client`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TokenCache(Arc<RwLock<HashMap<Vec<CompactString>, BearerAuth>>>);
+
+impl TokenCache {
+    fn key(scopes: &[&str]) -> Vec<CompactString> {
+        let mut key = scopes
+            .iter()
+            .map(|s| s.to_compact_string())
+            .collect::<Vec<_>>();
+        key.sort_unstable();
+        key.dedup();
+        key
+    }
+
+    fn is_fresh(bearer_auth: &BearerAuth, now: SystemTime) -> bool {
+        !bearer_auth.expires_within(now, TOKEN_EXPIRY_MARGIN)
+    }
+
+    /// Get a token granted for exactly this set of scopes, fresh as of `now`.
+    fn get(&self, scopes: &[&str], now: SystemTime) -> Option<BearerAuth> {
+        let cache = self.0.read().ok()?;
+        cache
+            .get(&Self::key(scopes))
+            .filter(|bearer_auth| Self::is_fresh(bearer_auth, now))
+            .cloned()
+    }
+
+    /// Get the refresh token of a stale token granted for exactly this set of scopes.
+    fn refresh_token(&self, scopes: &[&str]) -> Option<ArcStr> {
+        let cache = self.0.read().ok()?;
+        cache.get(&Self::key(scopes))?.refresh_token.clone()
+    }
+
+    fn insert(&self, scopes: &[&str], bearer_auth: BearerAuth, now: SystemTime) {
+        if let Ok(mut cache) = self.0.write() {
+            cache.retain(|_, b| b.refresh_token.is_some() || Self::is_fresh(b, now));
+            cache.insert(Self::key(scopes), bearer_auth);
+        }
+    }
+
+    /// Get the newest token granted `action` on the given repository, fresh as of `now`.
+    pub(crate) fn get_for_repository(
+        &self,
+        repository: &str,
+        action: &str,
+        now: SystemTime,
+    ) -> Option<BearerAuth> {
+        let cache = self.0.read().ok()?;
+        cache
+            .iter()
+            .filter(|(_, bearer_auth)| Self::is_fresh(bearer_auth, now))
+            .filter(|(scopes, _)| {
+                scopes
+                    .iter()
+                    .any(|scope| scope_grants(scope, repository, action))
+            })
+            .max_by_key(|(_, bearer_auth)| bearer_auth.received_at)
+            .map(|(_, bearer_auth)| bearer_auth.clone())
+    }
+}
+
+/// Scope of a bearer token, as `<resource type>:<name>:<actions>`.
+///
+/// ```
+/// # use dkregistry::v2::Scope;
+/// let scope = Scope::repository("library/busybox").pull().push();
+/// assert_eq!(scope.to_string(), "repository:library/busybox:pull,push");
+/// ```
+///
+/// See <https://docs.docker.com/registry/spec/auth/scope/>.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scope {
+    resource_type: String,
+    name: String,
+    actions: Vec<String>,
+}
+
+impl Scope {
+    /// Create a scope on any resource, without actions.
+    pub fn new(resource_type: &str, name: &str) -> Self {
+        Self {
+            resource_type: resource_type.to_string(),
+            name: name.to_string(),
+            actions: Vec::new(),
+        }
+    }
+
+    /// Create a scope on a repository, without actions.
+    pub fn repository(name: &str) -> Self {
+        Self::new("repository", name)
+    }
+
+    /// Create a scope on the catalog of the registry, with the `*` action it requires.
+    pub fn catalog() -> Self {
+        Self::new("registry", "catalog").action("*")
+    }
+
+    /// Add an action to the scope.
+    pub fn action(mut self, action: &str) -> Self {
+        if !self.actions.iter().any(|a| a == action) {
+            self.actions.push(action.to_string());
+        }
+        self
+    }
+
+    /// Add the `pull` action.
+    pub fn pull(self) -> Self {
+        self.action("pull")
+    }
+
+    /// Add the `push` action.
+    pub fn push(self) -> Self {
+        self.action("push")
+    }
+
+    /// Add the `delete` action.
+    pub fn delete(self) -> Self {
+        self.action("delete")
+    }
+
+    /// Infer the scope needed for a request to the registry API, if it addresses a repository.
+    ///
+    /// GET and HEAD requests need `pull`, other requests need `pull,push`.
+    pub(crate) fn for_request(method: &Method, url: &Url) -> Option<Self> {
+        let scope = Self::repository(repository_from_path(url.path())?).pull();
+        match *method {
+            Method::GET | Method::HEAD => Some(scope),
+            _ => Some(scope.push()),
+        }
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.resource_type,
+            self.name,
+            self.actions.join(",")
+        )
+    }
+}
+
+/// Whether a `repository:<name>:<actions>` scope grants `action` on `repository`.
+fn scope_grants(scope: &str, repository: &str, action: &str) -> bool {
+    let mut parts = scope.splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("repository"), Some(name), Some(actions)) => {
+            name == repository && actions.split(',').any(|a| a == action || a == "*")
+        }
+        _ => false,
+    }
+}
+
+/// Action a request with `method` needs on the repository it addresses.
+pub(crate) fn required_action(method: &reqwest::Method) -> &'static str {
+    match *method {
+        reqwest::Method::GET | reqwest::Method::HEAD => "pull",
+        reqwest::Method::DELETE => "delete",
+        _ => "push",
+    }
+}
+
+/// Extract the repository name from the path of a registry API endpoint, if any.
+///
+/// The path may start with the path prefix of the registry.
+pub(crate) fn repository_from_path(path: &str) -> Option<&str> {
+    let (_, rest) = path.split_once("/v2/")?;
+    ["/manifests/", "/blobs/", "/tags/", "/referrers/"]
+        .iter()
+        .filter_map(|marker| rest.rfind(marker))
+        .max()
+        .map(|i| &rest[..i])
+}
+
 /// Used for Basic HTTP Authentication.
 #[derive(Debug, Clone)]
 pub struct BasicAuth {
     user: CompactString,
-    password: Option<CompactString>,
+    password: Option<SecretString>,
 }
 
 /// Structured representation for the content of the authentication response header.
@@ -106,22 +654,110 @@ pub(crate) enum WwwAuthenticateHeaderContent {
     Basic(WwwAuthenticateHeaderContentBasic),
 }
 
-const REGEX: &str = r#"(?x)\s*
-((?P<method>[A-Za-z]+)\s)?
-\s*
-(
-        (?P<key>[A-Za-z]+)
-    \s*
-        =
-    \s*
-        "(?P<value>[^"]+)"
-    \s*
-)
-"#;
+/// A challenge of a `WWW-Authenticate` header: its lowercase scheme and its parameters.
+type Challenge<'a> = (String, Vec<(Cow<'a, str>, Cow<'a, str>)>);
+
+/// Whether `c` may appear in a token, as per RFC 7230.
+fn is_tchar(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+/// Split the challenges of a `WWW-Authenticate` header, as per RFC 7235.
+///
+/// Quoted parameter values may contain commas and escaped quotes. Unquoted values run
+/// to the next comma or whitespace. Parameter names are lowercased. Tokens which are
+/// neither schemes nor parameters, such as the `token68` credentials of some schemes,
+/// are skipped.
+fn parse_challenges(header: &str) -> std::result::Result<Vec<Challenge<'_>>, WwwHeaderParseError> {
+    let mut challenges: Vec<Challenge> = Vec::new();
+    let mut rest = header;
+    let mut after_scheme = false;
+    loop {
+        let trimmed = rest.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+        let separated = rest[..rest.len() - trimmed.len()].contains(',');
+        rest = trimmed;
+        if rest.is_empty() {
+            break;
+        }
+
+        let end = rest.find(|c| !is_tchar(c)).unwrap_or(rest.len());
+        let (token, after) = rest.split_at(end);
+        if token.is_empty() {
+            // Unexpected character: skip to the next element of the list.
+            rest = rest.find(',').map_or("", |i| &rest[i..]);
+            continue;
+        }
+
+        match after.trim_start().strip_prefix('=') {
+            Some(value) => {
+                let value = value.trim_start();
+                let (value, remaining) = match value.strip_prefix('"') {
+                    Some(quoted) => parse_quoted(quoted)?,
+                    None => {
+                        // Tokens can't have colons, but scopes are sometimes left unquoted.
+                        let end = value
+                            .find(|c: char| c == ',' || c.is_ascii_whitespace())
+                            .unwrap_or(value.len());
+                        (Cow::Borrowed(&value[..end]), &value[end..])
+                    }
+                };
+                let (_, params) = challenges
+                    .last_mut()
+                    .ok_or(WwwHeaderParseError::FieldMethodMissing)?;
+                params.push((token.cow_to_lowercase(), value));
+                rest = remaining;
+                after_scheme = false;
+            }
+            None if after_scheme && !separated => {
+                trace!("skipping token68 of authentication challenge");
+                rest = after;
+                after_scheme = false;
+            }
+            None => {
+                challenges.push((token.to_lowercase(), Vec::new()));
+                rest = after;
+                after_scheme = true;
+            }
+        }
+    }
+    Ok(challenges)
+}
+
+/// Parse a quoted string, given what follows its opening quote.
+///
+/// Returns the unescaped content and what follows the closing quote.
+fn parse_quoted(s: &str) -> std::result::Result<(Cow<'_, str>, &str), WwwHeaderParseError> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => {
+                let value = &s[..i];
+                let value = if value.contains('\\') {
+                    let mut unescaped = String::with_capacity(value.len());
+                    let mut chars = value.chars();
+                    while let Some(c) = chars.next() {
+                        unescaped.push(match c {
+                            '\\' => chars.next().unwrap_or(c),
+                            c => c,
+                        });
+                    }
+                    Cow::Owned(unescaped)
+                } else {
+                    Cow::Borrowed(value)
+                };
+                return Ok((value, &s[i + 1..]));
+            }
+            _ => {}
+        }
+    }
+    Err(WwwHeaderParseError::InvalidValue)
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum WwwHeaderParseError {
-    #[error("header value must conform to {}", REGEX)]
+    #[error("header value is not a valid list of challenges")]
     InvalidValue,
     #[error("'method' field missing")]
     FieldMethodMissing,
@@ -129,35 +765,67 @@ pub enum WwwHeaderParseError {
 
 impl WwwAuthenticateHeaderContent {
     /// Create a `WwwAuthenticateHeaderContent` by parsing a `HeaderValue` instance.
+    ///
+    /// If the header carries multiple challenges, Bearer is preferred over Basic.
     pub(crate) fn from_www_authentication_header(header_value: HeaderValue) -> Result<Self> {
+        Self::preferred_from_www_authentication_header(header_value, false)
+    }
+
+    /// Create a `WwwAuthenticateHeaderContent` by parsing a `HeaderValue` instance.
+    ///
+    /// If the header carries multiple challenges, Basic is preferred over Bearer if
+    /// `prefer_basic` is set, and the other way around otherwise.
+    pub(crate) fn preferred_from_www_authentication_header(
+        header_value: HeaderValue,
+        prefer_basic: bool,
+    ) -> Result<Self> {
+        let mut challenges = Self::all_from_www_authentication_header(header_value)?;
+        let preferred = challenges
+            .iter()
+            .position(|c| match c {
+                WwwAuthenticateHeaderContent::Bearer(_) => !prefer_basic,
+                WwwAuthenticateHeaderContent::Basic(_) => prefer_basic,
+            })
+            .unwrap_or(0);
+        Ok(challenges.swap_remove(preferred))
+    }
+
+    /// Parse all supported challenges of a `HeaderValue` instance, in order of appearance.
+    ///
+    /// Challenges with unsupported schemes are skipped.
+    pub(crate) fn all_from_www_authentication_header(
+        header_value: HeaderValue,
+    ) -> Result<Vec<Self>> {
         let header = CompactString::from_utf8(header_value.as_bytes())?;
 
-        // This regex will result in multiple captures which will contain one key-value pair each.
-        // The first capture will be the only one with the "method" group set.
-        let re = regex::Regex::new(REGEX).expect("this static regex is valid");
-        let captures = re.captures_iter(&header).collect::<Vec<_>>();
+        let challenges = parse_challenges(&header)?;
+        if challenges.is_empty() {
+            return Err(WwwHeaderParseError::InvalidValue.into());
+        }
 
-        let method = captures
-            .get(0)
-            .ok_or(WwwHeaderParseError::InvalidValue)?
-            .name("method")
-            .ok_or(WwwHeaderParseError::FieldMethodMissing)?
-            .as_str()
-            .to_lowercase();
+        let mut first_error = None;
+        let mut contents = Vec::with_capacity(challenges.len());
+        for (method, params) in challenges {
+            match Self::from_challenge(&method, params) {
+                Ok(content) => contents.push(content),
+                Err(e) => {
+                    warn!("skipping unsupported authentication challenge '{}'", method);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
 
+        match (contents.is_empty(), first_error) {
+            (true, Some(e)) => Err(e),
+            _ => Ok(contents),
+        }
+    }
+
+    fn from_challenge(method: &str, params: Vec<(Cow<str>, Cow<str>)>) -> Result<Self> {
         let serialized_content = {
-            let captures = captures.iter().filter_map(|capture| {
-                match (
-                    capture.name("key").map(|n| n.as_str().cow_to_lowercase()),
-                    capture.name("value").map(|n| n.as_str()),
-                ) {
-                    (Some(key), Some(value)) => Some((key, value)),
-                    _ => None,
-                }
-            });
             let mut output = Vec::with_capacity(128);
             let mut json = serde_json::ser::Serializer::new(&mut output);
-            json.collect_map(captures)?;
+            json.collect_map(params)?;
             // SAFETY:  serde_json only emits value UTF-8
             let output = unsafe { String::from_utf8_unchecked(output) };
 
@@ -190,10 +858,29 @@ pub(crate) struct WwwAuthenticateHeaderContentBearer {
     realm: String,
     service: Option<String>,
     scope: Option<String>,
+    error: Option<String>,
 }
 
 impl WwwAuthenticateHeaderContentBearer {
-    fn auth_ep(&self, scopes: &[&str]) -> String {
+    /// URL of the token endpoint given by the realm.
+    ///
+    /// A realm given as an absolute path is resolved against the registry URL.
+    fn realm_url(&self, base_url: &str) -> Result<Url> {
+        let invalid = || Error::InvalidAuthRealm(self.realm.clone());
+        let url = match Url::parse(&self.realm) {
+            Ok(url) => url,
+            Err(url::ParseError::RelativeUrlWithoutBase) if self.realm.starts_with('/') => {
+                Url::parse(base_url)?.join(&self.realm)?
+            }
+            Err(_) => return Err(invalid()),
+        };
+        match url.scheme() {
+            "http" | "https" if url.has_host() => Ok(url),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn auth_ep(&self, base_url: &str, scopes: &[&str]) -> Result<Url> {
         let service = self
             .service
             .as_ref()
@@ -216,7 +903,11 @@ impl WwwAuthenticateHeaderContentBearer {
             "&"
         };
 
-        format!("{}{}{}{}", self.realm, service, scope_prefix, scope)
+        let realm = self.realm_url(base_url)?;
+        Ok(Url::parse(&format!(
+            "{}{}{}{}",
+            realm, service, scope_prefix, scope
+        ))?)
     }
 }
 
@@ -234,45 +925,197 @@ impl Client {
             reqwest::Url::parse(&ep)?
         };
 
-        let r = self.build_reqwest(Method::GET, url).send().await?;
+        // The challenge is obtained without any current authentication.
+        let unauthenticated = Client {
+            auth: SharedAuth::default(),
+            ..self.clone()
+        };
+        let r = unauthenticated
+            .build_reqwest(Method::GET, url)
+            .send()
+            .await?;
 
         trace!("GET '{}' status: {:?}", r.url(), r.status());
-        r.headers()
-            .get(reqwest::header::WWW_AUTHENTICATE)
-            .ok_or(Error::MissingAuthHeader("WWW-Authenticate"))
-            .map(ToOwned::to_owned)
+        self.check_v2_header(&r)?;
+        // Registries offering several schemes may send one header per challenge.
+        let challenges = r
+            .headers()
+            .get_all(reqwest::header::WWW_AUTHENTICATE)
+            .iter()
+            .map(HeaderValue::to_str)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if challenges.is_empty() {
+            return Err(Error::MissingAuthHeader("WWW-Authenticate"));
+        }
+        HeaderValue::from_str(&challenges.join(", "))
+            .map_err(|_| WwwHeaderParseError::InvalidValue.into())
+    }
+
+    /// Obtain a bearer token for the given scopes and store it in the token cache.
+    ///
+    /// The refresh token of a previously cached token is preferred over sending credentials again.
+    /// With `oauth2` enabled, credentials are sent with the OAuth2 password grant instead of the
+    /// token GET request. With `anonymous_fallback` enabled, an anonymous token is requested
+    /// if the credentials are rejected.
+    async fn fetch_bearer_token(
+        &self,
+        scopes: &[&str],
+        bearer_header_content: &WwwAuthenticateHeaderContentBearer,
+    ) -> Result<BearerAuth> {
+        let refreshed = match self.token_cache.refresh_token(scopes) {
+            Some(refresh_token) => {
+                match BearerAuth::try_refresh(self, scopes, &refresh_token, bearer_header_content)
+                    .await
+                {
+                    Ok(bearer_auth) => Some(bearer_auth),
+                    Err(e) => {
+                        debug!(
+                            "authenticate: token refresh failed, using credentials: {}",
+                            e
+                        );
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let has_credentials = self.credentials.is_some();
+        let bearer_auth = match refreshed {
+            Some(bearer_auth) => Ok(bearer_auth),
+            None => match self.credentials().await? {
+                Some(credentials) if self.oauth2 => {
+                    BearerAuth::try_password(self, scopes, credentials, bearer_header_content).await
+                }
+                credentials => {
+                    BearerAuth::try_from_header_content(
+                        self,
+                        scopes,
+                        credentials,
+                        bearer_header_content,
+                    )
+                    .await
+                }
+            },
+        };
+        let bearer_auth = match bearer_auth {
+            Err(Error::UnexpectedHttpStatus(status))
+                if self.anonymous_fallback
+                    && has_credentials
+                    && (status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN) =>
+            {
+                debug!(
+                    "authenticate: credentials rejected with {}, requesting an anonymous token",
+                    status
+                );
+                BearerAuth::try_from_header_content(self, scopes, None, bearer_header_content)
+                    .await?
+            }
+            bearer_auth => bearer_auth?,
+        };
+        self.token_cache
+            .insert(scopes, bearer_auth.clone(), self.clock.now());
+
+        Ok(bearer_auth)
     }
 
-    /// Perform registry authentication and return the authenticated client.
+    /// Perform registry authentication, returning the scopes granted.
+    ///
+    /// If Bearer authentication is used the client will be authorized for the requested scopes.
+    /// Bearer tokens are cached per set of scopes and reused until shortly before they expire.
+    ///
+    /// The registry may grant fewer scopes than requested, e.g. only `pull` when `pull,push`
+    /// was asked for: the granted scopes are those of `BearerAuth::granted_scopes`,
+    /// or the requested ones if the registry does not tell.
     ///
-    /// If Bearer authentication is used the returned client will be authorized for the requested scopes.
-    pub async fn authenticate(&mut self, scopes: &[&str]) -> Result<()> {
-        self.auth = None;
-        self.auth = match self.get_www_authentication_header().await {
+    /// The authentication is shared by all clones of the client, so a client behind an `Arc`
+    /// can be authenticated while other tasks use it.
+    ///
+    /// With `Config::no_auth`, nothing is sent and the requested scopes are returned.
+    pub async fn authenticate<I, S>(&self, scopes: I) -> Result<Vec<String>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let scopes = scopes
+            .into_iter()
+            .map(|scope| scope.as_ref().to_string())
+            .collect::<Vec<_>>();
+        let scopes = scopes.iter().map(String::as_str).collect::<Vec<_>>();
+        spans::instrument!(
+            self.authenticate_scopes(&scopes),
+            "authenticate",
+            ?scopes,
+            cached = tracing::field::Empty,
+        )
+        .await
+    }
+
+    async fn authenticate_scopes(&self, scopes: &[&str]) -> Result<Vec<String>> {
+        if self.no_auth {
+            trace!("authenticate: left to the transport");
+            return Ok(scopes.iter().map(|s| s.to_string()).collect());
+        }
+        let cached = self.token_cache.get(scopes, self.clock.now());
+        spans::record!("cached", cached.is_some());
+        if let Some(bearer_auth) = cached {
+            trace!("authenticate: reusing cached token");
+            self.auth.set(Some(Auth::Bearer(bearer_auth)));
+            return Ok(self.granted_scopes(scopes));
+        }
+
+        let auth = self.fetch_auth(scopes).await;
+        if auth.is_err() {
+            self.auth.set(None);
+        }
+        self.auth.set(auth?);
+
+        trace!("authenticate: login succeeded");
+
+        Ok(self.granted_scopes(scopes))
+    }
+
+    /// Scopes granted by the current authentication, or `requested` if unknown.
+    fn granted_scopes(&self, requested: &[&str]) -> Vec<String> {
+        let granted = match self.auth.get() {
+            Some(Auth::Bearer(bearer_auth)) => bearer_auth.granted_scopes(),
+            _ => None,
+        };
+        granted.unwrap_or_else(|| requested.iter().map(|s| s.to_string()).collect())
+    }
+
+    /// Get the credentials from the credential provider, if any.
+    async fn credentials(&self) -> Result<Option<(CompactString, SecretString)>> {
+        match &self.credentials {
+            Some(provider) => provider.get().await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Obtain authentication for the given scopes, as challenged by the registry.
+    async fn fetch_auth(&self, scopes: &[&str]) -> Result<Option<Auth>> {
+        let auth = match self.get_www_authentication_header().await {
             Ok(authentication_header) => {
-                match WwwAuthenticateHeaderContent::from_www_authentication_header(
+                match WwwAuthenticateHeaderContent::preferred_from_www_authentication_header(
                     authentication_header,
+                    self.prefer_basic && self.credentials.is_some(),
                 )? {
                     WwwAuthenticateHeaderContent::Basic(_) => {
                         let basic_auth = self
-                            .credentials
-                            .as_ref()
+                            .credentials()
+                            .await?
                             .map(|(user, password)| BasicAuth {
-                                user: user.clone(),
-                                password: Some(password.clone()),
+                                user,
+                                password: Some(password),
                             })
                             .ok_or(Error::NoCredentials)?;
 
                         Some(Auth::Basic(basic_auth))
                     }
                     WwwAuthenticateHeaderContent::Bearer(bearer_header_content) => {
-                        let bearer_auth = BearerAuth::try_from_header_content(
-                            self,
-                            scopes,
-                            self.credentials.clone(),
-                            bearer_header_content,
-                        )
-                        .await?;
+                        let bearer_auth = self
+                            .fetch_bearer_token(scopes, &bearer_header_content)
+                            .await?;
 
                         Some(Auth::Bearer(bearer_auth))
                     }
@@ -281,10 +1124,103 @@ impl Client {
             Err(Error::MissingAuthHeader(_)) => None,
             Err(e) => return Err(e),
         };
+        Ok(auth)
+    }
 
-        trace!("authenticate: login succeeded");
+    /// Perform registry authentication for typed scopes.
+    ///
+    /// This is equivalent to `authenticate` with the scopes formatted as strings.
+    pub async fn authenticate_for(&self, scopes: &[Scope]) -> Result<Vec<String>> {
+        self.authenticate(scopes.iter().map(Scope::to_string)).await
+    }
 
-        Ok(())
+    /// Kind of authentication the client currently uses.
+    ///
+    /// After `authenticate`, this tells whether the registry granted anonymous access
+    /// or required Basic credentials or a bearer token.
+    pub fn auth_kind(&self) -> AuthKind {
+        match self.auth.get() {
+            None => AuthKind::Anonymous,
+            Some(Auth::Basic(_)) => AuthKind::Basic,
+            Some(Auth::Bearer(_)) => AuthKind::Bearer,
+        }
+    }
+
+    /// Whether the client currently sends credentials or a token with its requests.
+    pub fn is_authenticated(&self) -> bool {
+        self.auth_kind() != AuthKind::Anonymous
+    }
+
+    /// Obtain a new bearer token after a request has been rejected with `401 Unauthorized`.
+    ///
+    /// The token is requested for the scope in the challenge of `response`, falling back to
+    /// the repository addressed by `url`, and is stored in the token cache.
+    /// Returns `None` if the response does not carry a bearer challenge.
+    pub(crate) async fn reauthenticate(
+        &self,
+        response: &reqwest::Response,
+        method: &Method,
+        url: &Url,
+    ) -> Result<Option<BearerAuth>> {
+        let header = match response.headers().get(reqwest::header::WWW_AUTHENTICATE) {
+            Some(header) => header.to_owned(),
+            None => return Ok(None),
+        };
+        let bearer_header_content =
+            match WwwAuthenticateHeaderContent::from_www_authentication_header(header)? {
+                WwwAuthenticateHeaderContent::Bearer(bearer) => bearer,
+                WwwAuthenticateHeaderContent::Basic(_) => return Ok(None),
+            };
+
+        let scope = match &bearer_header_content.scope {
+            Some(scope) => Some(scope.clone()),
+            None => Scope::for_request(method, url).map(|scope| scope.to_string()),
+        };
+        let scopes = scope.as_deref().into_iter().collect::<Vec<_>>();
+        trace!("reauthenticate: requesting token for scopes {:?}", scopes);
+
+        let bearer_auth = self
+            .fetch_bearer_token(&scopes, &bearer_header_content)
+            .await?;
+
+        Ok(Some(bearer_auth))
+    }
+
+    /// Turn a rejected response carrying a bearer challenge with an `error` into `Error::AuthChallenge`.
+    pub(crate) fn challenge_error(response: &reqwest::Response) -> Option<Error> {
+        match response.status() {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {}
+            _ => return None,
+        };
+        let header = response
+            .headers()
+            .get(reqwest::header::WWW_AUTHENTICATE)?
+            .to_owned();
+        match WwwAuthenticateHeaderContent::from_www_authentication_header(header).ok()? {
+            WwwAuthenticateHeaderContent::Bearer(WwwAuthenticateHeaderContentBearer {
+                error: Some(error),
+                scope,
+                ..
+            }) => Some(Error::AuthChallenge { error, scope }),
+            _ => None,
+        }
+    }
+
+    /// Authenticate for the given scopes and check that the registry accepts the credentials.
+    ///
+    /// This combines `authenticate` and `is_auth`. If either fails, or the registry still
+    /// rejects the client, the cause is reported in `Error::LoginFailed`.
+    pub async fn login(&self, scopes: &[&str]) -> Result<()> {
+        let login = async {
+            self.authenticate(scopes).await?;
+            match self.is_auth().await? {
+                true => Ok(()),
+                false => Err(Error::Unauthorized(StatusCode::UNAUTHORIZED)),
+            }
+        };
+        login
+            .await
+            .map_err(|e| Error::LoginFailed { cause: Box::new(e) })
     }
 
     /// Check whether the client can successfully make requests to the registry.
@@ -315,6 +1251,67 @@ mod tests {
     use super::*;
     use test_case::test_case;
 
+    #[test]
+    fn invalid_token_is_masked_in_errors() {
+        let bearer = BearerAuth {
+            token: "secret\ntoken".into(),
+            ..Default::default()
+        };
+        let mut request = reqwest::Request::new(
+            reqwest::Method::GET,
+            "https://registry.example.com/v2/".parse().unwrap(),
+        );
+        match bearer.authorize_request(&mut request) {
+            Err(e @ Error::InvalidAuthToken(_)) => {
+                assert_eq!(e.to_string(), "invalid auth token 's****{12}****n'")
+            }
+            res => panic!("expected InvalidAuthToken, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn secrets_are_masked_in_debug() {
+        let basic = BasicAuth {
+            user: "user".into(),
+            password: Some(CompactString::from("hunter2").into()),
+        };
+        assert!(!format!("{:?}", basic).contains("hunter2"));
+        assert!(format!("{:?}", basic).contains("***"));
+
+        let bearer = BearerAuth {
+            token: "secret-token".into(),
+            refresh_token: Some("secret-refresh".into()),
+            ..Default::default()
+        };
+        let debug = format!("{:?}", bearer);
+        assert!(!debug.contains("secret-token"));
+        assert!(!debug.contains("secret-refresh"));
+
+        let client = crate::v2::Client::configure()
+            .username(Some("user".into()))
+            .password(Some("hunter2".into()));
+        assert!(!format!("{:?}", client).contains("hunter2"));
+        let client = client.build().unwrap();
+        assert!(!format!("{:?}", client).contains("hunter2"));
+    }
+
+    #[test]
+    fn scope_formats_correctly() {
+        assert_eq!(
+            Scope::repository("repo").pull().push().pull().to_string(),
+            "repository:repo:pull,push"
+        );
+        assert_eq!(Scope::catalog().to_string(), "registry:catalog:*");
+
+        let url = Url::parse("https://example.com/v2/my/repo/blobs/uploads/").unwrap();
+        assert_eq!(
+            Scope::for_request(&Method::POST, &url).map(|s| s.to_string()),
+            Some("repository:my/repo:pull,push".to_string())
+        );
+        let url = Url::parse("https://example.com/v2/_catalog").unwrap();
+        assert_eq!(Scope::for_request(&Method::GET, &url), None);
+    }
+
     #[test]
     fn bearer_realm_parses_correctly() -> Result<()> {
         let realm = "https://sat-r220-02.lab.eng.rdu2.redhat.com/v2/token";
@@ -359,6 +1356,7 @@ mod tests {
                     realm: realm.to_string(),
                     service: Some(service.to_string()),
                     scope: Some(scope.to_string()),
+                    error: None,
                 }),
                 content
             );
@@ -367,6 +1365,99 @@ mod tests {
         Ok(())
     }
 
+    #[test_case(r#"Bearer realm="https://auth.example.com/token",service="registry", Negotiate"#; "Trailing bare scheme")]
+    #[test_case(r#"Basic realm="Registry", Bearer realm="https://auth.example.com/token",service="registry""#; "Basic before Bearer")]
+    #[test_case(r#"Bearer realm="https://auth.example.com/token",service="registry", Basic realm="Registry""#; "Bearer before Basic")]
+    #[test_case(r#"Negotiate foo="bar", Bearer realm="https://auth.example.com/token",service="registry""#; "Unsupported scheme with parameters")]
+    fn multiple_challenges_prefer_bearer(header: &str) -> Result<()> {
+        let content = WwwAuthenticateHeaderContent::from_www_authentication_header(
+            HeaderValue::from_str(header).unwrap(),
+        )?;
+
+        assert_eq!(
+            WwwAuthenticateHeaderContent::Bearer(WwwAuthenticateHeaderContentBearer {
+                realm: "https://auth.example.com/token".to_string(),
+                service: Some("registry".to_string()),
+                scope: None,
+                error: None,
+            }),
+            content
+        );
+
+        Ok(())
+    }
+
+    #[test_case(r#"Bearer realm="https://auth.example.com/token",scope="repository:a:pull,push""# => Some("repository:a:pull,push".to_string()); "Comma in scope")]
+    #[test_case(r#"Bearer scope="repository:a:pull,push repository:b:pull" , realm="https://auth.example.com/token""# => Some("repository:a:pull,push repository:b:pull".to_string()); "Several scopes first")]
+    #[test_case(r#"Bearer realm="https://auth.example.com/token",scope="repository:\"a\":pull""# => Some(r#"repository:"a":pull"#.to_string()); "Escaped quotes")]
+    #[test_case(r#"Bearer realm="https://auth.example.com/token",scope=repository:a:pull"# => Some("repository:a:pull".to_string()); "Token value")]
+    #[test_case(r#"Negotiate YWJj==, Bearer realm="https://auth.example.com/token",scope="repository:a:pull,push""# => Some("repository:a:pull,push".to_string()); "After token68")]
+    fn quoted_values_parse_correctly(header: &str) -> Option<String> {
+        match WwwAuthenticateHeaderContent::from_www_authentication_header(
+            HeaderValue::from_str(header).unwrap(),
+        ) {
+            Ok(WwwAuthenticateHeaderContent::Bearer(bearer)) => {
+                assert_eq!(bearer.realm, "https://auth.example.com/token");
+                bearer.scope
+            }
+            res => panic!("expected a Bearer challenge, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn unterminated_quoted_value_is_invalid() {
+        let header = HeaderValue::from_static(r#"Bearer realm="https://auth.example.com/token"#);
+        assert!(matches!(
+            WwwAuthenticateHeaderContent::from_www_authentication_header(header),
+            Err(Error::Www(WwwHeaderParseError::InvalidValue))
+        ));
+    }
+
+    #[test]
+    fn bearer_error_parses_correctly() -> Result<()> {
+        let header = HeaderValue::from_static(
+            r#"Bearer realm="https://auth.example.com/token",service="registry",scope="repository:foo:pull",error="insufficient_scope""#,
+        );
+        let content = WwwAuthenticateHeaderContent::from_www_authentication_header(header)?;
+
+        assert_eq!(
+            WwwAuthenticateHeaderContent::Bearer(WwwAuthenticateHeaderContentBearer {
+                realm: "https://auth.example.com/token".to_string(),
+                service: Some("registry".to_string()),
+                scope: Some("repository:foo:pull".to_string()),
+                error: Some("insufficient_scope".to_string()),
+            }),
+            content
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_challenges_are_all_parsed() -> Result<()> {
+        let header = HeaderValue::from_static(
+            r#"Basic realm="Registry", Bearer realm="https://auth.example.com/token""#,
+        );
+        let challenges = WwwAuthenticateHeaderContent::all_from_www_authentication_header(header)?;
+
+        assert_eq!(
+            vec![
+                WwwAuthenticateHeaderContent::Basic(WwwAuthenticateHeaderContentBasic {
+                    realm: "Registry".to_string(),
+                }),
+                WwwAuthenticateHeaderContent::Bearer(WwwAuthenticateHeaderContentBearer {
+                    realm: "https://auth.example.com/token".to_string(),
+                    service: None,
+                    scope: None,
+                    error: None,
+                }),
+            ],
+            challenges
+        );
+
+        Ok(())
+    }
+
     // Testing for this situation to work:
     // [TRACE dkregistry::v2::auth] Sending request to 'https://localhost:5000/v2/'
     // [TRACE dkregistry::v2::auth] GET 'Response { url: "https://localhost:5000/v2/", status: 401, headers: {"content-type": "application/json; charset=utf-8", "docker-distribution-api-version": "registry/2.0", "www-authenticate": "Basic realm=\"Registry\"", "x-content-type-options": "nosniff", "date": "Thu, 18 Jun 2020 09:04:24 GMT", "content-length": "87"} }'
@@ -403,6 +1494,145 @@ mod tests {
         Ok(())
     }
 
+    #[test_case("/v2/" => None; "Base endpoint")]
+    #[test_case("/v2/library/busybox/manifests/latest" => Some("library/busybox"); "Manifest")]
+    #[test_case("/v2/a/b/c/blobs/sha256:00" => Some("a/b/c"); "Nested blob")]
+    #[test_case("/v2/repo/tags/list" => Some("repo"); "Tags")]
+    #[test_case("/token" => None; "Token endpoint")]
+    #[test_case("/myregistry/v2/repo/manifests/latest" => Some("repo"); "Path prefix")]
+    fn repository_from_path_extracts_name(path: &str) -> Option<&str> {
+        repository_from_path(path)
+    }
+
+    #[test]
+    fn token_cache_respects_expiry() {
+        let cache = TokenCache::default();
+        let now = SystemTime::now();
+        let bearer_auth = |expires_in, refresh_token: Option<&str>| BearerAuth {
+            token: "token".into(),
+            expires_in: Some(expires_in),
+            refresh_token: refresh_token.map(Into::into),
+            received_at: Some(now),
+            ..Default::default()
+        };
+
+        cache.insert(&["repository:repo:pull"], bearer_auth(300, None), now);
+        assert!(cache.get(&["repository:repo:pull"], now).is_some());
+        assert!(cache.get(&["repository:other:pull"], now).is_none());
+        assert!(cache.get_for_repository("repo", "pull", now).is_some());
+        assert!(cache.get_for_repository("repo", "push", now).is_none());
+        assert!(cache.get_for_repository("other", "pull", now).is_none());
+        assert!(cache.refresh_token(&["repository:repo:pull"]).is_none());
+
+        // Stale once within the safety margin of its expiry.
+        let later = now + Duration::from_secs(300) - TOKEN_EXPIRY_MARGIN;
+        assert!(cache.get(&["repository:repo:pull"], later).is_none());
+        assert!(cache.get_for_repository("repo", "pull", later).is_none());
+
+        // Stale within the safety margin, but still refreshable.
+        cache.insert(
+            &["repository:repo:pull"],
+            bearer_auth(3, Some("refresh")),
+            now,
+        );
+        assert!(cache.get(&["repository:repo:pull"], now).is_none());
+        assert!(cache.get_for_repository("repo", "pull", now).is_none());
+        assert_eq!(
+            cache.refresh_token(&["repository:repo:pull"]).as_deref(),
+            Some("refresh")
+        );
+    }
+
+    #[test]
+    fn bearer_auth_granted_scopes() {
+        let claims = URL_SAFE_NO_PAD.encode(
+            r#"{"access":[{"type":"repository","name":"repo","actions":["pull"]},{"type":"repository","name":"other","actions":[]}]}"#,
+        );
+        let jwt = BearerAuth::from_token(format!("e30.{}.c2ln", claims).into());
+        assert_eq!(
+            jwt.granted_scopes(),
+            Some(vec!["repository:repo:pull".to_string()])
+        );
+
+        let scoped = BearerAuth {
+            scope: Some("repository:repo:pull repository:other:pull,push".into()),
+            ..jwt
+        };
+        assert_eq!(
+            scoped.granted_scopes(),
+            Some(vec![
+                "repository:repo:pull".to_string(),
+                "repository:other:pull,push".to_string()
+            ])
+        );
+
+        let opaque = BearerAuth::from_token("opaque".into());
+        assert_eq!(opaque.granted_scopes(), None);
+        let no_access = BearerAuth::from_token(format!("e30.{}.c2ln", "e30").into());
+        assert_eq!(no_access.granted_scopes(), None);
+    }
+
+    #[test]
+    fn bearer_auth_expiry_starts_at_issued_at() {
+        let received_at = humantime::parse_rfc3339("2020-01-01T00:10:00Z").unwrap();
+        let bearer_auth = BearerAuth {
+            token: "token".into(),
+            expires_in: Some(300),
+            issued_at: Some("2020-01-01T00:00:00Z".into()),
+            refresh_token: None,
+            scope: None,
+            received_at: Some(received_at),
+        };
+        assert_eq!(
+            bearer_auth.expires_at(),
+            Some(humantime::parse_rfc3339("2020-01-01T00:05:00Z").unwrap())
+        );
+        assert!(bearer_auth.is_expired());
+        let expires_at = humantime::parse_rfc3339("2020-01-01T00:05:00Z").unwrap();
+        assert!(!bearer_auth.is_expired_at(expires_at - Duration::from_secs(1)));
+        assert!(bearer_auth.is_expired_at(expires_at));
+
+        let bearer_auth = BearerAuth {
+            issued_at: None,
+            expires_in: None,
+            ..bearer_auth
+        };
+        assert_eq!(
+            bearer_auth.expires_at(),
+            Some(received_at + Duration::from_secs(60))
+        );
+
+        let bearer_auth = BearerAuth {
+            received_at: None,
+            ..bearer_auth
+        };
+        assert_eq!(bearer_auth.expires_at(), None);
+        assert!(!bearer_auth.is_expired());
+        assert!(
+            !bearer_auth.is_expired_at(humantime::parse_rfc3339("2100-01-01T00:00:00Z").unwrap())
+        );
+    }
+
+    #[test_case("https://auth.example.com/token" => Some("https://auth.example.com/token".to_string()); "absolute")]
+    #[test_case("/token" => Some("https://registry.example.com/token".to_string()); "relative")]
+    #[test_case("Registry" => None; "bare token")]
+    #[test_case("localhost:5000/token" => None; "without scheme")]
+    #[test_case("" => None; "empty")]
+    fn bearer_realm_url(realm: &str) -> Option<String> {
+        let bearer_header_content = WwwAuthenticateHeaderContentBearer {
+            realm: realm.to_string(),
+            ..Default::default()
+        };
+        match bearer_header_content.realm_url("https://registry.example.com") {
+            Ok(url) => Some(url.to_string()),
+            Err(Error::InvalidAuthRealm(r)) => {
+                assert_eq!(r, realm);
+                None
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
     // The following test checks the url construction within the 'auth_ep'
     // method of WwwAuthenticateHeaderContentBearer.
     // Tests that the result is correctly parsed by Url::parse and that the
@@ -424,6 +1654,7 @@ mod tests {
                 None
             },
             scope: None,
+            error: None,
         };
 
         // build list of expected headers
@@ -436,8 +1667,9 @@ mod tests {
             expected_headers.insert(0, ("service".to_owned(), service.to_string()));
         }
 
-        let result = bearer_header_content.auth_ep(scopes);
-        let url = Url::parse(&result).unwrap();
+        let url = bearer_header_content
+            .auth_ep("https://registry.example.com", scopes)
+            .unwrap();
 
         assert_eq!(
             url.query_pairs().into_owned().collect::<Vec<_>>(),
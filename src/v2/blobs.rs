@@ -1,80 +1,539 @@
 use crate::errors::{Error, Result};
+use crate::reference::Digest;
 use crate::v2::*;
 
+use std::convert::{TryFrom, TryInto};
+use std::ops::Range;
 use std::pin::Pin;
 
-use bytes::Bytes;
-use futures::stream::Stream;
+use async_stream::try_stream;
+use bytes::{Bytes, BytesMut};
+use futures::stream::{self, Stream, StreamExt};
 use futures::task::{Context, Poll};
 use pin_project::pin_project;
-use reqwest::{self, Method, StatusCode};
+use reqwest::{self, header, Method, StatusCode};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio_util::sync::WaitForCancellationFutureOwned;
 use url::Url;
 
 impl Client {
     #[inline]
-    fn blob_url(
-        &self,
-        name: &str,
-        digest: &str,
-        ns: Option<&str>,
-    ) -> core::result::Result<Url, url::ParseError> {
-        let ep = match ns {
+    fn blob_url(&self, name: &str, digest: &str, ns: Option<&str>) -> Result<Url> {
+        validate_name(name)?;
+        let ep = match self.namespace(ns) {
             Some(v) => format!("{}/v2/{}/blobs/{}?ns={}", self.base_url, name, digest, v),
             None => format!("{}/v2/{}/blobs/{}", self.base_url, name, digest),
         };
-        Url::parse(&ep)
+        Ok(Url::parse(&ep)?)
+    }
+
+    /// A client verifying the digest of blobs, whatever `Config::verify_digests` says.
+    pub(crate) fn verifying_digests(&self) -> Client {
+        Client {
+            verify_digests: true,
+            ..self.clone()
+        }
+    }
+
+    /// Prepare the verification of a blob, honoring `Config::verify_digests`.
+    fn content_digest(&self, digest: &str) -> Result<ContentDigest> {
+        Ok(ContentDigest::try_new(digest)?.enabled(self.verify_digests))
+    }
+
+    /// Send a blob request, following redirects to blob storage according to the
+    /// `BlobRedirectPolicy`.
+    async fn send_blob_request(
+        &self,
+        builder: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        if self.blob_redirect_policy != BlobRedirectPolicy::KeepAuth {
+            return self.send_request(builder).await;
+        }
+
+        let (method, authorization, timeout) = match builder.try_clone() {
+            Some(builder) => {
+                let request = builder.build()?;
+                (
+                    request.method().clone(),
+                    request.headers().get(header::AUTHORIZATION).cloned(),
+                    request.timeout().copied(),
+                )
+            }
+            None => return self.send_request(builder).await,
+        };
+
+        let mut resp = self.send_request(builder).await?;
+        let mut redirects = 0;
+        while resp.status().is_redirection() {
+            if redirects == self.max_redirects {
+                return Err(Error::TooManyRedirects(self.max_redirects));
+            }
+            redirects += 1;
+            let location = resp
+                .headers()
+                .get(header::LOCATION)
+                .ok_or(Error::MissingHeader(header::LOCATION.as_str()))?
+                .to_str()?;
+            let location = resp.url().join(location)?;
+            trace!(
+                "Following blob redirect to {}",
+                location.host_str().unwrap_or_default()
+            );
+
+            let client = self.blob_redirect_client.as_ref().unwrap_or(&self.client);
+            let mut builder = client.request(method.clone(), location);
+            if let Some(authorization) = &authorization {
+                builder = builder.header(header::AUTHORIZATION, authorization.clone());
+            }
+            if let Some(ua) = &self.user_agent {
+                builder = builder.header(header::USER_AGENT, ua.as_str());
+            }
+            if let Some(timeout) = timeout {
+                builder = builder.timeout(timeout);
+            }
+            resp = self.send_request_with(client, builder).await?;
+        }
+        Ok(resp)
     }
 
     /// Check if a blob exists.
-    pub async fn has_blob(&self, name: &str, digest: &str, ns: Option<&str>) -> Result<bool> {
-        let url = self.blob_url(name, digest, ns)?;
-        let res = self.build_reqwest(Method::HEAD, url).send().await?;
+    ///
+    /// The digest is either a `Digest` or a string, validated before the request is sent.
+    ///
+    /// This is checked with a HEAD request. As some registries reject HEAD requests to blobs
+    /// while serving them, a HEAD answered with `401 Unauthorized` or `405 Method Not Allowed`
+    /// costs a second request: a GET of the first byte of the blob.
+    ///
+    /// With `Config::mirrors`, the mirrors are asked first, as in `Client::blob_exists_any`.
+    pub async fn has_blob<D>(&self, name: &str, digest: D, ns: Option<&str>) -> Result<bool>
+    where
+        D: TryInto<Digest>,
+        Error: From<D::Error>,
+    {
+        let digest = digest.try_into()?.to_string();
+        Ok(self.blob_exists_any(name, &digest, ns).await?.is_some())
+    }
 
+    /// Check if a blob exists in this registry, ignoring mirrors.
+    pub(crate) async fn has_blob_here(
+        &self,
+        name: &str,
+        digest: &str,
+        ns: Option<&str>,
+    ) -> Result<bool> {
+        let url = self.blob_url(name, digest, ns)?;
+        let mut res = self
+            .send_blob_request(self.build_reqwest(Method::HEAD, url.clone()))
+            .await?;
         trace!("Blob HEAD status: {:?}", res.status());
 
+        if matches!(
+            res.status(),
+            StatusCode::UNAUTHORIZED | StatusCode::METHOD_NOT_ALLOWED
+        ) {
+            res = self
+                .send_blob_request(
+                    self.build_reqwest(Method::GET, url)
+                        .header(header::RANGE, "bytes=0-0"),
+                )
+                .await?;
+            trace!("Blob ranged GET status: {:?}", res.status());
+        }
+
         match res.status() {
-            StatusCode::OK => Ok(true),
+            StatusCode::OK | StatusCode::PARTIAL_CONTENT => Ok(true),
+            StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&res)),
             _ => Ok(false),
         }
     }
 
+    /// Check which of the given blobs exist, with up to `concurrency` HEAD requests in flight.
+    ///
+    /// Returns each digest along with whether it exists, in the order of `digests`.
+    /// This is useful to plan an upload, skipping the blobs already in the repository.
+    pub async fn which_blobs_exist(
+        &self,
+        name: &str,
+        digests: &[&str],
+        concurrency: usize,
+        ns: Option<&str>,
+    ) -> Result<Vec<(String, bool)>> {
+        stream::iter(digests)
+            .map(|digest| async move {
+                let exists = self.has_blob_here(name, digest, ns).await?;
+                Ok((digest.to_string(), exists))
+            })
+            .buffered(concurrency.max(1))
+            .try_collect()
+            .await
+    }
+
+    /// Fetch the metadata of a blob with a HEAD request, without fetching its content.
+    ///
+    /// Returns `None` if the blob does not exist.
+    pub async fn blob_metadata(
+        &self,
+        name: &str,
+        digest: &str,
+        ns: Option<&str>,
+    ) -> Result<Option<BlobMeta>> {
+        let url = self.blob_url(name, digest, ns)?;
+        let res = self
+            .send_blob_request(self.build_reqwest(Method::HEAD, url))
+            .await?;
+
+        trace!("Blob HEAD status: {:?}", res.status());
+
+        match res.status() {
+            StatusCode::OK => {}
+            StatusCode::TOO_MANY_REQUESTS => return Err(rate_limited(&res)),
+            _ => return Ok(None),
+        }
+
+        let headers = res.headers();
+        let size = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok());
+        let digest = content_digest_from_headers(headers).unwrap_or_else(|| digest.to_string());
+        let media_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        Ok(Some(BlobMeta {
+            size,
+            digest,
+            media_type,
+            supports_ranges: supports_ranges(headers),
+        }))
+    }
+
     pub async fn get_blob_response(
         &self,
         name: &str,
         digest: &str,
         ns: Option<&str>,
+    ) -> Result<BlobResponse> {
+        spans::instrument!(
+            self.blob_response(name, digest, None, ns),
+            "get_blob",
+            repo = name,
+            digest,
+            status = tracing::field::Empty,
+            bytes = tracing::field::Empty,
+        )
+        .await
+    }
+
+    /// Retrieve blob response, checking its size against the declared size of the blob.
+    ///
+    /// This fails early with `Error::SizeMismatch` if the response carries a `Content-Length`
+    /// different from `expected_size`, for example the `size` of a layer descriptor.
+    pub async fn get_blob_response_sized(
+        &self,
+        name: &str,
+        digest: &str,
+        expected_size: u64,
+        ns: Option<&str>,
+    ) -> Result<BlobResponse> {
+        self.blob_response(name, digest, Some(expected_size), ns)
+            .await
+    }
+
+    async fn blob_response(
+        &self,
+        name: &str,
+        digest: &str,
+        expected_size: Option<u64>,
+        ns: Option<&str>,
     ) -> Result<BlobResponse> {
         let url = self.blob_url(name, digest, ns)?;
 
-        let resp = self.build_reqwest(Method::GET, url).send().await?;
+        let mut builder = self.build_reqwest(Method::GET, url);
+        if let Some(timeout) = self.blob_timeout {
+            builder = builder.timeout(timeout);
+        }
+        let resp = self.send_blob_request(builder).await?;
 
         let status = resp.status();
         trace!("GET {} status: {}", resp.url(), status);
+        spans::record!("status", status.as_u16());
 
         match resp.error_for_status_ref() {
+            Ok(_) if status.is_redirection() => {
+                error!("Redirect to blob storage was not followed: '{}'", status);
+                Err(Error::UnexpectedHttpStatus(status))
+            }
             Ok(_) => {
                 if let Some(len) = resp.content_length() {
                     trace!("Receiving a blob with {} bytes", len);
+                    spans::record!("bytes", len);
                 } else {
                     trace!("Receiving a blob");
                 }
-                Ok(BlobResponse::new(resp, ContentDigest::try_new(digest)?))
-            }
-            Err(_) if status.is_client_error() => Err(Error::Client { status }),
-            Err(_) if status.is_server_error() => Err(Error::Server { status }),
-            Err(_) => {
-                error!("Received unexpected HTTP status '{}'", status);
-                Err(Error::UnexpectedHttpStatus(status))
+                match (expected_size, resp.content_length()) {
+                    (Some(expected), Some(actual)) if expected != actual => {
+                        return Err(Error::SizeMismatch { expected, actual });
+                    }
+                    _ => {}
+                }
+                Ok(BlobResponse::new(
+                    resp,
+                    self.content_digest(digest)?,
+                    self.cancellation_token.clone(),
+                ))
             }
+            Err(_) => Err(response_error(resp, status_error(status)).await),
         }
     }
 
     /// Retrieve blob.
-    pub async fn get_blob(&self, name: &str, digest: &str, ns: Option<&str>) -> Result<Vec<u8>> {
-        self.get_blob_response(name, digest, ns)
-            .await?
-            .bytes()
-            .await
+    ///
+    /// The digest is either a `Digest` or a string, validated before the request is sent.
+    /// If a `BlobCache` is configured, the blob is served from it when present,
+    /// and stored in it after download otherwise.
+    ///
+    /// With `Config::mirrors`, the blob is downloaded from the first mirror serving it,
+    /// falling back to this registry.
+    ///
+    /// Blobs larger than `Config::max_blob_bytes` are rejected with `Error::BlobTooLarge`.
+    pub async fn get_blob<D>(&self, name: &str, digest: D, ns: Option<&str>) -> Result<Vec<u8>>
+    where
+        D: TryInto<Digest>,
+        Error: From<D::Error>,
+    {
+        let digest = digest.try_into()?.to_string();
+        self.blob_bytes(name, &digest, None, ns).await
+    }
+
+    /// Retrieve blob, checking its size against the declared size of the blob.
+    ///
+    /// The blob is retrieved as in `get_blob`, failing with `Error::SizeMismatch`
+    /// if it doesn't have `expected_size` bytes.
+    pub async fn get_blob_sized(
+        &self,
+        name: &str,
+        digest: &str,
+        expected_size: u64,
+        ns: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        self.blob_bytes(name, digest, Some(expected_size), ns).await
+    }
+
+    /// Retrieve blob as `get_blob` does, checking its size if `expected_size` is given.
+    async fn blob_bytes(
+        &self,
+        name: &str,
+        digest: &str,
+        expected_size: Option<u64>,
+        ns: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        let blob = match self.cached_blob(digest) {
+            Some(blob) => blob,
+            None => {
+                let blob = self
+                    .with_mirrors(|client| async move {
+                        let blob = match expected_size {
+                            Some(size) => {
+                                client
+                                    .get_blob_response_sized(name, digest, size, ns)
+                                    .await?
+                            }
+                            None => client.get_blob_response(name, digest, ns).await?,
+                        };
+                        match client.max_blob_bytes {
+                            Some(limit) => blob.bytes_capped(limit as u64).await,
+                            None => blob.bytes().await,
+                        }
+                    })
+                    .await?;
+                self.cache_blob(digest, &blob);
+                blob
+            }
+        };
+        match expected_size {
+            Some(expected) if expected != blob.len() as u64 => Err(Error::SizeMismatch {
+                expected,
+                actual: blob.len() as u64,
+            }),
+            _ => Ok(blob),
+        }
+    }
+
+    /// Retrieve a byte range of a blob.
+    ///
+    /// The digest can't be verified on a partial range, so the returned bytes are not verified.
+    /// If the registry ignores the `Range` header and sends the whole blob, it is verified
+    /// before the range is sliced out of it. `BlobMeta::supports_ranges` tells beforehand
+    /// whether the registry supports ranges.
+    pub async fn get_blob_range(
+        &self,
+        name: &str,
+        digest: &str,
+        range: Range<u64>,
+        ns: Option<&str>,
+    ) -> Result<Bytes> {
+        if range.is_empty() {
+            return Ok(Bytes::new());
+        }
+
+        let url = self.blob_url(name, digest, ns)?;
+        let mut builder = self.build_reqwest(Method::GET, url).header(
+            header::RANGE,
+            format!("bytes={}-{}", range.start, range.end - 1),
+        );
+        if let Some(timeout) = self.blob_timeout {
+            builder = builder.timeout(timeout);
+        }
+        let resp = self.send_blob_request(builder).await?;
+
+        let status = resp.status();
+        trace!("GET {} status: {}", resp.url(), status);
+
+        match status {
+            StatusCode::PARTIAL_CONTENT => {
+                let content_range = resp
+                    .headers()
+                    .get(header::CONTENT_RANGE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string);
+                if content_range.as_deref().and_then(content_range_start) != Some(range.start) {
+                    return Err(Error::UnexpectedContentRange(content_range));
+                }
+                Ok(resp.bytes().await?)
+            }
+            StatusCode::OK => {
+                trace!("Registry ignored the Range header, receiving the whole blob");
+                let blob = BlobResponse::new(
+                    resp,
+                    self.content_digest(digest)?,
+                    self.cancellation_token.clone(),
+                )
+                .bytes()
+                .await?;
+                let end = usize::try_from(range.end).map_or(blob.len(), |end| end.min(blob.len()));
+                let start = usize::try_from(range.start).map_or(end, |start| start.min(end));
+                Ok(Bytes::from(blob).slice(start..end))
+            }
+            _ => Err(response_error(resp, status_error(status)).await),
+        }
+    }
+
+    /// Retrieve the blob of a layer, falling back to the layer URLs for foreign layers.
+    ///
+    /// The registry and its mirrors are tried first. If none has the blob of a foreign layer,
+    /// each of the layer URLs is tried in turn. The digest is verified in both cases.
+    /// Registry credentials are not sent to the layer URLs.
+    pub async fn get_foreign_blob(
+        &self,
+        name: &str,
+        layer: &manifest::Layer,
+        ns: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        let mut err = match self.get_blob(name, layer.digest(), ns).await {
+            Err(e) if e.is_not_found() => e,
+            res => return res,
+        };
+        if !layer.is_foreign() {
+            return Err(err);
+        }
+
+        for url in layer.urls().unwrap_or_default() {
+            trace!("Fetching foreign layer {} from {}", layer.digest(), url);
+            match self.get_blob_from_url(url, layer.digest()).await {
+                Ok(blob) => {
+                    self.cache_blob(layer.digest(), &blob);
+                    return Ok(blob);
+                }
+                Err(e) => {
+                    warn!("Failed to fetch foreign layer from {}: {}", url, e);
+                    err = e;
+                }
+            }
+        }
+        Err(err)
+    }
+
+    /// Retrieve a blob from an URL outside of the registry, without credentials.
+    ///
+    /// The request is never re-authenticated, whatever `Config::auto_reauth` says.
+    async fn get_blob_from_url(&self, url: &str, digest: &str) -> Result<Vec<u8>> {
+        let mut builder = self.client.get(Url::parse(url)?);
+        if let Some(ua) = &self.user_agent {
+            builder = builder.header(header::USER_AGENT, ua.as_str());
+        }
+        if let Some(timeout) = self.blob_timeout {
+            builder = builder.timeout(timeout);
+        }
+        let resp = self.send_foreign_request(builder).await?;
+
+        let status = resp.status();
+        trace!("GET {} status: {}", resp.url(), status);
+        match status {
+            StatusCode::OK => {
+                BlobResponse::new(
+                    resp,
+                    self.content_digest(digest)?,
+                    self.cancellation_token.clone(),
+                )
+                .bytes()
+                .await
+            }
+            _ => Err(response_error(resp, status_error(status)).await),
+        }
+    }
+
+    /// Retrieve several blobs, with up to `concurrency` downloads in flight.
+    ///
+    /// Each blob is yielded with its digest as soon as it is downloaded and verified,
+    /// so blobs may come out of order.
+    pub fn get_blobs_concurrent<'a>(
+        &'a self,
+        name: &'a str,
+        digests: &'a [&'a str],
+        concurrency: usize,
+        ns: Option<&'a str>,
+    ) -> impl Stream<Item = Result<(String, Vec<u8>)>> + 'a {
+        stream::iter(digests.iter().map(move |digest| async move {
+            let blob = self.get_blob(name, *digest, ns).await?;
+            Ok((digest.to_string(), blob))
+        }))
+        .buffer_unordered(concurrency.max(1))
+    }
+
+    /// Retrieve the layers of an image in order, base layer first, with up to `ahead`
+    /// downloads in flight.
+    ///
+    /// Unlike `get_blobs_concurrent`, layers are yielded in the order of the manifest,
+    /// as needed to apply them, while the next ones are downloaded. Downloads only proceed
+    /// while the stream is polled, so no more than `ahead` layers are buffered.
+    /// The architecture is checked as in `Manifest::layers_digests`, and manifest lists
+    /// result in `ManifestError::LayerDigestsUnsupported`.
+    pub fn prefetch_layers<'a>(
+        &'a self,
+        name: &'a str,
+        manifest: &'a manifest::Manifest,
+        architecture: Option<&'a str>,
+        ahead: usize,
+        ns: Option<&'a str>,
+    ) -> impl Stream<Item = Result<(String, Vec<u8>)>> + 'a {
+        try_stream! {
+            // Manifest lists have no layers: `layers_digests` would list their manifests.
+            if let manifest::Manifest::ML(_) = manifest {
+                Err(manifest::ManifestError::LayerDigestsUnsupported(manifest.media_type()))?;
+            }
+            let digests = manifest.layers_digests(architecture)?;
+            let mut layers = stream::iter(digests)
+                .map(move |digest| async move {
+                    let blob = self.get_blob(name, digest, ns).await?;
+                    Ok::<_, Error>((digest.to_string(), blob))
+                })
+                .buffered(ahead.max(1));
+            while let Some(layer) = layers.next().await {
+                yield layer?;
+            }
+        }
     }
 
     /// Retrieve blob stream.
@@ -83,20 +542,152 @@ impl Client {
         name: &str,
         digest: &str,
         ns: Option<&str>,
-    ) -> Result<impl Stream<Item = Result<Bytes>>> {
+    ) -> Result<BlobStream<impl Stream<Item = reqwest::Result<Bytes>> + Unpin>> {
         Ok(self.get_blob_response(name, digest, ns).await?.stream())
     }
+
+    /// Retrieve blob stream, coalesced into chunks of `chunk_size` bytes.
+    ///
+    /// All chunks but the last one have exactly `chunk_size` bytes, whatever the size
+    /// of the chunks received from the registry. The digest is verified before the
+    /// last chunk is yielded.
+    pub async fn get_blob_stream_chunked(
+        &self,
+        name: &str,
+        digest: &str,
+        chunk_size: usize,
+        ns: Option<&str>,
+    ) -> Result<ChunkedBlobStream<impl Stream<Item = reqwest::Result<Bytes>> + Unpin>> {
+        let stream = self.get_blob_stream(name, digest, ns).await?;
+        Ok(ChunkedBlobStream::new(stream, chunk_size))
+    }
+
+    /// Download a blob into a writer, such as a `tokio::fs::File`, without buffering it in memory.
+    ///
+    /// Returns the number of bytes written. The digest is verified once the whole blob has been
+    /// written, whatever `Config::verify_digests` says, and the writer is flushed.
+    ///
+    /// On error, including `Error::DigestMismatch`, whatever was written is left in the writer:
+    /// the caller is responsible for discarding it, for example by deleting the partial file.
+    pub async fn download_blob_to<W>(
+        &self,
+        name: &str,
+        digest: &str,
+        mut writer: W,
+        ns: Option<&str>,
+    ) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut stream = self
+            .verifying_digests()
+            .get_blob_stream(name, digest, ns)
+            .await?;
+        let mut written = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            writer.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+        writer.flush().await?;
+        trace!("Downloaded {} bytes of blob {}", written, digest);
+        Ok(written)
+    }
+}
+
+/// Parse the first byte position of a `Content-Range` header, e.g. `bytes 0-99/1234`.
+fn content_range_start(content_range: &str) -> Option<u64> {
+    let (start, _) = content_range.strip_prefix("bytes ")?.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+/// Maximum number of redirects followed by a request, unless set with `Config::max_redirects`.
+pub(crate) const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// How blob requests follow redirects, typically to object storage such as S3 or GCS.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlobRedirectPolicy {
+    /// Follow redirects, dropping the `Authorization` header when the redirect leads to
+    /// another host, as object storage usually rejects registry credentials.
+    ///
+    /// This is the default.
+    #[default]
+    StripAuth,
+    /// Follow redirects, sending the `Authorization` header to the redirect target too.
+    ///
+    /// Only use this if the storage behind the registry expects the registry credentials.
+    KeepAuth,
+}
+
+/// Whether `path` addresses a blob, as `/v2/<name>/blobs/<digest>`, rather than an upload.
+pub(crate) fn is_blob_path(path: &str) -> bool {
+    path.rsplit('/').nth(1) == Some("blobs")
+}
+
+/// Metadata of a blob, as returned by `Client::blob_metadata`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlobMeta {
+    size: Option<u64>,
+    digest: String,
+    media_type: Option<String>,
+    supports_ranges: bool,
+}
+
+impl BlobMeta {
+    /// Size of the blob in bytes, from the `Content-Length` header.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Digest of the blob, from the `Docker-Content-Digest` header if sent.
+    pub fn digest(&self) -> &str {
+        &self.digest
+    }
+
+    /// Media type of the blob, from the `Content-Type` header.
+    ///
+    /// Registries usually serve blobs as `application/octet-stream`.
+    pub fn media_type(&self) -> Option<&str> {
+        self.media_type.as_deref()
+    }
+
+    /// Whether the registry announces support for byte ranges with `Accept-Ranges: bytes`.
+    ///
+    /// When it doesn't, an interrupted download is better restarted than resumed with
+    /// `Client::get_blob_range`, which may receive the whole blob anyway.
+    pub fn supports_ranges(&self) -> bool {
+        self.supports_ranges
+    }
+}
+
+/// Whether the headers of a response announce support for byte ranges.
+fn supports_ranges(headers: &header::HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT_RANGES)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|unit| unit.trim().eq_ignore_ascii_case("bytes"))
 }
 
 #[derive(Debug)]
 pub struct BlobResponse {
     resp: reqwest::Response,
     digest: ContentDigest,
+    cancellation_token: Option<CancellationToken>,
 }
 
 impl BlobResponse {
-    fn new(resp: reqwest::Response, digest: ContentDigest) -> Self {
-        Self { resp, digest }
+    fn new(
+        resp: reqwest::Response,
+        digest: ContentDigest,
+        cancellation_token: Option<CancellationToken>,
+    ) -> Self {
+        Self {
+            resp,
+            digest,
+            cancellation_token,
+        }
     }
 
     /// Get size of the blob.
@@ -105,9 +696,40 @@ impl BlobResponse {
         self.resp.content_length()
     }
 
+    /// Whether the server announces support for byte ranges with `Accept-Ranges: bytes`.
+    ///
+    /// If this download is interrupted, it can then be resumed with `Client::get_blob_range`
+    /// rather than restarted.
+    pub fn supports_ranges(&self) -> bool {
+        supports_ranges(self.resp.headers())
+    }
+
+    /// URL the blob was served from, after following redirects.
+    ///
+    /// For registries backed by object storage, this is the storage URL the registry
+    /// redirected to, e.g. a presigned S3 URL.
+    pub fn final_url(&self) -> &Url {
+        self.resp.url()
+    }
+
+    /// Headers of the response, for example to look at vendor-specific headers.
+    pub fn headers(&self) -> &header::HeaderMap {
+        self.resp.headers()
+    }
+
+    /// Media type of the blob, from the `Content-Type` of the response.
+    pub fn media_type(&self) -> Option<MediaTypes> {
+        let content_type = self.resp.headers().get(header::CONTENT_TYPE)?;
+        Some(MediaTypes::from_content_type(content_type.to_str().ok()?))
+    }
+
     /// Retrieve content of the blob.
     pub async fn bytes(self) -> Result<Vec<u8>> {
-        let blob = self.resp.bytes().await?.to_vec();
+        let resp = self.resp;
+        let read = async move { Ok(resp.bytes().await?) };
+        let blob = cancellable(self.cancellation_token.as_ref(), read)
+            .await?
+            .to_vec();
 
         let mut digest = self.digest;
         digest.update(&blob);
@@ -116,14 +738,45 @@ impl BlobResponse {
         Ok(blob)
     }
 
+    /// Retrieve content of the blob, failing with `Error::BlobTooLarge` past `limit` bytes.
+    async fn bytes_capped(mut self, limit: u64) -> Result<Vec<u8>> {
+        if let Some(size) = self.size().filter(|&size| size > limit) {
+            return Err(Error::BlobTooLarge { size, limit });
+        }
+
+        // The Content-Length may be missing or wrong, so the read is capped as well.
+        let mut blob = Vec::new();
+        let token = self.cancellation_token.take();
+        let resp = &mut self.resp;
+        while let Some(chunk) =
+            cancellable(token.as_ref(), async { Ok(resp.chunk().await?) }).await?
+        {
+            let size = (blob.len() + chunk.len()) as u64;
+            if size > limit {
+                return Err(Error::BlobTooLarge { size, limit });
+            }
+            blob.extend_from_slice(&chunk);
+        }
+
+        self.digest.update(&blob);
+        self.digest.verify()?;
+
+        Ok(blob)
+    }
+
     /// Get bytes stream of the blob.
-    pub fn stream(self) -> impl Stream<Item = Result<Bytes>> {
-        BlobStream::new(self.resp.bytes_stream(), self.digest)
+    pub fn stream(self) -> BlobStream<impl Stream<Item = reqwest::Result<Bytes>> + Unpin> {
+        BlobStream::new(
+            self.resp.bytes_stream(),
+            self.digest,
+            self.cancellation_token,
+        )
     }
 }
 
+/// Bytes stream of a blob, verifying its digest once the stream ends.
 #[pin_project]
-struct BlobStream<S>
+pub struct BlobStream<S>
 where
     S: Stream<Item = reqwest::Result<Bytes>>,
 {
@@ -131,18 +784,58 @@ where
     stream: S,
     #[pin]
     digest: Option<ContentDigest>,
+    verified: Option<String>,
+    downloaded: u64,
+    cancelled: Option<Pin<Box<WaitForCancellationFutureOwned>>>,
+}
+
+impl<S> std::fmt::Debug for BlobStream<S>
+where
+    S: Stream<Item = reqwest::Result<Bytes>>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlobStream")
+            .field("digest", &self.digest)
+            .field("verified", &self.verified)
+            .field("downloaded", &self.downloaded)
+            .finish_non_exhaustive()
+    }
 }
 
 impl<S> BlobStream<S>
 where
     S: Stream<Item = reqwest::Result<Bytes>> + Unpin,
 {
-    fn new(stream: S, digest: ContentDigest) -> Self {
+    fn new(
+        stream: S,
+        digest: ContentDigest,
+        cancellation_token: Option<CancellationToken>,
+    ) -> Self {
         Self {
             stream,
             digest: Some(digest),
+            verified: None,
+            downloaded: 0,
+            cancelled: cancellation_token.map(|token| Box::pin(token.cancelled_owned())),
         }
     }
+
+    /// Get the digest of the streamed content.
+    ///
+    /// This returns `None` until the stream has been consumed to its end
+    /// and the content matched the requested digest, and always if
+    /// digest verification is disabled.
+    pub fn digest(&self) -> Option<&str> {
+        self.verified.as_deref()
+    }
+
+    /// Number of bytes received so far, for example to account for egress.
+    ///
+    /// Once the stream has ended, this is the number of bytes transferred for this response,
+    /// which is less than the size of the blob for a resumed or ranged download.
+    pub fn bytes_downloaded(&self) -> u64 {
+        self.downloaded
+    }
 }
 
 impl<S> Stream for BlobStream<S>
@@ -153,6 +846,18 @@ where
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut this = self.project();
+        if this.digest.is_none() {
+            return Poll::Ready(None);
+        }
+        if let Some(cancelled) = this.cancelled {
+            if cancelled.as_mut().poll(cx).is_ready() {
+                // The partial digest is dropped, and the stream ends after the error.
+                *this.cancelled = None;
+                this.digest.set(None);
+                trace!("Blob download cancelled");
+                return Poll::Ready(Some(Err(Error::Cancelled)));
+            }
+        }
         match this.stream.poll_next(cx) {
             Poll::Ready(Some(chunk_res)) => {
                 let mut digest = match this.digest.as_pin_mut() {
@@ -160,17 +865,117 @@ where
                     None => return Poll::Ready(None),
                 };
                 let chunk = chunk_res?;
+                *this.downloaded += chunk.len() as u64;
                 digest.update(&chunk);
                 Poll::Ready(Some(Ok(chunk)))
             }
             Poll::Ready(None) => match this.digest.take() {
-                Some(digest) => match digest.verify() {
-                    Ok(()) => Poll::Ready(None),
-                    Err(err) => Poll::Ready(Some(Err(err.into()))),
-                },
+                Some(digest) => {
+                    let expected =
+                        Some(digest.as_str().to_string()).filter(|_| digest.is_enabled());
+                    match digest.verify() {
+                        Ok(()) => {
+                            *this.verified = expected;
+                            Poll::Ready(None)
+                        }
+                        Err(err) => Poll::Ready(Some(Err(err.into()))),
+                    }
+                }
                 None => Poll::Ready(None),
             },
             Poll::Pending => Poll::Pending,
         }
     }
 }
+
+/// Bytes stream of a blob coalesced into chunks of a fixed size,
+/// as returned by `Client::get_blob_stream_chunked`.
+#[pin_project]
+pub struct ChunkedBlobStream<S>
+where
+    S: Stream<Item = reqwest::Result<Bytes>>,
+{
+    #[pin]
+    stream: BlobStream<S>,
+    chunk_size: usize,
+    buf: BytesMut,
+    done: bool,
+}
+
+impl<S> std::fmt::Debug for ChunkedBlobStream<S>
+where
+    S: Stream<Item = reqwest::Result<Bytes>>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkedBlobStream")
+            .field("stream", &self.stream)
+            .field("chunk_size", &self.chunk_size)
+            .field("buffered", &self.buf.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> ChunkedBlobStream<S>
+where
+    S: Stream<Item = reqwest::Result<Bytes>> + Unpin,
+{
+    fn new(stream: BlobStream<S>, chunk_size: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        Self {
+            stream,
+            chunk_size,
+            buf: BytesMut::with_capacity(chunk_size),
+            done: false,
+        }
+    }
+
+    /// Get the digest of the streamed content.
+    ///
+    /// See `BlobStream::digest`.
+    pub fn digest(&self) -> Option<&str> {
+        self.stream.digest()
+    }
+
+    /// Number of bytes received so far, including those not yet yielded as a chunk.
+    ///
+    /// See `BlobStream::bytes_downloaded`.
+    pub fn bytes_downloaded(&self) -> u64 {
+        self.stream.bytes_downloaded()
+    }
+}
+
+impl<S> Stream for ChunkedBlobStream<S>
+where
+    S: Stream<Item = reqwest::Result<Bytes>> + Unpin,
+{
+    type Item = Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if this.buf.len() >= *this.chunk_size {
+                let chunk = this.buf.split_to(*this.chunk_size).freeze();
+                return Poll::Ready(Some(Ok(chunk)));
+            }
+            if *this.done {
+                return Poll::Ready(None);
+            }
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => this.buf.extend_from_slice(&chunk),
+                Poll::Ready(Some(Err(err))) => {
+                    // Don't hand out buffered data which failed verification.
+                    this.buf.clear();
+                    *this.done = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    if !this.buf.is_empty() {
+                        return Poll::Ready(Some(Ok(this.buf.split().freeze())));
+                    }
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
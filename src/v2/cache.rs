@@ -0,0 +1,147 @@
+//! Content-addressed caching of blobs.
+
+use std::fmt::Debug;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::v2::*;
+
+/// A content-addressed store of blobs, keyed by digest.
+///
+/// Blobs are immutable, so a cached blob never needs to be invalidated.
+/// The client verifies the digest of cached blobs before using them.
+pub trait BlobCache: Debug + Send + Sync {
+    /// Get the blob with the given digest, if cached.
+    fn get(&self, digest: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// Store the blob with the given digest.
+    fn put(&self, digest: &str, blob: &[u8]) -> io::Result<()>;
+}
+
+/// A `BlobCache` storing blobs as files under a directory, as `<root>/<algorithm>/<hex>`.
+#[derive(Clone, Debug)]
+pub struct FsBlobCache {
+    root: PathBuf,
+}
+
+impl FsBlobCache {
+    /// Create a cache under the given directory, which is created on demand.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, digest: &str) -> io::Result<PathBuf> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("bad digest {}", digest),
+            )
+        };
+        let (algorithm, hex) = digest.split_once(':').ok_or_else(invalid)?;
+        let valid_algorithm = !algorithm.is_empty()
+            && algorithm
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+        let valid_hex = !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit());
+        if !valid_algorithm || !valid_hex {
+            return Err(invalid());
+        }
+        Ok(self.root.join(algorithm).join(hex))
+    }
+}
+
+impl BlobCache for FsBlobCache {
+    fn get(&self, digest: &str) -> io::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.path(digest)?) {
+            Ok(blob) => Ok(Some(blob)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn put(&self, digest: &str, blob: &[u8]) -> io::Result<()> {
+        let path = self.path(digest)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        std::fs::create_dir_all(dir)?;
+
+        // Write to a temporary file first, so that readers never see a partial blob.
+        let tmp = dir.join(format!(
+            ".{}.{}.tmp",
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default(),
+            std::process::id()
+        ));
+        std::fs::write(&tmp, blob)?;
+        std::fs::rename(&tmp, &path)
+    }
+}
+
+impl Client {
+    /// Get a blob from the cache, if any, ignoring cached blobs with a wrong digest.
+    pub(crate) fn cached_blob(&self, digest: &str) -> Option<Vec<u8>> {
+        let cache = self.blob_cache.as_ref()?;
+        let blob = match cache.get(digest) {
+            Ok(blob) => blob?,
+            Err(e) => {
+                warn!("Failed to read blob {} from cache: {}", digest, e);
+                return None;
+            }
+        };
+
+        let mut content_digest = ContentDigest::try_new(digest).ok()?;
+        content_digest.update(&blob);
+        match content_digest.verify() {
+            Ok(()) => {
+                trace!("Blob {} found in cache", digest);
+                Some(blob)
+            }
+            Err(e) => {
+                warn!("Ignoring cached blob {}: {}", digest, e);
+                None
+            }
+        }
+    }
+
+    /// Store a verified blob in the cache, if any.
+    pub(crate) fn cache_blob(&self, digest: &str, blob: &[u8]) {
+        if let Some(cache) = &self.blob_cache {
+            if let Err(e) = cache.put(digest, blob) {
+                warn!("Failed to write blob {} to cache: {}", digest, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(name: &str) -> FsBlobCache {
+        let root =
+            std::env::temp_dir().join(format!("dkregistry-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        FsBlobCache::new(root)
+    }
+
+    #[test]
+    fn test_fs_blob_cache() {
+        let cache = temp_cache("roundtrip");
+        let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+        assert_eq!(cache.get(digest).unwrap(), None);
+        cache.put(digest, b"hello").unwrap();
+        assert_eq!(cache.get(digest).unwrap(), Some(b"hello".to_vec()));
+        assert!(cache.root.join("sha256").join(&digest[7..]).is_file());
+
+        std::fs::remove_dir_all(&cache.root).unwrap();
+    }
+
+    #[test]
+    fn test_fs_blob_cache_bad_digest() {
+        let cache = temp_cache("bad-digest");
+        assert!(cache.get("sha256:../../etc/passwd").is_err());
+        assert!(cache.put("../sha256:abcd", b"hello").is_err());
+        assert!(cache.get("abcd").is_err());
+    }
+}
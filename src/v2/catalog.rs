@@ -29,7 +29,7 @@ impl v2::Client {
         try_stream! {
             let req = self.build_reqwest(Method::GET, url?);
 
-            let catalog = fetch_catalog(req).await?;
+            let catalog = fetch_catalog(self, req).await?;
 
             for repo in catalog.repositories {
                 yield repo;
@@ -38,8 +38,8 @@ impl v2::Client {
     }
 }
 
-async fn fetch_catalog(req: RequestBuilder) -> Result<Catalog> {
-    let r = req.send().await?;
+async fn fetch_catalog(client: &v2::Client, req: RequestBuilder) -> Result<Catalog> {
+    let r = client.send_request(req).await?;
     let status = r.status();
     trace!("Got status: {:?}", status);
     match status {
@@ -0,0 +1,37 @@
+//! Source of the current time, replaceable for tests.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Clock of a `Client`, telling the current time when token expiry is checked.
+///
+/// It defaults to `SystemTime::now`, see `Config::clock` to replace it.
+#[derive(Clone)]
+pub(crate) struct Clock(Arc<dyn Fn() -> SystemTime + Send + Sync>);
+
+impl Clock {
+    pub(crate) fn new<F>(now: F) -> Self
+    where
+        F: Fn() -> SystemTime + Send + Sync + 'static,
+    {
+        Clock(Arc::new(now))
+    }
+
+    /// Get the current time.
+    pub(crate) fn now(&self) -> SystemTime {
+        (self.0)()
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::new(SystemTime::now)
+    }
+}
+
+impl fmt::Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Clock").finish_non_exhaustive()
+    }
+}
@@ -1,5 +1,6 @@
 use arcstr::ArcStr;
 use compact_str::CompactString;
+use std::time::Duration;
 
 use crate::{mediatypes::MediaTypes, v2::*};
 
@@ -7,22 +8,85 @@ use crate::{mediatypes::MediaTypes, v2::*};
 #[derive(Debug)]
 pub struct Config {
     index: String,
+    path_prefix: String,
     insecure_registry: bool,
     user_agent: Option<ArcStr>,
     username: Option<CompactString>,
-    password: Option<CompactString>,
+    password: Option<auth::SecretString>,
+    credential_provider: Option<auth::CredentialProvider>,
     accept_invalid_certs: bool,
     accepted_types: Option<Vec<(MediaTypes, Option<f64>)>>,
+    accept_quality_weights: Option<bool>,
+    auto_reauth: bool,
+    no_auth: bool,
+    require_v2_header: bool,
+    oauth2: bool,
+    anonymous_fallback: bool,
+    prefer_basic: bool,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    http2_prior_knowledge: bool,
+    http1_only: bool,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    blob_timeout: Option<Duration>,
+    auth_timeout: Duration,
+    http_client: Option<reqwest::Client>,
+    #[cfg(any(
+        feature = "reqwest-default-tls",
+        feature = "reqwest-rustls",
+        feature = "reqwest-native-tls-alpn"
+    ))]
+    root_certificates: Vec<Vec<u8>>,
+    proxy: Option<reqwest::Url>,
+    no_proxy: Vec<String>,
+    use_env_proxy: bool,
+    default_headers: reqwest::header::HeaderMap,
+    retry: Option<RetryPolicy>,
+    blob_cache: Option<std::sync::Arc<dyn BlobCache>>,
+    verify_digests: bool,
+    digest_algorithm: Algorithm,
+    dry_run: bool,
+    max_manifest_bytes: Option<usize>,
+    max_blob_bytes: Option<usize>,
+    blob_redirect_policy: BlobRedirectPolicy,
+    max_redirects: usize,
+    max_pagination_pages: Option<usize>,
+    clock: clock::Clock,
+    default_tag: ArcStr,
+    default_namespace: Option<ArcStr>,
+    #[cfg(feature = "schema1-signatures")]
+    verify_schema1_signatures: bool,
+    mirrors: Vec<reqwest::Url>,
+    resolve_platform: Option<(CompactString, CompactString)>,
+    bearer_token: Option<ArcStr>,
+    cancellation_token: Option<CancellationToken>,
 }
 
 impl Config {
     /// Set registry service to use (vhost or IP).
+    ///
+    /// A leading `http://` or `https://` sets the scheme to connect with,
+    /// overriding `insecure_registry`.
     pub fn registry(mut self, reg: &str) -> Self {
         self.index = reg.to_owned();
         self
     }
 
-    /// Whether to use an insecure HTTP connection to the registry.
+    /// Set a path prefix under which the registry is hosted, e.g. `myregistry` for
+    /// a registry serving its API at `https://host/myregistry/v2/`.
+    pub fn path_prefix(mut self, prefix: &str) -> Self {
+        let prefix = prefix.trim_matches('/');
+        self.path_prefix = if prefix.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", prefix)
+        };
+        self
+    }
+
+    /// Whether to use an insecure HTTP connection to the registry,
+    /// unless `registry` is given with a scheme.
     pub fn insecure_registry(mut self, insecure: bool) -> Self {
         self.insecure_registry = insecure;
         self
@@ -43,7 +107,382 @@ impl Config {
         self
     }
 
-    /// Set the user-agent to be used for registry authentication.
+    /// Set whether `q` quality weights are sent in Accept headers.
+    ///
+    /// By default they are, except to `gcr.io` and its subdomains, which mis-parse them.
+    /// Disabling them also strips the weights set with `accepted_types`.
+    pub fn accept_quality_weights(mut self, accept_quality_weights: bool) -> Self {
+        self.accept_quality_weights = Some(accept_quality_weights);
+        self
+    }
+
+    /// Only accept OCI manifests, indexes and configs.
+    ///
+    /// This overrides `accepted_types`.
+    pub fn oci_only(self) -> Self {
+        self.accepted_types(Some(vec![
+            (MediaTypes::OciV1Manifest, None),
+            (MediaTypes::OciV1ManifestList, None),
+            (MediaTypes::OciV1ManifestConfig, None),
+        ]))
+    }
+
+    /// Only accept Docker manifests and manifest lists.
+    ///
+    /// This overrides `accepted_types`.
+    pub fn docker_only(self) -> Self {
+        self.accepted_types(Some(vec![
+            (MediaTypes::ManifestV2S2, None),
+            (MediaTypes::ManifestV2S1Signed, None),
+            (MediaTypes::ManifestList, None),
+        ]))
+    }
+
+    /// Whether to re-authenticate and retry once when a request is rejected with `401 Unauthorized`.
+    pub fn auto_reauth(mut self, auto_reauth: bool) -> Self {
+        self.auto_reauth = auto_reauth;
+        self
+    }
+
+    /// Leave authentication to the transport, such as an authenticating proxy in front of
+    /// the registry.
+    ///
+    /// The client then never adds credentials or tokens to its requests, `Client::authenticate`
+    /// succeeds without sending anything, and `auto_reauth` is ignored.
+    /// Headers set with `default_headers`, including `Authorization`, are still sent.
+    pub fn no_auth(mut self) -> Self {
+        self.no_auth = true;
+        self
+    }
+
+    /// Whether to require the `Docker-Distribution-Api-Version: registry/2.0` header
+    /// on the `/v2/` endpoint, which is off by default.
+    ///
+    /// When enabled, `ping` and `authenticate` fail with `Error::NotARegistry` if the header
+    /// is missing, catching a URL which points at something else than a registry, such as
+    /// a login portal, instead of failing later on unexpected content.
+    pub fn require_v2_header(mut self, require: bool) -> Self {
+        self.require_v2_header = require;
+        self
+    }
+
+    /// Whether to request bearer tokens with the OAuth2 password grant (form POST to the realm).
+    ///
+    /// This is required by some registries which reject the token GET request.
+    /// Anonymous token requests always use the GET request.
+    pub fn oauth2(mut self, oauth2: bool) -> Self {
+        self.oauth2 = oauth2;
+        self
+    }
+
+    /// Whether to request an anonymous bearer token when the token request with credentials
+    /// is rejected with `401` or `403`, as the Docker CLI does for public images.
+    ///
+    /// This is disabled by default, so that wrong credentials are reported as an error.
+    pub fn anonymous_fallback(mut self, anonymous_fallback: bool) -> Self {
+        self.anonymous_fallback = anonymous_fallback;
+        self
+    }
+
+    /// Whether to use Basic authentication when the registry offers both Basic and Bearer.
+    ///
+    /// Some registries, such as Harbor with robot accounts (`robot$name`), advertise a Bearer
+    /// challenge but only accept the credentials with Basic. Bearer is preferred by default,
+    /// and always without credentials.
+    pub fn prefer_basic(mut self, prefer_basic: bool) -> Self {
+        self.prefer_basic = prefer_basic;
+        self
+    }
+
+    /// Set a timeout for each request, from connecting until the response body has been read.
+    ///
+    /// The timeout applies per request, not per byte: a slow blob download fails once
+    /// the whole transfer exceeds it. Use `blob_timeout` to allow blob downloads more time.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set a timeout for establishing connections to the registry and the auth realm.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Speak HTTP/2 to the registry right away, without negotiating it.
+    ///
+    /// Only for registries known to support HTTP/2, typically over plain HTTP
+    /// where it can't be negotiated with ALPN. Over TLS, only `h2` is offered with ALPN,
+    /// for proxies which require it.
+    pub fn http2_prior_knowledge(mut self, http2_prior_knowledge: bool) -> Self {
+        self.http2_prior_knowledge = http2_prior_knowledge;
+        self
+    }
+
+    /// Speak only HTTP/1.1 to the registry, offering only `http/1.1` with ALPN over TLS.
+    ///
+    /// For TLS-terminating proxies which break with HTTP/2. By default, the protocol
+    /// is negotiated, which with native TLS requires the `reqwest-native-tls-alpn` feature.
+    /// This takes precedence over `http2_prior_knowledge`.
+    pub fn http1_only(mut self, http1_only: bool) -> Self {
+        self.http1_only = http1_only;
+        self
+    }
+
+    /// Set the maximum number of idle connections kept open to each host.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Set how long idle connections are kept open for reuse.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Set a timeout for blob downloads, overriding `timeout` for those requests.
+    pub fn blob_timeout(mut self, timeout: Duration) -> Self {
+        self.blob_timeout = Some(timeout);
+        self
+    }
+
+    /// Set a timeout for requests to the token endpoint, 30 seconds by default.
+    ///
+    /// Token requests timing out fail with `Error::AuthEndpointError`.
+    pub fn auth_timeout(mut self, timeout: Duration) -> Self {
+        self.auth_timeout = timeout;
+        self
+    }
+
+    /// Whether to verify the digest of downloaded blobs, which is the default.
+    ///
+    /// Disabling this saves hashing every blob, but the content of blobs is then
+    /// trusted as sent by the registry: a corrupted or tampered blob goes unnoticed.
+    /// Only disable it when blobs are verified by other means, for example after
+    /// being written to disk. Blobs served from a `BlobCache` are always verified.
+    pub fn verify_digests(mut self, verify_digests: bool) -> Self {
+        self.verify_digests = verify_digests;
+        self
+    }
+
+    /// Whether to skip all writes to the registry, which is off by default.
+    ///
+    /// In dry-run mode, uploads, mounts and manifest pushes send nothing: digests are
+    /// still computed and verified, and the digest which would have been pushed is
+    /// returned. Reads, such as the existence checks of `Client::copy_image`, are sent
+    /// as usual, so `Client::plan_copy` reports what a real copy would do.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Set the algorithm used to compute digests, sha256 by default.
+    ///
+    /// This applies to blobs uploaded with `Client::push_blob` and to manifests
+    /// pushed or fetched by tag. Digests given by the registry or by the caller
+    /// are always verified with their own algorithm.
+    pub fn digest_algorithm(mut self, digest_algorithm: Algorithm) -> Self {
+        self.digest_algorithm = digest_algorithm;
+        self
+    }
+
+    /// Whether to verify the signatures of schema 1 manifests fetched by `Client::get_manifest`,
+    /// which is disabled by default.
+    ///
+    /// Manifests with an invalid signature are rejected with `Error::InvalidManifestSignature`.
+    /// See `ManifestSchema1Signed::verify_signature`.
+    #[cfg(feature = "schema1-signatures")]
+    pub fn verify_schema1_signatures(mut self, verify: bool) -> Self {
+        self.verify_schema1_signatures = verify;
+        self
+    }
+
+    /// Set the maximum size of manifests and config blobs, which are buffered to be parsed.
+    ///
+    /// Larger bodies are rejected with `Error::ManifestTooLarge` without being read in full.
+    /// This is recommended when talking to untrusted registries.
+    pub fn max_manifest_bytes(mut self, max_manifest_bytes: usize) -> Self {
+        self.max_manifest_bytes = Some(max_manifest_bytes);
+        self
+    }
+
+    /// Set the maximum size of blobs buffered in memory by `Client::get_blob`.
+    ///
+    /// Larger blobs are rejected with `Error::BlobTooLarge`, up front if the registry
+    /// sends their `Content-Length`. Streaming downloads with `Client::get_blob_stream`
+    /// are not limited.
+    pub fn max_blob_bytes(mut self, max_blob_bytes: usize) -> Self {
+        self.max_blob_bytes = Some(max_blob_bytes);
+        self
+    }
+
+    /// Set a token to cancel all requests and blob downloads of the client.
+    ///
+    /// Once the token is cancelled, requests in flight and blob streams fail promptly
+    /// with `Error::Cancelled`, and so do later ones. `Client::with_cancellation_token`
+    /// sets a token for some operations only.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Set how blob downloads follow redirects, which registries commonly use to serve
+    /// blobs from object storage.
+    ///
+    /// By default, the `Authorization` header is dropped on redirects to another host.
+    /// `BlobRedirectPolicy::KeepAuth` is not supported with a client set with `http_client`.
+    pub fn blob_redirect_policy(mut self, policy: BlobRedirectPolicy) -> Self {
+        self.blob_redirect_policy = policy;
+        self
+    }
+
+    /// Set the maximum number of redirects followed by a request, 10 by default.
+    ///
+    /// Blob downloads may be redirected several times, e.g. from the registry to a CDN
+    /// and then to a signed storage URL. Going past the limit fails with
+    /// `Error::TooManyRedirects`, and `0` disables redirects altogether.
+    /// Each redirect is logged at trace level with its target host only, as the full URL
+    /// may carry a signature. With `http_client`, only blob redirects with
+    /// `BlobRedirectPolicy::KeepAuth` are subject to this limit.
+    pub fn max_redirects(mut self, max: usize) -> Self {
+        self.max_redirects = max;
+        self
+    }
+
+    /// Set the maximum number of pages fetched when listing tags, unbounded by default.
+    ///
+    /// This bounds listings of huge repositories, or of registries announcing next pages
+    /// forever. When more pages are announced, the listing stream yields the tags of the
+    /// pages fetched and then ends with `Error::PaginationTruncated`. The first page is
+    /// always fetched.
+    pub fn max_pagination_pages(mut self, max: usize) -> Self {
+        self.max_pagination_pages = Some(max);
+        self
+    }
+
+    /// Set the clock telling the current time, `SystemTime::now` by default.
+    ///
+    /// It is used to check whether cached bearer tokens have expired, so that tests can
+    /// advance time instead of sleeping until a token expires.
+    pub fn clock<F>(mut self, now: F) -> Self
+    where
+        F: Fn() -> std::time::SystemTime + Send + Sync + 'static,
+    {
+        self.clock = clock::Clock::new(now);
+        self
+    }
+
+    /// Set mirrors of the registry, such as pull-through caches, to fetch blobs from.
+    ///
+    /// `get_blob` and `has_blob` try each mirror in order, then the registry itself.
+    /// Mirrors are accessed without the credentials of the registry, and the digest of
+    /// blobs fetched from them is always verified. A mirror which isn't an http(s) URL
+    /// makes `build` fail with `Error::InvalidRegistry`.
+    pub fn mirrors(mut self, mirrors: Vec<reqwest::Url>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// Set the platform, as an `(os, arch)` pair, that `get_manifest` resolves manifest lists to.
+    ///
+    /// When set, a manifest list returned by the registry is transparently followed to the
+    /// manifest of that platform, failing with `ManifestError::PlatformNotFound` if the list
+    /// has none. When unset, the default, manifest lists are returned as-is.
+    pub fn resolve_platform(mut self, platform: Option<(&str, &str)>) -> Self {
+        self.resolve_platform = platform.map(|(os, arch)| (os.into(), arch.into()));
+        self
+    }
+
+    /// Set the tag used for references with neither a tag nor a digest,
+    /// `reference::DEFAULT_TAG` by default.
+    pub fn default_tag(mut self, tag: &str) -> Self {
+        self.default_tag = tag.into();
+        self
+    }
+
+    /// Set the namespace of the upstream registry, sent as the `ns` query parameter
+    /// by methods called with `ns: None`.
+    ///
+    /// Pull-through caches such as Harbor or Zot use it to pick the upstream registry,
+    /// e.g. `docker.io`. A namespace passed explicitly to a method takes precedence.
+    pub fn default_namespace(mut self, ns: Option<&str>) -> Self {
+        self.default_namespace = ns.map(Into::into);
+        self
+    }
+
+    /// Trust additional root certificates, in PEM format, when verifying the registry.
+    ///
+    /// The input may be a bundle of several certificates, and this can be called repeatedly.
+    /// An invalid certificate makes `build` fail with `Error::Certificate`.
+    ///
+    /// Only available with a TLS backend, such as the default `reqwest-default-tls` feature.
+    #[cfg(any(
+        feature = "reqwest-default-tls",
+        feature = "reqwest-rustls",
+        feature = "reqwest-native-tls-alpn"
+    ))]
+    pub fn add_root_certificate(mut self, pem: &[u8]) -> Self {
+        self.root_certificates.push(pem.to_vec());
+        self
+    }
+
+    /// Retry idempotent requests which failed transiently, according to `policy`.
+    ///
+    /// Requests are not retried by default.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Cache blobs fetched with `get_blob`, and serve them from the cache on later calls.
+    pub fn blob_cache(mut self, cache: impl BlobCache + 'static) -> Self {
+        self.blob_cache = Some(std::sync::Arc::new(cache));
+        self
+    }
+
+    /// Set extra headers to send with every request.
+    ///
+    /// These never override the headers computed by the client: `Accept` is ignored, and
+    /// `Authorization` and `User-Agent` are only sent when the client doesn't set them itself.
+    pub fn default_headers(mut self, headers: reqwest::header::HeaderMap) -> Self {
+        self.default_headers = headers;
+        self
+    }
+
+    /// Route all requests, including token requests to the auth realm, through a proxy.
+    pub fn proxy(mut self, proxy: reqwest::Url) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Set hosts, domains or IP networks which bypass the proxy set with `proxy`.
+    pub fn no_proxy(mut self, no_proxy: Vec<String>) -> Self {
+        self.no_proxy = no_proxy;
+        self
+    }
+
+    /// Whether to honor the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables.
+    ///
+    /// Enabled by default. A proxy set with `proxy` takes precedence over them.
+    pub fn use_env_proxy(mut self, use_env_proxy: bool) -> Self {
+        self.use_env_proxy = use_env_proxy;
+        self
+    }
+
+    /// Use an existing `reqwest::Client` for all requests, including token requests.
+    ///
+    /// The caller owns the configuration of the supplied client, so `accept_invalid_certs`,
+    /// `add_root_certificate`, the proxy settings, `timeout`, `connect_timeout` and the
+    /// connection settings are ignored. `blob_timeout` still applies per request.
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// Set the user-agent sent with every request, including token requests.
+    ///
+    /// Defaults to `USER_AGENT`; `None` leaves the header to the HTTP client.
     pub fn user_agent(mut self, user_agent: Option<ArcStr>) -> Self {
         self.user_agent = user_agent;
         self
@@ -57,47 +496,205 @@ impl Config {
 
     /// Set the password to be used for registry authentication.
     pub fn password(mut self, password: Option<CompactString>) -> Self {
-        self.password = password;
+        self.password = password.map(Into::into);
+        self
+    }
+
+    /// Get the username and password from `provider` whenever they are needed.
+    ///
+    /// This suits credentials which rotate, such as short-lived tokens: the provider is
+    /// called for each token request, including re-authentication, instead of sending
+    /// the same secret every time. It takes precedence over `username` and `password`.
+    pub fn credential_provider<F, Fut>(mut self, provider: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<(String, String)>> + Send + 'static,
+    {
+        self.credential_provider = Some(auth::CredentialProvider::new(provider));
+        self
+    }
+
+    /// Use a bearer token obtained elsewhere, for example through a cloud IAM exchange.
+    ///
+    /// The token is sent with every request without going through `Client::authenticate`,
+    /// which replaces it if called. A token rejected by the registry is replaced
+    /// through the usual flow if `auto_reauth` is enabled.
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = Some(token.into().into());
         self
     }
 
+    /// Authenticate to Google Container Registry or Artifact Registry with an OAuth2 access token.
+    ///
+    /// This sets the `oauth2accesstoken` username along with the token as password.
+    /// See the `gcr` module to obtain a token.
+    #[cfg(feature = "gcr")]
+    pub fn gcr_access_token(self, token: &str) -> Self {
+        self.username(Some(crate::gcr::ACCESS_TOKEN_USERNAME.into()))
+            .password(Some(token.into()))
+    }
+
     /// Read credentials from a JSON config file
     pub fn read_credentials<T: ::std::io::Read>(mut self, reader: T) -> Self {
         if let Ok(creds) = crate::get_credentials(reader, &self.index) {
             self.username = creds.0;
-            self.password = creds.1;
+            self.password = creds.1.map(Into::into);
         };
         self
     }
 
+    /// Configure the HTTP client to build, unless one is given with `http_client`.
+    fn http_client_builder(&self) -> Result<reqwest::ClientBuilder> {
+        let mut builder = reqwest::ClientBuilder::new();
+        #[cfg(any(
+            feature = "reqwest-default-tls",
+            feature = "reqwest-rustls",
+            feature = "reqwest-native-tls-alpn"
+        ))]
+        {
+            builder = builder.danger_accept_invalid_certs(self.accept_invalid_certs);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if self.http1_only {
+            builder = builder.http1_only();
+        } else if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if !self.use_env_proxy {
+            builder = builder.no_proxy();
+        }
+        if let Some(url) = &self.proxy {
+            let no_proxy = reqwest::NoProxy::from_string(&self.no_proxy.join(","));
+            builder = builder.proxy(reqwest::Proxy::all(url.clone())?.no_proxy(no_proxy));
+        }
+        #[cfg(any(
+            feature = "reqwest-default-tls",
+            feature = "reqwest-rustls",
+            feature = "reqwest-native-tls-alpn"
+        ))]
+        for pem in &self.root_certificates {
+            let certificates = reqwest::Certificate::from_pem_bundle(pem)
+                .map_err(|e| Error::Certificate(e.to_string()))?;
+            if certificates.is_empty() {
+                return Err(Error::Certificate("no certificate found in PEM".into()));
+            }
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        Ok(builder)
+    }
+
+    /// HTTP client for requests to other services than the registry, such as credential
+    /// endpoints: the one given with `http_client`, or one with the configured settings.
+    #[cfg(any(feature = "ecr", feature = "gcr"))]
+    pub(crate) fn auxiliary_http_client(&self) -> Result<reqwest::Client> {
+        if let Some(client) = &self.http_client {
+            return Ok(client.clone());
+        }
+        let mut builder = self.http_client_builder()?;
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent.as_str());
+        }
+        Ok(builder.build()?)
+    }
+
     /// Return a `Client` to interact with a v2 registry.
-    pub fn build(self) -> Result<Client> {
-        let base = if self.insecure_registry {
-            format!("http://{}", self.index).into()
-        } else {
-            format!("https://{}", self.index).into()
+    ///
+    /// Fails with `Error::InvalidRegistry` if the registry is not a valid host,
+    /// for example if it is empty, or has a path or credentials.
+    pub fn build(mut self) -> Result<Client> {
+        let (scheme, index, host) = normalize_registry(&self.index)?;
+        let scheme = match scheme {
+            Some(scheme) => scheme,
+            None if self.insecure_registry => "http",
+            None => "https",
+        };
+        let base = format!("{}://{}{}", scheme, index, self.path_prefix).into();
+        trace!("Built client for {}: endpoint {}", index, base);
+        let keep_auth = self.blob_redirect_policy == BlobRedirectPolicy::KeepAuth;
+        let (client, blob_redirect_client) = match self.http_client.take() {
+            Some(client) => (client, None),
+            None => {
+                let max_redirects = self.max_redirects;
+                let builder = self.http_client_builder()?;
+                let client = builder
+                    .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+                        // Redirects of blob requests are followed by the client itself,
+                        // as reqwest always strips credentials on cross-host redirects.
+                        let from_blob = attempt
+                            .previous()
+                            .first()
+                            .is_some_and(|url| blobs::is_blob_path(url.path()));
+                        if keep_auth && from_blob {
+                            attempt.stop()
+                        } else if attempt.previous().len() > max_redirects {
+                            attempt.error(Error::TooManyRedirects(max_redirects))
+                        } else {
+                            trace!(
+                                "Following redirect to {}",
+                                attempt.url().host_str().unwrap_or_default()
+                            );
+                            attempt.follow()
+                        }
+                    }))
+                    .build()?;
+                // Each hop of a blob redirect is then a request of its own.
+                let blob_redirect_client = match keep_auth {
+                    true => Some(
+                        self.http_client_builder()?
+                            .redirect(reqwest::redirect::Policy::none())
+                            .build()?,
+                    ),
+                    false => None,
+                };
+                (client, blob_redirect_client)
+            }
         };
-        trace!(
-            "Built client for {}: endpoint {} - user {:?}",
-            self.index,
-            base,
-            self.username
-        );
-        let creds = match (self.username, self.password) {
-            (None, None) => None,
-            (u, p) => Some((
+
+        let creds = match (self.credential_provider, self.username, self.password) {
+            (Some(provider), _, _) => Some(provider),
+            (None, None, None) => None,
+            (None, u, p) => Some(auth::CredentialProvider::fixed(
                 u.unwrap_or_else(|| "".into()),
-                p.unwrap_or_else(|| "".into()),
+                p.unwrap_or_default(),
             )),
         };
-        let client = reqwest::ClientBuilder::new()
-            .danger_accept_invalid_certs(self.accept_invalid_certs)
-            .build()?;
 
+        let mirrors = self
+            .mirrors
+            .iter()
+            .map(|mirror| match mirror.scheme() {
+                "http" | "https" if mirror.has_host() => {
+                    Ok(mirror.as_str().trim_end_matches('/').into())
+                }
+                _ => Err(Error::InvalidRegistry(mirror.to_string())),
+            })
+            .collect::<Result<Vec<ArcStr>>>()?;
+
+        // GCR incorrectly parses `q` parameters, so we don't send them by default.
+        // Bug: https://issuetracker.google.com/issues/159827510.
+        let quality_weights = self
+            .accept_quality_weights
+            .unwrap_or_else(|| !(host == "gcr.io" || host.ends_with(".gcr.io")));
         let accepted_types = match self.accepted_types {
+            Some(a) if self.accept_quality_weights == Some(false) => {
+                a.into_iter().map(|(t, _)| (t, None)).collect()
+            }
             Some(a) => a,
-            None => match self.index == "gcr.io" || self.index.ends_with(".gcr.io") {
-                false => vec![
+            None => match quality_weights {
+                true => vec![
                     // accept header types and their q value, as documented in
                     // https://tools.ietf.org/html/rfc7231#section-5.3.2
                     (MediaTypes::ManifestV2S2, Some(0.5)),
@@ -106,10 +703,7 @@ impl Config {
                     (MediaTypes::OciV1ManifestList, Some(0.3)),
                     (MediaTypes::OciV1Manifest, Some(0.3)),
                 ],
-                // GCR incorrectly parses `q` parameters, so we use special Accept for it.
-                // Bug: https://issuetracker.google.com/issues/159827510.
-                // TODO: when bug is fixed, this workaround should be removed.
-                true => vec![
+                false => vec![
                     (MediaTypes::ManifestV2S2, None),
                     (MediaTypes::ManifestV2S1Signed, None),
                     (MediaTypes::ManifestList, None),
@@ -118,13 +712,48 @@ impl Config {
                 ],
             },
         };
+        let no_auth = self.no_auth;
         let c = Client {
             base_url: base,
             credentials: creds,
             user_agent: self.user_agent,
-            auth: None,
+            auth: auth::SharedAuth::new(
+                self.bearer_token
+                    .filter(|_| !no_auth)
+                    .map(|token| auth::Auth::Bearer(auth::BearerAuth::from_token(token))),
+            ),
+            token_cache: Default::default(),
             client,
+            blob_redirect_client,
             accepted_types,
+            auto_reauth: self.auto_reauth && !self.no_auth,
+            no_auth: self.no_auth,
+            require_v2_header: self.require_v2_header,
+            oauth2: self.oauth2,
+            anonymous_fallback: self.anonymous_fallback,
+            prefer_basic: self.prefer_basic,
+            blob_timeout: self.blob_timeout,
+            auth_timeout: self.auth_timeout,
+            default_headers: self.default_headers,
+            retry: self.retry,
+            last_rate_limit: Default::default(),
+            blob_cache: self.blob_cache,
+            verify_digests: self.verify_digests,
+            digest_algorithm: self.digest_algorithm,
+            dry_run: self.dry_run,
+            max_manifest_bytes: self.max_manifest_bytes,
+            max_blob_bytes: self.max_blob_bytes,
+            blob_redirect_policy: self.blob_redirect_policy,
+            max_redirects: self.max_redirects,
+            max_pagination_pages: self.max_pagination_pages,
+            clock: self.clock,
+            default_tag: self.default_tag,
+            default_namespace: self.default_namespace,
+            #[cfg(feature = "schema1-signatures")]
+            verify_schema1_signatures: self.verify_schema1_signatures,
+            mirrors,
+            resolve_platform: self.resolve_platform,
+            cancellation_token: self.cancellation_token,
         };
         Ok(c)
     }
@@ -135,12 +764,158 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             index: "registry-1.docker.io".into(),
+            path_prefix: String::new(),
             insecure_registry: false,
             accept_invalid_certs: false,
             accepted_types: None,
+            accept_quality_weights: None,
+            auto_reauth: false,
+            no_auth: false,
+            require_v2_header: false,
+            oauth2: false,
+            anonymous_fallback: false,
+            prefer_basic: false,
+            timeout: None,
+            connect_timeout: None,
+            http2_prior_knowledge: false,
+            http1_only: false,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            blob_timeout: None,
+            auth_timeout: auth::DEFAULT_AUTH_TIMEOUT,
+            http_client: None,
+            #[cfg(any(
+                feature = "reqwest-default-tls",
+                feature = "reqwest-rustls",
+                feature = "reqwest-native-tls-alpn"
+            ))]
+            root_certificates: Vec::new(),
+            proxy: None,
+            no_proxy: Vec::new(),
+            use_env_proxy: true,
+            default_headers: Default::default(),
+            retry: None,
+            blob_cache: None,
+            verify_digests: true,
+            digest_algorithm: Algorithm::default(),
+            dry_run: false,
+            max_manifest_bytes: None,
+            max_blob_bytes: None,
+            blob_redirect_policy: BlobRedirectPolicy::default(),
+            max_redirects: blobs::DEFAULT_MAX_REDIRECTS,
+            max_pagination_pages: None,
+            clock: clock::Clock::default(),
+            default_tag: crate::reference::DEFAULT_TAG.into(),
+            default_namespace: None,
+            #[cfg(feature = "schema1-signatures")]
+            verify_schema1_signatures: false,
+            mirrors: Vec::new(),
+            resolve_platform: None,
+            bearer_token: None,
+            cancellation_token: None,
             user_agent: Some(crate::USER_AGENT.clone()),
             username: None,
             password: None,
+            credential_provider: None,
         }
     }
 }
+
+/// Normalize a registry into its `host[:port]`, also returning the scheme it was given with,
+/// if any, and the host alone.
+fn normalize_registry(registry: &str) -> Result<(Option<&'static str>, String, String)> {
+    let invalid = || Error::InvalidRegistry(registry.to_string());
+    let trimmed = registry.trim();
+    let (scheme, trimmed) = match trimmed.split_once("://") {
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("https") => (Some("https"), rest),
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => (Some("http"), rest),
+        _ => (None, trimmed),
+    };
+    let trimmed = trimmed.strip_suffix('/').unwrap_or(trimmed);
+    if trimmed.is_empty() || trimmed.contains(['/', '?', '#', '@']) {
+        return Err(invalid());
+    }
+
+    let url = reqwest::Url::parse(&format!("https://{}", trimmed)).map_err(|_| invalid())?;
+    let host = url.host_str().ok_or_else(invalid)?.to_string();
+    // The port is kept even if it is the default one of a scheme,
+    // as the scheme is not known yet.
+    let index = match trimmed.rsplit_once(':') {
+        Some((_, port)) if !port.ends_with(']') => {
+            let port: u16 = port.parse().map_err(|_| invalid())?;
+            format!("{}:{}", host, port)
+        }
+        _ => host.clone(),
+    };
+    Ok((scheme, index, host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[cfg(any(
+        feature = "reqwest-default-tls",
+        feature = "reqwest-rustls",
+        feature = "reqwest-native-tls-alpn"
+    ))]
+    #[test]
+    fn test_add_root_certificate() {
+        let pem = include_bytes!("../../tests/fixtures/root_ca.pem");
+        let client = Client::configure()
+            .add_root_certificate(pem)
+            .add_root_certificate(pem)
+            .build();
+        assert!(client.is_ok());
+    }
+
+    #[cfg(any(
+        feature = "reqwest-default-tls",
+        feature = "reqwest-rustls",
+        feature = "reqwest-native-tls-alpn"
+    ))]
+    #[test]
+    fn test_add_invalid_root_certificate() {
+        let client = Client::configure()
+            .add_root_certificate(b"not a certificate")
+            .build();
+        assert!(matches!(client, Err(Error::Certificate(_))));
+    }
+
+    #[test_case("quay.io" => Some("quay.io".to_string()); "host")]
+    #[test_case("127.0.0.1:5000" => Some("127.0.0.1:5000".to_string()); "with port")]
+    #[test_case("registry.example.com:443" => Some("registry.example.com:443".to_string()); "with default port")]
+    #[test_case("[::1]:5000" => Some("[::1]:5000".to_string()); "ipv6")]
+    #[test_case(" Quay.IO/ " => Some("quay.io".to_string()); "normalized")]
+    #[test_case("https://quay.io" => Some("quay.io".to_string()); "with scheme")]
+    #[test_case("" => None; "empty")]
+    #[test_case("quay.io/library" => None; "with path")]
+    #[test_case("user:secret@quay.io" => None; "with credentials")]
+    #[test_case("quay.io:port" => None; "bad port")]
+    #[test_case("quay.io:" => None; "empty port")]
+    #[test_case("bad host" => None; "bad host")]
+    #[test_case("ftp://quay.io" => None; "unsupported scheme")]
+    fn test_normalize_registry(registry: &str) -> Option<String> {
+        normalize_registry(registry).ok().map(|(_, index, _)| index)
+    }
+
+    #[test_case("quay.io", false => "https://quay.io"; "default")]
+    #[test_case("quay.io", true => "http://quay.io"; "insecure")]
+    #[test_case("http://localhost:5000", false => "http://localhost:5000"; "http scheme")]
+    #[test_case("HTTPS://quay.io", true => "https://quay.io"; "https scheme")]
+    fn test_registry_scheme(registry: &str, insecure: bool) -> String {
+        let client = Config::default()
+            .registry(registry)
+            .insecure_registry(insecure)
+            .build()
+            .unwrap();
+        client.base_url.to_string()
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_registry() {
+        let err = Config::default().registry("").build().unwrap_err();
+        assert!(matches!(err, Error::InvalidRegistry(_)));
+    }
+}
@@ -6,6 +6,7 @@ use std::str;
 #[derive(Display, Clone, Debug)]
 pub enum DigestAlgorithm {
     Sha256(sha2::Sha256),
+    Sha512(sha2::Sha512),
 }
 
 impl std::str::FromStr for DigestAlgorithm {
@@ -14,14 +15,71 @@ impl std::str::FromStr for DigestAlgorithm {
     fn from_str(name: &str) -> Result<Self, Self::Err> {
         match name {
             "sha256" => Ok(DigestAlgorithm::Sha256(sha2::Sha256::new())),
+            "sha512" => Ok(DigestAlgorithm::Sha512(sha2::Sha512::new())),
             _ => Err(ContentDigestError::AlgorithmUnknown(name.to_string())),
         }
     }
 }
 
+/// Algorithm used to compute digests of content, such as uploaded blobs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn hasher(self) -> DigestAlgorithm {
+        match self {
+            Algorithm::Sha256 => DigestAlgorithm::Sha256(sha2::Sha256::new()),
+            Algorithm::Sha512 => DigestAlgorithm::Sha512(sha2::Sha512::new()),
+        }
+    }
+
+    /// Compute the digest of some content, as `<algorithm>:<hex>`.
+    pub fn digest(self, input: &[u8]) -> String {
+        let mut hasher = self.hasher();
+        hasher.update(input);
+        hasher.digest()
+    }
+}
+
+/// Compute the sha256 digest of some content, such as a manifest, as `sha256:<hex>`.
+///
+/// Use `Algorithm::digest` for other algorithms.
+pub fn digest_of(input: &[u8]) -> String {
+    Algorithm::Sha256.digest(input)
+}
+
+/// Get the digest a registry sent in the `Docker-Content-Digest` header, if any.
+///
+/// The header name is matched whatever its case, and the value is trimmed.
+/// An empty value, or one which is not visible ASCII, is ignored.
+pub(crate) fn content_digest_from_headers(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    let value = headers.get("docker-content-digest")?;
+    match value.to_str().map(str::trim) {
+        Ok("") => None,
+        Ok(digest) => Some(digest.to_string()),
+        Err(_) => {
+            debug!("ignoring invalid Docker-Content-Digest header {:?}", value);
+            None
+        }
+    }
+}
+
+impl std::fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Algorithm::Sha256 => write!(f, "sha256"),
+            Algorithm::Sha512 => write!(f, "sha512"),
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ContentDigestError {
-    #[error("digest {0} does not have algorithm prefix")]
+    #[error("invalid digest: {0}")]
     BadDigest(String),
     #[error("unknown algorithm: {0}")]
     AlgorithmUnknown(String),
@@ -34,6 +92,7 @@ pub enum ContentDigestError {
 pub struct ContentDigest {
     digest: String,
     algorithm: DigestAlgorithm,
+    enabled: bool,
 }
 
 impl ContentDigest {
@@ -42,25 +101,83 @@ impl ContentDigest {
     /// Success depends on
     /// - the string having an "algorithm:" prefix
     /// - the algorithm being supported by DigestAlgorithm
+    /// - the encoded part being lowercase hex of the length the algorithm produces,
+    ///   64 characters for sha256 and 128 for sha512
     pub fn try_new(digest: &str) -> std::result::Result<Self, ContentDigestError> {
-        let digest_split = digest.split(':').collect::<Vec<&str>>();
+        let bad_digest =
+            |reason: &str| ContentDigestError::BadDigest(format!("'{digest}' {reason}"));
+        let (algorithm_name, encoded) = digest
+            .split_once(':')
+            .ok_or_else(|| bad_digest("has no algorithm prefix"))?;
+        if algorithm_name.is_empty() {
+            return Err(bad_digest("has no algorithm prefix"));
+        }
 
-        if digest_split.len() != 2 {
-            return Err(ContentDigestError::BadDigest(digest.to_string()));
+        let algorithm: DigestAlgorithm = algorithm_name.parse()?;
+        let len = match algorithm {
+            DigestAlgorithm::Sha256(_) => 64,
+            DigestAlgorithm::Sha512(_) => 128,
+        };
+        if !encoded
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        {
+            return Err(bad_digest("is not lowercase hex"));
+        }
+        if encoded.len() != len {
+            return Err(bad_digest(&format!(
+                "has {} hex characters, {} takes {}",
+                encoded.len(),
+                algorithm_name,
+                len
+            )));
         }
 
-        let algorithm = std::str::FromStr::from_str(digest_split[0])?;
         Ok(ContentDigest {
             digest: digest.to_string(),
             algorithm,
+            enabled: true,
         })
     }
 
+    /// Enable or disable verification. When disabled, content is not hashed
+    /// and `verify` always succeeds.
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Whether the content is actually verified.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Compute the digest of some content, using the algorithm of `reference` if it is
+    /// a digest with a supported algorithm, and `default` otherwise.
+    pub fn compute(reference: &str, input: &[u8], default: Algorithm) -> String {
+        let mut algorithm = reference
+            .split_once(':')
+            .and_then(|(algorithm, _)| algorithm.parse().ok())
+            .unwrap_or_else(|| default.hasher());
+        algorithm.update(input);
+        algorithm.digest()
+    }
+
+    /// Return the expected digest string, including the algorithm prefix
+    pub fn as_str(&self) -> &str {
+        &self.digest
+    }
+
     pub fn update(&mut self, input: &[u8]) {
-        self.algorithm.update(input)
+        if self.enabled {
+            self.algorithm.update(input)
+        }
     }
 
     pub fn verify(self) -> std::result::Result<(), ContentDigestError> {
+        if !self.enabled {
+            return Ok(());
+        }
         let digest = self.algorithm.digest();
         if digest != self.digest {
             return Err(ContentDigestError::Verify {
@@ -84,14 +201,17 @@ impl DigestAlgorithm {
             DigestAlgorithm::Sha256(hash) => {
                 hash.update(input);
             }
+            DigestAlgorithm::Sha512(hash) => {
+                hash.update(input);
+            }
         }
     }
 
     fn digest(self) -> String {
-        let (algo, digest) = match self {
-            DigestAlgorithm::Sha256(hash) => ("sha256", hash.finalize()),
-        };
-        format!("{}:{:x}", algo, &digest)
+        match self {
+            DigestAlgorithm::Sha256(hash) => format!("sha256:{:x}", hash.finalize()),
+            DigestAlgorithm::Sha512(hash) => format!("sha512:{:x}", hash.finalize()),
+        }
     }
 }
 
@@ -99,16 +219,59 @@ impl DigestAlgorithm {
 mod tests {
     use super::*;
     use sha2;
+    use test_case::test_case;
 
     type Fallible<T> = Result<T, crate::Error>;
 
+    #[test]
+    fn content_digest_header() {
+        use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+        let headers = |name: &str, value: &[u8]| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_bytes(value).unwrap(),
+            );
+            headers
+        };
+
+        assert_eq!(
+            content_digest_from_headers(&headers("Docker-Content-Digest", b" sha256:abc ")),
+            Some("sha256:abc".to_string())
+        );
+        assert_eq!(
+            content_digest_from_headers(&headers("DOCKER-CONTENT-DIGEST", b"sha256:abc")),
+            Some("sha256:abc".to_string())
+        );
+        assert_eq!(
+            content_digest_from_headers(&headers("docker-content-digest", b"")),
+            None
+        );
+        assert_eq!(
+            content_digest_from_headers(&headers("docker-content-digest", b"sha256:\xff")),
+            None
+        );
+        assert_eq!(content_digest_from_headers(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn digest_of_content() {
+        assert_eq!(
+            digest_of(b"somecontent"),
+            "sha256:d5a3477d91583e65a7aba6f6db7a53e2de739bc7bf8f4a08f0df0457b637f1fb"
+        );
+        assert_eq!(
+            Algorithm::Sha512.digest(b"somecontent"),
+            "sha512:075acbafc43b4285903d2db3db7be7cebe056d50fba6e8a9f9bcdf7f3a2bba841786c29fa385780cd0bb631e0d44be60a863f9a088c16b131ea94f4ca180844d"
+        );
+    }
+
     #[test]
     fn try_new_succeeds_with_correct_digest() -> Fallible<()> {
-        for correct_digest in
-            &["sha256:0000000000000000000000000000000000000000000000000000000000000000"]
-        {
-            ContentDigest::try_new(correct_digest)?;
-        }
+        let correct_digest =
+            "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+        ContentDigest::try_new(correct_digest)?;
 
         Ok(())
     }
@@ -129,6 +292,21 @@ mod tests {
         }
     }
 
+    #[test_case("0000000000000000000000000000000000000000000000000000000000000000" => "'0000000000000000000000000000000000000000000000000000000000000000' has no algorithm prefix"; "missing prefix")]
+    #[test_case(":0000000000000000000000000000000000000000000000000000000000000000" => "':0000000000000000000000000000000000000000000000000000000000000000' has no algorithm prefix"; "empty algorithm")]
+    #[test_case("sha256:" => "'sha256:' has 0 hex characters, sha256 takes 64"; "empty hex")]
+    #[test_case("sha256:0000" => "'sha256:0000' has 4 hex characters, sha256 takes 64"; "short sha256")]
+    #[test_case("sha512:0000000000000000000000000000000000000000000000000000000000000000" => "'sha512:0000000000000000000000000000000000000000000000000000000000000000' has 64 hex characters, sha512 takes 128"; "sha256 length for sha512")]
+    #[test_case("sha256:D5A3477D91583E65A7ABA6F6DB7A53E2DE739BC7BF8F4A08F0DF0457B637F1FB" => "'sha256:D5A3477D91583E65A7ABA6F6DB7A53E2DE739BC7BF8F4A08F0DF0457B637F1FB' is not lowercase hex"; "uppercase")]
+    #[test_case("sha256:d5a3477d91583e65a7aba6f6db7a53e2de739bc7bf8f4a08f0df0457b637f1fz" => "'sha256:d5a3477d91583e65a7aba6f6db7a53e2de739bc7bf8f4a08f0df0457b637f1fz' is not lowercase hex"; "not hex")]
+    #[test_case("sha256:0000000000000000000000000000000000000000000000000000000000000000:00" => "'sha256:0000000000000000000000000000000000000000000000000000000000000000:00' is not lowercase hex"; "second separator")]
+    fn try_new_rejects_malformed_digest(digest: &str) -> String {
+        match ContentDigest::try_new(digest).unwrap_err().into() {
+            crate::Error::InvalidDigest(reason) => reason,
+            err => panic!("expected InvalidDigest, got {:?}", err),
+        }
+    }
+
     #[test]
     fn verify_succeeds_with_same_content() -> Fallible<()> {
         let blob: &[u8] = b"somecontent";
@@ -149,6 +327,67 @@ mod tests {
         content_digest.verify().map_err(Into::into)
     }
 
+    #[test]
+    fn verify_succeeds_with_same_content_sha512() -> Fallible<()> {
+        let blob: &[u8] = b"somecontent";
+        let mut content_digest = ContentDigest::try_new(
+            "sha512:075acbafc43b4285903d2db3db7be7cebe056d50fba6e8a9f9bcdf7f3a2bba841786c29fa385780cd0bb631e0d44be60a863f9a088c16b131ea94f4ca180844d",
+        )?;
+        content_digest.update(blob);
+        content_digest.verify().map_err(Into::into)
+    }
+
+    #[test]
+    fn verify_fails_with_different_content_sha512() -> Fallible<()> {
+        let mut content_digest = ContentDigest::try_new(
+            "sha512:075acbafc43b4285903d2db3db7be7cebe056d50fba6e8a9f9bcdf7f3a2bba841786c29fa385780cd0bb631e0d44be60a863f9a088c16b131ea94f4ca180844d",
+        )?;
+        content_digest.update(b"someothercontent");
+        if content_digest.verify().is_ok() {
+            panic!("expected verify to fail for a different blob");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn try_new_fails_with_unknown_algorithm() {
+        let err: crate::Error = ContentDigest::try_new("md5:d41d8cd98f00b204e9800998ecf8427e")
+            .unwrap_err()
+            .into();
+        match err {
+            crate::Error::UnsupportedDigestAlgorithm(algorithm) => assert_eq!(algorithm, "md5"),
+            err => panic!("expected UnsupportedDigestAlgorithm, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn verify_succeeds_when_disabled() -> Fallible<()> {
+        let mut content_digest = ContentDigest::try_new(
+            "sha256:d5a3477d91583e65a7aba6f6db7a53e2de739bc7bf8f4a08f0df0457b637f1fb",
+        )?
+        .enabled(false);
+        content_digest.update(b"someothercontent");
+        content_digest.verify().map_err(Into::into)
+    }
+
+    #[test]
+    fn compute_uses_algorithm_of_reference() {
+        let sha512 = "sha512:075acbafc43b4285903d2db3db7be7cebe056d50fba6e8a9f9bcdf7f3a2bba841786c29fa385780cd0bb631e0d44be60a863f9a088c16b131ea94f4ca180844d";
+        assert_eq!(
+            ContentDigest::compute("latest", b"somecontent", Algorithm::Sha256),
+            "sha256:d5a3477d91583e65a7aba6f6db7a53e2de739bc7bf8f4a08f0df0457b637f1fb"
+        );
+        assert_eq!(
+            ContentDigest::compute("latest", b"somecontent", Algorithm::Sha512),
+            sha512
+        );
+        assert_eq!(
+            ContentDigest::compute("sha512:0000", b"somecontent", Algorithm::Sha256),
+            sha512
+        );
+        assert_eq!(Algorithm::Sha512.digest(b"somecontent"), sha512);
+    }
+
     #[test]
     fn verify_fails_with_different_content() -> Fallible<()> {
         let blob: &[u8] = b"somecontent";
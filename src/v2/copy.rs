@@ -0,0 +1,204 @@
+//! Copying images between repositories and registries.
+
+use crate::errors::{Error, Result};
+use crate::mediatypes::MediaTypes;
+use crate::reference::Reference;
+use crate::v2::manifest::{ManifestList, ManifestSchema1Signed, ManifestSchema2Spec};
+use crate::v2::*;
+use futures::future::BoxFuture;
+use reqwest::Body;
+
+/// Blobs of a copy, as planned by `Client::plan_copy`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CopyPlan {
+    digest: String,
+    to_upload: Vec<String>,
+    to_mount: Vec<String>,
+    already_present: Vec<String>,
+}
+
+impl CopyPlan {
+    /// Digest of the manifest the copy pushes to the destination.
+    pub fn digest(&self) -> &str {
+        &self.digest
+    }
+
+    /// Digests of the blobs to upload to the destination.
+    pub fn to_upload(&self) -> &[String] {
+        &self.to_upload
+    }
+
+    /// Digests of the blobs to mount from the source repository, on the same registry.
+    ///
+    /// Registries which cannot mount a blob have it uploaded instead.
+    pub fn to_mount(&self) -> &[String] {
+        &self.to_mount
+    }
+
+    /// Digests of the blobs already present in the destination.
+    pub fn already_present(&self) -> &[String] {
+        &self.already_present
+    }
+
+    fn contains(&self, digest: &str) -> bool {
+        [&self.to_upload, &self.to_mount, &self.already_present]
+            .iter()
+            .any(|digests| digests.iter().any(|d| d == digest))
+    }
+}
+
+impl Client {
+    /// Copy an image from `src`, read with this client, to `dst`, written with `dst_client`.
+    ///
+    /// Blobs already present in the destination are skipped, and blobs are mounted
+    /// instead of uploaded when both repositories are on the same registry.
+    /// Manifest lists are copied along with all the manifests they reference.
+    /// References without a version use the default tag of their client.
+    /// Returns the digest of the manifest pushed to the destination.
+    ///
+    /// With `Config::dry_run` set on `dst_client`, nothing is written to the destination.
+    pub async fn copy_image(
+        &self,
+        src: &Reference,
+        dst: &Reference,
+        dst_client: &Client,
+    ) -> Result<String> {
+        Ok(self.copy_image_planned(src, dst, dst_client).await?.digest)
+    }
+
+    /// Plan a copy with `copy_image`, without writing anything to the destination.
+    ///
+    /// The destination is checked for existing blobs as it would be by a real copy,
+    /// which is planned as if `dst_client` had `Config::dry_run` set.
+    pub async fn plan_copy(
+        &self,
+        src: &Reference,
+        dst: &Reference,
+        dst_client: &Client,
+    ) -> Result<CopyPlan> {
+        let dst_client = Client {
+            dry_run: true,
+            ..dst_client.clone()
+        };
+        self.copy_image_planned(src, dst, &dst_client).await
+    }
+
+    async fn copy_image_planned(
+        &self,
+        src: &Reference,
+        dst: &Reference,
+        dst_client: &Client,
+    ) -> Result<CopyPlan> {
+        let src_version = src.version_or(&self.default_tag);
+        let dst_version = dst.version_or(&dst_client.default_tag);
+        let mut plan = CopyPlan::default();
+        plan.digest = self
+            .copy_manifest(
+                src.repository(),
+                &src_version,
+                dst.repository(),
+                &dst_version,
+                dst_client,
+                &mut plan,
+            )
+            .await?;
+        Ok(plan)
+    }
+
+    fn copy_manifest<'a>(
+        &'a self,
+        src_name: &'a str,
+        src_reference: &'a str,
+        dst_name: &'a str,
+        dst_reference: &'a str,
+        dst_client: &'a Client,
+        plan: &'a mut CopyPlan,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let (body, media_type, _) = self
+                .get_raw_manifest_and_metadata(src_name, src_reference, None)
+                .await?;
+
+            let blobs: Vec<String> = match media_type {
+                MediaTypes::ManifestList | MediaTypes::OciV1ManifestList => {
+                    let list: ManifestList = serde_json::from_slice(&body)?;
+                    for manifest in &list.manifests {
+                        self.copy_manifest(
+                            src_name,
+                            &manifest.digest,
+                            dst_name,
+                            &manifest.digest,
+                            dst_client,
+                            plan,
+                        )
+                        .await?;
+                    }
+                    vec![]
+                }
+                MediaTypes::ManifestV2S2 | MediaTypes::OciV1Manifest => {
+                    let spec: ManifestSchema2Spec = serde_json::from_slice(&body)?;
+                    // Foreign layers are not stored by registries, so they are not copied.
+                    std::iter::once(spec.config().digest.clone())
+                        .chain(
+                            spec.layers()
+                                .filter(|l| !l.is_foreign())
+                                .map(|l| l.digest().to_string()),
+                        )
+                        .collect()
+                }
+                MediaTypes::ManifestV2S1Signed => {
+                    let manifest: ManifestSchema1Signed = serde_json::from_slice(&body)?;
+                    manifest.get_layers().map(str::to_string).collect()
+                }
+                unsupported => return Err(Error::UnsupportedMediaType(unsupported)),
+            };
+
+            for digest in blobs {
+                if !plan.contains(&digest) {
+                    self.copy_blob(src_name, dst_name, digest, dst_client, plan)
+                        .await?;
+                }
+            }
+
+            dst_client
+                .put_manifest(dst_name, dst_reference, &media_type, body)
+                .await
+        })
+    }
+
+    async fn copy_blob(
+        &self,
+        src_name: &str,
+        dst_name: &str,
+        digest: String,
+        dst_client: &Client,
+        plan: &mut CopyPlan,
+    ) -> Result<()> {
+        if dst_client.has_blob_here(dst_name, &digest, None).await? {
+            trace!("Blob {} already present in {}", digest, dst_name);
+            plan.already_present.push(digest);
+            return Ok(());
+        }
+
+        let session = if self.base_url == dst_client.base_url {
+            match dst_client.mount_blob(dst_name, &digest, src_name).await? {
+                None => {
+                    plan.to_mount.push(digest);
+                    return Ok(());
+                }
+                Some(session) => session,
+            }
+        } else {
+            dst_client.start_upload(dst_name).await?
+        };
+
+        if !dst_client.dry_run {
+            let blob = self.get_blob_stream(src_name, &digest, None).await?;
+            dst_client
+                .finish_upload(session, &digest, Body::wrap_stream(blob))
+                .await?;
+        }
+        plan.to_upload.push(digest);
+        Ok(())
+    }
+}
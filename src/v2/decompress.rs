@@ -0,0 +1,143 @@
+//! Decompression of layer blobs, enabled with the `decompress` cargo feature.
+
+use std::io::{self, Read};
+
+use crate::errors::{Error, Result};
+use crate::mediatypes::MediaTypes;
+use crate::v2::*;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression of a layer blob.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// An uncompressed tar.
+    None,
+    /// A gzip-compressed tar.
+    Gzip,
+    /// A zstd-compressed tar.
+    Zstd,
+}
+
+impl Compression {
+    /// Compression of a layer with the given media type, if it is a known layer type.
+    pub fn from_media_type(media_type: &MediaTypes) -> Option<Self> {
+        match media_type {
+            MediaTypes::OciV1LayerTar => Some(Compression::None),
+            MediaTypes::ImageLayerTgz
+            | MediaTypes::ForeignImageLayerTgz
+            | MediaTypes::OciV1LayerTgz
+            | MediaTypes::OciV1NonDistributableLayerTgz => Some(Compression::Gzip),
+            MediaTypes::OciV1LayerTzst => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Detect the compression of a blob from its leading magic bytes.
+    pub fn detect(blob: &[u8]) -> Self {
+        if blob.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if blob.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Decompress a blob.
+    pub fn decompress(self, blob: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        match self {
+            Compression::None => out.extend_from_slice(blob),
+            Compression::Gzip => {
+                libflate::gzip::MultiDecoder::new(blob)
+                    .and_then(|mut decoder| decoder.read_to_end(&mut out))
+                    .map_err(Error::Decompress)?;
+            }
+            Compression::Zstd => {
+                // A zstd stream may consist of several concatenated frames.
+                let mut input = blob;
+                while !input.is_empty() {
+                    let mut decoder = ruzstd::StreamingDecoder::new(&mut input).map_err(|e| {
+                        Error::Decompress(io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+                    })?;
+                    decoder.read_to_end(&mut out).map_err(Error::Decompress)?;
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl Client {
+    /// Retrieve a layer blob and decompress it, returning the tar it contains.
+    ///
+    /// The compression is taken from the media type of the response, or else detected
+    /// from the content of the blob. The digest is verified before decompressing,
+    /// as it covers the compressed blob.
+    pub async fn get_blob_decompressed(
+        &self,
+        name: &str,
+        digest: &str,
+        ns: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        let resp = self.get_blob_response(name, digest, ns).await?;
+        let compression = resp
+            .media_type()
+            .as_ref()
+            .and_then(Compression::from_media_type);
+        let blob = resp.bytes().await?;
+
+        let compression = compression.unwrap_or_else(|| Compression::detect(&blob));
+        trace!("Decompressing blob {} as {:?}", digest, compression);
+        compression.decompress(&blob)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    // "hello\n", compressed with `gzip` and `zstd`.
+    const GZIP: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0xe7, 0x02, 0x00, 0x20, 0x30, 0x3a, 0x36, 0x06, 0x00, 0x00, 0x00,
+    ];
+    const ZSTD: &[u8] = &[
+        0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x58, 0x31, 0x00, 0x00, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x0a,
+        0x53, 0x88, 0xbd, 0x91,
+    ];
+
+    #[test_case(b"hello\n" => Compression::None; "none")]
+    #[test_case(GZIP => Compression::Gzip; "gzip")]
+    #[test_case(ZSTD => Compression::Zstd; "zstd")]
+    fn test_detect(blob: &[u8]) -> Compression {
+        Compression::detect(blob)
+    }
+
+    #[test_case(Compression::None, b"hello\n"; "none")]
+    #[test_case(Compression::Gzip, GZIP; "gzip")]
+    #[test_case(Compression::Zstd, ZSTD; "zstd")]
+    fn test_decompress(compression: Compression, blob: &[u8]) {
+        assert_eq!(compression.decompress(blob).unwrap(), b"hello\n");
+    }
+
+    #[test]
+    fn test_decompress_zstd_frames() {
+        let blob = [ZSTD, ZSTD].concat();
+        assert_eq!(
+            Compression::Zstd.decompress(&blob).unwrap(),
+            b"hello\nhello\n"
+        );
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        assert!(matches!(
+            Compression::Zstd.decompress(b"not zstd"),
+            Err(Error::Decompress(_))
+        ));
+    }
+}
@@ -2,6 +2,8 @@ use std::collections::HashMap;
 
 use compact_str::CompactString;
 
+use super::ContainerConfig;
+
 /// Manifest version 2 schema 1, signed.
 ///
 /// Specification is at <https://docs.docker.com/registry/spec/manifest-v2-1/>.
@@ -15,16 +17,16 @@ pub struct ManifestSchema1Signed {
     #[serde(rename = "fsLayers")]
     fs_layers: Vec<S1Layer>,
     history: Vec<V1Compat>,
-    signatures: Vec<Signature>,
+    pub(super) signatures: Vec<Signature>,
+    #[serde(skip)]
+    pub(crate) raw_manifest: Vec<u8>,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
-struct Signature {
-    // TODO(lucab): switch to jsonwebtokens crate
-    // https://github.com/Keats/rust-jwt/pull/23
-    header: serde_json::Value,
-    signature: String,
-    protected: String,
+pub(super) struct Signature {
+    pub(super) header: serde_json::Value,
+    pub(super) signature: String,
+    pub(super) protected: String,
 }
 
 /// Compatibility entry for version 1 manifest interoperability.
@@ -34,6 +36,26 @@ struct V1Compat {
     v1_compat: String,
 }
 
+/// Parsed `v1Compatibility` entry of a schema 1 manifest, describing one layer.
+///
+/// All fields are optional, as these entries are free-form JSON documents.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct V1Compatibility {
+    pub id: Option<String>,
+    pub parent: Option<String>,
+    /// Creation timestamp of the layer (RFC 3339).
+    pub created: Option<String>,
+    pub author: Option<String>,
+    pub architecture: Option<String>,
+    pub os: Option<String>,
+    /// Configuration of the container the layer was built in.
+    pub container_config: Option<ContainerConfig>,
+    /// Configuration of the image, as of this layer.
+    pub config: Option<ContainerConfig>,
+    #[serde(default)]
+    pub throwaway: bool,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct S1Layer {
     #[serde(rename = "blobSum")]
@@ -48,6 +70,19 @@ impl ManifestSchema1Signed {
         self.fs_layers.iter().rev().map(|l| l.blob_sum.as_ref())
     }
 
+    /// List the parsed `v1Compatibility` entries of the history of this manifest.
+    ///
+    /// The entries are ordered like the `fsLayers`, starting with the top-most layer.
+    /// Entries which can't be parsed are returned empty.
+    pub fn history(&self) -> impl Iterator<Item = V1Compatibility> + '_ {
+        self.history.iter().map(|entry| {
+            serde_json::from_str(&entry.v1_compat).unwrap_or_else(|e| {
+                debug!("failed to parse v1Compatibility entry: {}", e);
+                V1Compatibility::default()
+            })
+        })
+    }
+
     /// Get a collection of all image labels stored in the history array of this manifest.
     ///
     /// Note that for this manifest type any `layer` beyond 0 probably returns None.
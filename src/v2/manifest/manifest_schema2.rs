@@ -1,7 +1,9 @@
 use crate::errors::{Error, Result};
 use crate::mediatypes::MediaTypes;
+use crate::v2::ContentDigest;
 use compact_str::CompactString;
 use reqwest::Method;
+use std::collections::{BTreeMap, HashMap};
 
 /// Manifest version 2 schema 2.
 ///
@@ -13,7 +15,139 @@ pub struct ManifestSchema2Spec {
     #[serde(rename = "mediaType")]
     media_type: MediaTypes,
     config: Config,
-    layers: Vec<S2Layer>,
+    layers: Vec<Layer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    subject: Option<Box<Descriptor>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotations: Option<BTreeMap<String, String>>,
+}
+
+/// Builder of a schema 2 manifest, Docker or OCI, from blobs already pushed.
+///
+/// ```
+/// # use dkregistry::mediatypes::MediaTypes;
+/// # use dkregistry::v2::manifest::ManifestSchema2Builder;
+/// let spec = ManifestSchema2Builder::new()
+///     .config("sha256:b5b2b2c507a0944348e0303114d8d93aaaa081732b86451d9bce1f432a537bc7", 7023)
+///     .add_layer(
+///         &MediaTypes::ImageLayerTgz,
+///         "sha256:e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f",
+///         32654,
+///         None,
+///     )
+///     .build();
+/// let manifest = serde_json::to_vec(&spec).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct ManifestSchema2Builder {
+    media_type: MediaTypes,
+    config: Option<(String, u64)>,
+    layers: Vec<Layer>,
+}
+
+impl Default for ManifestSchema2Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManifestSchema2Builder {
+    /// Start a Docker schema 2 manifest, of type `application/vnd.docker.distribution.manifest.v2+json`.
+    pub fn new() -> Self {
+        Self {
+            media_type: MediaTypes::ManifestV2S2,
+            config: None,
+            layers: Vec::new(),
+        }
+    }
+
+    /// Start an OCI image manifest, of type `application/vnd.oci.image.manifest.v1+json`.
+    pub fn oci() -> Self {
+        Self {
+            media_type: MediaTypes::OciV1Manifest,
+            ..Self::new()
+        }
+    }
+
+    /// Set the config blob of the image.
+    ///
+    /// Its media type is the Docker or OCI image config, as is the manifest. A manifest
+    /// built without a config refers to the OCI empty descriptor, which only suits artifacts.
+    pub fn config(mut self, digest: &str, size: u64) -> Self {
+        self.config = Some((digest.to_string(), size));
+        self
+    }
+
+    /// Append a layer blob, after the ones already added: the base layer comes first.
+    ///
+    /// `urls` are where foreign layers can be downloaded from, if not from the registry.
+    pub fn add_layer(
+        mut self,
+        media_type: &MediaTypes,
+        digest: &str,
+        size: u64,
+        urls: Option<Vec<String>>,
+    ) -> Self {
+        self.layers.push(Layer {
+            media_type: media_type.to_string().into(),
+            size,
+            digest: digest.to_string(),
+            urls,
+            annotations: None,
+        });
+        self
+    }
+
+    /// Build the manifest, ready to be serialized and pushed with `Client::put_manifest`.
+    pub fn build(self) -> ManifestSchema2Spec {
+        let config = match self.config {
+            Some((digest, size)) => Config {
+                media_type: match self.media_type {
+                    MediaTypes::OciV1Manifest => MediaTypes::OciV1ManifestConfig,
+                    _ => MediaTypes::ContainerConfigV1,
+                }
+                .to_string()
+                .into(),
+                size,
+                digest,
+                annotations: None,
+            },
+            None => Config {
+                media_type: MediaTypes::OciV1EmptyConfig.to_string().into(),
+                size: OCI_EMPTY_CONFIG.len() as u64,
+                digest: OCI_EMPTY_CONFIG_DIGEST.to_string(),
+                annotations: None,
+            },
+        };
+        ManifestSchema2Spec {
+            schema_version: 2,
+            media_type: self.media_type,
+            config,
+            layers: self.layers,
+            subject: None,
+            annotations: None,
+        }
+    }
+}
+
+/// Content of the OCI empty descriptor.
+const OCI_EMPTY_CONFIG: &[u8] = b"{}";
+
+/// Digest of `OCI_EMPTY_CONFIG`.
+const OCI_EMPTY_CONFIG_DIGEST: &str =
+    "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a";
+
+/// Descriptor of a piece of content, such as the manifest an artifact refers to.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Descriptor {
+    #[serde(rename = "mediaType")]
+    pub media_type: CompactString,
+    pub size: u64,
+    pub digest: String,
+    #[serde(rename = "artifactType", skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<CompactString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
 }
 
 /// Super-type for combining a ManifestSchema2 with a ConfigBlob.
@@ -23,6 +157,15 @@ pub struct ManifestSchema2 {
     pub manifest_spec: ManifestSchema2Spec,
     #[serde(skip)]
     pub config_blob: ConfigBlob,
+    /// Config blob as fetched, byte for byte.
+    ///
+    /// Unlike a re-serialized `config_blob`, this matches the config digest, as checked
+    /// when fetched with `Config::verify_digests`.
+    #[serde(skip)]
+    pub raw_config_blob: Vec<u8>,
+    /// Manifest as fetched, byte for byte, or empty if it was not fetched by a `Client`.
+    #[serde(skip)]
+    pub raw_manifest: Vec<u8>,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize, Clone)]
@@ -31,6 +174,8 @@ pub struct Config {
     pub media_type: CompactString,
     pub size: u64,
     pub digest: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
 }
 
 /// Partial representation of a container image (application/vnd.docker.container.image.v1+json).
@@ -41,15 +186,56 @@ pub struct Config {
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ConfigBlob {
     architecture: CompactString,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    os: Option<CompactString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variant: Option<CompactString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config: Option<Box<ContainerConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<HistoryEntry>,
+}
+
+/// Entry of the history of an image, describing how one of its layers was built.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    /// Creation timestamp of the layer (RFC 3339).
+    pub created: Option<String>,
+    /// Command which created the layer.
+    pub created_by: Option<String>,
+    pub author: Option<String>,
+    pub comment: Option<String>,
+    /// Whether this entry did not create a filesystem layer, e.g. for `ENV` instructions.
+    #[serde(default)]
+    pub empty_layer: bool,
 }
 
+/// Execution parameters of a container image, as found in its config blob.
 #[derive(Debug, Default, Deserialize, Serialize)]
-struct S2Layer {
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerConfig {
+    pub user: Option<String>,
+    pub env: Option<Vec<String>>,
+    pub cmd: Option<Vec<String>>,
+    pub entrypoint: Option<Vec<String>>,
+    pub labels: Option<HashMap<String, String>>,
+    pub working_dir: Option<String>,
+    pub exposed_ports: Option<HashMap<String, serde_json::Value>>,
+}
+
+/// Layer descriptor of a schema 2 manifest.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Layer {
     #[serde(rename = "mediaType")]
     media_type: CompactString,
     size: u64,
     digest: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     urls: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotations: Option<BTreeMap<String, String>>,
 }
 
 /// Manifest List.
@@ -60,6 +246,10 @@ pub struct ManifestList {
     #[serde(rename = "mediaType")]
     media_type: CompactString,
     pub manifests: Vec<ManifestObj>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotations: Option<BTreeMap<String, String>>,
+    #[serde(skip)]
+    pub(crate) raw_manifest: Vec<u8>,
 }
 
 /// Manifest object.
@@ -69,11 +259,16 @@ pub struct ManifestObj {
     media_type: CompactString,
     size: u64,
     pub digest: String,
+    #[serde(default)]
     pub platform: Platform,
+    #[serde(rename = "artifactType", skip_serializing_if = "Option::is_none")]
+    artifact_type: Option<CompactString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotations: Option<BTreeMap<String, String>>,
 }
 
 /// Platform-related manifest entries.
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Platform {
     pub architecture: CompactString,
     pub os: CompactString,
@@ -85,38 +280,177 @@ pub struct Platform {
     pub features: Option<Vec<CompactString>>,
 }
 
+impl Platform {
+    /// Check whether this platform is the given one, whatever the spelling of either.
+    ///
+    /// Both sides are normalized as Go names them: `x86_64` is `amd64`, `aarch64` is `arm64`,
+    /// `armhf` is `arm` variant `v7`, and so on. The default variant of an architecture,
+    /// such as `v8` for `arm64`, is the same as no variant. A `None` variant matches any variant.
+    ///
+    /// The raw fields are left as-is.
+    pub fn matches(&self, os: &str, arch: &str, variant: Option<&str>) -> bool {
+        let (self_arch, self_variant) = normalize_arch(&self.architecture, self.variant.as_deref());
+        let (arch, wanted_variant) = normalize_arch(arch, variant);
+        normalize_os(&self.os) == normalize_os(os)
+            && self_arch == arch
+            && (variant.is_none() || self_variant == wanted_variant)
+    }
+
+    /// Check whether the `os.version` of this platform is compatible with `os_version`.
+    ///
+    /// Versions are compared component by component, the given version being a prefix:
+    /// a host at `10.0.19041` matches an image at `10.0.19041.1415`, but not `10.0.190410`.
+    /// Only Windows images are versioned this way, so other platforms always match,
+    /// as do platforms without an `os.version`.
+    pub fn matches_os_version(&self, os_version: &str) -> bool {
+        if normalize_os(&self.os) != "windows" {
+            return true;
+        }
+        let own = match &self.os_version {
+            Some(own) => own,
+            None => return true,
+        };
+        let mut own = own.split('.');
+        os_version
+            .split('.')
+            .filter(|c| !c.is_empty())
+            .all(|c| own.next() == Some(c))
+    }
+
+    /// Check whether this is the `unknown/unknown` platform, which image indexes give
+    /// to entries that are not images, such as the attestation manifests of BuildKit.
+    pub fn is_unknown(&self) -> bool {
+        self.os == "unknown" && self.architecture == "unknown"
+    }
+}
+
+/// Normalize an OS name to the name Go uses for it.
+pub fn normalize_os(os: &str) -> String {
+    match os.to_ascii_lowercase().as_str() {
+        "macos" => "darwin".to_string(),
+        os => os.to_string(),
+    }
+}
+
+/// Normalize an architecture and its variant to the names Go uses for them.
+///
+/// The default variant of an architecture is normalized to `None`, except for
+/// `arm` where `v7` is always explicit.
+pub fn normalize_arch(arch: &str, variant: Option<&str>) -> (String, Option<String>) {
+    let arch = arch.to_ascii_lowercase();
+    let variant = variant
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_ascii_lowercase());
+    let (arch, variant) = match (arch.as_str(), variant.as_deref()) {
+        ("i386" | "i686" | "x86", _) => ("386", None),
+        ("x86_64" | "x86-64" | "amd64", Some("v1")) => ("amd64", None),
+        ("x86_64" | "x86-64", v) => ("amd64", v),
+        ("aarch64" | "arm64", Some("8" | "v8")) => ("arm64", None),
+        ("aarch64", v) => ("arm64", v),
+        ("armhf", _) => ("arm", Some("v7")),
+        ("armel", _) => ("arm", Some("v6")),
+        ("arm", None) => ("arm", Some("v7")),
+        ("arm", Some(v @ ("5" | "6" | "7" | "8"))) => {
+            return ("arm".to_string(), Some(format!("v{}", v)))
+        }
+        (a, v) => (a, v),
+    };
+    (arch.to_string(), variant.map(str::to_string))
+}
+
 impl ManifestSchema2Spec {
+    /// Get the media type of the manifest, Docker or OCI.
+    pub fn media_type(&self) -> &MediaTypes {
+        &self.media_type
+    }
+
     /// Get `Config` object referenced by this manifest.
     pub fn config(&self) -> &Config {
         &self.config
     }
 
+    /// List descriptors of all layers referenced by this manifest.
+    ///
+    /// The returned layers list is ordered starting with the base image first.
+    pub fn layers(&self) -> impl Iterator<Item = &Layer> {
+        self.layers.iter()
+    }
+
+    /// Total size in bytes of the image, that is its config blob plus all of its layers.
+    pub fn total_size(&self) -> u64 {
+        self.config.size + self.layers().map(Layer::size).sum::<u64>()
+    }
+
+    /// Get the manifest this manifest refers to, if any.
+    ///
+    /// Only OCI manifests carry a subject, typically signatures and attestations of an image.
+    pub fn subject(&self) -> Option<&Descriptor> {
+        self.subject.as_deref()
+    }
+
+    /// Get the annotations of the manifest, if any.
+    ///
+    /// Only OCI manifests carry annotations.
+    pub fn annotations(&self) -> Option<&BTreeMap<String, String>> {
+        self.annotations.as_ref()
+    }
+
+    /// Get the media type of the config blob, Docker or OCI.
+    pub fn config_media_type(&self) -> MediaTypes {
+        MediaTypes::from_content_type(&self.config.media_type)
+    }
+
     /// Fetch the config blob for this manifest
+    ///
+    /// With `Config::verify_digests`, the default, a blob which doesn't match the config
+    /// digest results in `Error::DigestMismatch`.
+    ///
+    /// Both the Docker and the OCI image config are supported, other config media types
+    /// result in `Error::UnsupportedMediaType`.
     pub(crate) async fn fetch_config_blob(
         self,
         client: &crate::v2::Client,
         repo: &str,
     ) -> Result<ManifestSchema2> {
+        match self.config_media_type() {
+            MediaTypes::ContainerConfigV1 | MediaTypes::OciV1ManifestConfig => {}
+            m => return Err(Error::UnsupportedMediaType(m)),
+        }
         let ep = format!(
             "{}/v2/{}/blobs/{}",
             &client.base_url, repo, self.config.digest
         );
         let url = reqwest::Url::parse(&ep)?;
 
-        let r = client.build_reqwest(Method::GET, url).send().await?;
+        let r = client
+            .send_request(client.build_reqwest(Method::GET, url))
+            .await?;
 
         let status = r.status();
         trace!("GET {:?}: {}", ep, &status);
 
         if !status.is_success() {
-            return Err(Error::UnexpectedHttpStatus(status));
+            return Err(crate::v2::response_error(r, Error::UnexpectedHttpStatus(status)).await);
         }
 
-        let config_blob = r.json::<ConfigBlob>().await?;
+        let body = client.read_manifest_body(r).await?;
+        if client.verify_digests {
+            let actual =
+                ContentDigest::compute(&self.config.digest, &body, client.digest_algorithm);
+            if actual != self.config.digest {
+                return Err(Error::DigestMismatch {
+                    expected: self.config.digest.clone(),
+                    actual,
+                });
+            }
+        }
+        let config_blob = serde_json::from_slice::<ConfigBlob>(&body)?;
 
         Ok(ManifestSchema2 {
             manifest_spec: self,
             config_blob,
+            raw_config_blob: body.to_vec(),
+            raw_manifest: Vec::new(),
         })
     }
 }
@@ -129,9 +463,149 @@ impl ManifestSchema2 {
         self.manifest_spec.layers.iter().map(|l| l.digest.as_ref())
     }
 
+    /// List descriptors of all layers referenced by this manifest.
+    ///
+    /// The returned layers list is ordered starting with the base image first.
+    pub fn layers(&self) -> impl Iterator<Item = &Layer> {
+        self.manifest_spec.layers()
+    }
+
+    /// Get the annotations of the manifest, if any.
+    pub fn annotations(&self) -> Option<&BTreeMap<String, String>> {
+        self.manifest_spec.annotations()
+    }
+
+    /// Total size in bytes of the config blob and all layers.
+    pub fn total_size(&self) -> u64 {
+        self.manifest_spec.total_size()
+    }
+
+    /// Get the media type of the config blob, Docker or OCI.
+    pub fn config_media_type(&self) -> MediaTypes {
+        self.manifest_spec.config_media_type()
+    }
+
     /// Get the architecture from the config
     pub fn architecture(&self) -> &str {
-        self.config_blob.architecture.as_ref()
+        self.config_blob.architecture()
+    }
+
+    /// Get the operating system from the config, if available.
+    pub fn os(&self) -> Option<&str> {
+        self.config_blob.os()
+    }
+
+    /// Get the image labels from the config, if available.
+    pub fn labels(&self) -> Option<&HashMap<String, String>> {
+        self.config_blob.config()?.labels.as_ref()
+    }
+
+    /// Get the environment variables (`KEY=value`) from the config, if available.
+    pub fn env(&self) -> Option<&[String]> {
+        self.config_blob.config()?.env.as_deref()
+    }
+
+    /// Get the default command from the config, if available.
+    pub fn cmd(&self) -> Option<&[String]> {
+        self.config_blob.config()?.cmd.as_deref()
+    }
+
+    /// Get the entrypoint from the config, if available.
+    pub fn entrypoint(&self) -> Option<&[String]> {
+        self.config_blob.config()?.entrypoint.as_deref()
+    }
+
+    /// Get the creation timestamp (RFC 3339) from the config, if available.
+    pub fn created(&self) -> Option<&str> {
+        self.config_blob.created()
+    }
+
+    /// Get the history of the image from the config, oldest entry first.
+    pub fn history(&self) -> &[HistoryEntry] {
+        self.config_blob.history()
+    }
+}
+
+impl ConfigBlob {
+    /// Get the architecture of the image.
+    pub fn architecture(&self) -> &str {
+        self.architecture.as_ref()
+    }
+
+    /// Get the operating system of the image, if available.
+    pub fn os(&self) -> Option<&str> {
+        self.os.as_deref()
+    }
+
+    /// Get the architecture variant of the image, such as `v7` for `arm`, if available.
+    pub fn variant(&self) -> Option<&str> {
+        self.variant.as_deref()
+    }
+
+    /// Get the platform of the image, as a manifest list would give it.
+    pub fn platform(&self) -> Platform {
+        Platform {
+            architecture: self.architecture.clone(),
+            os: self.os.clone().unwrap_or_default(),
+            variant: self.variant.clone(),
+            ..Platform::default()
+        }
+    }
+
+    /// Get the execution parameters of the image, if available.
+    pub fn config(&self) -> Option<&ContainerConfig> {
+        self.config.as_deref()
+    }
+
+    /// Get the creation timestamp of the image, if available.
+    pub fn created(&self) -> Option<&str> {
+        self.created.as_deref()
+    }
+
+    /// Get the history of the image, oldest entry first.
+    ///
+    /// This is empty if the config does not record any history.
+    pub fn history(&self) -> &[HistoryEntry] {
+        &self.history
+    }
+}
+
+impl Layer {
+    /// Get the digest of the layer.
+    pub fn digest(&self) -> &str {
+        self.digest.as_ref()
+    }
+
+    /// Get the size of the layer in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Get the media type of the layer.
+    pub fn media_type(&self) -> &str {
+        self.media_type.as_ref()
+    }
+
+    /// Get the annotations of the layer, if any.
+    pub fn annotations(&self) -> Option<&BTreeMap<String, String>> {
+        self.annotations.as_ref()
+    }
+
+    /// Get the URLs the layer may be fetched from, if any.
+    ///
+    /// This is usually only set on foreign layers.
+    pub fn urls(&self) -> Option<&[String]> {
+        self.urls.as_deref()
+    }
+
+    /// Whether this is a foreign layer, whose content may not be stored by the registry.
+    ///
+    /// Such layers are fetched with `Client::get_foreign_blob`.
+    pub fn is_foreign(&self) -> bool {
+        self.media_type == MediaTypes::ForeignImageLayerTgz.to_string()
+            || self
+                .media_type
+                .starts_with("application/vnd.oci.image.layer.nondistributable.")
     }
 }
 
@@ -145,16 +619,104 @@ impl ManifestObj {
     pub fn digest(&self) -> &str {
         self.digest.as_ref()
     }
+
+    /// Get the media type of the manifest object.
+    pub fn media_type(&self) -> &str {
+        self.media_type.as_ref()
+    }
+
+    /// Get the artifact type of the manifest object, if any.
+    ///
+    /// This is usually only set on entries returned by the referrers API.
+    pub fn artifact_type(&self) -> Option<&str> {
+        self.artifact_type.as_deref()
+    }
+
+    /// Get the annotations of the manifest object, if any.
+    pub fn annotations(&self) -> Option<&BTreeMap<String, String>> {
+        self.annotations.as_ref()
+    }
 }
 
 impl ManifestList {
-    /// Get architecture of all the manifests
+    /// Get the media type of the manifest list, Docker or OCI.
+    pub fn media_type(&self) -> MediaTypes {
+        match MediaTypes::from_content_type(&self.media_type) {
+            MediaTypes::OciV1ManifestList => MediaTypes::OciV1ManifestList,
+            _ => MediaTypes::ManifestList,
+        }
+    }
+
+    /// Get the annotations of the manifest list, if any.
+    pub fn annotations(&self) -> Option<&BTreeMap<String, String>> {
+        self.annotations.as_ref()
+    }
+
+    /// Get architecture of all the manifests, skipping attestations.
     pub fn architectures(&self) -> impl Iterator<Item = &str> {
-        self.manifests.iter().map(|mo| mo.architecture())
+        self.manifests
+            .iter()
+            .filter(|mo| !mo.platform.is_unknown())
+            .map(|mo| mo.architecture())
+    }
+
+    /// Get the attestation manifests, which have the `unknown/unknown` platform.
+    pub fn attestations(&self) -> impl Iterator<Item = &ManifestObj> {
+        self.manifests.iter().filter(|mo| mo.platform.is_unknown())
     }
 
     /// Get the digest for all the manifest images in the ManifestList
     pub fn get_digests(&self) -> impl Iterator<Item = &str> {
         self.manifests.iter().map(|mo| mo.digest())
     }
+
+    /// Find the manifest object matching the given platform, as per `Platform::matches`.
+    ///
+    /// A `None` variant matches any variant. Attestations are never returned.
+    pub fn manifest_for_platform(
+        &self,
+        os: &str,
+        arch: &str,
+        variant: Option<&str>,
+    ) -> Option<&ManifestObj> {
+        self.manifest_for_platform_version(os, arch, variant, None)
+    }
+
+    /// Find the manifest object matching the given platform, as `manifest_for_platform`,
+    /// and the `os.version` of Windows images as per `Platform::matches_os_version`.
+    ///
+    /// A `None` OS version matches any version, as does `manifest_for_platform`.
+    pub fn manifest_for_platform_version(
+        &self,
+        os: &str,
+        arch: &str,
+        variant: Option<&str>,
+        os_version: Option<&str>,
+    ) -> Option<&ManifestObj> {
+        self.manifests.iter().find(|mo| {
+            !mo.platform.is_unknown()
+                && mo.platform.matches(os, arch, variant)
+                && os_version.is_none_or(|v| mo.platform.matches_os_version(v))
+        })
+    }
+
+    /// Get the manifest objects for the given OS, whatever their architecture.
+    ///
+    /// The OS is normalized as by `Platform::matches`. Entries with the `unknown/unknown`
+    /// platform are never returned, even for the `unknown` OS.
+    pub fn entries_for_os<'a>(&'a self, os: &str) -> impl Iterator<Item = &'a ManifestObj> {
+        let os = normalize_os(os);
+        self.entries_matching(move |p| !p.is_unknown() && normalize_os(&p.os) == os)
+    }
+
+    /// Get the manifest objects whose platform satisfies `pred`.
+    ///
+    /// `pred` sees every entry, including those with the `unknown/unknown` platform:
+    /// use `Platform::is_unknown` to skip them.
+    pub fn entries_matching<F>(&self, pred: F) -> impl Iterator<Item = &ManifestObj>
+    where
+        F: Fn(&Platform) -> bool,
+    {
+        self.manifests.iter().filter(move |mo| pred(&mo.platform))
+    }
 }
@@ -1,9 +1,13 @@
 use crate::errors::{Error, Result};
 use crate::mediatypes::MediaTypes;
+use crate::reference::{Reference, Version};
 use crate::v2::*;
 use bytes::Bytes;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
 use itertools::Either;
 use reqwest::{self, header, StatusCode};
+use std::borrow::Cow;
+use std::convert::TryInto;
 use std::iter::FromIterator;
 use std::str::FromStr;
 use url::Url;
@@ -12,113 +16,548 @@ mod manifest_schema1;
 pub use self::manifest_schema1::*;
 
 mod manifest_schema2;
+#[cfg(feature = "schema1-signatures")]
+mod schema1_signature;
 pub use self::manifest_schema2::{
-    ConfigBlob, ManifestList, ManifestObj, ManifestSchema2, ManifestSchema2Spec, Platform,
+    normalize_arch, normalize_os, Config, ConfigBlob, ContainerConfig, Descriptor, HistoryEntry,
+    Layer, ManifestList, ManifestObj, ManifestSchema2, ManifestSchema2Builder, ManifestSchema2Spec,
+    Platform,
 };
 
+/// Number of tags resolved at once by `Client::resolve_short_digest`.
+const SHORT_DIGEST_CONCURRENCY: usize = 8;
+
 impl Client {
     #[inline]
-    fn manifest_url(
-        &self,
-        name: &str,
-        reference: &str,
-        ns: Option<&str>,
-    ) -> core::result::Result<Url, url::ParseError> {
-        let ep = match ns {
+    fn manifest_url(&self, name: &str, reference: &str, ns: Option<&str>) -> Result<Url> {
+        validate_name(name)?;
+        let ep = match self.namespace(ns) {
             Some(v) => format!(
                 "{}/v2/{}/manifests/{}?ns={}",
                 self.base_url, name, reference, v
             ),
             None => format!("{}/v2/{}/manifests/{}", self.base_url, name, reference),
         };
-        reqwest::Url::parse(&ep)
+        Ok(reqwest::Url::parse(&ep)?)
     }
 
     /// Fetch an image manifest.
     ///
     /// The name and reference parameters identify the image.
-    /// The reference may be either a tag or digest.
-    pub async fn get_manifest(
+    /// The reference may be either a tag or digest, as a `Version`, a `Digest`,
+    /// or a string which is validated before the request is sent.
+    ///
+    /// With `Config::resolve_platform`, a manifest list is followed to the manifest
+    /// of the configured platform, as `get_manifest_for_platform` does.
+    pub async fn get_manifest<R>(
+        &self,
+        name: &str,
+        reference: R,
+        ns: Option<&str>,
+    ) -> Result<Manifest>
+    where
+        R: TryInto<Version>,
+        Error: From<R::Error>,
+    {
+        let reference = reference.try_into()?.to_string();
+        let (manifest, _) = self.get_manifest_and_ref(name, &reference, ns).await?;
+        match (manifest, &self.resolve_platform) {
+            (Manifest::ML(ml), Some((os, arch))) => {
+                let digest = Self::platform_digest(&ml, os, arch)?;
+                trace!(
+                    "Resolved {}:{} to {} for {}/{}",
+                    name,
+                    reference,
+                    digest,
+                    os,
+                    arch
+                );
+                self.get_manifest_and_ref(name, digest, ns)
+                    .await
+                    .map(|(manifest, _)| manifest)
+            }
+            (manifest, _) => Ok(manifest),
+        }
+    }
+
+    /// Find the digest of the manifest for a platform in a manifest list.
+    fn platform_digest<'a>(ml: &'a ManifestList, os: &str, arch: &str) -> Result<&'a str> {
+        ml.manifest_for_platform(os, arch, None)
+            .map(ManifestObj::digest)
+            .ok_or_else(|| ManifestError::PlatformNotFound(format!("{}/{}", os, arch)).into())
+    }
+
+    /// Fetch the image manifest for a specific platform.
+    ///
+    /// If the reference points to a manifest list, the entry matching `os` and `arch`
+    /// is selected and its manifest is fetched by digest.
+    /// Any other manifest is returned as-is, provided its architecture matches.
+    /// Platform names are normalized, as per `Platform::matches`.
+    pub async fn get_manifest_for_platform(
         &self,
         name: &str,
         reference: &str,
+        os: &str,
+        arch: &str,
         ns: Option<&str>,
     ) -> Result<Manifest> {
-        self.get_manifest_and_ref(name, reference, ns)
-            .await
-            .map(|(manifest, _)| manifest)
+        match self.get_manifest_and_ref(name, reference, ns).await?.0 {
+            Manifest::ML(ml) => {
+                let digest = Self::platform_digest(&ml, os, arch)?;
+                self.get_manifest_and_ref(name, digest, ns)
+                    .await
+                    .map(|(manifest, _)| manifest)
+            }
+            m => {
+                let (arch, _) = normalize_arch(arch, None);
+                if !m
+                    .architectures()?
+                    .any(|a| normalize_arch(a, None).0 == arch)
+                {
+                    return Err(ManifestError::ArchitectureMismatch.into());
+                }
+                Ok(m)
+            }
+        }
+    }
+
+    /// Fetch all manifests referenced by a manifest list, returning the result for each digest.
+    ///
+    /// Unlike `get_manifest_for_platform`, a failure to fetch one manifest does not prevent
+    /// fetching the others, so callers can proceed with the platforms which are available.
+    /// Results are in the order of the list.
+    pub async fn get_all_child_manifests(
+        &self,
+        name: &str,
+        list: &ManifestList,
+        ns: Option<&str>,
+    ) -> Vec<(String, Result<Manifest>)> {
+        futures::future::join_all(list.manifests.iter().map(|child| async move {
+            let manifest = self.get_manifest(name, child.digest(), ns).await;
+            if let Err(e) = &manifest {
+                warn!("Failed to fetch manifest {}: {}", child.digest(), e);
+            }
+            (child.digest().to_string(), manifest)
+        }))
+        .await
+    }
+
+    /// Stream the manifests referenced by a manifest list, each with its platform,
+    /// with up to `concurrency` manifests being fetched at once.
+    ///
+    /// Manifests are yielded in the order of the list, as they are consumed.
+    pub fn stream_manifest_list<'a>(
+        &'a self,
+        name: &'a str,
+        list: &'a ManifestList,
+        concurrency: usize,
+        ns: Option<&'a str>,
+    ) -> impl Stream<Item = Result<(Platform, Manifest)>> + 'a {
+        stream::iter(&list.manifests)
+            .map(move |child| async move {
+                let manifest = self.get_manifest(name, child.digest(), ns).await?;
+                Ok((child.platform.clone(), manifest))
+            })
+            .buffered(concurrency.max(1))
     }
 
     /// Fetch an image manifest and return it with its digest.
     ///
     /// The name and reference parameters identify the image.
     /// The reference may be either a tag or digest.
+    /// If the registry does not send a `Docker-Content-Digest` header,
+    /// the digest is computed from the manifest content.
     pub async fn get_manifest_and_ref(
         &self,
         name: &str,
         reference: &str,
         ns: Option<&str>,
+    ) -> Result<(Manifest, Option<String>)> {
+        spans::instrument!(
+            self.manifest_and_ref(name, reference, &self.accepted_types, ns),
+            "get_manifest",
+            repo = name,
+            reference,
+            status = tracing::field::Empty,
+            bytes = tracing::field::Empty,
+            media_type = tracing::field::Empty,
+            digest = tracing::field::Empty,
+        )
+        .await
+    }
+
+    /// Fetch an image manifest and return it with the headers of the response.
+    ///
+    /// This helps looking into registry quirks, e.g. at `X-Registry-Supports-Signatures`
+    /// or caching headers. The headers are those of the manifest response only, not of
+    /// the request fetching the config blob of schema 2 manifests.
+    pub async fn get_manifest_and_headers(
+        &self,
+        name: &str,
+        reference: &str,
+        ns: Option<&str>,
+    ) -> Result<(Manifest, header::HeaderMap)> {
+        let res = self
+            .manifest_response(name, reference, &self.accepted_types, None, ns)
+            .await?;
+        let headers = res.headers().clone();
+        let (body, media_type, _) = self.read_manifest_response(res, reference).await?;
+        let manifest = self.parse_manifest(name, &body, media_type).await?;
+        Ok((manifest, headers))
+    }
+
+    /// Fetch an image manifest, accepting only the given media types for this request.
+    ///
+    /// This overrides the `accepted_types` of the client, for example to always get
+    /// the manifest list of a multi-platform image.
+    pub async fn get_manifest_with_accept(
+        &self,
+        name: &str,
+        reference: &str,
+        accept: &[MediaTypes],
+        ns: Option<&str>,
+    ) -> Result<Manifest> {
+        let accepted_types: Vec<_> = accept.iter().map(|ty| (ty.clone(), None)).collect();
+        self.manifest_and_ref(name, reference, &accepted_types, ns)
+            .await
+            .map(|(manifest, _)| manifest)
+    }
+
+    /// Get the platforms of an image, fetching as little as possible.
+    ///
+    /// The platforms of a manifest list are taken from the list itself, skipping
+    /// attestations. Only for a single image manifest is its config blob fetched,
+    /// for a single platform; schema 1 manifests give theirs from the manifest.
+    pub async fn get_image_platforms(
+        &self,
+        name: &str,
+        reference: &str,
+        ns: Option<&str>,
+    ) -> Result<Vec<Platform>> {
+        let (body, media_type, _) = self
+            .get_raw_manifest_and_metadata(name, reference, ns)
+            .await?;
+        match media_type {
+            MediaTypes::ManifestList | MediaTypes::OciV1ManifestList => {
+                let list: ManifestList = serde_json::from_slice(&body)?;
+                Ok(list
+                    .manifests
+                    .into_iter()
+                    .filter(|mo| !mo.platform.is_unknown())
+                    .map(|mo| mo.platform)
+                    .collect())
+            }
+            MediaTypes::ManifestV2S2 | MediaTypes::OciV1Manifest => {
+                let spec: ManifestSchema2Spec = serde_json::from_slice(&body)?;
+                let m = spec.fetch_config_blob(self, name).await?;
+                Ok(vec![m.config_blob.platform()])
+            }
+            MediaTypes::ManifestV2S1Signed => {
+                let m: ManifestSchema1Signed = serde_json::from_slice(&body)?;
+                let os = m.history().next().and_then(|h| h.os);
+                Ok(vec![Platform {
+                    architecture: m.architecture,
+                    os: os.map(Into::into).unwrap_or_default(),
+                    ..Platform::default()
+                }])
+            }
+            m => Err(Error::UnsupportedMediaType(m)),
+        }
+    }
+
+    async fn manifest_and_ref(
+        &self,
+        name: &str,
+        reference: &str,
+        accepted_types: &[(MediaTypes, Option<f64>)],
+        ns: Option<&str>,
     ) -> Result<(Manifest, Option<String>)> {
+        let (body, media_type, content_digest) = self
+            .raw_manifest_and_metadata(name, reference, accepted_types, ns)
+            .await?;
+        let manifest = self.parse_manifest(name, &body, media_type).await?;
+        Ok((manifest, content_digest))
+    }
+
+    /// Parse a raw manifest, fetching the config blob of schema 2 and OCI image manifests.
+    async fn parse_manifest(
+        &self,
+        name: &str,
+        body: &[u8],
+        media_type: MediaTypes,
+    ) -> Result<Manifest> {
+        match media_type {
+            MediaTypes::ManifestV2S1Signed => {
+                let mut m: ManifestSchema1Signed = serde_json::from_slice(body)?;
+                #[cfg(feature = "schema1-signatures")]
+                if self.verify_schema1_signatures {
+                    m.verify_signature(body)?;
+                }
+                m.raw_manifest = body.to_vec();
+                Ok(Manifest::S1Signed(m))
+            }
+            MediaTypes::ManifestV2S2 | MediaTypes::OciV1Manifest => {
+                let spec: ManifestSchema2Spec = serde_json::from_slice(body)?;
+                let mut m = spec.fetch_config_blob(self, name).await?;
+                m.raw_manifest = body.to_vec();
+                Ok(Manifest::S2(m))
+            }
+            MediaTypes::ManifestList | MediaTypes::OciV1ManifestList => {
+                let mut m: ManifestList = serde_json::from_slice(body)?;
+                m.raw_manifest = body.to_vec();
+                Ok(Manifest::ML(m))
+            }
+            unsupported => Err(Error::UnsupportedMediaType(unsupported)),
+        }
+    }
+
+    /// Fetch an image manifest unless it matches `etag`, as returned by a previous call.
+    ///
+    /// The request carries an `If-None-Match` header, so registries honoring it answer
+    /// `304 Not Modified` without sending the manifest again, and `None` is returned.
+    /// Otherwise, the manifest is returned with the tag to pass next time: the `ETag`
+    /// of the response, or the digest of the manifest if the registry sends none.
+    /// A digest may be passed as `etag` as well.
+    ///
+    /// If the registry ignores the header and sends the manifest anyway, `None` is still
+    /// returned when its tag matches `etag`, so only bandwidth is lost.
+    pub async fn get_manifest_if_changed(
+        &self,
+        name: &str,
+        reference: &str,
+        etag: Option<&str>,
+        ns: Option<&str>,
+    ) -> Result<Option<(Manifest, String)>> {
+        let res = self
+            .manifest_response(name, reference, &self.accepted_types, etag, ns)
+            .await?;
+        if res.status() == StatusCode::NOT_MODIFIED {
+            trace!("Manifest {}:{} not modified", name, reference);
+            return Ok(None);
+        }
+
+        let response_etag = res
+            .headers()
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let (body, media_type, content_digest) =
+            self.read_manifest_response(res, reference).await?;
+        let new_etag = match response_etag.or(content_digest) {
+            Some(new_etag) => new_etag,
+            None => ContentDigest::compute(reference, &body, self.digest_algorithm),
+        };
+        if etag.map(unquote_etag) == Some(unquote_etag(&new_etag)) {
+            trace!(
+                "Manifest {}:{} unchanged, If-None-Match ignored",
+                name,
+                reference
+            );
+            return Ok(None);
+        }
+
+        let manifest = self.parse_manifest(name, &body, media_type).await?;
+        Ok(Some((manifest, new_etag)))
+    }
+
+    /// Fetch a schema 2 or OCI image manifest and return it with its digest,
+    /// without fetching its config blob.
+    ///
+    /// This saves a request compared to `get_manifest` when only the manifest itself,
+    /// such as its layers, is needed. Other manifest types result in `Error::UnsupportedMediaType`.
+    pub async fn get_manifest_spec(
+        &self,
+        name: &str,
+        reference: &str,
+        ns: Option<&str>,
+    ) -> Result<(ManifestSchema2Spec, Option<String>)> {
         let (body, media_type, content_digest) = self
             .get_raw_manifest_and_metadata(name, reference, ns)
             .await?;
 
         match media_type {
-            MediaTypes::ManifestV2S1Signed => Ok((
-                Manifest::S1Signed(serde_json::from_slice(body.as_ref())?),
-                content_digest,
-            )),
-            MediaTypes::ManifestV2S2 => {
-                let m: ManifestSchema2Spec = serde_json::from_slice(body.as_ref())?;
-                Ok((
-                    m.fetch_config_blob(self, name).await.map(Manifest::S2)?,
-                    content_digest,
-                ))
+            MediaTypes::ManifestV2S2 | MediaTypes::OciV1Manifest => {
+                Ok((serde_json::from_slice(body.as_ref())?, content_digest))
             }
-            MediaTypes::ManifestList => Ok((
-                Manifest::ML(serde_json::from_slice(body.as_ref())?),
-                content_digest,
-            )),
             unsupported => Err(Error::UnsupportedMediaType(unsupported)),
         }
     }
 
+    /// Fetch the config blob described by `config`, as returned by `ManifestSchema2Spec::config`.
+    ///
+    /// The blob must have the declared size, and must hash to the declared digest
+    /// whatever `Config::verify_digests` says. Along with `get_manifest_spec`, this
+    /// fetches a manifest and its config blob without the eager fetch of `get_manifest`.
+    pub async fn get_config_blob(
+        &self,
+        name: &str,
+        config: &Config,
+        ns: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        let blob = self
+            .get_blob_sized(name, &config.digest, config.size, ns)
+            .await?;
+        if !self.verify_digests {
+            let actual = ContentDigest::compute(&config.digest, &blob, self.digest_algorithm);
+            if actual != config.digest {
+                return Err(Error::DigestMismatch {
+                    expected: config.digest.clone(),
+                    actual,
+                });
+            }
+        }
+        Ok(blob)
+    }
+
+    /// Fetch and parse the config blob described by `config`, as in `get_config_blob`.
+    pub async fn get_config(
+        &self,
+        name: &str,
+        config: &Config,
+        ns: Option<&str>,
+    ) -> Result<ConfigBlob> {
+        let blob = self.get_config_blob(name, config, ns).await?;
+        Ok(serde_json::from_slice(&blob)?)
+    }
+
+    /// Push a raw manifest of the given media type, tagging it with `reference`.
+    ///
+    /// All blobs referenced by the manifest must already exist in the repository.
+    /// Returns the digest of the manifest, as reported by the registry, or else computed locally.
+    ///
+    /// With `Config::verify_digests`, the default, a reported digest which doesn't match
+    /// the manifest sent results in `Error::DigestMismatch`: the registry altered the manifest
+    /// on ingest, so the digest of what was sent doesn't reference what was stored.
+    ///
+    /// With `Config::dry_run`, nothing is sent and the digest computed locally is returned.
+    pub async fn put_manifest(
+        &self,
+        name: &str,
+        reference: &str,
+        media_type: &MediaTypes,
+        manifest: Bytes,
+    ) -> Result<String> {
+        let url = self.manifest_url(name, reference, None)?;
+        let digest = ContentDigest::compute(reference, &manifest, self.digest_algorithm);
+        if self.dry_run {
+            trace!("Dry run: not pushing manifest {} to {:?}", digest, url);
+            return Ok(digest);
+        }
+
+        trace!("PUT {:?}", url);
+        let res = self
+            .send_request(
+                self.build_reqwest(Method::PUT, url)
+                    .header(header::CONTENT_TYPE, media_type.to_string())
+                    .body(manifest.clone()),
+            )
+            .await?;
+
+        let status = res.status();
+        trace!("PUT '{}' status: {:?}", res.url(), status);
+        match status {
+            StatusCode::CREATED => {}
+            StatusCode::PAYLOAD_TOO_LARGE => return Err(Error::QuotaExceeded),
+            s => return Err(response_error(res, status_error(s)).await),
+        }
+
+        let reported = match content_digest_from_headers(res.headers()) {
+            Some(reported) => reported,
+            None => return Ok(digest),
+        };
+        if self.verify_digests {
+            match ContentDigest::try_new(&reported) {
+                Ok(mut content_digest) => {
+                    content_digest.update(&manifest);
+                    content_digest.verify()?;
+                }
+                Err(e) => warn!(
+                    "Cannot verify digest {} of pushed manifest: {}",
+                    reported, e
+                ),
+            }
+        }
+        Ok(reported)
+    }
+
     pub async fn get_raw_manifest_and_metadata(
         &self,
         name: &str,
         reference: &str,
         ns: Option<&str>,
     ) -> Result<(Bytes, MediaTypes, Option<String>)> {
-        let url = self.manifest_url(name, reference, ns)?;
-
-        let accept_headers = build_accept_headers(&self.accepted_types);
+        self.raw_manifest_and_metadata(name, reference, &self.accepted_types, ns)
+            .await
+    }
 
+    async fn raw_manifest_and_metadata(
+        &self,
+        name: &str,
+        reference: &str,
+        accepted_types: &[(MediaTypes, Option<f64>)],
+        ns: Option<&str>,
+    ) -> Result<(Bytes, MediaTypes, Option<String>)> {
         let res = self
-            .build_reqwest(Method::GET, url)
-            .headers(accept_headers)
-            .send()
+            .manifest_response(name, reference, accepted_types, None, ns)
             .await?;
+        self.read_manifest_response(res, reference).await
+    }
+
+    /// Send a manifest request, failing unless the response is `200 OK`,
+    /// or `304 Not Modified` if `if_none_match` is set.
+    async fn manifest_response(
+        &self,
+        name: &str,
+        reference: &str,
+        accepted_types: &[(MediaTypes, Option<f64>)],
+        if_none_match: Option<&str>,
+        ns: Option<&str>,
+    ) -> Result<reqwest::Response> {
+        let url = self.manifest_url(name, reference, ns)?;
+
+        let accept_headers = build_accept_headers(accepted_types);
+
+        let mut builder = self.build_reqwest(Method::GET, url).headers(accept_headers);
+        if let Some(etag) = if_none_match {
+            builder = builder.header(header::IF_NONE_MATCH, quote_etag(etag).as_ref());
+        }
+        let res = self.send_request(builder).await?;
 
         let status = res.status();
         trace!("GET '{}' status: {:?}", res.url(), status);
+        spans::record!("status", status.as_u16());
 
         match status {
-            StatusCode::OK => {}
-            _ => return Err(Error::UnexpectedHttpStatus(status)),
+            StatusCode::OK => Ok(res),
+            StatusCode::NOT_MODIFIED if if_none_match.is_some() => Ok(res),
+            _ => match response_error(res, Error::UnexpectedHttpStatus(status)).await {
+                // A bare 404 may come from anything on the way, such as a wrong URL:
+                // only the error body of the registry confirms the manifest is missing.
+                Error::Registry { status, errors }
+                    if status == StatusCode::NOT_FOUND
+                        && errors.iter().any(|e| e.code() == "MANIFEST_UNKNOWN") =>
+                {
+                    Err(Error::ManifestUnknown {
+                        name: name.to_string(),
+                        reference: reference.to_string(),
+                    })
+                }
+                e => Err(e),
+            },
         }
+    }
 
+    /// Read the manifest of a successful response, with its media type and digest.
+    async fn read_manifest_response(
+        &self,
+        res: reqwest::Response,
+        reference: &str,
+    ) -> Result<(Bytes, MediaTypes, Option<String>)> {
         let headers = res.headers();
-        let content_digest =
-            match headers.get(header::HeaderName::from_static("docker-content-digest")) {
-                Some(content_digest_value) => Some(content_digest_value.to_str()?.to_string()),
-                None => {
-                    debug!("cannot find manifestref in headers");
-                    None
-                }
-            };
+        let header_content_digest = content_digest_from_headers(headers);
 
         let header_content_type = headers.get(header::CONTENT_TYPE);
-        let media_type = evaluate_media_type(header_content_type, res.url())?;
+        let media_type = evaluate_media_type(header_content_type, res.url());
 
         trace!(
             "content-type: {:?}, media-type: {:?}",
@@ -126,7 +565,98 @@ impl Client {
             media_type
         );
 
-        Ok((res.bytes().await?, media_type, content_digest))
+        let body = self.read_manifest_body(res).await?;
+
+        // Some intermediaries drop the content-type, so infer it from the manifest itself.
+        let media_type = match media_type {
+            Err(Error::MediaTypeSniff) => {
+                let sniffed = sniff_media_type(&body).ok_or(Error::MediaTypeSniff)?;
+                debug!("Missing content-type, using '{}'", sniffed);
+                sniffed
+            }
+            media_type => media_type?,
+        };
+
+        // Fall back to the media type declared in the manifest itself,
+        // if the registry labeled it with an unfamiliar type.
+        let media_type = match media_type {
+            MediaTypes::Other(ref other) => match sniff_media_type(&body) {
+                Some(sniffed) => {
+                    debug!("Unknown content-type '{}', using '{}'", other, sniffed);
+                    sniffed
+                }
+                None => media_type,
+            },
+            m => m,
+        };
+
+        // The digest of a manifest is the digest of its raw content,
+        // so it can be computed when the registry does not send it.
+        let content_digest = match header_content_digest {
+            Some(content_digest) => content_digest,
+            None => {
+                debug!("cannot find manifestref in headers, computing it from the body");
+                ContentDigest::compute(reference, &body, self.digest_algorithm)
+            }
+        };
+        spans::record!("bytes", body.len());
+        spans::record!("media_type", tracing::field::display(&media_type));
+        spans::record!("digest", content_digest.as_str());
+
+        Ok((body, media_type, Some(content_digest)))
+    }
+
+    /// Fetch a manifest and compute its digest locally, without trusting the registry.
+    ///
+    /// Fails with `Error::DigestMismatch` if the computed digest differs from the
+    /// `Docker-Content-Digest` header, or from `reference` if it is a digest.
+    /// Returns the computed digest.
+    pub async fn verify_manifest_digest(
+        &self,
+        name: &str,
+        reference: &str,
+        ns: Option<&str>,
+    ) -> Result<String> {
+        let (body, _, content_digest) = self
+            .get_raw_manifest_and_metadata(name, reference, ns)
+            .await?;
+
+        // Digests are computed with the algorithm of each expected digest.
+        let pinned = Some(reference)
+            .filter(|r| r.contains(':'))
+            .map(str::to_string);
+        for expected in content_digest.into_iter().chain(pinned) {
+            let actual = ContentDigest::compute(&expected, &body, self.digest_algorithm);
+            if actual != expected {
+                return Err(Error::DigestMismatch { expected, actual });
+            }
+        }
+        Ok(ContentDigest::compute(
+            reference,
+            &body,
+            self.digest_algorithm,
+        ))
+    }
+
+    /// Read the body of a manifest or config blob response, honoring `Config::max_manifest_bytes`.
+    pub(crate) async fn read_manifest_body(&self, mut res: reqwest::Response) -> Result<Bytes> {
+        let limit = match self.max_manifest_bytes {
+            Some(limit) => limit,
+            None => return Ok(res.bytes().await?),
+        };
+        if res.content_length().is_some_and(|len| len > limit as u64) {
+            return Err(Error::ManifestTooLarge(limit));
+        }
+
+        // The Content-Length may be missing or wrong, so the read is capped as well.
+        let mut body = bytes::BytesMut::new();
+        while let Some(chunk) = res.chunk().await? {
+            if body.len() + chunk.len() > limit {
+                return Err(Error::ManifestTooLarge(limit));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body.freeze())
     }
 
     /// Fetch content digest for a particular tag.
@@ -141,9 +671,10 @@ impl Client {
         let accept_headers = build_accept_headers(&self.accepted_types);
 
         let res = self
-            .build_reqwest(Method::HEAD, url)
-            .headers(accept_headers)
-            .send()
+            .send_request(
+                self.build_reqwest(Method::HEAD, url)
+                    .headers(accept_headers),
+            )
             .await?;
 
         let status = res.status();
@@ -151,12 +682,11 @@ impl Client {
 
         match status {
             StatusCode::OK => {}
-            _ => return Err(Error::UnexpectedHttpStatus(status)),
+            _ => return Err(response_error(res, Error::UnexpectedHttpStatus(status)).await),
         }
 
-        let headers = res.headers();
-        let content_digest = match headers.get("docker-content-digest") {
-            Some(content_digest_value) => Some(content_digest_value.to_str()?.to_string()),
+        let content_digest = match content_digest_from_headers(res.headers()) {
+            Some(content_digest) => Some(content_digest),
             None => {
                 debug!("cannot find manifestref in headers");
                 None
@@ -165,10 +695,87 @@ impl Client {
         Ok(content_digest)
     }
 
+    /// Resolve a reference to the digest of its manifest, returning a `repo@digest` reference
+    /// to the same registry and repository.
+    ///
+    /// The digest is taken from a HEAD request, or computed from the manifest content
+    /// if the registry does not send it. A reference without a version resolves
+    /// the client's default tag.
+    pub async fn resolve_digest(
+        &self,
+        reference: &Reference,
+        ns: Option<&str>,
+    ) -> Result<Reference> {
+        let name = reference.repository();
+        let version = reference.version_or(&self.default_tag);
+        let digest = match self.get_manifestref(name, &version, ns).await? {
+            Some(digest) => digest,
+            None => self.verify_manifest_digest(name, &version, ns).await?,
+        };
+        Ok(format!("{}/{}@{}", reference.registry(), name, digest).parse()?)
+    }
+
+    /// Resolve an unambiguous prefix of the digest of a tagged manifest into the full digest,
+    /// like `docker` and `git` accept short digests.
+    ///
+    /// The prefix may omit the algorithm, as in `3f4a` or `sha256:3f4a`. Registries can't
+    /// look digests up by prefix, so this lists the tags of the repository and fetches the
+    /// digest of each of them, which makes it only suitable for interactive tools.
+    /// Untagged manifests are not found.
+    pub async fn resolve_short_digest(
+        &self,
+        name: &str,
+        prefix: &str,
+        ns: Option<&str>,
+    ) -> Result<String> {
+        let matches = |digest: &str| match prefix.contains(':') {
+            true => digest.starts_with(prefix),
+            false => digest
+                .split_once(':')
+                .is_some_and(|(_, encoded)| encoded.starts_with(prefix)),
+        };
+
+        let tags: Vec<String> = self.get_tags(name, None).try_collect().await?;
+        let mut candidates: Vec<String> = stream::iter(tags)
+            .map(|tag| async move {
+                match self.get_manifestref(name, &tag, ns).await? {
+                    Some(digest) => Ok(digest),
+                    None => self.verify_manifest_digest(name, &tag, ns).await,
+                }
+            })
+            .buffer_unordered(SHORT_DIGEST_CONCURRENCY)
+            .try_filter(|digest| future::ready(matches(digest)))
+            .try_collect()
+            .await?;
+        candidates.sort();
+        candidates.dedup();
+
+        match candidates.len() {
+            0 => Err(Error::NotFound(format!(
+                "no manifest of {} with a digest starting with '{}'",
+                name, prefix
+            ))),
+            1 => Ok(candidates.remove(0)),
+            _ => Err(Error::AmbiguousDigest {
+                prefix: prefix.to_string(),
+                candidates,
+            }),
+        }
+    }
+
     /// Check if an image manifest exists.
     ///
     /// The name and reference parameters identify the image.
     /// The reference may be either a tag or digest.
+    ///
+    /// The accepted media types default to `ManifestV2S2`; see
+    /// `MediaTypes::all_manifest_types` to accept any manifest. An entry which isn't
+    /// a valid media type is rejected with `Error::InvalidMediaType`.
+    ///
+    /// Returns `Error::Unauthorized` if the registry answers `401` or `403`,
+    /// in which case the client may need to `authenticate` before retrying.
+    ///
+    /// `manifest_head` makes the same request, and also returns the digest and size.
     pub async fn has_manifest(
         &self,
         name: &str,
@@ -176,30 +783,46 @@ impl Client {
         ns: Option<&str>,
         mediatypes: Option<&[&str]>,
     ) -> Result<Option<MediaTypes>> {
+        Ok(self
+            .manifest_head(name, reference, ns, mediatypes)
+            .await?
+            .map(|head| head.media_type))
+    }
+
+    /// Check if an image manifest exists, returning its media type, digest and size.
+    ///
+    /// This works like `has_manifest`, and saves a request to `get_manifestref`
+    /// when the digest is needed too.
+    pub async fn manifest_head(
+        &self,
+        name: &str,
+        reference: &str,
+        ns: Option<&str>,
+        mediatypes: Option<&[&str]>,
+    ) -> Result<Option<ManifestHead>> {
         let url = self.manifest_url(name, reference, ns)?;
         let accept_types = match mediatypes {
-            None => {
-                let m = MediaTypes::ManifestV2S2.to_mime();
-                Either::Left(std::iter::once(m))
-            }
-            Some(v) => Either::Right(to_mimes(v)),
+            None => vec![MediaTypes::ManifestV2S2.to_mime()],
+            Some(v) => to_mimes(v)?,
         };
 
-        let mut accept_headers = header::HeaderMap::with_capacity(accept_types.size_hint().0);
-        for accept_type in accept_types {
-            let header_value = header::HeaderValue::from_str(accept_type.as_ref())
-                .expect("mime type is always valid header value");
-            accept_headers.insert(header::ACCEPT, header_value);
-        }
+        // A single comma-separated value, as some registries only read the first Accept header.
+        let accept_header = accept_types
+            .iter()
+            .map(AsRef::as_ref)
+            .collect::<Vec<&str>>()
+            .join(",");
+        let accept_header = header::HeaderValue::from_str(&accept_header)
+            .expect("mime types are always valid header values");
 
         trace!("HEAD {:?}", url);
 
         let r = self
-            .build_reqwest(Method::HEAD, url)
-            .headers(accept_headers)
-            .send()
-            .await
-            .map_err(Error::from)?;
+            .send_request(
+                self.build_reqwest(Method::HEAD, url)
+                    .header(header::ACCEPT, accept_header),
+            )
+            .await?;
 
         let status = r.status();
 
@@ -214,26 +837,58 @@ impl Client {
             | StatusCode::TEMPORARY_REDIRECT
             | StatusCode::FOUND
             | StatusCode::OK => {
-                let media_type =
-                    evaluate_media_type(r.headers().get(header::CONTENT_TYPE), r.url())?;
+                let headers = r.headers();
+                let media_type = evaluate_media_type(headers.get(header::CONTENT_TYPE), r.url())?;
                 trace!("Manifest media-type: {:?}", media_type);
-                Ok(Some(media_type))
+                Ok(Some(ManifestHead {
+                    media_type,
+                    digest: content_digest_from_headers(headers),
+                    size: headers
+                        .get(header::CONTENT_LENGTH)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.parse().ok()),
+                }))
             }
             StatusCode::NOT_FOUND => Ok(None),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(Error::Unauthorized(status)),
+            StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&r)),
             _ => Err(Error::UnexpectedHttpStatus(status)),
         }
     }
 }
 
-fn to_mimes<'a>(v: &'a [&'a str]) -> impl Iterator<Item = mime::Mime> + 'a {
-    let res = v.iter().filter_map(|x| {
-        let mtype = MediaTypes::from_str(x);
-        match mtype {
-            Ok(m) => Some(m.to_mime()),
-            _ => None,
-        }
-    });
-    res
+fn to_mimes(v: &[&str]) -> Result<Vec<mime::Mime>> {
+    v.iter()
+        .map(|x| match MediaTypes::from_str(x)? {
+            MediaTypes::Other(s) => s.parse().map_err(|_| Error::InvalidMediaType(s)),
+            m => Ok(m.to_mime()),
+        })
+        .collect()
+}
+
+/// Read the `mediaType` field of a manifest, if any.
+fn sniff_media_type(body: &[u8]) -> Option<MediaTypes> {
+    #[derive(Deserialize)]
+    struct ManifestFields {
+        #[serde(rename = "mediaType")]
+        media_type: Option<MediaTypes>,
+        #[serde(rename = "schemaVersion")]
+        schema_version: Option<u16>,
+        manifests: Option<serde::de::IgnoredAny>,
+        signatures: Option<serde::de::IgnoredAny>,
+    }
+
+    let fields = serde_json::from_slice::<ManifestFields>(body).ok()?;
+    match (fields.media_type, fields.schema_version) {
+        (Some(MediaTypes::Other(_)), _) => None,
+        (Some(media_type), _) => Some(media_type),
+        (None, Some(1)) if fields.signatures.is_some() => Some(MediaTypes::ManifestV2S1Signed),
+        (None, Some(1)) => Some(MediaTypes::ManifestV2S1),
+        // Docker schema 2 manifests always declare their media type, unlike OCI ones.
+        (None, Some(2)) if fields.manifests.is_some() => Some(MediaTypes::OciV1ManifestList),
+        (None, Some(2)) => Some(MediaTypes::OciV1Manifest),
+        _ => None,
+    }
 }
 
 // Evaluate the `MediaTypes` from the the request header.
@@ -245,10 +900,11 @@ fn evaluate_media_type(
         .map(|hv| hv.to_str())
         .map(std::result::Result::unwrap_or_default);
 
-    let is_pulp_based = url.path().starts_with("/pulp/docker/v2");
+    // Pulp may itself be served under the path prefix of the registry.
+    let is_pulp_based = url.path().contains("/pulp/docker/v2/");
 
     match (header_content_type, is_pulp_based) {
-        (Some(header_value), false) => MediaTypes::from_str(header_value).map_err(Into::into),
+        (Some(header_value), false) => Ok(MediaTypes::from_content_type(header_value)),
         (None, false) => Err(Error::MediaTypeSniff),
         (Some(header_value), true) => {
             // TODO: remove this workaround once Satellite returns a proper content-type here
@@ -262,7 +918,7 @@ fn evaluate_media_type(
                 }
                 _ => {
                     debug!("Received content-type '{}' from pulp-based registry. Feeling lucky and trying to parse it...", header_value);
-                    MediaTypes::from_str(header_value).map_err(Into::into)
+                    Ok(MediaTypes::from_content_type(header_value))
                 }
             }
         }
@@ -298,6 +954,21 @@ fn build_accept_headers(accepted_types: &[(MediaTypes, Option<f64>)]) -> header:
     )])
 }
 
+/// Format an entity tag for `If-None-Match`, quoting a bare digest.
+fn quote_etag(etag: &str) -> Cow<'_, str> {
+    if etag.starts_with('"') || etag.starts_with("W/") {
+        Cow::Borrowed(etag)
+    } else {
+        Cow::Owned(format!("\"{}\"", etag))
+    }
+}
+
+/// The opaque part of an entity tag, to compare it with a digest or another entity tag.
+fn unquote_etag(etag: &str) -> &str {
+    let etag = etag.strip_prefix("W/").unwrap_or(etag);
+    etag.trim_matches('"')
+}
+
 /// Umbrella type for common actions on the different manifest schema types
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(untagged)]
@@ -313,10 +984,83 @@ pub enum ManifestError {
     NoArchitecture,
     #[error("architecture mismatch")]
     ArchitectureMismatch,
-    #[error("manifest {0} does not support the 'layer_digests' method")]
-    LayerDigestsUnsupported(String),
-    #[error("manifest {0} does not support the 'architecture' method")]
-    ArchitectureNotSupported(String),
+    #[error("manifest of type {0} does not support the 'layer_digests' method")]
+    LayerDigestsUnsupported(MediaTypes),
+    #[error("manifest of type {0} does not support the 'architecture' method")]
+    ArchitectureNotSupported(MediaTypes),
+    #[error("manifest of type {0} does not support the 'total_size' method")]
+    TotalSizeUnsupported(MediaTypes),
+    #[error("no manifest for platform {0}")]
+    PlatformNotFound(String),
+}
+
+/// Metadata of a manifest, as returned by `Client::manifest_head`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestHead {
+    media_type: MediaTypes,
+    digest: Option<String>,
+    size: Option<u64>,
+}
+
+impl ManifestHead {
+    /// Get the media type of the manifest.
+    pub fn media_type(&self) -> MediaTypes {
+        self.media_type.clone()
+    }
+
+    /// Get the digest of the manifest, if the registry sent it.
+    pub fn digest(&self) -> Option<&str> {
+        self.digest.as_deref()
+    }
+
+    /// Get the size of the manifest in bytes, if the registry sent it.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+}
+
+/// Layer of an image, as described by its manifest whatever its schema.
+///
+/// Schema 1 manifests only carry the digest of layers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LayerDescriptor<'a> {
+    digest: &'a str,
+    size: Option<u64>,
+    media_type: Option<&'a str>,
+    urls: Option<&'a [String]>,
+}
+
+impl<'a> LayerDescriptor<'a> {
+    /// Get the digest of the layer.
+    pub fn digest(&self) -> &'a str {
+        self.digest
+    }
+
+    /// Get the size of the layer in bytes, if the manifest declares it.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Get the media type of the layer, if the manifest declares it.
+    pub fn media_type(&self) -> Option<&'a str> {
+        self.media_type
+    }
+
+    /// Get the URLs the layer may be fetched from, if any.
+    pub fn urls(&self) -> Option<&'a [String]> {
+        self.urls
+    }
+}
+
+impl<'a> From<&'a Layer> for LayerDescriptor<'a> {
+    fn from(layer: &'a Layer) -> Self {
+        Self {
+            digest: layer.digest(),
+            size: Some(layer.size()),
+            media_type: Some(layer.media_type()),
+            urls: layer.urls(),
+        }
+    }
 }
 
 impl Manifest {
@@ -360,7 +1104,63 @@ impl Manifest {
             (Manifest::ML(m), _, _) => {
                 Ok(Either::Right(Either::Right(Either::Right(m.get_digests()))))
             }
-            _ => Err(ManifestError::LayerDigestsUnsupported(format!("{:?}", self)).into()),
+            _ => Err(ManifestError::LayerDigestsUnsupported(self.media_type()).into()),
+        }
+    }
+
+    /// List the digests of all blobs of the image, that is its config blob, if any,
+    /// followed by its layers.
+    ///
+    /// Schema 1 manifests have no config blob, so only their layers are listed.
+    /// Like `layers_digests`, this lists the digests of the child manifests of a manifest list.
+    pub fn all_blob_digests(
+        &self,
+        architecture: Option<&str>,
+    ) -> Result<impl Iterator<Item = &str>> {
+        let config = match self {
+            Manifest::S2(m) => Some(m.manifest_spec.config().digest.as_str()),
+            _ => None,
+        };
+        Ok(config.into_iter().chain(self.layers_digests(architecture)?))
+    }
+
+    /// Describe the layers of the image, base layer first.
+    ///
+    /// This gives the same view of the layers of schema 1 and schema 2 manifests,
+    /// except that schema 1 manifests only declare their digests.
+    /// Manifest lists have no layers, and result in an error.
+    pub fn descriptors(&self) -> Result<impl Iterator<Item = LayerDescriptor<'_>>> {
+        match self {
+            Manifest::S1Signed(m) => {
+                Ok(Either::Left(m.get_layers().map(|digest| LayerDescriptor {
+                    digest,
+                    size: None,
+                    media_type: None,
+                    urls: None,
+                })))
+            }
+            Manifest::S2(m) => Ok(Either::Right(
+                m.manifest_spec.layers().map(LayerDescriptor::from),
+            )),
+            Manifest::ML(_) => {
+                Err(ManifestError::LayerDigestsUnsupported(self.media_type()).into())
+            }
+        }
+    }
+
+    /// Total size in bytes of the image, that is its config blob plus all of its layers.
+    ///
+    /// Only schema 2 manifests carry size information, other manifest types result in an error.
+    pub fn total_size(&self, architecture: Option<&str>) -> Result<u64> {
+        match (self, architecture) {
+            (Manifest::S2(m), None) => Ok(m.total_size()),
+            (Manifest::S2(m), Some(a)) => {
+                if m.architecture() != a {
+                    return Err(ManifestError::ArchitectureMismatch.into());
+                }
+                Ok(m.total_size())
+            }
+            _ => Err(ManifestError::TotalSizeUnsupported(self.media_type()).into()),
         }
     }
 
@@ -373,12 +1173,69 @@ impl Manifest {
         }
     }
 
-    /// Media type of manifest
+    /// The architectures of the image the manifest points to, as owned strings.
+    ///
+    /// This is `architectures` collected, for keeping them once the manifest is dropped.
+    pub fn architectures_owned(&self) -> Vec<String> {
+        match self {
+            Manifest::S1Signed(m) => vec![m.architecture.to_string()],
+            Manifest::S2(m) => vec![m.architecture().to_string()],
+            Manifest::ML(m) => m.architectures().map(str::to_string).collect(),
+        }
+    }
+
+    /// List the digests of the layers, as owned strings.
+    ///
+    /// This is `layers_digests` collected, for keeping them once the manifest is dropped.
+    pub fn layers_digests_owned(&self, architecture: Option<&str>) -> Result<Vec<String>> {
+        Ok(self
+            .layers_digests(architecture)?
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Annotations of the manifest, if any.
+    ///
+    /// Only OCI manifests and indexes carry annotations.
+    pub fn annotations(&self) -> Option<&std::collections::BTreeMap<String, String>> {
+        match self {
+            Manifest::S1Signed(_) => None,
+            Manifest::S2(m) => m.annotations(),
+            Manifest::ML(m) => m.annotations(),
+        }
+    }
+
+    /// Schema version of the manifest: 1 for schema 1 manifests, 2 otherwise.
+    ///
+    /// Registries may return a schema 1 manifest for an image which was never converted,
+    /// even when schema 2 was requested.
+    pub fn schema_version(&self) -> u8 {
+        match self {
+            Manifest::S1Signed(_) => 1,
+            Manifest::S2(_) | Manifest::ML(_) => 2,
+        }
+    }
+
+    /// The manifest as fetched from the registry, byte for byte.
+    ///
+    /// Unlike re-serializing the manifest, this hashes to the digest of the manifest and
+    /// keeps the signatures of schema 1 manifests valid, so it can be pushed or stored as-is.
+    /// This is empty for manifests which were not fetched by a `Client`, such as
+    /// manifests deserialized by the caller.
+    pub fn raw_bytes(&self) -> &[u8] {
+        match self {
+            Manifest::S1Signed(m) => &m.raw_manifest,
+            Manifest::S2(m) => &m.raw_manifest,
+            Manifest::ML(m) => &m.raw_manifest,
+        }
+    }
+
+    /// Media type of manifest, Docker or OCI.
     pub fn media_type(&self) -> MediaTypes {
         match self {
             Manifest::S1Signed(_) => MediaTypes::ManifestV2S1Signed,
-            Manifest::S2(_) => MediaTypes::ManifestV2S2,
-            Manifest::ML(_) => MediaTypes::ManifestList,
+            Manifest::S2(m) => m.manifest_spec.media_type().clone(),
+            Manifest::ML(m) => m.media_type(),
         }
     }
 }
@@ -388,13 +1245,13 @@ mod tests {
     use super::*;
     use test_case::test_case;
 
-    use crate::v2::Client;
+    use crate::v2::{Client, Config};
 
-    #[test_case("not-gcr.io" => "application/vnd.docker.distribution.manifest.v2+json; q=0.5,application/vnd.docker.distribution.manifest.v1+prettyjws; q=0.4,application/vnd.docker.distribution.manifest.list.v2+json; q=0.5"; "Not gcr registry")]
-    #[test_case("gcr.io" => "application/vnd.docker.distribution.manifest.v2+json,application/vnd.docker.distribution.manifest.v1+prettyjws,application/vnd.docker.distribution.manifest.list.v2+json"; "gcr.io")]
-    #[test_case("foobar.gcr.io" => "application/vnd.docker.distribution.manifest.v2+json,application/vnd.docker.distribution.manifest.v1+prettyjws,application/vnd.docker.distribution.manifest.list.v2+json"; "Custom gcr.io registry")]
+    #[test_case("not-gcr.io" => "application/vnd.docker.distribution.manifest.v2+json; q=0.5,application/vnd.docker.distribution.manifest.v1+prettyjws; q=0.4,application/vnd.docker.distribution.manifest.list.v2+json; q=0.5,application/vnd.oci.image.index.v1+json; q=0.3,application/vnd.oci.image.manifest.v1+json; q=0.3"; "Not gcr registry")]
+    #[test_case("gcr.io" => "application/vnd.docker.distribution.manifest.v2+json,application/vnd.docker.distribution.manifest.v1+prettyjws,application/vnd.docker.distribution.manifest.list.v2+json,application/vnd.oci.image.index.v1+json,application/vnd.oci.image.manifest.v1+json"; "gcr.io")]
+    #[test_case("foobar.gcr.io" => "application/vnd.docker.distribution.manifest.v2+json,application/vnd.docker.distribution.manifest.v1+prettyjws,application/vnd.docker.distribution.manifest.list.v2+json,application/vnd.oci.image.index.v1+json,application/vnd.oci.image.manifest.v1+json"; "Custom gcr.io registry")]
     fn gcr_io_accept_headers(registry: &str) -> String {
-        let client_builder = Client::configure().registry(&registry);
+        let client_builder = Client::configure().registry(registry);
         let client = client_builder.build().unwrap();
         let header_map = build_accept_headers(&client.accepted_types);
         header_map
@@ -404,7 +1261,7 @@ mod tests {
             .unwrap()
             .to_string()
     }
-    #[test_case(None => "application/vnd.docker.distribution.manifest.v2+json; q=0.5,application/vnd.docker.distribution.manifest.v1+prettyjws; q=0.4,application/vnd.docker.distribution.manifest.list.v2+json; q=0.5"; "Default settings")]
+    #[test_case(None => "application/vnd.docker.distribution.manifest.v2+json; q=0.5,application/vnd.docker.distribution.manifest.v1+prettyjws; q=0.4,application/vnd.docker.distribution.manifest.list.v2+json; q=0.5,application/vnd.oci.image.index.v1+json; q=0.3,application/vnd.oci.image.manifest.v1+json; q=0.3"; "Default settings")]
     #[test_case(Some(vec![
         (MediaTypes::ManifestV2S2, Some(0.5)),
         (MediaTypes::ManifestV2S1Signed, Some(0.2)),
@@ -418,7 +1275,7 @@ mod tests {
         let registry = "https://example.com";
 
         let client_builder = Client::configure()
-            .registry(&registry)
+            .registry(registry)
             .accepted_types(accept_headers);
         let client = client_builder.build().unwrap();
         let header_map = build_accept_headers(&client.accepted_types);
@@ -429,4 +1286,85 @@ mod tests {
             .unwrap()
             .to_string()
     }
+
+    #[test_case(Config::oci_only => "application/vnd.oci.image.manifest.v1+json,application/vnd.oci.image.index.v1+json,application/vnd.oci.image.config.v1+json"; "OCI only")]
+    #[test_case(Config::docker_only => "application/vnd.docker.distribution.manifest.v2+json,application/vnd.docker.distribution.manifest.v1+prettyjws,application/vnd.docker.distribution.manifest.list.v2+json"; "Docker only")]
+    fn pinned_accept_headers(pin: fn(Config) -> Config) -> String {
+        let client = pin(Client::configure().registry("https://example.com"))
+            .build()
+            .unwrap();
+        let header_map = build_accept_headers(&client.accepted_types);
+        header_map
+            .get(header::ACCEPT)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test_case("gcr.io", true => "application/vnd.docker.distribution.manifest.v2+json; q=0.5,application/vnd.docker.distribution.manifest.v1+prettyjws; q=0.4,application/vnd.docker.distribution.manifest.list.v2+json; q=0.5,application/vnd.oci.image.index.v1+json; q=0.3,application/vnd.oci.image.manifest.v1+json; q=0.3"; "forced on gcr.io")]
+    #[test_case("example.com", false => "application/vnd.docker.distribution.manifest.v2+json,application/vnd.docker.distribution.manifest.v1+prettyjws,application/vnd.docker.distribution.manifest.list.v2+json,application/vnd.oci.image.index.v1+json,application/vnd.oci.image.manifest.v1+json"; "disabled elsewhere")]
+    fn accept_quality_weights(registry: &str, weights: bool) -> String {
+        let client = Client::configure()
+            .registry(registry)
+            .accept_quality_weights(weights)
+            .build()
+            .unwrap();
+        let header_map = build_accept_headers(&client.accepted_types);
+        header_map
+            .get(header::ACCEPT)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn accept_quality_weights_stripped_from_custom_types() {
+        let client = Client::configure()
+            .registry("example.com")
+            .accepted_types(Some(vec![(MediaTypes::ManifestV2S2, Some(0.5))]))
+            .accept_quality_weights(false)
+            .build()
+            .unwrap();
+        assert_eq!(
+            client.accepted_types,
+            vec![(MediaTypes::ManifestV2S2, None)]
+        );
+    }
+
+    #[test_case("https://example.com/pulp/docker/v2/repo/manifests/latest"; "pulp")]
+    #[test_case("https://example.com/prefix/pulp/docker/v2/repo/manifests/latest"; "pulp under a path prefix")]
+    fn pulp_workaround(url: &str) {
+        let url = Url::parse(url).unwrap();
+        let content_type = header::HeaderValue::from_static("application/x-troff-man");
+        assert_eq!(
+            evaluate_media_type(Some(&content_type), &url).unwrap(),
+            MediaTypes::ManifestV2S1Signed
+        );
+        assert_eq!(
+            evaluate_media_type(None, &url).unwrap(),
+            MediaTypes::ManifestV2S1Signed
+        );
+    }
+
+    #[test_case(r#"{"schemaVersion": 2, "mediaType": "application/vnd.docker.distribution.manifest.v2+json"}"# => Some(MediaTypes::ManifestV2S2); "declared")]
+    #[test_case(r#"{"schemaVersion": 1, "signatures": []}"# => Some(MediaTypes::ManifestV2S1Signed); "schema1 signed")]
+    #[test_case(r#"{"schemaVersion": 1}"# => Some(MediaTypes::ManifestV2S1); "schema1")]
+    #[test_case(r#"{"schemaVersion": 2, "manifests": []}"# => Some(MediaTypes::OciV1ManifestList); "oci index")]
+    #[test_case(r#"{"schemaVersion": 2, "layers": []}"# => Some(MediaTypes::OciV1Manifest); "oci manifest")]
+    #[test_case(r#"{"mediaType": "application/x-unknown"}"# => None; "unknown")]
+    #[test_case("not json" => None; "not json")]
+    fn sniff(body: &str) -> Option<MediaTypes> {
+        sniff_media_type(body.as_bytes())
+    }
+
+    #[test]
+    fn unsupported_error_shows_media_type() {
+        let err = ManifestError::TotalSizeUnsupported(MediaTypes::ManifestList);
+        assert_eq!(
+            err.to_string(),
+            "manifest of type application/vnd.docker.distribution.manifest.list.v2+json does not support the 'total_size' method"
+        );
+    }
 }
@@ -0,0 +1,140 @@
+//! Verification of the JWS signatures of schema 1 manifests.
+//!
+//! Each signature covers the manifest without its `signatures` block, which is rebuilt
+//! from the raw manifest with the `formatLength` and `formatTail` of the protected header,
+//! as described at <https://docs.docker.com/registry/spec/manifest-v2-1/#signed-manifests>.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::engine::Engine;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+
+use super::ManifestSchema1Signed;
+use crate::errors::{Error, Result};
+
+/// Unprotected header of a signature.
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    jwk: Option<Jwk>,
+}
+
+/// Public key embedded in a signature header.
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+/// Protected header of a signature, telling how to rebuild the signed payload.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Protected {
+    format_length: usize,
+    format_tail: String,
+}
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidManifestSignature(reason.into())
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|e| invalid(format!("invalid base64 in {}: {}", field, e)))
+}
+
+impl ManifestSchema1Signed {
+    /// Verify the signatures of this manifest, given the raw manifest it was parsed from.
+    ///
+    /// All signatures must be valid, and cover the content of the manifest.
+    /// Signatures are checked against the `jwk` key in their header, with the `ES256`,
+    /// `ES384` and `RS256` algorithms: this proves that the manifest was not altered since
+    /// it was signed, not who signed it.
+    ///
+    /// Failures are reported as `Error::InvalidManifestSignature`.
+    pub fn verify_signature(&self, raw: &[u8]) -> Result<()> {
+        if self.signatures.is_empty() {
+            return Err(invalid("manifest is not signed"));
+        }
+
+        let mut unsigned: serde_json::Value =
+            serde_json::from_slice(raw).map_err(|e| invalid(e.to_string()))?;
+        if let Some(object) = unsigned.as_object_mut() {
+            object.remove("signatures");
+        }
+
+        for sig in &self.signatures {
+            let protected: Protected =
+                serde_json::from_slice(&decode("protected", &sig.protected)?)
+                    .map_err(|e| invalid(format!("invalid protected header: {}", e)))?;
+            let mut payload = raw
+                .get(..protected.format_length)
+                .ok_or_else(|| invalid("formatLength exceeds the manifest"))?
+                .to_vec();
+            payload.extend(decode("formatTail", &protected.format_tail)?);
+            match serde_json::from_slice::<serde_json::Value>(&payload) {
+                Ok(signed) if signed == unsigned => {}
+                _ => return Err(invalid("signed payload differs from the manifest")),
+            }
+
+            let header: Header = serde_json::from_value(sig.header.clone())
+                .map_err(|e| invalid(format!("invalid header: {}", e)))?;
+            let message = format!("{}.{}", sig.protected, URL_SAFE_NO_PAD.encode(&payload));
+            verify(
+                &header,
+                message.as_bytes(),
+                &decode("signature", &sig.signature)?,
+            )?;
+        }
+        trace!("Verified {} signatures of manifest", self.signatures.len());
+
+        Ok(())
+    }
+}
+
+fn verify(header: &Header, message: &[u8], sig: &[u8]) -> Result<()> {
+    let jwk = header
+        .jwk
+        .as_ref()
+        .ok_or_else(|| invalid("no jwk in signature header"))?;
+    let ec_point = || -> Result<Vec<u8>> {
+        let (x, y) = match (&jwk.x, &jwk.y) {
+            (Some(x), Some(y)) => (decode("x", x)?, decode("y", y)?),
+            _ => return Err(invalid("incomplete EC key")),
+        };
+        Ok([&[0x04], x.as_slice(), y.as_slice()].concat())
+    };
+
+    let verified = match (header.alg.as_str(), jwk.kty.as_str(), jwk.crv.as_deref()) {
+        ("ES256", "EC", Some("P-256")) => {
+            UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, ec_point()?)
+                .verify(message, sig)
+        }
+        ("ES384", "EC", Some("P-384")) => {
+            UnparsedPublicKey::new(&signature::ECDSA_P384_SHA384_FIXED, ec_point()?)
+                .verify(message, sig)
+        }
+        ("RS256", "RSA", _) => {
+            let (n, e) = match (&jwk.n, &jwk.e) {
+                (Some(n), Some(e)) => (decode("n", n)?, decode("e", e)?),
+                _ => return Err(invalid("incomplete RSA key")),
+            };
+            RsaPublicKeyComponents { n, e }.verify(
+                &signature::RSA_PKCS1_2048_8192_SHA256,
+                message,
+                sig,
+            )
+        }
+        (alg, kty, _) => {
+            return Err(invalid(format!(
+                "unsupported algorithm {} with {} key",
+                alg, kty
+            )))
+        }
+    };
+    verified.map_err(|_| invalid("signature does not match"))
+}
@@ -0,0 +1,87 @@
+//! Fallback to registry mirrors for blobs.
+
+use std::future::Future;
+
+use crate::errors::{Error, Result};
+use crate::v2::*;
+
+impl Client {
+    /// Clients for the configured mirrors, in order.
+    ///
+    /// Mirrors are accessed without the credentials of the registry,
+    /// and always verify the digest of blobs.
+    fn mirror_clients(&self) -> impl Iterator<Item = Client> + '_ {
+        self.mirrors.iter().map(move |mirror| Client {
+            base_url: mirror.clone(),
+            credentials: None,
+            auth: Default::default(),
+            token_cache: Default::default(),
+            mirrors: Vec::new(),
+            verify_digests: true,
+            ..self.clone()
+        })
+    }
+
+    /// Run `op` against each mirror in order, then against this registry, until one succeeds.
+    ///
+    /// If all of them fail with mirrors configured, the error of each is returned
+    /// in `Error::Mirrors`.
+    pub(crate) async fn with_mirrors<T, F, Fut>(&self, op: F) -> Result<T>
+    where
+        F: Fn(Client) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut errors = Vec::new();
+        for client in self.mirror_clients() {
+            let mirror = client.base_url.to_string();
+            match op(client).await {
+                Ok(t) => return Ok(t),
+                Err(e) => {
+                    warn!("Mirror {} failed: {}", mirror, e);
+                    errors.push((mirror, e));
+                }
+            }
+        }
+        match op(self.clone()).await {
+            Ok(t) => Ok(t),
+            Err(e) if errors.is_empty() => Err(e),
+            Err(e) => {
+                errors.push((self.base_url.to_string(), e));
+                Err(Error::Mirrors(errors))
+            }
+        }
+    }
+
+    /// Find the first registry which has a blob, trying the mirrors in order, then this registry.
+    ///
+    /// Returns the base URL of that registry, or `None` if none of them has the blob.
+    /// Mirrors failing to answer are skipped, and reported in `Error::Mirrors`
+    /// along with the error of this registry if it fails too.
+    pub async fn blob_exists_any(
+        &self,
+        name: &str,
+        digest: &str,
+        ns: Option<&str>,
+    ) -> Result<Option<String>> {
+        let mut errors = Vec::new();
+        for client in self.mirror_clients() {
+            match client.has_blob_here(name, digest, ns).await {
+                Ok(true) => return Ok(Some(client.base_url.to_string())),
+                Ok(false) => {}
+                Err(e) => {
+                    warn!("Mirror {} failed: {}", client.base_url, e);
+                    errors.push((client.base_url.to_string(), e));
+                }
+            }
+        }
+        match self.has_blob_here(name, digest, ns).await {
+            Ok(true) => Ok(Some(self.base_url.to_string())),
+            Ok(false) => Ok(None),
+            Err(e) if errors.is_empty() => Err(e),
+            Err(e) => {
+                errors.push((self.base_url.to_string(), e));
+                Err(Error::Mirrors(errors))
+            }
+        }
+    }
+}
@@ -32,7 +32,8 @@ use crate::mediatypes::MediaTypes;
 use arcstr::ArcStr;
 use compact_str::CompactString;
 use futures::prelude::*;
-use reqwest::{Method, StatusCode, Url};
+use reqwest::{header, Method, StatusCode, Url};
+pub use tokio_util::sync::CancellationToken;
 
 mod config;
 pub use self::config::Config;
@@ -40,27 +41,98 @@ pub use self::config::Config;
 mod catalog;
 
 mod auth;
-pub use auth::WwwHeaderParseError;
+pub use auth::{AuthKind, BearerAuth, Scope, WwwHeaderParseError};
 
 pub mod manifest;
 
 mod tags;
 
+mod referrers;
+
 mod blobs;
+pub use self::blobs::{BlobMeta, BlobRedirectPolicy, BlobResponse, BlobStream, ChunkedBlobStream};
+
+mod mirrors;
+
+mod cache;
+pub use self::cache::{BlobCache, FsBlobCache};
+
+#[cfg(feature = "decompress")]
+mod decompress;
+#[cfg(feature = "decompress")]
+pub use self::decompress::Compression;
+
+mod upload;
+pub use self::upload::UploadSession;
+
+mod copy;
+pub use self::copy::CopyPlan;
+
+mod oci_layout;
+
+mod artifact;
+pub use self::artifact::ArtifactManifest;
+
+mod verify;
+pub use self::verify::ImageReport;
+
+mod pull;
+pub use self::pull::PulledImage;
+
+mod retry;
+pub(crate) use self::retry::is_retryable_status;
+pub use self::retry::RetryPolicy;
+
+mod rate_limit;
+pub use self::rate_limit::RateLimit;
 
 mod content_digest;
-pub(crate) use self::content_digest::ContentDigest;
-pub use self::content_digest::ContentDigestError;
+pub(crate) use self::content_digest::{content_digest_from_headers, ContentDigest};
+pub use self::content_digest::{digest_of, Algorithm, ContentDigestError};
+
+mod spans;
+
+mod clock;
 
 /// A Client to make outgoing API requests to a registry.
 #[derive(Clone, Debug)]
 pub struct Client {
     base_url: ArcStr,
-    credentials: Option<(CompactString, CompactString)>,
+    credentials: Option<auth::CredentialProvider>,
     user_agent: Option<ArcStr>,
-    auth: Option<auth::Auth>,
+    auth: auth::SharedAuth,
+    token_cache: auth::TokenCache,
     client: reqwest::Client,
+    blob_redirect_client: Option<reqwest::Client>,
     accepted_types: Vec<(MediaTypes, Option<f64>)>,
+    auto_reauth: bool,
+    no_auth: bool,
+    require_v2_header: bool,
+    oauth2: bool,
+    anonymous_fallback: bool,
+    prefer_basic: bool,
+    blob_timeout: Option<std::time::Duration>,
+    auth_timeout: std::time::Duration,
+    default_headers: reqwest::header::HeaderMap,
+    retry: Option<RetryPolicy>,
+    last_rate_limit: rate_limit::LastRateLimit,
+    blob_cache: Option<std::sync::Arc<dyn BlobCache>>,
+    verify_digests: bool,
+    digest_algorithm: Algorithm,
+    dry_run: bool,
+    max_manifest_bytes: Option<usize>,
+    max_blob_bytes: Option<usize>,
+    blob_redirect_policy: BlobRedirectPolicy,
+    max_redirects: usize,
+    max_pagination_pages: Option<usize>,
+    clock: clock::Clock,
+    default_tag: ArcStr,
+    default_namespace: Option<ArcStr>,
+    #[cfg(feature = "schema1-signatures")]
+    verify_schema1_signatures: bool,
+    mirrors: Vec<ArcStr>,
+    resolve_platform: Option<(CompactString, CompactString)>,
+    cancellation_token: Option<CancellationToken>,
 }
 
 impl Client {
@@ -113,11 +185,93 @@ impl Client {
         b
     }
 
+    /// Check that the remote registry speaks the v2 API, and whether it requires authentication.
+    ///
+    /// Unlike `is_auth`, this tells a registry requiring authentication apart from
+    /// a host which is not a registry at all, reported as `Error::UnexpectedHttpStatus`,
+    /// or as `Error::NotARegistry` with `Config::require_v2_header`.
+    pub async fn ping(&self) -> Result<RegistryInfo> {
+        let url = Url::parse(&format!("{}/v2/", self.base_url))?;
+        trace!("GET {:?}", url);
+        let response = self.build_reqwest(Method::GET, url).send().await?;
+        self.last_rate_limit.update(response.headers());
+        self.check_v2_header(&response)?;
+
+        let auth_required = match response.status() {
+            StatusCode::OK => false,
+            StatusCode::UNAUTHORIZED => true,
+            s => return Err(Error::UnexpectedHttpStatus(s)),
+        };
+        let api_version = response
+            .headers()
+            .get("docker-distribution-api-version")
+            .map(|v| v.to_str())
+            .transpose()?
+            .map(str::to_string);
+
+        Ok(RegistryInfo {
+            api_version,
+            auth_required,
+        })
+    }
+
+    /// Check the API version header of a response of the `/v2/` endpoint,
+    /// if `Config::require_v2_header` is set.
+    fn check_v2_header(&self, response: &reqwest::Response) -> Result<()> {
+        let version = response.headers().get("docker-distribution-api-version");
+        if self.require_v2_header && version.is_none_or(|v| v != "registry/2.0") {
+            trace!("Unexpected API version header {:?}", version);
+            return Err(Error::NotARegistry(response.url().to_string()));
+        }
+        Ok(())
+    }
+
+    /// A client sharing the configuration and authentication of this one,
+    /// whose requests and downloads are aborted once `token` is cancelled.
+    ///
+    /// This allows to cancel each operation separately, for example the pulls made on behalf
+    /// of a client of a server, while `Config::cancellation_token` cancels everything.
+    pub fn with_cancellation_token(&self, token: CancellationToken) -> Self {
+        Client {
+            cancellation_token: Some(token),
+            ..self.clone()
+        }
+    }
+
+    /// Namespace to send in the `ns` query parameter, falling back to `Config::default_namespace`.
+    fn namespace<'a>(&'a self, ns: Option<&'a str>) -> Option<&'a str> {
+        ns.or(self.default_namespace.as_deref())
+    }
+
     /// Takes reqwest's async RequestBuilder and injects an authentication header if a token is present
+    ///
+    /// The newest cached bearer token granting the action `method` needs on the repository
+    /// addressed by `url` takes precedence over the token obtained by the last call to
+    /// `authenticate`: `pull` for `GET` and `HEAD`, `delete` for `DELETE`, `push` otherwise.
+    /// Default headers are added first, skipping those computed by the client.
     fn build_reqwest(&self, method: Method, url: Url) -> reqwest::RequestBuilder {
-        let mut builder = self.client.request(method, url);
+        let cached = auth::repository_from_path(url.path()).and_then(|repository| {
+            self.token_cache.get_for_repository(
+                repository,
+                auth::required_action(&method),
+                self.clock.now(),
+            )
+        });
+
+        let mut default_headers = self.default_headers.clone();
+        default_headers.remove(reqwest::header::ACCEPT);
+        let auth = self.auth.get();
+        if cached.is_some() || auth.is_some() {
+            default_headers.remove(reqwest::header::AUTHORIZATION);
+        }
+        if self.user_agent.is_some() {
+            default_headers.remove(reqwest::header::USER_AGENT);
+        }
+        let mut builder = self.client.request(method, url).headers(default_headers);
 
-        if let Some(auth) = &self.auth {
+        if let Some(bearer_auth) = cached {
+            builder = auth::Auth::Bearer(bearer_auth).add_auth_headers(builder);
+        } else if let Some(auth) = &auth {
             builder = auth.add_auth_headers(builder);
         };
 
@@ -127,16 +281,359 @@ impl Client {
 
         builder
     }
+
+    /// Send a request obtained from `build_reqwest`.
+    ///
+    /// If `auto_reauth` is enabled and the registry answers `401 Unauthorized` with a bearer
+    /// challenge, a new token is obtained for the scope of the request and the request is retried once.
+    /// A rejection whose challenge names an `error` results in `Error::AuthChallenge`.
+    ///
+    /// GET and HEAD requests are retried on transient failures according to the `RetryPolicy`.
+    ///
+    /// The request fails with `Error::Cancelled` once the cancellation token of the client
+    /// is cancelled.
+    async fn send_request(&self, builder: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        self.send_request_with(&self.client, builder).await
+    }
+
+    /// Send a request as `send_request` does, executing it with `client`.
+    async fn send_request_with(
+        &self,
+        client: &reqwest::Client,
+        builder: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        cancellable(
+            self.cancellation_token.as_ref(),
+            self.send_request_retrying(client, builder),
+        )
+        .await
+    }
+
+    /// Send a request to a host outside of the registry, such as the URL of a foreign layer.
+    ///
+    /// The request is retried and cancelled as by `send_request`, but it is never
+    /// re-authenticated: the challenges of such hosts are not trusted with credentials,
+    /// and their tokens are not cached.
+    async fn send_foreign_request(
+        &self,
+        builder: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        cancellable(
+            self.cancellation_token.as_ref(),
+            self.retrying(builder, |request| async move {
+                self.client.execute(request).await.map_err(transport_error)
+            }),
+        )
+        .await
+    }
+
+    async fn send_request_retrying(
+        &self,
+        client: &reqwest::Client,
+        builder: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        self.retrying(builder, |request| self.execute_request(client, request))
+            .await
+    }
+
+    /// Execute a request with `execute`, retrying it according to the `RetryPolicy`.
+    async fn retrying<F, Fut>(
+        &self,
+        builder: reqwest::RequestBuilder,
+        execute: F,
+    ) -> Result<reqwest::Response>
+    where
+        F: Fn(reqwest::Request) -> Fut,
+        Fut: Future<Output = Result<reqwest::Response>>,
+    {
+        let mut request = builder.build()?;
+        let policy = match &self.retry {
+            Some(policy) if matches!(*request.method(), Method::GET | Method::HEAD) => policy,
+            _ => return execute(request).await,
+        };
+
+        let mut attempt = 1;
+        loop {
+            let next = match request.try_clone() {
+                Some(next) if attempt < policy.max_attempts => next,
+                _ => return execute(request).await,
+            };
+
+            let delay = match execute(request).await {
+                Ok(response) if retry::is_retryable_status(response.status()) => {
+                    let retry_after =
+                        retry::retry_after(response.headers(), std::time::SystemTime::now());
+                    trace!("{} {} got {}", next.method(), next.url(), response.status());
+                    policy.delay(attempt, retry_after)
+                }
+                Err(Error::Reqwest(e)) if e.is_connect() => {
+                    trace!("{} {} failed to connect: {}", next.method(), next.url(), e);
+                    policy.delay(attempt, None)
+                }
+                result => return result,
+            };
+
+            trace!("retrying in {:?} (attempt {})", delay, attempt + 1);
+            tokio::time::sleep(delay).await;
+            request = next;
+            attempt += 1;
+        }
+    }
+
+    /// Execute a request, re-authenticating once if allowed.
+    async fn execute_request(
+        &self,
+        client: &reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response> {
+        let retry = if self.auto_reauth {
+            request.try_clone()
+        } else {
+            None
+        };
+
+        let mut response = client.execute(request).await.map_err(transport_error)?;
+        if let Some(mut retry) = retry.filter(|_| response.status() == StatusCode::UNAUTHORIZED) {
+            if let Some(bearer_auth) = self
+                .reauthenticate(&response, retry.method(), retry.url())
+                .await?
+            {
+                bearer_auth.authorize_request(&mut retry)?;
+                trace!(
+                    "{} {} retrying after re-authentication",
+                    retry.method(),
+                    retry.url()
+                );
+                response = client.execute(retry).await.map_err(transport_error)?;
+            }
+        }
+
+        self.last_rate_limit.update(response.headers());
+        match Self::challenge_error(&response) {
+            Some(e) => Err(e),
+            None => Ok(response),
+        }
+    }
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
-struct ApiError {
-    code: String,
-    message: String,
-    detail: String,
+/// Information about a registry, as returned by `Client::ping`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegistryInfo {
+    api_version: Option<String>,
+    auth_required: bool,
+}
+
+impl RegistryInfo {
+    /// Get the value of the `Docker-Distribution-API-Version` header, e.g. `registry/2.0`, if sent.
+    pub fn api_version(&self) -> Option<&str> {
+        self.api_version.as_deref()
+    }
+
+    /// Whether the registry requires authentication for the v2 API.
+    pub fn auth_required(&self) -> bool {
+        self.auth_required
+    }
+}
+
+/// Run `fut` until it completes, or fail with `Error::Cancelled` once `token` is cancelled.
+pub(crate) async fn cancellable<T>(
+    token: Option<&CancellationToken>,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let token = match token {
+        Some(token) => token,
+        None => return fut.await,
+    };
+    let fut = std::pin::pin!(fut);
+    let cancelled = std::pin::pin!(token.cancelled());
+    match future::select(fut, cancelled).await {
+        future::Either::Left((result, _)) => result,
+        future::Either::Right(_) => {
+            trace!("Operation cancelled");
+            Err(Error::Cancelled)
+        }
+    }
+}
+
+/// Check a repository name against the grammar of the distribution spec.
+///
+/// Names are made of lowercase alphanumeric path components, separated by `/` and
+/// optionally containing `.`, `_`, `__` or runs of `-`, and are shorter than 256 characters.
+pub fn validate_name(name: &str) -> Result<()> {
+    const REGEX: &str =
+        r"^[a-z0-9]+(?:(?:\.|_|__|-+)[a-z0-9]+)*(?:/[a-z0-9]+(?:(?:\.|_|__|-+)[a-z0-9]+)*)*$";
+
+    let valid = name.len() < 256
+        && regex::Regex::new(REGEX)
+            .expect("hardcoded regex is invalid")
+            .is_match(name);
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidName(name.to_string()))
+    }
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 struct Errors {
-    errors: Vec<ApiError>,
+    errors: Vec<RegistryError>,
+}
+
+/// Parse the distribution error body of a failed response into `Error::Registry`, if any.
+async fn registry_error(resp: reqwest::Response) -> Option<Error> {
+    let status = resp.status();
+    let body = resp.bytes().await.ok()?;
+    let errors = serde_json::from_slice::<Errors>(&body).ok()?.errors;
+    if errors.is_empty() {
+        return None;
+    }
+    Some(Error::Registry { status, errors })
+}
+
+/// Turn a response with an unexpected status into an error.
+///
+/// A `429 Too Many Requests` is reported as `Error::RateLimited`. Otherwise, the distribution
+/// error body is reported as `Error::Registry` when the registry sends one,
+/// and `fallback` is returned if not.
+pub(crate) async fn response_error(resp: reqwest::Response, fallback: Error) -> Error {
+    if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+        return rate_limited(&resp);
+    }
+    registry_error(resp).await.unwrap_or(fallback)
+}
+
+/// Convert a transport error, unwrapping the `Error::TooManyRedirects` of the redirect policy.
+fn transport_error(e: reqwest::Error) -> Error {
+    let too_many_redirects = std::error::Error::source(&e)
+        .and_then(|source| source.downcast_ref::<Error>())
+        .and_then(|source| match source {
+            Error::TooManyRedirects(max) => Some(*max),
+            _ => None,
+        });
+    match too_many_redirects {
+        Some(max) if e.is_redirect() => Error::TooManyRedirects(max),
+        _ => e.into(),
+    }
+}
+
+/// `Error::RateLimited` for a `429 Too Many Requests` response.
+pub(crate) fn rate_limited(resp: &reqwest::Response) -> Error {
+    Error::RateLimited {
+        retry_after: retry::retry_after(resp.headers(), std::time::SystemTime::now()),
+    }
+}
+
+/// Parse a `Link` header, as described in RFC 8288, returning the target of its `rel="next"` link.
+///
+/// Relative targets, as sent by Quay, are resolved against `base`, the URL of the request.
+/// Links with other relations are ignored.
+pub(crate) fn parse_link_next(header: &header::HeaderValue, base: &Url) -> Option<Url> {
+    let mut rest = header.to_str().ok()?;
+    loop {
+        let start = rest.find('<')?;
+        let end = start + rest[start..].find('>')?;
+        let target = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+
+        // Parameters run until the next link, at a comma outside of a quoted string.
+        let mut quoted = false;
+        let params_end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    quoted = !quoted;
+                }
+                c == ',' && !quoted
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        let (params, next) = rest.split_at(params_end);
+        rest = next;
+
+        let is_next = params.split(';').any(|param| {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            key.trim().eq_ignore_ascii_case("rel")
+                && value
+                    .trim()
+                    .trim_matches('"')
+                    .split_ascii_whitespace()
+                    .any(|rel| rel.eq_ignore_ascii_case("next"))
+        });
+        if is_next {
+            return base.join(target.trim()).ok();
+        }
+    }
+}
+
+/// Error for a response with the given unexpected status, without its body.
+pub(crate) fn status_error(status: StatusCode) -> Error {
+    match status {
+        s if s.is_client_error() => Error::Client { status },
+        s if s.is_server_error() => Error::Server { status },
+        s => {
+            error!("Received unexpected HTTP status '{}'", s);
+            Error::UnexpectedHttpStatus(s)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{self, HeaderMap, HeaderValue};
+
+    #[test]
+    fn test_default_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-meta-test", HeaderValue::from_static("value"));
+        headers.insert(header::ACCEPT, HeaderValue::from_static("text/plain"));
+        headers.insert(header::USER_AGENT, HeaderValue::from_static("other-ua"));
+        let client = Client::configure()
+            .user_agent(Some("test-ua".into()))
+            .default_headers(headers)
+            .build()
+            .unwrap();
+
+        let url = Url::parse("https://registry.example.com/v2/repo/tags/list").unwrap();
+        let request = client
+            .build_reqwest(Method::GET, url)
+            .header(header::ACCEPT, "application/json")
+            .build()
+            .unwrap();
+
+        let headers = request.headers();
+        assert_eq!(headers.get("x-meta-test").unwrap(), "value");
+        let accept: Vec<_> = headers.get_all(header::ACCEPT).iter().collect();
+        assert_eq!(accept, vec!["application/json"]);
+        let user_agent: Vec<_> = headers.get_all(header::USER_AGENT).iter().collect();
+        assert_eq!(user_agent, vec!["test-ua"]);
+    }
+
+    #[test_case::test_case("busybox" => true; "single component")]
+    #[test_case::test_case("library/busy.box" => true; "namespace")]
+    #[test_case::test_case("a/b__c/d---e_f" => true; "separators")]
+    #[test_case::test_case("Busybox" => false; "uppercase")]
+    #[test_case::test_case("busybox/" => false; "trailing slash")]
+    #[test_case::test_case("busy..box" => false; "double dot")]
+    #[test_case::test_case("../busybox" => false; "traversal")]
+    #[test_case::test_case("" => false; "empty")]
+    fn test_validate_name(name: &str) -> bool {
+        validate_name(name).is_ok()
+    }
+
+    #[test_case::test_case(r#"<https://registry.example.com/v2/repo/tags/list?n=1&last=t1>; rel="next""# => Some("https://registry.example.com/v2/repo/tags/list?n=1&last=t1".to_string()); "absolute")]
+    #[test_case::test_case(r#"</v2/_catalog?n=1&last=repo>; rel="next""# => Some("https://other.example.com/v2/_catalog?n=1&last=repo".to_string()); "relative")]
+    #[test_case::test_case(r#"<?last=t1>; rel=next"# => Some("https://other.example.com/v2/repo/tags/list?last=t1".to_string()); "query only, unquoted")]
+    #[test_case::test_case(r#"</v2/first>; rel="first", </v2/page2?last=a,b>; title="x, y"; REL="prev next""# => Some("https://other.example.com/v2/page2?last=a,b".to_string()); "multiple links and rels")]
+    #[test_case::test_case(r#"</v2/prev>; rel="prev""# => None; "no next")]
+    #[test_case::test_case("garbage" => None; "garbage")]
+    fn test_parse_link_next(link: &str) -> Option<String> {
+        let base = Url::parse("https://other.example.com/v2/repo/tags/list?n=1").unwrap();
+        parse_link_next(&header::HeaderValue::from_str(link).unwrap(), &base).map(String::from)
+    }
+
+    #[test]
+    fn test_validate_name_length() {
+        assert!(validate_name(&"a".repeat(255)).is_ok());
+        assert!(validate_name(&"a".repeat(256)).is_err());
+    }
 }
@@ -0,0 +1,388 @@
+//! Exporting and importing images as OCI image layouts.
+//!
+//! See <https://github.com/opencontainers/image-spec/blob/main/image-layout.md>.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+
+use crate::errors::{Error, Result};
+use crate::mediatypes::MediaTypes;
+use crate::reference::{Digest, Reference};
+use crate::v2::manifest::{Descriptor, Manifest, ManifestList, ManifestSchema2Spec};
+use crate::v2::*;
+
+/// Annotation of the entries of `index.json` holding the tag of an image.
+const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+/// Content of the `oci-layout` file.
+const OCI_LAYOUT: &[u8] = br#"{"imageLayoutVersion":"1.0.0"}"#;
+
+/// The `index.json` file of a layout.
+///
+/// Entries are kept as they are, so that those written by other tools survive an export.
+#[derive(Debug, Deserialize, Serialize)]
+struct ImageIndex {
+    #[serde(rename = "schemaVersion")]
+    schema_version: u16,
+    #[serde(rename = "mediaType", default, skip_serializing_if = "Option::is_none")]
+    media_type: Option<MediaTypes>,
+    manifests: Vec<serde_json::Value>,
+    #[serde(flatten)]
+    other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Default for ImageIndex {
+    fn default() -> Self {
+        Self {
+            schema_version: 2,
+            media_type: Some(MediaTypes::OciV1ManifestList),
+            manifests: Vec::new(),
+            other: Default::default(),
+        }
+    }
+}
+
+/// Tag of an entry of `index.json`, if any.
+fn ref_name(entry: &serde_json::Value) -> Option<&str> {
+    entry
+        .pointer(&format!("/annotations/{}", REF_NAME_ANNOTATION))
+        .and_then(serde_json::Value::as_str)
+}
+
+impl ImageIndex {
+    /// Add an entry, replacing any entry for the same tag, or for the same untagged manifest.
+    fn add(&mut self, descriptor: &Descriptor) -> Result<()> {
+        let new_ref_name = descriptor
+            .annotations
+            .as_ref()
+            .and_then(|a| a.get(REF_NAME_ANNOTATION))
+            .map(String::as_str);
+        self.manifests.retain(|m| match new_ref_name {
+            Some(_) => ref_name(m) != new_ref_name,
+            None => ref_name(m).is_some() || m["digest"] != descriptor.digest.as_str(),
+        });
+        self.manifests.push(serde_json::to_value(descriptor)?);
+        Ok(())
+    }
+
+    /// Find the entry tagged `tag`, or else the only entry of the index.
+    fn find(&self, tag: Option<&str>) -> Option<&serde_json::Value> {
+        let tagged = tag.and_then(|tag| self.manifests.iter().find(|m| ref_name(m) == Some(tag)));
+        match (tagged, self.manifests.as_slice()) {
+            (Some(entry), _) => Some(entry),
+            (None, [entry]) => Some(entry),
+            (None, _) => None,
+        }
+    }
+}
+
+/// Tag of the image a reference points to, falling back to `default_tag`.
+fn reference_tag<'a>(reference: &'a Reference, default_tag: &'a str) -> Option<&'a str> {
+    match reference.has_version() {
+        true => reference.tag(),
+        false => Some(default_tag),
+    }
+}
+
+/// Path of a blob in a layout, as `blobs/<algorithm>/<encoded>`.
+fn blob_path(dir: &Path, digest: &str) -> Result<PathBuf> {
+    let digest: Digest = digest.parse()?;
+    Ok(dir
+        .join("blobs")
+        .join(digest.algorithm())
+        .join(digest.encoded()))
+}
+
+/// Path to write a file to before renaming it, so that a layout never holds partial files.
+fn tmp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+async fn write_file(path: &Path, content: &[u8]) -> Result<()> {
+    let tmp = tmp_path(path);
+    tokio::fs::write(&tmp, content).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+fn verify_blob(digest: &str, blob: &[u8]) -> Result<()> {
+    let mut content_digest = ContentDigest::try_new(digest)?;
+    content_digest.update(blob);
+    content_digest.verify()?;
+    Ok(())
+}
+
+/// Write a blob held in memory to a layout, after verifying its digest.
+async fn write_blob(dir: &Path, digest: &str, blob: &[u8]) -> Result<()> {
+    verify_blob(digest, blob)?;
+    let path = blob_path(dir, digest)?;
+    tokio::fs::create_dir_all(path.parent().unwrap_or(dir)).await?;
+    write_file(&path, blob).await
+}
+
+/// Read a blob from a layout, verifying that it matches the digest it is stored under.
+async fn read_blob(dir: &Path, digest: &str) -> Result<Vec<u8>> {
+    let blob = tokio::fs::read(blob_path(dir, digest)?).await?;
+    verify_blob(digest, &blob)?;
+    Ok(blob)
+}
+
+impl Client {
+    /// Pull an image into `dir` as an OCI image layout, as read by `skopeo`, `podman load`
+    /// or containerd.
+    ///
+    /// The image is resolved as in `pull`, and its manifest, config blob and layers are
+    /// written under `blobs/`, each verified against its digest. Foreign layers are not
+    /// stored by registries, so they are left out. Blobs already in the layout are not
+    /// downloaded again.
+    ///
+    /// The manifest is then added to `index.json`, annotated with the tag of the reference,
+    /// replacing any image with the same tag. Other entries of an existing layout are kept.
+    ///
+    /// Schema 1 manifests cannot be stored in a layout, and result in
+    /// `Error::UnsupportedMediaType`.
+    pub async fn export_oci_layout(
+        &self,
+        reference: &Reference,
+        dir: &Path,
+        platform: Option<(&str, &str)>,
+    ) -> Result<()> {
+        let image = self.pull(reference, platform).await?;
+        let (manifest, config) = match image.manifest() {
+            Manifest::S2(m) => (m, image.config().unwrap_or_default()),
+            m => return Err(Error::UnsupportedMediaType(m.media_type())),
+        };
+        let name = reference.repository();
+        let version = reference.version_or(&self.default_tag);
+
+        tokio::fs::create_dir_all(dir).await?;
+        write_file(&dir.join("oci-layout"), OCI_LAYOUT).await?;
+        write_blob(dir, &manifest.manifest_spec.config().digest, config).await?;
+
+        let client = self.verifying_digests();
+        for layer in manifest.layers().filter(|l| !l.is_foreign()) {
+            let path = blob_path(dir, layer.digest())?;
+            if tokio::fs::metadata(&path)
+                .await
+                .is_ok_and(|metadata| metadata.is_file())
+            {
+                trace!("Blob {} already in {}", layer.digest(), dir.display());
+                continue;
+            }
+            tokio::fs::create_dir_all(path.parent().unwrap_or(dir)).await?;
+            let tmp = tmp_path(&path);
+            let written = client.write_blob_to_file(name, layer.digest(), &tmp).await;
+            if let Err(e) = written {
+                let _ = tokio::fs::remove_file(&tmp).await;
+                return Err(e);
+            }
+            tokio::fs::rename(&tmp, &path).await?;
+        }
+
+        let raw_manifest = image.manifest().raw_bytes();
+        let digest = ContentDigest::compute(&version, raw_manifest, self.digest_algorithm);
+        write_blob(dir, &digest, raw_manifest).await?;
+
+        let tag = reference_tag(reference, &self.default_tag);
+        let descriptor = Descriptor {
+            media_type: manifest.manifest_spec.media_type().to_string().into(),
+            size: raw_manifest.len() as u64,
+            digest,
+            artifact_type: None,
+            annotations: tag.map(|tag| {
+                std::iter::once((REF_NAME_ANNOTATION.to_string(), tag.to_string())).collect()
+            }),
+        };
+
+        let index_path = dir.join("index.json");
+        let mut index = match tokio::fs::read(&index_path).await {
+            Ok(index) => serde_json::from_slice(&index)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => ImageIndex::default(),
+            Err(e) => return Err(e.into()),
+        };
+        index.add(&descriptor)?;
+        write_file(&index_path, &serde_json::to_vec_pretty(&index)?).await?;
+        trace!("Exported {}:{} to {}", name, version, dir.display());
+        Ok(())
+    }
+
+    /// Push the image of the OCI image layout in `dir` to `reference`, as written by
+    /// `export_oci_layout`, `skopeo` or `podman save`.
+    ///
+    /// The image is the entry of `index.json` tagged with the tag of the reference, or else
+    /// the only entry of the layout. Layouts with several images and none with this tag
+    /// result in `Error::NotFound`. Manifest lists are pushed along with all the manifests
+    /// they reference. Returns the digest of the manifest pushed.
+    ///
+    /// Every blob is checked against the digest it is stored under before it is pushed,
+    /// so a corrupted layout is rejected with `Error::DigestMismatch`. Blobs already in the
+    /// repository are skipped, and the registry is asked to mount the others from any
+    /// repository it has them in before they are uploaded.
+    pub async fn import_oci_layout(&self, dir: &Path, reference: &Reference) -> Result<String> {
+        let name = reference.repository();
+        let version = reference.version_or(&self.default_tag);
+        if !self.is_authenticated() {
+            self.authenticate_for(&[Scope::repository(name).pull().push()])
+                .await?;
+        }
+
+        let index: ImageIndex =
+            serde_json::from_slice(&tokio::fs::read(dir.join("index.json")).await?)?;
+        let tag = reference_tag(reference, &self.default_tag);
+        let entry = index.find(tag).ok_or_else(|| {
+            Error::NotFound(format!(
+                "no image tagged {} in {}",
+                tag.unwrap_or_default(),
+                dir.display()
+            ))
+        })?;
+        let descriptor: Descriptor = serde_json::from_value(entry.clone())?;
+        let digest = self
+            .import_manifest(
+                dir,
+                name,
+                &descriptor.digest,
+                &version,
+                &descriptor.media_type,
+            )
+            .await?;
+        trace!("Imported {} to {}:{}", dir.display(), name, version);
+        Ok(digest)
+    }
+
+    fn import_manifest<'a>(
+        &'a self,
+        dir: &'a Path,
+        name: &'a str,
+        digest: &'a str,
+        reference: &'a str,
+        media_type: &'a str,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let body = read_blob(dir, digest).await?;
+            let media_type = match media_type {
+                "" => {
+                    #[derive(Deserialize)]
+                    struct MediaTypeOnly {
+                        #[serde(rename = "mediaType")]
+                        media_type: MediaTypes,
+                    }
+                    serde_json::from_slice::<MediaTypeOnly>(&body)?.media_type
+                }
+                media_type => MediaTypes::from_content_type(media_type),
+            };
+
+            if media_type.is_index() {
+                let list: ManifestList = serde_json::from_slice(&body)?;
+                for manifest in &list.manifests {
+                    self.import_manifest(
+                        dir,
+                        name,
+                        manifest.digest(),
+                        manifest.digest(),
+                        manifest.media_type(),
+                    )
+                    .await?;
+                }
+            } else if let MediaTypes::ManifestV2S2 | MediaTypes::OciV1Manifest = media_type {
+                let spec: ManifestSchema2Spec = serde_json::from_slice(&body)?;
+                // Foreign layers are not stored by registries, nor in layouts.
+                let blobs = std::iter::once(spec.config().digest.as_str()).chain(
+                    spec.layers()
+                        .filter(|l| !l.is_foreign())
+                        .map(|l| l.digest()),
+                );
+                for blob in blobs {
+                    self.import_blob(dir, name, blob).await?;
+                }
+            } else {
+                return Err(Error::UnsupportedMediaType(media_type));
+            }
+
+            self.put_manifest(name, reference, &media_type, Bytes::from(body))
+                .await
+        })
+    }
+
+    async fn import_blob(&self, dir: &Path, name: &str, digest: &str) -> Result<()> {
+        if self.has_blob_here(name, digest, None).await? {
+            trace!("Blob {} already present in {}", digest, name);
+            return Ok(());
+        }
+        let blob = read_blob(dir, digest).await?;
+        if let Some(session) = self.start_upload_mounting(name, digest, None).await? {
+            self.finish_upload(session, digest, blob).await?;
+        }
+        Ok(())
+    }
+
+    /// Stream a blob to a file, verifying its digest at the end.
+    async fn write_blob_to_file(&self, name: &str, digest: &str, path: &Path) -> Result<()> {
+        let mut file = tokio::fs::File::create(path).await?;
+        self.download_blob_to(name, digest, &mut file, None).await?;
+        file.sync_all().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(digest: &str, tag: Option<&str>) -> Descriptor {
+        Descriptor {
+            media_type: MediaTypes::OciV1Manifest.to_string().into(),
+            size: 1,
+            digest: digest.to_string(),
+            artifact_type: None,
+            annotations: tag.map(|tag| {
+                std::iter::once((REF_NAME_ANNOTATION.to_string(), tag.to_string())).collect()
+            }),
+        }
+    }
+
+    #[test]
+    fn index_replaces_same_tag() {
+        let mut index: ImageIndex = serde_json::from_str(
+            r#"{"schemaVersion":2,"manifests":[{"mediaType":"application/vnd.oci.image.manifest.v1+json","size":1,"digest":"sha256:aa","platform":{"os":"linux","architecture":"amd64"},"annotations":{"org.opencontainers.image.ref.name":"v1"}}]}"#,
+        )
+        .unwrap();
+        index.add(&descriptor("sha256:bb", Some("v2"))).unwrap();
+        index.add(&descriptor("sha256:cc", None)).unwrap();
+        index.add(&descriptor("sha256:cc", None)).unwrap();
+        assert_eq!(index.manifests.len(), 3);
+        assert_eq!(index.manifests[0]["platform"]["os"], "linux");
+
+        index.add(&descriptor("sha256:dd", Some("v1"))).unwrap();
+        let digests: Vec<_> = index
+            .manifests
+            .iter()
+            .map(|m| m["digest"].as_str().unwrap())
+            .collect();
+        assert_eq!(digests, ["sha256:bb", "sha256:cc", "sha256:dd"]);
+    }
+
+    #[test]
+    fn blob_path_rejects_bad_digests() {
+        let dir = Path::new("layout");
+        assert_eq!(
+            blob_path(
+                dir,
+                "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"
+            )
+            .unwrap(),
+            dir.join(
+                "blobs/sha256/0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"
+            )
+        );
+        assert!(blob_path(dir, "sha256:../../etc").is_err());
+        assert!(blob_path(dir, "../sha256:abcd").is_err());
+    }
+}
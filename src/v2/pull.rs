@@ -0,0 +1,152 @@
+//! Pulling images in a single call.
+
+use crate::errors::{Error, Result};
+use crate::mediatypes::MediaTypes;
+use crate::reference::Reference;
+use crate::v2::manifest::{
+    LayerDescriptor, Manifest, ManifestError, ManifestList, ManifestObj, ManifestSchema2Spec,
+};
+use crate::v2::*;
+
+use bytes::Bytes;
+use futures::stream::{self, Stream, TryStreamExt};
+
+/// Image fetched by `Client::pull`, with the means to stream its layers.
+#[derive(Debug)]
+pub struct PulledImage<'a> {
+    client: &'a Client,
+    name: String,
+    manifest: Manifest,
+}
+
+impl<'a> PulledImage<'a> {
+    /// The image manifest, resolved from the manifest list if there was one.
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// The config blob as fetched, byte for byte.
+    ///
+    /// Schema 1 manifests have no config blob.
+    pub fn config(&self) -> Option<&[u8]> {
+        match &self.manifest {
+            Manifest::S2(m) => Some(&m.raw_config_blob),
+            _ => None,
+        }
+    }
+
+    /// The layers of the image, base layer first, each with the stream of its content.
+    ///
+    /// Streams are lazy: a layer is only requested once its stream is polled, so layers
+    /// can be consumed one after the other without holding a connection for each of them.
+    /// The digest of each layer is verified at the end of its stream, as per
+    /// `Config::verify_digests`.
+    pub fn layers(
+        &self,
+    ) -> impl Iterator<
+        Item = (
+            LayerDescriptor<'_>,
+            impl Stream<Item = Result<Bytes>> + Send + '_,
+        ),
+    > {
+        let client = self.client;
+        let name = self.name.as_str();
+        // Only image manifests are kept, so the descriptors are always available.
+        self.manifest
+            .descriptors()
+            .into_iter()
+            .flatten()
+            .map(move |layer| {
+                let blob =
+                    stream::once(client.get_blob_stream(name, layer.digest(), None)).try_flatten();
+                (layer, blob)
+            })
+    }
+
+    /// Take the manifest out of the pulled image.
+    pub fn into_manifest(self) -> Manifest {
+        self.manifest
+    }
+}
+
+impl Client {
+    /// Pull an image: fetch its manifest and config blob, and get ready to stream its layers.
+    ///
+    /// The client authenticates for pulling from the repository if it has not authenticated
+    /// yet. If the reference points to a manifest list, the entry for `platform`, given as
+    /// `(os, architecture)`, is selected as in `get_manifest_for_platform`. Without a platform,
+    /// a manifest list results in `Error::UnsupportedMediaType`.
+    /// A reference without a version pulls the default tag of the client.
+    ///
+    /// The lower-level methods used here remain available for finer control.
+    pub async fn pull(
+        &self,
+        reference: &Reference,
+        platform: Option<(&str, &str)>,
+    ) -> Result<PulledImage<'_>> {
+        let name = reference.repository();
+        let version = reference.version_or(&self.default_tag);
+        if !self.is_authenticated() {
+            self.authenticate_for(&[Scope::repository(name).pull()])
+                .await?;
+        }
+
+        let manifest = match platform {
+            Some((os, arch)) => {
+                self.get_manifest_for_platform(name, &version, os, arch, None)
+                    .await?
+            }
+            None => self.get_manifest_and_ref(name, &version, None).await?.0,
+        };
+        if let Manifest::ML(_) = manifest {
+            return Err(Error::UnsupportedMediaType(manifest.media_type()));
+        }
+        trace!("Pulled manifest of {}:{}", name, version);
+
+        Ok(PulledImage {
+            client: self,
+            name: name.to_string(),
+            manifest,
+        })
+    }
+
+    /// Compute the number of bytes to download to pull an image, that is the size of its
+    /// config blob plus all of its layers, as declared by its manifest.
+    ///
+    /// Only the manifest is fetched. If the reference points to a manifest list, the entry
+    /// for `platform`, given as `(os, architecture)`, is selected as in `pull`, and a manifest
+    /// list without a platform results in `Error::UnsupportedMediaType`. The platform of
+    /// an image manifest is not checked, as that would take fetching its config blob.
+    ///
+    /// Schema 1 manifests do not declare sizes, and result in
+    /// `ManifestError::TotalSizeUnsupported`.
+    pub async fn image_download_size(
+        &self,
+        reference: &Reference,
+        platform: Option<(&str, &str)>,
+        ns: Option<&str>,
+    ) -> Result<u64> {
+        let name = reference.repository();
+        let version = reference.version_or(&self.default_tag);
+        let (mut body, mut media_type, _) = self
+            .get_raw_manifest_and_metadata(name, &version, ns)
+            .await?;
+        if media_type.is_index() {
+            let (os, arch) = platform.ok_or(Error::UnsupportedMediaType(media_type))?;
+            let list: ManifestList = serde_json::from_slice(&body)?;
+            let digest = list
+                .manifest_for_platform(os, arch, None)
+                .map(ManifestObj::digest)
+                .ok_or_else(|| ManifestError::PlatformNotFound(format!("{}/{}", os, arch)))?;
+            (body, media_type, _) = self.get_raw_manifest_and_metadata(name, digest, ns).await?;
+        }
+
+        match media_type {
+            MediaTypes::ManifestV2S2 | MediaTypes::OciV1Manifest => {
+                let spec: ManifestSchema2Spec = serde_json::from_slice(&body)?;
+                Ok(spec.total_size())
+            }
+            m => Err(ManifestError::TotalSizeUnsupported(m).into()),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Rate-limit information announced by registries.
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+
+use crate::v2::*;
+
+const RATELIMIT_LIMIT: &str = "ratelimit-limit";
+const RATELIMIT_REMAINING: &str = "ratelimit-remaining";
+
+/// Rate limit announced by the `RateLimit-Limit` and `RateLimit-Remaining` response headers.
+///
+/// Docker Hub sends these as `100;w=21600`, with the window given in seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    limit: u64,
+    remaining: u64,
+    window: Option<Duration>,
+}
+
+impl RateLimit {
+    /// Parse the rate limit from response headers, if announced.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let (limit, limit_window) = parse_value(headers.get(RATELIMIT_LIMIT)?.to_str().ok()?)?;
+        let (remaining, remaining_window) =
+            parse_value(headers.get(RATELIMIT_REMAINING)?.to_str().ok()?)?;
+        Some(Self {
+            limit,
+            remaining,
+            window: limit_window.or(remaining_window),
+        })
+    }
+
+    /// Maximum number of requests allowed in a window.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Number of requests remaining in the current window.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Length of the window, if announced.
+    pub fn window(&self) -> Option<Duration> {
+        self.window
+    }
+}
+
+/// Parse a value like `100;w=21600`.
+fn parse_value(value: &str) -> Option<(u64, Option<Duration>)> {
+    let mut parts = value.split(';').map(str::trim);
+    let quota = parts.next()?.parse().ok()?;
+    let window = parts
+        .filter_map(|param| param.strip_prefix("w="))
+        .find_map(|w| w.parse().ok())
+        .map(Duration::from_secs);
+    Some((quota, window))
+}
+
+/// Last rate limit seen by a `Client`, shared by all its clones.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LastRateLimit(Arc<RwLock<Option<RateLimit>>>);
+
+impl LastRateLimit {
+    pub(crate) fn get(&self) -> Option<RateLimit> {
+        *self.0.read().ok()?
+    }
+
+    pub(crate) fn update(&self, headers: &HeaderMap) {
+        if let Some(rate_limit) = RateLimit::from_headers(headers) {
+            if let Ok(mut last) = self.0.write() {
+                *last = Some(rate_limit);
+            }
+        }
+    }
+}
+
+impl Client {
+    /// Return the rate limit announced by the last response which carried one.
+    ///
+    /// This allows pacing requests before the registry answers `429 Too Many Requests`.
+    pub fn last_rate_limit(&self) -> Option<RateLimit> {
+        self.last_rate_limit.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use test_case::test_case;
+
+    #[test_case("100;w=21600" => Some((100, Some(Duration::from_secs(21600)))); "with window")]
+    #[test_case("100" => Some((100, None)); "without window")]
+    #[test_case(" 76 ; w=60 " => Some((76, Some(Duration::from_secs(60)))); "whitespace")]
+    #[test_case("many" => None; "invalid")]
+    fn test_parse_value(value: &str) -> Option<(u64, Option<Duration>)> {
+        parse_value(value)
+    }
+
+    #[test]
+    fn test_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(RateLimit::from_headers(&headers), None);
+
+        headers.insert(RATELIMIT_LIMIT, HeaderValue::from_static("100;w=21600"));
+        headers.insert(RATELIMIT_REMAINING, HeaderValue::from_static("76;w=21600"));
+        let rate_limit = RateLimit::from_headers(&headers).unwrap();
+        assert_eq!(rate_limit.limit(), 100);
+        assert_eq!(rate_limit.remaining(), 76);
+        assert_eq!(rate_limit.window(), Some(Duration::from_secs(21600)));
+    }
+}
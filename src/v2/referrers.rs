@@ -0,0 +1,67 @@
+//! Listing the manifests referring to another with the OCI referrers API.
+
+use crate::errors::{Error, Result};
+use crate::mediatypes::MediaTypes;
+use crate::v2::manifest::ManifestList;
+use crate::v2::*;
+use reqwest::{self, header, StatusCode, Url};
+
+impl Client {
+    #[inline]
+    fn referrers_url(
+        &self,
+        name: &str,
+        digest: &str,
+        artifact_type: Option<&str>,
+        ns: Option<&str>,
+    ) -> Result<Url> {
+        validate_name(name)?;
+        let ep = format!("{}/v2/{}/referrers/{}", self.base_url, name, digest);
+        let mut url = Url::parse(&ep)?;
+        let ns = self.namespace(ns);
+        if artifact_type.is_some() || ns.is_some() {
+            let mut query = url.query_pairs_mut();
+            if let Some(v) = artifact_type {
+                query.append_pair("artifactType", v);
+            }
+            if let Some(v) = ns {
+                query.append_pair("ns", v);
+            }
+        }
+        Ok(url)
+    }
+
+    /// List the manifests referring to the given digest.
+    ///
+    /// The result is an OCI image index, optionally filtered by `artifact_type`, and is subject
+    /// to `Config::max_manifest_bytes`. Registries without the referrers API are reported
+    /// as `Error::ReferrersUnsupported`, unlike a missing repository or manifest on
+    /// a registry with the API, whose errors are reported as `Error::Registry`.
+    pub async fn get_referrers(
+        &self,
+        name: &str,
+        digest: &str,
+        artifact_type: Option<&str>,
+        ns: Option<&str>,
+    ) -> Result<ManifestList> {
+        let url = self.referrers_url(name, digest, artifact_type, ns)?;
+
+        let res = self
+            .send_request(
+                self.build_reqwest(Method::GET, url)
+                    .header(header::ACCEPT, MediaTypes::OciV1ManifestList.to_string()),
+            )
+            .await?;
+
+        let status = res.status();
+        trace!("GET '{}' status: {:?}", res.url(), status);
+
+        match status {
+            StatusCode::OK => Ok(serde_json::from_slice(
+                &self.read_manifest_body(res).await?,
+            )?),
+            StatusCode::NOT_FOUND => Err(response_error(res, Error::ReferrersUnsupported).await),
+            _ => Err(response_error(res, status_error(status)).await),
+        }
+    }
+}
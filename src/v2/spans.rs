@@ -0,0 +1,34 @@
+//! Structured `tracing` spans, enabled with the `tracing` cargo feature.
+//!
+//! Without the feature these macros expand to no-ops, leaving only the `log` output.
+
+/// Instrument a future with a debug-level span.
+#[cfg(feature = "tracing")]
+macro_rules! instrument {
+    ($fut:expr, $($span:tt)+) => {
+        tracing::Instrument::instrument($fut, tracing::debug_span!($($span)+))
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! instrument {
+    ($fut:expr, $($span:tt)+) => {
+        $fut
+    };
+}
+
+/// Record a field declared on the current span.
+#[cfg(feature = "tracing")]
+macro_rules! record {
+    ($field:literal, $value:expr) => {
+        tracing::Span::current().record($field, $value);
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! record {
+    ($field:literal, $value:expr) => {};
+}
+
+pub(crate) use instrument;
+pub(crate) use record;
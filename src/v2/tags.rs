@@ -1,8 +1,9 @@
 use crate::errors::Result;
+use crate::v2::manifest::Manifest;
 use crate::v2::*;
 use async_stream::try_stream;
+use futures::stream::StreamExt;
 use reqwest::{self, header, Url};
-use std::borrow::Cow;
 use std::fmt::Debug;
 
 /// A chunk of tags for an image.
@@ -19,48 +20,157 @@ struct TagsChunk {
 
 impl Client {
     /// List existing tags for an image.
+    ///
+    /// Pages are followed as announced by the registry, up to `Config::max_pagination_pages`:
+    /// past that, the stream ends with `Error::PaginationTruncated`.
     pub fn get_tags<'a, 'b: 'a, 'c: 'a>(
         &'b self,
         name: &'c str,
         paginate: Option<u32>,
     ) -> impl Stream<Item = Result<String>> + 'a {
-        let base_url = format!("{}/v2/{}/tags/list", self.base_url, name);
-        let mut link: Option<String> = None;
+        self.get_tags_ns(name, paginate, None)
+    }
 
+    /// List existing tags for an image, in the namespace `ns` if given.
+    fn get_tags_ns<'a>(
+        &'a self,
+        name: &'a str,
+        paginate: Option<u32>,
+        ns: Option<&'a str>,
+    ) -> impl Stream<Item = Result<String>> + 'a {
         try_stream! {
+            validate_name(name)?;
+            let base_url = Url::parse(&format!("{}/v2/{}/tags/list", self.base_url, name))?;
+            let mut url = base_url.clone();
+            if let Some(n) = paginate {
+                url.query_pairs_mut().append_pair("n", &n.to_string());
+            }
+            if let Some(ns) = self.namespace(ns) {
+                url.query_pairs_mut().append_pair("ns", ns);
+            }
+            let mut pages = 0;
             loop {
-                let (tags_chunk, last) = self.fetch_tags_chunk(paginate, &base_url, &link).await?;
+                let (tags_chunk, next) = self.fetch_tags_url(url).await?;
+                pages += 1;
                 for tag in tags_chunk.tags {
                     yield tag;
                 }
+                if next.is_some() && self.max_pagination_pages.is_some_and(|max| pages >= max) {
+                    Err(Error::PaginationTruncated(pages))?;
+                }
 
-                link = match last {
-                    None => break,
-                    Some(ref s) if s.is_empty() => None,
-                    s => s,
+                // Only the query of the next link is followed, as some registries
+                // send a path other than the one of the tags endpoint.
+                url = match next.as_ref().and_then(Url::query) {
+                    None | Some("") => break,
+                    Some(query) => {
+                        let mut url = base_url.clone();
+                        url.set_query(Some(query));
+                        if let Some(ns) = self.namespace(ns) {
+                            if !url.query_pairs().any(|(k, _)| k == "ns") {
+                                url.query_pairs_mut().append_pair("ns", ns);
+                            }
+                        }
+                        url
+                    }
                 };
             }
         }
     }
 
-    async fn fetch_tags_chunk(
+    /// List existing tags for an image, along with the digest of the manifest each one points to.
+    ///
+    /// Tags are listed following pagination, and their digests are resolved with up to
+    /// `concurrency` HEAD requests in flight, so results may arrive in any order.
+    pub fn stream_tags_with_digests<'a>(
+        &'a self,
+        name: &'a str,
+        concurrency: usize,
+        ns: Option<&'a str>,
+    ) -> impl Stream<Item = Result<(String, Option<String>)>> + 'a {
+        self.get_tags_ns(name, None, ns)
+            .map(move |tag| async move {
+                let tag = tag?;
+                let digest = self.get_manifestref(name, &tag, ns).await?;
+                Ok((tag, digest))
+            })
+            .buffer_unordered(concurrency.max(1))
+    }
+
+    /// List existing tags for an image, along with the manifest each one points to.
+    ///
+    /// Tags are listed following pagination, and their manifests are fetched with up to
+    /// `concurrency` requests in flight, so results may arrive in any order. Failing to
+    /// fetch the manifest of a tag yields an error for that tag only, and the stream goes on.
+    pub fn stream_tags_with_manifests<'a>(
+        &'a self,
+        name: &'a str,
+        concurrency: usize,
+        ns: Option<&'a str>,
+    ) -> impl Stream<Item = Result<(String, Manifest)>> + 'a {
+        self.get_tags_ns(name, None, ns)
+            .map(move |tag| async move {
+                let tag = tag?;
+                let manifest = self.get_manifest(name, &tag, ns).await?;
+                Ok((tag, manifest))
+            })
+            .buffer_unordered(concurrency.max(1))
+    }
+
+    /// Fetch a single page of tags for an image, for callers driving pagination themselves.
+    ///
+    /// At most `n` tags are requested, following the tag `last` if given.
+    /// Returns the tags along with the `last` value to fetch the next page with,
+    /// taken from the `Link` header or else the final tag of a full page,
+    /// or `None` if there are no more pages.
+    pub async fn get_tags_page(
         &self,
-        paginate: Option<u32>,
-        base_url: &str,
-        link: &Option<String>,
-    ) -> Result<(TagsChunk, Option<String>)> {
-        let url_paginated = match (paginate, link) {
-            (Some(p), None) => Cow::Owned(format!("{}?n={}", base_url, p)),
-            (None, Some(l)) => Cow::Owned(format!("{}?{}", base_url, l)),
-            (Some(_p), Some(l)) => Cow::Owned(format!("{}?{}", base_url, l)),
-            _ => Cow::Borrowed(base_url),
+        name: &str,
+        n: Option<u32>,
+        last: Option<&str>,
+        ns: Option<&str>,
+    ) -> Result<(Vec<String>, Option<String>)> {
+        validate_name(name)?;
+        let mut url = Url::parse(&format!("{}/v2/{}/tags/list", self.base_url, name))?;
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(n) = n {
+                query.append_pair("n", &n.to_string());
+            }
+            if let Some(last) = last {
+                query.append_pair("last", last);
+            }
+            if let Some(ns) = self.namespace(ns) {
+                query.append_pair("ns", ns);
+            }
+        }
+        if url.query() == Some("") {
+            url.set_query(None);
+        }
+
+        let (tags_chunk, link) = self.fetch_tags_url(url).await?;
+        let next = match link {
+            Some(link) => link
+                .query_pairs()
+                .find(|(key, _)| key == "last")
+                .map(|(_, value)| value.into_owned())
+                .or_else(|| tags_chunk.tags.last().cloned()),
+            None => match n {
+                Some(n) if tags_chunk.tags.len() >= n as usize && n > 0 => {
+                    tags_chunk.tags.last().cloned()
+                }
+                _ => None,
+            },
         };
-        let url = Url::parse(&url_paginated)?;
+        Ok((tags_chunk.tags, next))
+    }
 
+    async fn fetch_tags_url(&self, url: Url) -> Result<(TagsChunk, Option<Url>)> {
         let resp = self
-            .build_reqwest(Method::GET, url)
-            .header(header::ACCEPT, "application/json")
-            .send()
+            .send_request(
+                self.build_reqwest(Method::GET, url)
+                    .header(header::ACCEPT, "application/json"),
+            )
             .await?
             .error_for_status()?;
 
@@ -80,39 +190,13 @@ impl Client {
         }
 
         // extract the response body and parse the LINK header
-        let next = parse_link(resp.headers().get(header::LINK));
+        let next = resp
+            .headers()
+            .get(header::LINK)
+            .and_then(|link| parse_link_next(link, resp.url()));
         trace!("next_page {:?}", next);
 
         let tags_chunk = resp.json::<TagsChunk>().await?;
         Ok((tags_chunk, next))
     }
 }
-
-/// Parse a `Link` header.
-///
-/// Format is described at https://docs.docker.com/registry/spec/api/#listing-image-tags#pagination.
-fn parse_link(hdr: Option<&header::HeaderValue>) -> Option<String> {
-    // TODO(lucab): this a brittle string-matching parser. Investigate
-    // whether there is a a common library to do this, in the future.
-
-    // Raw Header value bytes.
-    let hval = match hdr {
-        Some(v) => v,
-        None => return None,
-    };
-
-    // Header value string.
-    let sval = match hval.to_str() {
-        Ok(v) => v.to_owned(),
-        _ => return None,
-    };
-
-    // Query parameters for next page URL.
-    let uri = sval.trim_end_matches(">; rel=\"next\"");
-    let query: Vec<&str> = uri.splitn(2, '?').collect();
-    match query.get(1) {
-        //use the entire query param string since some registries have different ways of pagination
-        Some(v) if !v.is_empty() => Some(v.to_string()),
-        _ => None,
-    }
-}
@@ -0,0 +1,397 @@
+use crate::errors::{Error, Result};
+use crate::v2::*;
+use bytes::Bytes;
+use reqwest::{self, header, Body, StatusCode, Url};
+
+/// An upload session for a blob, started with `Client::start_upload`.
+///
+/// Data is sent with `Client::upload_chunk` and the blob is committed
+/// with `Client::finish_upload`.
+///
+/// A session may outlive the process by persisting its `location` and `offset`,
+/// see `UploadSession::from_location` to resume it.
+#[derive(Clone, Debug)]
+pub struct UploadSession {
+    location: Url,
+    uuid: Option<String>,
+    offset: u64,
+    digest: Option<ContentDigest>,
+}
+
+impl UploadSession {
+    fn new(location: Url) -> Self {
+        Self {
+            location,
+            uuid: None,
+            offset: 0,
+            digest: None,
+        }
+    }
+
+    /// Reconstruct a session from a persisted `location`, after `offset` bytes were uploaded.
+    ///
+    /// The location must be on the registry of `client`. As the offset may be stale,
+    /// `Client::upload_status` should be used to get the one of the registry before resuming.
+    pub fn from_location(client: &Client, location: Url, offset: u64) -> Result<Self> {
+        let registry = Url::parse(&client.base_url)?;
+        if location.scheme() != registry.scheme()
+            || location.host_str() != registry.host_str()
+            || location.port_or_known_default() != registry.port_or_known_default()
+        {
+            return Err(Error::ForeignUploadLocation(location.to_string()));
+        }
+        Ok(Self {
+            offset,
+            ..Self::new(location)
+        })
+    }
+
+    /// Create a session at `location`, with the UUID announced in `resp`.
+    fn from_response(location: Url, resp: &reqwest::Response) -> Self {
+        Self {
+            uuid: upload_uuid(resp),
+            ..Self::new(location)
+        }
+    }
+
+    /// URL to send the next request of this upload to.
+    pub fn location(&self) -> &Url {
+        &self.location
+    }
+
+    /// Identifier of the session, from the `Docker-Upload-UUID` header of the registry.
+    ///
+    /// This helps correlating the requests of an upload in logs, especially with registries
+    /// whose `Location` URLs are opaque.
+    pub fn uuid(&self) -> Option<&str> {
+        self.uuid.as_deref()
+    }
+
+    /// Number of bytes uploaded so far, as last reported by the registry.
+    ///
+    /// Streamed chunks are only accounted for if the registry reports the `Range` of the upload.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Hash the data sent through this session, so that `Client::finish_upload` fails
+    /// with `Error::DigestMismatch` before committing the blob if it doesn't match `digest`.
+    ///
+    /// Only data held in memory can be hashed: sending a streamed body through
+    /// a verified session fails with `Error::UnverifiableUploadBody`, as does verifying
+    /// a session resumed with `UploadSession::from_location` after data was sent.
+    pub fn verify_digest(mut self, digest: &str) -> Result<Self> {
+        if self.offset > 0 {
+            return Err(Error::UnverifiableUploadBody);
+        }
+        self.digest = Some(ContentDigest::try_new(digest)?);
+        Ok(self)
+    }
+
+    /// Hash a body about to be sent, if verifying.
+    fn hash(&mut self, body: &Body) -> Result<()> {
+        if let Some(digest) = &mut self.digest {
+            digest.update(body.as_bytes().ok_or(Error::UnverifiableUploadBody)?);
+        }
+        Ok(())
+    }
+}
+
+/// Get the `Docker-Upload-UUID` header of an upload response, if any.
+fn upload_uuid(resp: &reqwest::Response) -> Option<String> {
+    let uuid = resp
+        .headers()
+        .get("docker-upload-uuid")?
+        .to_str()
+        .ok()?
+        .trim();
+    Some(uuid.to_string()).filter(|uuid| !uuid.is_empty())
+}
+
+/// Get the number of bytes uploaded from the `Range` header of an upload response.
+///
+/// The header is inclusive, `0-1023` meaning that 1024 bytes were uploaded.
+fn upload_range_end(resp: &reqwest::Response) -> Result<Option<u64>> {
+    let range = match resp.headers().get(header::RANGE) {
+        Some(range) => range.to_str()?,
+        None => return Ok(None),
+    };
+    range
+        .trim()
+        .strip_prefix("0-")
+        .and_then(|end| end.parse::<u64>().ok())
+        .map(|end| Some(end + 1))
+        .ok_or_else(|| Error::InvalidUploadRange(range.to_string()))
+}
+
+impl Client {
+    /// Resolve the `Location` header of an upload response, which may be relative.
+    fn upload_location(&self, resp: &reqwest::Response) -> Result<Url> {
+        let location = resp
+            .headers()
+            .get(header::LOCATION)
+            .ok_or(Error::MissingHeader(header::LOCATION.as_str()))?
+            .to_str()?;
+        Ok(Url::parse(&self.base_url)?.join(location)?)
+    }
+
+    /// Check the status of an upload response.
+    ///
+    /// A `413 Payload Too Large` is reported as `Error::QuotaExceeded`.
+    async fn check_upload_status(
+        resp: reqwest::Response,
+        expected: StatusCode,
+    ) -> Result<reqwest::Response> {
+        let status = resp.status();
+        trace!("{} status: {}", resp.url(), status);
+        match status {
+            s if s == expected => Ok(resp),
+            StatusCode::PAYLOAD_TOO_LARGE => Err(Error::QuotaExceeded),
+            s => Err(response_error(resp, status_error(s)).await),
+        }
+    }
+
+    /// Start an upload session for a blob in the given repository.
+    ///
+    /// With `Config::dry_run`, no session is started on the registry.
+    pub async fn start_upload(&self, name: &str) -> Result<UploadSession> {
+        validate_name(name)?;
+        let url = Url::parse(&format!("{}/v2/{}/blobs/uploads/", self.base_url, name))?;
+        if self.dry_run {
+            trace!("Dry run: not starting an upload to {}", name);
+            return Ok(UploadSession::new(url));
+        }
+        let resp = self
+            .send_request(self.build_reqwest(Method::POST, url))
+            .await?;
+        let resp = Self::check_upload_status(resp, StatusCode::ACCEPTED).await?;
+
+        let session = UploadSession::from_response(self.upload_location(&resp)?, &resp);
+        trace!("Started upload {:?} to {}", session.uuid(), name);
+        Ok(session)
+    }
+
+    /// Mount a blob from another repository of the same registry, without uploading it.
+    ///
+    /// Returns `None` if the blob was mounted. Registries which cannot mount the blob
+    /// start a regular upload session instead, which is returned to upload the blob with.
+    /// With `Config::dry_run`, nothing is sent and the blob is reported as mounted.
+    pub async fn mount_blob(
+        &self,
+        name: &str,
+        digest: &str,
+        from: &str,
+    ) -> Result<Option<UploadSession>> {
+        validate_name(from)?;
+        self.start_upload_mounting(name, digest, Some(from)).await
+    }
+
+    /// Start an upload session, asking the registry to mount the blob from `from` instead.
+    ///
+    /// Without `from`, registries supporting it look for the blob in any repository
+    /// the client may read from, and others start a regular upload session.
+    /// Returns `None` if the blob was mounted, as `mount_blob`.
+    pub(crate) async fn start_upload_mounting(
+        &self,
+        name: &str,
+        digest: &str,
+        from: Option<&str>,
+    ) -> Result<Option<UploadSession>> {
+        validate_name(name)?;
+        let mut url = Url::parse(&format!("{}/v2/{}/blobs/uploads/", self.base_url, name))?;
+        if self.dry_run {
+            return match from {
+                Some(from) => {
+                    trace!(
+                        "Dry run: not mounting blob {} from {} into {}",
+                        digest,
+                        from,
+                        name
+                    );
+                    Ok(None)
+                }
+                None => {
+                    trace!("Dry run: not starting an upload to {}", name);
+                    Ok(Some(UploadSession::new(url)))
+                }
+            };
+        }
+        url.query_pairs_mut().append_pair("mount", digest);
+        if let Some(from) = from {
+            url.query_pairs_mut().append_pair("from", from);
+        }
+        let resp = self
+            .send_request(self.build_reqwest(Method::POST, url))
+            .await?;
+        if resp.status() == StatusCode::CREATED {
+            trace!("Mounted blob {} into {}", digest, name);
+            return Ok(None);
+        }
+        let resp = Self::check_upload_status(resp, StatusCode::ACCEPTED).await?;
+
+        let session = UploadSession::from_response(self.upload_location(&resp)?, &resp);
+        trace!("Started upload {:?} to {}", session.uuid(), name);
+        Ok(Some(session))
+    }
+
+    /// Send a chunk of data to an upload session.
+    ///
+    /// The body is streamed to the registry when built from a stream, for example with
+    /// `reqwest::Body::wrap_stream`.
+    pub async fn upload_chunk(
+        &self,
+        session: &mut UploadSession,
+        body: impl Into<Body>,
+    ) -> Result<()> {
+        let body = body.into();
+        session.hash(&body)?;
+        let len = body.as_bytes().map(|bytes| bytes.len() as u64);
+        if self.dry_run {
+            return Ok(());
+        }
+        trace!("Sending chunk of upload {:?}", session.uuid());
+        let resp = self
+            .send_request(
+                self.build_reqwest(Method::PATCH, session.location.clone())
+                    .header(header::CONTENT_TYPE, "application/octet-stream")
+                    .body(body),
+            )
+            .await?;
+        let resp = Self::check_upload_status(resp, StatusCode::ACCEPTED).await?;
+
+        session.location = self.upload_location(&resp)?;
+        if let Some(uuid) = upload_uuid(&resp) {
+            session.uuid = Some(uuid);
+        }
+        match (upload_range_end(&resp)?, len) {
+            (Some(offset), _) => session.offset = offset,
+            (None, Some(len)) => session.offset += len,
+            (None, None) => {}
+        }
+        trace!(
+            "Sent chunk of upload {:?}, at offset {}",
+            session.uuid(),
+            session.offset
+        );
+        Ok(())
+    }
+
+    /// Get the status of an upload session from the registry, to resume it.
+    ///
+    /// The location and offset of the session are updated from the response,
+    /// and the offset is returned.
+    pub async fn upload_status(&self, session: &mut UploadSession) -> Result<u64> {
+        if self.dry_run {
+            return Ok(session.offset);
+        }
+        let resp = self
+            .send_request(self.build_reqwest(Method::GET, session.location.clone()))
+            .await?;
+        let resp = Self::check_upload_status(resp, StatusCode::NO_CONTENT).await?;
+
+        if resp.headers().contains_key(header::LOCATION) {
+            session.location = self.upload_location(&resp)?;
+        }
+        if let Some(uuid) = upload_uuid(&resp) {
+            session.uuid = Some(uuid);
+        }
+        session.offset =
+            upload_range_end(&resp)?.ok_or(Error::MissingHeader(header::RANGE.as_str()))?;
+        trace!(
+            "Upload {:?} is at offset {}",
+            session.uuid(),
+            session.offset
+        );
+        Ok(session.offset)
+    }
+
+    /// Commit an upload session as the blob with the given digest, sending a last chunk of data.
+    ///
+    /// Returns the digest of the blob as reported by the registry.
+    ///
+    /// If the session verifies its digest, the data sent is checked against `digest`
+    /// before the blob is committed. A registry rejecting a digest algorithm other than
+    /// sha256 results in `Error::UnsupportedDigestAlgorithm`.
+    ///
+    /// With `Config::dry_run`, the session is verified as usual, but nothing is sent
+    /// and `digest` is returned.
+    pub async fn finish_upload(
+        &self,
+        mut session: UploadSession,
+        digest: &str,
+        body: impl Into<Body>,
+    ) -> Result<String> {
+        let body = body.into();
+        session.hash(&body)?;
+        if let Some(content_digest) = session.digest {
+            if content_digest.as_str() != digest {
+                return Err(Error::DigestMismatch {
+                    expected: content_digest.as_str().to_string(),
+                    actual: digest.to_string(),
+                });
+            }
+            content_digest.verify()?;
+        }
+        if self.dry_run {
+            trace!("Dry run: not committing blob {}", digest);
+            return Ok(digest.to_string());
+        }
+
+        let mut url = session.location;
+        url.query_pairs_mut().append_pair("digest", digest);
+        let resp = self
+            .send_request(
+                self.build_reqwest(Method::PUT, url)
+                    .header(header::CONTENT_TYPE, "application/octet-stream")
+                    .body(body),
+            )
+            .await?;
+        let resp = match Self::check_upload_status(resp, StatusCode::CREATED).await {
+            Err(Error::Registry { errors, .. })
+                if !digest.starts_with("sha256:")
+                    && errors
+                        .iter()
+                        .any(|e| matches!(e.code(), "DIGEST_INVALID" | "UNSUPPORTED")) =>
+            {
+                let algorithm = digest.split(':').next().unwrap_or_default();
+                return Err(Error::UnsupportedDigestAlgorithm(algorithm.to_string()));
+            }
+            res => res?,
+        };
+
+        let digest =
+            content_digest_from_headers(resp.headers()).unwrap_or_else(|| digest.to_string());
+        Ok(digest)
+    }
+
+    /// Upload a blob held in memory, computing its digest with `Config::digest_algorithm`.
+    ///
+    /// Returns the digest of the blob as reported by the registry.
+    /// Registries rejecting a digest algorithm other than sha256 result in
+    /// `Error::UnsupportedDigestAlgorithm`.
+    pub async fn push_blob(&self, name: &str, data: Vec<u8>) -> Result<String> {
+        let digest = self.digest_algorithm.digest(&data);
+        self.upload_blob(name, &digest, data).await
+    }
+
+    /// Upload a blob held in memory.
+    pub async fn upload_blob(&self, name: &str, digest: &str, data: Vec<u8>) -> Result<String> {
+        let session = self.start_upload(name).await?;
+        self.finish_upload(session, digest, data).await
+    }
+
+    /// Upload a blob from a stream of bytes, without buffering it in memory.
+    ///
+    /// The stream is sent as the body of the final request of the upload,
+    /// so it must produce exactly the content of the blob with the given digest.
+    pub async fn upload_blob_stream<S>(&self, name: &str, digest: &str, body: S) -> Result<String>
+    where
+        S: futures::TryStream + Send + Sync + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        Bytes: From<S::Ok>,
+    {
+        let session = self.start_upload(name).await?;
+        self.finish_upload(session, digest, Body::wrap_stream(body))
+            .await
+    }
+}
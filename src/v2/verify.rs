@@ -0,0 +1,125 @@
+//! End-to-end verification of images.
+
+use std::collections::HashSet;
+
+use crate::errors::{Error, Result};
+use crate::mediatypes::MediaTypes;
+use crate::reference::Digest;
+use crate::v2::manifest::{ManifestSchema1Signed, ManifestSchema2Spec};
+use crate::v2::*;
+
+/// Content of an image verified by `Client::verify_image`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageReport {
+    digest: String,
+    config: Option<String>,
+    layers: Vec<String>,
+    foreign_layers: Vec<String>,
+    total_size: u64,
+}
+
+impl ImageReport {
+    /// Digest of the manifest.
+    pub fn digest(&self) -> &str {
+        &self.digest
+    }
+
+    /// Digest of the config blob, if the manifest has one.
+    pub fn config(&self) -> Option<&str> {
+        self.config.as_deref()
+    }
+
+    /// Digests of the layers, base layer first.
+    pub fn layers(&self) -> &[String] {
+        &self.layers
+    }
+
+    /// Digests of the foreign layers, which are not verified.
+    ///
+    /// These are also listed by `layers`.
+    pub fn foreign_layers(&self) -> &[String] {
+        &self.foreign_layers
+    }
+
+    /// Total size in bytes of the blobs downloaded, each distinct blob counted once.
+    pub fn total_size(&self) -> u64 {
+        self.total_size
+    }
+}
+
+impl Client {
+    /// Fetch an image by digest and verify all of its content.
+    ///
+    /// The manifest must hash to `digest`, and the config blob and every layer must
+    /// hash to the digest the manifest declares for them, whatever `Config::verify_digests`
+    /// says. Blobs are downloaded one after the other, failing on the first mismatch
+    /// with `Error::DigestMismatch`.
+    ///
+    /// Foreign layers, which registries usually do not store, are skipped and listed
+    /// by `ImageReport::foreign_layers`.
+    ///
+    /// Manifest lists are not images, and result in `Error::UnsupportedMediaType`.
+    pub async fn verify_image(
+        &self,
+        name: &str,
+        digest: &str,
+        ns: Option<&str>,
+    ) -> Result<ImageReport> {
+        let digest = digest.parse::<Digest>()?.to_string();
+        let (body, media_type, _) = self
+            .get_raw_manifest_and_metadata(name, &digest, ns)
+            .await?;
+        let actual = ContentDigest::compute(&digest, &body, self.digest_algorithm);
+        if actual != digest {
+            return Err(Error::DigestMismatch {
+                expected: digest,
+                actual,
+            });
+        }
+
+        let (config, layers, foreign_layers): (Option<String>, Vec<String>, Vec<String>) =
+            match media_type {
+                MediaTypes::ManifestV2S2 | MediaTypes::OciV1Manifest => {
+                    let spec: ManifestSchema2Spec = serde_json::from_slice(&body)?;
+                    (
+                        Some(spec.config().digest.clone()),
+                        spec.layers().map(|l| l.digest().to_string()).collect(),
+                        spec.layers()
+                            .filter(|l| l.is_foreign())
+                            .map(|l| l.digest().to_string())
+                            .collect(),
+                    )
+                }
+                MediaTypes::ManifestV2S1Signed => {
+                    let m: ManifestSchema1Signed = serde_json::from_slice(&body)?;
+                    (None, m.get_layers().map(str::to_string).collect(), vec![])
+                }
+                unsupported => return Err(Error::UnsupportedMediaType(unsupported)),
+            };
+
+        let client = Client {
+            verify_digests: true,
+            ..self.clone()
+        };
+        let mut verified = HashSet::new();
+        let mut total_size = 0;
+        for blob in config.iter().chain(&layers) {
+            if foreign_layers.contains(blob) || !verified.insert(blob.as_str()) {
+                continue;
+            }
+            let mut stream = client.get_blob_stream(name, blob, ns).await?;
+            while let Some(chunk) = stream.try_next().await? {
+                total_size += chunk.len() as u64;
+            }
+            trace!("Verified blob {} of {}@{}", blob, name, digest);
+        }
+
+        Ok(ImageReport {
+            digest,
+            config,
+            layers,
+            foreign_layers,
+            total_size,
+        })
+    }
+}
@@ -1,5 +1,7 @@
 extern crate serde_json;
 
+use dkregistry::mediatypes::MediaTypes;
+use dkregistry::v2::manifest::ManifestSchema2Spec;
 use std::collections::HashMap;
 use std::{fs, io};
 
@@ -7,8 +9,52 @@ use std::{fs, io};
 fn test_deserialize_manifest_v2s1_signed() {
     let f = fs::File::open("tests/fixtures/manifest_v2_s1.json").expect("Missing fixture");
     let bufrd = io::BufReader::new(f);
-    let _manif: dkregistry::v2::manifest::ManifestSchema1Signed =
+    let manif: dkregistry::v2::manifest::ManifestSchema1Signed =
         serde_json::from_reader(bufrd).unwrap();
+
+    let manifest = dkregistry::v2::manifest::Manifest::S1Signed(manif);
+    assert_eq!(1, manifest.schema_version());
+    assert_eq!(MediaTypes::ManifestV2S1Signed, manifest.media_type());
+    assert!(manifest
+        .all_blob_digests(None)
+        .unwrap()
+        .eq(manifest.layers_digests(None).unwrap()));
+
+    let descriptors = manifest.descriptors().unwrap().collect::<Vec<_>>();
+    assert!(descriptors
+        .iter()
+        .map(|d| d.digest())
+        .eq(manifest.layers_digests(None).unwrap()));
+    assert!(descriptors
+        .iter()
+        .all(|d| d.size().is_none() && d.media_type().is_none() && d.urls().is_none()));
+}
+
+#[test]
+fn test_history_manifest_v2s1_signed() {
+    let f = fs::File::open("tests/fixtures/manifest_v2_s1.json").expect("Missing fixture");
+    let bufrd = io::BufReader::new(f);
+    let manif: dkregistry::v2::manifest::ManifestSchema1Signed =
+        serde_json::from_reader(bufrd).unwrap();
+
+    let history = manif.history().collect::<Vec<_>>();
+    assert_eq!(2, history.len());
+    assert_eq!(
+        Some("2014-12-31T22:57:59.178729048Z"),
+        history[0].created.as_deref()
+    );
+    assert_eq!(Some("amd64"), history[0].architecture.as_deref());
+    assert_eq!(
+        Some(vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            "#(nop) CMD [/hello]".to_string()
+        ]),
+        history[0]
+            .container_config
+            .as_ref()
+            .and_then(|c| c.cmd.clone())
+    );
 }
 
 #[test]
@@ -27,19 +73,20 @@ fn deserialize_manifest_v2s2_config(
         serde_json::from_reader::<_, dkregistry::v2::manifest::ManifestSchema2Spec>(f)?
     };
 
-    let config_blob = {
-        let f = fs::File::open(format!(
-            "tests/fixtures/quay.io_v2_openshift-release-dev_ocp-release_manifests_4.1.0-rc.9/{}",
-            &manifest_spec.config().digest.replace(":", "_")
-        ))
-        .expect("Missing fixture");
-        serde_json::from_reader::<_, dkregistry::v2::manifest::ConfigBlob>(f)?
-    };
+    let raw_config_blob = fs::read(format!(
+        "tests/fixtures/quay.io_v2_openshift-release-dev_ocp-release_manifests_4.1.0-rc.9/{}",
+        &manifest_spec.config().digest.replace(":", "_")
+    ))
+    .expect("Missing fixture");
+    let config_blob =
+        serde_json::from_slice::<dkregistry::v2::manifest::ConfigBlob>(&raw_config_blob)?;
 
     Ok(dkregistry::v2::manifest::Manifest::S2(
         dkregistry::v2::manifest::ManifestSchema2 {
             manifest_spec,
             config_blob,
+            raw_config_blob,
+            raw_manifest: Vec::new(),
         },
     ))
 }
@@ -54,6 +101,7 @@ fn test_deserialize_manifest_v2s2_config() -> Result<(), Box<dyn std::error::Err
 fn test_manifest_v2s2() -> Result<(), Box<dyn std::error::Error>> {
     let manifest = deserialize_manifest_v2s2_config()?;
 
+    assert_eq!(2, manifest.schema_version());
     assert_eq!("amd64", manifest.architectures()?.next().unwrap());
     assert_eq!(
         vec![
@@ -66,6 +114,48 @@ fn test_manifest_v2s2() -> Result<(), Box<dyn std::error::Error>> {
         manifest.layers_digests(Some("amd64"))?.collect::<Vec<_>>()
     );
 
+    let (architectures, layers) = {
+        let manifest = deserialize_manifest_v2s2_config()?;
+        (
+            manifest.architectures_owned(),
+            manifest.layers_digests_owned(Some("amd64"))?,
+        )
+    };
+    assert_eq!(architectures, vec!["amd64".to_string()]);
+    assert!(layers
+        .iter()
+        .map(String::as_str)
+        .eq(manifest.layers_digests(None)?));
+    assert!(manifest.layers_digests_owned(Some("arm64")).is_err());
+
+    let blobs = manifest.all_blob_digests(None)?.collect::<Vec<_>>();
+    assert_eq!(blobs.len(), 6);
+    assert_eq!(
+        blobs[0],
+        match &manifest {
+            dkregistry::v2::manifest::Manifest::S2(m) => m.manifest_spec.config().digest.as_str(),
+            _ => unreachable!(),
+        }
+    );
+    assert!(manifest.all_blob_digests(Some("arm64")).is_err());
+
+    let descriptors = manifest.descriptors()?.collect::<Vec<_>>();
+    assert!(descriptors
+        .iter()
+        .map(|d| d.digest())
+        .eq(manifest.layers_digests(None)?));
+    assert_eq!(
+        descriptors.iter().map(|d| d.size().unwrap()).sum::<u64>()
+            + match &manifest {
+                dkregistry::v2::manifest::Manifest::S2(m) => m.manifest_spec.config().size,
+                _ => unreachable!(),
+            },
+        manifest.total_size(None)?
+    );
+    assert!(descriptors
+        .iter()
+        .all(|d| d.media_type() == Some("application/vnd.docker.image.rootfs.diff.tar.gzip")));
+
     Ok(())
 }
 
@@ -76,6 +166,181 @@ fn test_deserialize_manifest_list_v2() {
     let _manif: dkregistry::v2::manifest::ManifestList = serde_json::from_reader(bufrd).unwrap();
 }
 
+#[test]
+fn test_manifest_list_for_platform() {
+    let f = fs::File::open("tests/fixtures/manifest_list_v2.json").expect("Missing fixture");
+    let bufrd = io::BufReader::new(f);
+    let manif: dkregistry::v2::manifest::ManifestList = serde_json::from_reader(bufrd).unwrap();
+
+    assert_eq!(
+        Some("sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270"),
+        manif
+            .manifest_for_platform("linux", "amd64", None)
+            .map(|mo| mo.digest())
+    );
+    assert!(manif
+        .manifest_for_platform("linux", "amd64", Some("v8"))
+        .is_none());
+    assert!(manif
+        .manifest_for_platform("windows", "amd64", None)
+        .is_none());
+}
+
+#[test]
+fn test_manifest_for_platform_aliases() {
+    let f = fs::File::open("tests/fixtures/manifest_list_v2.json").expect("Missing fixture");
+    let bufrd = io::BufReader::new(f);
+    let manif: dkregistry::v2::manifest::ManifestList = serde_json::from_reader(bufrd).unwrap();
+
+    assert_eq!(
+        manif
+            .manifest_for_platform("Linux", "x86_64", None)
+            .map(|mo| mo.digest()),
+        manif
+            .manifest_for_platform("linux", "amd64", None)
+            .map(|mo| mo.digest()),
+    );
+    assert!(manif
+        .manifest_for_platform("linux", "x86_64", None)
+        .is_some());
+    assert_eq!(manif.manifests[1].platform.architecture, "amd64");
+}
+
+#[test]
+fn test_manifest_list_for_windows_os_version() {
+    let f = fs::File::open("tests/fixtures/manifest_list_windows.json").expect("Missing fixture");
+    let bufrd = io::BufReader::new(f);
+    let manif: dkregistry::v2::manifest::ManifestList = serde_json::from_reader(bufrd).unwrap();
+
+    let digest = |os: &str, os_version: Option<&str>| {
+        manif
+            .manifest_for_platform_version(os, "amd64", None, os_version)
+            .map(|mo| mo.digest())
+    };
+    assert_eq!(
+        digest("windows", Some("10.0.19041")),
+        Some("sha256:4a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b")
+    );
+    assert_eq!(
+        digest("windows", Some("10.0.20348.473")),
+        Some("sha256:5b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b3c")
+    );
+    assert_eq!(
+        digest("windows", None),
+        manif
+            .manifest_for_platform("windows", "amd64", None)
+            .map(|mo| mo.digest())
+    );
+    assert_eq!(digest("windows", Some("10.0.1904")), None);
+    assert_eq!(digest("windows", Some("10.0.22000")), None);
+    assert_eq!(
+        digest("linux", Some("10.0.19041")),
+        Some("sha256:6c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d")
+    );
+}
+
+#[test]
+fn test_platform_matches() {
+    use dkregistry::v2::manifest::Platform;
+
+    let platform = |arch: &str, variant: Option<&str>| Platform {
+        os: "linux".into(),
+        architecture: arch.into(),
+        variant: variant.map(Into::into),
+        ..Default::default()
+    };
+
+    assert!(platform("amd64", None).matches("linux", "x86_64", None));
+    assert!(platform("x86_64", None).matches("linux", "amd64", Some("v1")));
+    assert!(platform("arm64", None).matches("linux", "aarch64", Some("v8")));
+    assert!(platform("arm64", Some("v8")).matches("linux", "arm64", None));
+    assert!(platform("arm", None).matches("linux", "arm", Some("v7")));
+    assert!(platform("arm", Some("7")).matches("linux", "armhf", Some("v7")));
+    assert!(platform("arm", Some("v6")).matches("linux", "armel", None));
+    assert!(!platform("arm", Some("v6")).matches("linux", "arm", Some("v7")));
+    assert!(!platform("arm64", None).matches("linux", "amd64", None));
+    assert!(!platform("amd64", None).matches("windows", "amd64", None));
+}
+
+#[test]
+fn test_manifest_list_skips_attestations() {
+    let f = fs::File::open("tests/fixtures/manifest_list_buildx.json").expect("Missing fixture");
+    let bufrd = io::BufReader::new(f);
+    let manif: dkregistry::v2::manifest::ManifestList = serde_json::from_reader(bufrd).unwrap();
+
+    assert_eq!(
+        manif.architectures().collect::<Vec<_>>(),
+        vec!["amd64", "arm64"]
+    );
+    assert!(manif
+        .manifest_for_platform("unknown", "unknown", None)
+        .is_none());
+    assert_eq!(
+        manif
+            .manifest_for_platform("linux", "arm64", None)
+            .map(|mo| mo.digest()),
+        Some("sha256:9b1e4f2c3d5a6b7c8d9e0f1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b1c")
+    );
+
+    let attestations = manif.attestations().collect::<Vec<_>>();
+    assert_eq!(attestations.len(), 2);
+    assert_eq!(
+        attestations[0]
+            .annotations()
+            .and_then(|a| a.get("vnd.docker.reference.digest"))
+            .map(String::as_str),
+        manif
+            .manifest_for_platform("linux", "amd64", None)
+            .map(|mo| mo.digest())
+    );
+}
+
+#[test]
+fn test_manifest_list_entries_for_os() {
+    let entry = |digest: char, os: &str, arch: &str, variant: Option<&str>| {
+        serde_json::json!({
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "size": 1,
+            "digest": format!("sha256:{}", digest.to_string().repeat(64)),
+            "platform": { "os": os, "architecture": arch, "variant": variant },
+        })
+    };
+    let manif: dkregistry::v2::manifest::ManifestList = serde_json::from_value(serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.index.v1+json",
+        "manifests": [
+            entry('a', "linux", "amd64", None),
+            entry('b', "linux", "arm", Some("v7")),
+            entry('c', "windows", "amd64", None),
+            entry('d', "linux", "arm64", Some("v8")),
+            entry('e', "unknown", "unknown", None),
+        ],
+    }))
+    .unwrap();
+    let first_chars = |entries: Vec<&dkregistry::v2::manifest::ManifestObj>| {
+        entries
+            .into_iter()
+            .map(|mo| mo.digest().chars().nth(7).unwrap())
+            .collect::<String>()
+    };
+
+    assert_eq!(first_chars(manif.entries_for_os("Linux").collect()), "abd");
+    assert_eq!(first_chars(manif.entries_for_os("windows").collect()), "c");
+    assert_eq!(first_chars(manif.entries_for_os("unknown").collect()), "");
+    assert_eq!(
+        first_chars(
+            manif
+                .entries_matching(|p| p.architecture.starts_with("arm"))
+                .collect()
+        ),
+        "bd"
+    );
+    assert_eq!(
+        first_chars(manif.entries_matching(|p| p.is_unknown()).collect()),
+        "e"
+    );
+}
+
 #[test]
 fn test_deserialize_etcd_manifest() {
     let f =
@@ -106,3 +371,263 @@ fn test_labels_manifest_v2s1_signed() {
     assert_eq!(expected_labels_0, labels_0);
     assert_eq!(None, manif.get_labels(1));
 }
+
+#[test]
+fn test_deserialize_container_config_blob() {
+    let f = fs::File::open("tests/fixtures/container_config_blob.json").expect("Missing fixture");
+    let bufrd = io::BufReader::new(f);
+    let blob: dkregistry::v2::manifest::ConfigBlob = serde_json::from_reader(bufrd).unwrap();
+
+    assert_eq!("amd64", blob.architecture());
+    assert_eq!(Some("linux"), blob.os());
+    assert_eq!(Some("2019-08-16T14:50:54Z"), blob.created());
+    assert_eq!(6, blob.history().len());
+    assert_eq!(
+        Some("Release image for OpenShift"),
+        blob.history()[0].comment.as_deref()
+    );
+
+    let config = blob.config().expect("Missing config");
+    assert_eq!(
+        Some(vec!["/usr/bin/cluster-version-operator".to_string()]),
+        config.entrypoint
+    );
+    assert_eq!(
+        Some("4.1.12"),
+        config
+            .labels
+            .as_ref()
+            .and_then(|l| l.get("io.openshift.release"))
+            .map(String::as_str)
+    );
+}
+
+#[test]
+fn test_manifest_v2s2_config_accessors() -> Result<(), Box<dyn std::error::Error>> {
+    let manifest = match deserialize_manifest_v2s2_config()? {
+        dkregistry::v2::manifest::Manifest::S2(m) => m,
+        _ => unreachable!(),
+    };
+
+    assert_eq!(Some("linux"), manifest.os());
+    assert_eq!(
+        Some("4.1.0-rc.9"),
+        manifest
+            .labels()
+            .and_then(|l| l.get("io.openshift.release"))
+            .map(String::as_str)
+    );
+    assert!(manifest.env().unwrap().iter().any(|e| e == "container=oci"));
+    assert_eq!(None, manifest.cmd());
+    assert!(manifest.created().is_some());
+    assert!(!manifest.history().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_manifest_v2s2_layers() -> Result<(), Box<dyn std::error::Error>> {
+    let manifest = deserialize_manifest_v2s2_config()?;
+    let m = match &manifest {
+        dkregistry::v2::manifest::Manifest::S2(m) => m,
+        _ => unreachable!(),
+    };
+
+    let layers = m.layers().collect::<Vec<_>>();
+    assert_eq!(5, layers.len());
+    assert_eq!(
+        "sha256:9391a94f7498d07a595f560d60350d428b1259d622e19beee61a2363edc4eb94",
+        layers[0].digest()
+    );
+    assert_eq!(
+        "application/vnd.docker.image.rootfs.diff.tar.gzip",
+        layers[0].media_type()
+    );
+    assert_eq!(None, layers[0].urls());
+
+    let expected_size =
+        layers.iter().map(|l| l.size()).sum::<u64>() + m.manifest_spec.config().size;
+    assert_eq!(expected_size, manifest.total_size(None)?);
+    assert_eq!(expected_size, manifest.total_size(Some("amd64"))?);
+    assert!(manifest.total_size(Some("arm64")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_deserialize_oci_artifact_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let manifest: ManifestSchema2Spec = serde_json::from_value(serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "artifactType": "application/vnd.example.sbom.v1+json",
+        "config": {
+            "mediaType": "application/vnd.oci.empty.v1+json",
+            "size": 2,
+            "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        },
+        "layers": [
+            {
+                "mediaType": "application/vnd.example.sbom.v1+json",
+                "size": 1234,
+                "digest": "sha256:e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f"
+            }
+        ]
+    }))?;
+
+    assert_eq!(
+        manifest.config().media_type,
+        MediaTypes::OciV1EmptyConfig.to_string()
+    );
+    assert_eq!(manifest.layers().count(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_oci_manifest_subject() -> Result<(), Box<dyn std::error::Error>> {
+    let subject = serde_json::json!({
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "size": 1234,
+        "digest": "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+    });
+    let mut json = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "config": {
+            "mediaType": "application/vnd.oci.empty.v1+json",
+            "size": 2,
+            "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        },
+        "layers": []
+    });
+
+    let manifest: ManifestSchema2Spec = serde_json::from_value(json.clone())?;
+    assert!(manifest.subject().is_none());
+    assert_eq!(serde_json::to_value(&manifest)?.get("subject"), None);
+
+    json["subject"] = subject.clone();
+    let manifest: ManifestSchema2Spec = serde_json::from_value(json)?;
+    let descriptor = manifest.subject().unwrap();
+    assert_eq!(
+        descriptor.digest,
+        "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+    );
+    assert_eq!(descriptor.size, 1234);
+    assert_eq!(serde_json::to_value(&manifest)?["subject"], subject);
+    Ok(())
+}
+
+#[test]
+fn test_deserialize_oci_manifest_annotations() -> Result<(), Box<dyn std::error::Error>> {
+    let manifest: ManifestSchema2Spec = serde_json::from_value(serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "config": {
+            "mediaType": "application/vnd.oci.image.config.v1+json",
+            "size": 2,
+            "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a",
+            "annotations": { "config.key": "config.value" }
+        },
+        "layers": [
+            {
+                "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                "size": 1234,
+                "digest": "sha256:e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f",
+                "annotations": { "org.opencontainers.image.title": "layer.tar.gz" }
+            }
+        ],
+        "annotations": { "org.opencontainers.image.created": "2023-01-01T00:00:00Z" }
+    }))?;
+
+    assert_eq!(
+        manifest
+            .annotations()
+            .and_then(|a| a.get("org.opencontainers.image.created"))
+            .map(String::as_str),
+        Some("2023-01-01T00:00:00Z")
+    );
+    assert_eq!(
+        manifest
+            .config()
+            .annotations
+            .as_ref()
+            .and_then(|a| a.get("config.key"))
+            .map(String::as_str),
+        Some("config.value")
+    );
+    let layer = manifest.layers().next().unwrap();
+    assert_eq!(
+        layer
+            .annotations()
+            .and_then(|a| a.get("org.opencontainers.image.title"))
+            .map(String::as_str),
+        Some("layer.tar.gz")
+    );
+
+    // Docker manifests have no annotations, and none are serialized back.
+    let f = fs::File::open("tests/fixtures/manifest_v2_s2.json")?;
+    let docker: ManifestSchema2Spec = serde_json::from_reader(f)?;
+    assert!(docker.annotations().is_none());
+    assert!(!serde_json::to_string(&docker)?.contains("annotations"));
+    Ok(())
+}
+
+#[cfg(feature = "schema1-signatures")]
+#[test_case::test_case("tests/fixtures/quayio_coreos_etcd_latest.json"; "RS256")]
+#[test_case::test_case("tests/fixtures/quayio_steveej_cincinnati-test-labels_dkregistry-test.json"; "ES256")]
+fn test_manifest_v2s1_signature(fixture: &str) {
+    use dkregistry::v2::manifest::ManifestSchema1Signed;
+
+    let raw = fs::read(fixture).expect("Missing fixture");
+    let manif: ManifestSchema1Signed = serde_json::from_slice(&raw).unwrap();
+    manif.verify_signature(&raw).unwrap();
+
+    let tampered = String::from_utf8(raw)
+        .unwrap()
+        .replacen("sha256:a3ed", "sha256:b3ed", 1);
+    let manif: ManifestSchema1Signed = serde_json::from_str(&tampered).unwrap();
+    match manif.verify_signature(tampered.as_bytes()) {
+        Err(dkregistry::errors::Error::InvalidManifestSignature(_)) => {}
+        res => panic!("expected InvalidManifestSignature, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_manifest_v2s2_builder() -> Result<(), Box<dyn std::error::Error>> {
+    use dkregistry::v2::manifest::ManifestSchema2Builder;
+
+    let fixture: ManifestSchema2Spec =
+        serde_json::from_slice(&fs::read("tests/fixtures/manifest_v2_s2.json")?)?;
+    let builder = fixture.layers().fold(
+        ManifestSchema2Builder::new().config(&fixture.config().digest, fixture.config().size),
+        |builder, layer| {
+            builder.add_layer(
+                &MediaTypes::ImageLayerTgz,
+                layer.digest(),
+                layer.size(),
+                None,
+            )
+        },
+    );
+    let built = serde_json::to_value(builder.build())?;
+    let expected: serde_json::Value =
+        serde_json::from_slice(&fs::read("tests/fixtures/manifest_v2_s2.json")?)?;
+    assert_eq!(built, expected);
+
+    let oci = ManifestSchema2Builder::oci()
+        .add_layer(
+            &MediaTypes::OciV1LayerTgz,
+            "sha256:e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f",
+            32654,
+            Some(vec!["https://example.com/layer.tar.gz".to_string()]),
+        )
+        .build();
+    assert_eq!(oci.media_type(), &MediaTypes::OciV1Manifest);
+    assert_eq!(oci.config_media_type(), MediaTypes::OciV1EmptyConfig);
+    assert_eq!(oci.config().size, 2);
+    let layer = oci.layers().next().unwrap();
+    assert_eq!(
+        layer.urls(),
+        Some(&["https://example.com/layer.tar.gz".to_string()][..])
+    );
+    Ok(())
+}
@@ -2,16 +2,17 @@ extern crate dkregistry;
 extern crate mockito;
 extern crate tokio;
 
-use self::mockito::mock;
 use self::tokio::runtime::Runtime;
 
-static API_VERSION_K: &'static str = "Docker-Distribution-API-Version";
-static API_VERSION_V: &'static str = "registry/2.0";
+static API_VERSION_K: &str = "Docker-Distribution-API-Version";
+static API_VERSION_V: &str = "registry/2.0";
 
 #[test]
 fn test_version_check_status_ok() {
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", "/v2/")
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
         .with_status(200)
         .with_header(API_VERSION_K, API_VERSION_V)
         .create();
@@ -27,18 +28,18 @@ fn test_version_check_status_ok() {
 
     let is_v2 = dclient.is_v2_supported();
     let ok = runtime.block_on(is_v2).unwrap();
-    assert_eq!(ok, true);
+    assert!(ok);
 
     let ensure_v2 = dclient.ensure_v2_registry();
     let _dclient = runtime.block_on(ensure_v2).unwrap();
-
-    mockito::reset();
 }
 
 #[test]
 fn test_version_check_status_unauth() {
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", "/v2/")
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
         .with_status(401)
         .with_header(API_VERSION_K, API_VERSION_V)
         .create();
@@ -55,15 +56,15 @@ fn test_version_check_status_unauth() {
     let futcheck = dclient.is_v2_supported();
 
     let res = runtime.block_on(futcheck).unwrap();
-    assert_eq!(res, true);
-
-    mockito::reset();
+    assert!(res);
 }
 
 #[test]
 fn test_version_check_status_notfound() {
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", "/v2/")
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
         .with_status(404)
         .with_header(API_VERSION_K, API_VERSION_V)
         .create();
@@ -80,15 +81,15 @@ fn test_version_check_status_notfound() {
     let futcheck = dclient.is_v2_supported();
 
     let res = runtime.block_on(futcheck).unwrap();
-    assert_eq!(res, false);
-
-    mockito::reset();
+    assert!(!res);
 }
 
 #[test]
 fn test_version_check_status_forbidden() {
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", "/v2/")
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
         .with_status(403)
         .with_header(API_VERSION_K, API_VERSION_V)
         .create();
@@ -105,15 +106,14 @@ fn test_version_check_status_forbidden() {
     let futcheck = dclient.is_v2_supported();
 
     let res = runtime.block_on(futcheck).unwrap();
-    assert_eq!(res, false);
-
-    mockito::reset();
+    assert!(!res);
 }
 
 #[test]
 fn test_version_check_noheader() {
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", "/v2/").with_status(403).create();
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server.mock("GET", "/v2/").with_status(403).create();
 
     let runtime = Runtime::new().unwrap();
     let dclient = dkregistry::v2::Client::configure()
@@ -127,15 +127,15 @@ fn test_version_check_noheader() {
     let futcheck = dclient.is_v2_supported();
 
     let res = runtime.block_on(futcheck).unwrap();
-    assert_eq!(res, false);
-
-    mockito::reset();
+    assert!(!res);
 }
 
 #[test]
 fn test_version_check_trailing_slash() {
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", "/v2")
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2")
         .with_status(200)
         .with_header(API_VERSION_K, API_VERSION_V)
         .create();
@@ -152,7 +152,100 @@ fn test_version_check_trailing_slash() {
     let futcheck = dclient.is_v2_supported();
 
     let res = runtime.block_on(futcheck).unwrap();
-    assert_eq!(res, false);
+    assert!(!res);
+}
+
+#[test]
+fn test_ping() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let info = runtime.block_on(dclient.ping()).unwrap();
+    assert_eq!(info.api_version(), Some(API_VERSION_V));
+    assert!(info.auth_required());
+}
 
-    mockito::reset();
+#[test]
+fn test_ping_not_a_registry() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server.mock("GET", "/v2/").with_status(404).create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.ping()) {
+        Err(dkregistry::errors::Error::UnexpectedHttpStatus(status)) => {
+            assert_eq!(status, 404);
+        }
+        res => panic!("expected UnexpectedHttpStatus, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_require_v2_header() {
+    let mut portal = mockito::Server::new();
+    let _m = portal
+        .mock("GET", "/v2/")
+        .with_status(200)
+        .with_header("Content-Type", "text/html")
+        .with_body("<html>Please log in</html>")
+        .create();
+    let mut registry = mockito::Server::new();
+    let _m = registry
+        .mock("GET", "/v2/")
+        .with_status(200)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let client_for = |addr: &str, require: bool| {
+        dkregistry::v2::Client::configure()
+            .registry(addr)
+            .insecure_registry(true)
+            .require_v2_header(require)
+            .username(None)
+            .password(None)
+            .build()
+            .unwrap()
+    };
+
+    let dclient = client_for(&portal.host_with_port(), false);
+    runtime.block_on(dclient.ping()).unwrap();
+
+    let dclient = client_for(&portal.host_with_port(), true);
+    assert!(matches!(
+        runtime.block_on(dclient.ping()),
+        Err(dkregistry::errors::Error::NotARegistry(_))
+    ));
+    assert!(matches!(
+        runtime.block_on(dclient.authenticate(&["repository:repo:pull"])),
+        Err(dkregistry::errors::Error::NotARegistry(_))
+    ));
+
+    let dclient = client_for(&registry.host_with_port(), true);
+    runtime.block_on(dclient.ping()).unwrap();
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
 }
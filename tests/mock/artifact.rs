@@ -0,0 +1,91 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate tokio;
+
+use self::tokio::runtime::Runtime;
+use crate::mock::digest;
+use dkregistry::mediatypes::MediaTypes;
+use dkregistry::v2::ArtifactManifest;
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[test]
+fn put_artifact() -> Fallible<()> {
+    let sbom = br#"{"spdxVersion":"SPDX-2.3"}"#;
+    let subject = "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _config_present = server
+        .mock("HEAD", format!("/v2/repo/blobs/{}", digest(b"{}")).as_str())
+        .with_status(200)
+        .create();
+    let _layer_missing = server
+        .mock("HEAD", format!("/v2/repo/blobs/{}", digest(sbom)).as_str())
+        .with_status(404)
+        .create();
+    let start = server
+        .mock("POST", "/v2/repo/blobs/uploads/")
+        .with_status(202)
+        .with_header("Location", "/v2/repo/blobs/uploads/uuid1")
+        .expect(1)
+        .create();
+    let finish = server
+        .mock("PUT", "/v2/repo/blobs/uploads/uuid1")
+        .match_query(mockito::Matcher::UrlEncoded("digest".into(), digest(sbom)))
+        .match_body(&*String::from_utf8_lossy(sbom))
+        .with_status(201)
+        .expect(1)
+        .create();
+    let put_manifest = server
+        .mock("PUT", "/v2/repo/manifests/sbom")
+        .match_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .match_body(mockito::Matcher::Json(serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "artifactType": "application/spdx+json",
+            "config": {
+                "mediaType": "application/vnd.oci.empty.v1+json",
+                "size": 2,
+                "digest": digest(b"{}"),
+            },
+            "layers": [{
+                "mediaType": "application/spdx+json",
+                "size": sbom.len(),
+                "digest": digest(sbom),
+            }],
+            "subject": {
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "size": 1234,
+                "digest": subject,
+            },
+            "annotations": {"org.opencontainers.image.created": "2024-01-01T00:00:00Z"},
+        })))
+        .with_status(201)
+        .with_header_from_request("Docker-Content-Digest", |request| {
+            digest(request.body().unwrap())
+        })
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let artifact = ArtifactManifest::new("application/spdx+json")
+        .layer("application/spdx+json", &sbom[..])
+        .subject(&MediaTypes::OciV1Manifest, subject, 1234)
+        .annotation("org.opencontainers.image.created", "2024-01-01T00:00:00Z");
+    let pushed = runtime.block_on(dclient.put_artifact("repo", "sbom", artifact))?;
+    assert!(pushed.starts_with("sha256:"));
+
+    start.assert();
+    finish.assert();
+    put_manifest.assert();
+    Ok(())
+}
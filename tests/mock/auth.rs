@@ -0,0 +1,986 @@
+extern crate dkregistry;
+extern crate futures;
+extern crate mockito;
+extern crate tokio;
+
+use self::futures::StreamExt;
+use self::tokio::runtime::Runtime;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn test_auth_bearer_token_is_cached() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                server.url()
+            ),
+        )
+        .create();
+    let token_mock = server
+        .mock("GET", "/token?service=registry&scope=repository:repo:pull")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "abcdef", "expires_in": 300}"#)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+
+    token_mock.assert();
+}
+
+#[test]
+fn test_auth_reauthenticates_on_unauthorized() {
+    let name = "repo";
+    let ep = format!("/v2/{}/tags/list", name);
+    let tags = r#"{"name": "repo", "tags": [ "t1" ]}"#;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _unauthorized = server
+        .mock("GET", ep.as_str())
+        .match_header("Authorization", mockito::Matcher::Missing)
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry",scope="repository:repo:pull""#,
+                server.url()
+            ),
+        )
+        .expect(1)
+        .create();
+    let token_mock = server
+        .mock("GET", "/token?service=registry&scope=repository:repo:pull")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "abcdef"}"#)
+        .expect(1)
+        .create();
+    let authorized = server
+        .mock("GET", ep.as_str())
+        .match_header("Authorization", "Bearer abcdef")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(tags)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .auto_reauth(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.get_tags(name, None).collect::<Vec<_>>());
+    assert_eq!(
+        res.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+        vec!["t1"]
+    );
+
+    token_mock.assert();
+    authorized.assert();
+}
+
+#[test]
+fn test_auth_reauthenticate_disabled_by_default() {
+    let name = "repo";
+    let ep = format!("/v2/{}/tags/list", name);
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _unauthorized = server
+        .mock("GET", ep.as_str())
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                server.url()
+            ),
+        )
+        .create();
+    let token_mock = server
+        .mock("GET", mockito::Matcher::Regex("^/token".to_string()))
+        .expect(0)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.get_tags(name, None).collect::<Vec<_>>());
+    assert!(res[0].is_err());
+
+    token_mock.assert();
+}
+
+#[test]
+fn test_auth_uses_refresh_token_when_expired() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                server.url()
+            ),
+        )
+        .create();
+    // Expires within the safety margin, hence stale right away.
+    let token_mock = server
+        .mock("GET", "/token?service=registry&scope=repository:repo:pull")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "abcdef", "expires_in": 1, "refresh_token": "refresh"}"#)
+        .expect(1)
+        .create();
+    let refresh_mock = server
+        .mock("POST", "/token")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("grant_type".into(), "refresh_token".into()),
+            mockito::Matcher::UrlEncoded("refresh_token".into(), "refresh".into()),
+            mockito::Matcher::UrlEncoded("service".into(), "registry".into()),
+            mockito::Matcher::UrlEncoded("scope".into(), "repository:repo:pull".into()),
+        ]))
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"access_token": "ghijkl", "expires_in": 300}"#)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    // The refreshed token is fresh and served from the cache.
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+
+    token_mock.assert();
+    refresh_mock.assert();
+}
+
+#[test]
+fn test_auth_oauth2_password_grant() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                server.url()
+            ),
+        )
+        .create();
+    let token_mock = server
+        .mock("POST", "/token")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("grant_type".into(), "password".into()),
+            mockito::Matcher::UrlEncoded("username".into(), "user".into()),
+            mockito::Matcher::UrlEncoded("password".into(), "secret".into()),
+            mockito::Matcher::UrlEncoded("service".into(), "registry".into()),
+            mockito::Matcher::UrlEncoded("scope".into(), "repository:repo:pull".into()),
+        ]))
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"access_token": "abcdef", "expires_in": 300}"#)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .oauth2(true)
+        .username(Some("user".into()))
+        .password(Some("secret".into()))
+        .build()
+        .unwrap();
+
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+
+    token_mock.assert();
+}
+
+#[test]
+fn test_auth_challenge_error_is_surfaced() {
+    let name = "repo";
+    let ep = format!("/v2/{}/tags/list", name);
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry",scope="repository:repo:pull",error="insufficient_scope""#,
+                server.url()
+            ),
+        )
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.get_tags(name, None).collect::<Vec<_>>());
+    match &res[0] {
+        Err(dkregistry::errors::Error::AuthChallenge { error, scope }) => {
+            assert_eq!(error, "insufficient_scope");
+            assert_eq!(scope.as_deref(), Some("repository:repo:pull"));
+        }
+        res => panic!("expected AuthChallenge, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_auth_token_request_uses_proxy() {
+    let mut server = mockito::Server::new();
+    let proxy = reqwest::Url::parse(&server.url()).unwrap();
+    let _m = server
+        .mock("GET", "/v2/")
+        .match_header("Host", "registry.invalid")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            r#"Bearer realm="http://auth.invalid/token",service="registry""#,
+        )
+        .create();
+    let token_mock = server
+        .mock("GET", "/token?service=registry&scope=repository:repo:pull")
+        .match_header("Host", "auth.invalid")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "abcdef"}"#)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry("registry.invalid")
+        .insecure_registry(true)
+        .proxy(proxy)
+        .use_env_proxy(false)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+
+    token_mock.assert();
+}
+
+#[test]
+fn test_auth_anonymous_fallback() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                server.url()
+            ),
+        )
+        .create();
+    let rejected = server
+        .mock("GET", "/token?service=registry&scope=repository:repo:pull")
+        .match_header("Authorization", mockito::Matcher::Any)
+        .with_status(401)
+        .expect(2)
+        .create();
+    let anonymous = server
+        .mock("GET", "/token?service=registry&scope=repository:repo:pull")
+        .match_header("Authorization", mockito::Matcher::Missing)
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "anonymous"}"#)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let strict = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(Some("user".into()))
+        .password(Some("wrong".into()))
+        .build()
+        .unwrap();
+    assert!(runtime
+        .block_on(strict.authenticate(&["repository:repo:pull"]))
+        .is_err());
+
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .anonymous_fallback(true)
+        .username(Some("user".into()))
+        .password(Some("wrong".into()))
+        .build()
+        .unwrap();
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+
+    rejected.assert();
+    anonymous.assert();
+}
+
+#[test]
+fn test_auth_login() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _challenge = server
+        .mock("GET", "/v2/")
+        .match_header("Authorization", mockito::Matcher::Missing)
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                server.url()
+            ),
+        )
+        .create();
+    let _authorized = server
+        .mock("GET", "/v2/")
+        .match_header("Authorization", "Bearer abcdef")
+        .with_status(200)
+        .create();
+    let _token = server
+        .mock("GET", "/token?service=registry&scope=repository:repo:pull")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "abcdef"}"#)
+        .create();
+    let _rejected = server
+        .mock(
+            "GET",
+            "/token?service=registry&scope=repository:private:pull",
+        )
+        .with_status(401)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    runtime
+        .block_on(dclient.login(&["repository:repo:pull"]))
+        .unwrap();
+
+    match runtime.block_on(dclient.login(&["repository:private:pull"])) {
+        Err(dkregistry::errors::Error::LoginFailed { cause }) => match *cause {
+            dkregistry::errors::Error::UnexpectedHttpStatus(status) => assert_eq!(status, 401),
+            e => panic!("unexpected cause {:?}", e),
+        },
+        res => panic!("expected LoginFailed, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_auth_shared_between_clones() {
+    let name = "repo";
+    let ep = format!("/v2/{}/tags/list", name);
+    let tags = r#"{"name": "repo", "tags": [ "t1" ]}"#;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                server.url()
+            ),
+        )
+        .create();
+    let _token = server
+        .mock("GET", "/token?service=registry&scope=repository:repo:pull")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "abcdef"}"#)
+        .create();
+    let authorized = server
+        .mock("GET", ep.as_str())
+        .match_header("Authorization", "Bearer abcdef")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(tags)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = std::sync::Arc::new(
+        dkregistry::v2::Client::configure()
+            .registry(&addr)
+            .insecure_registry(true)
+            .username(None)
+            .password(None)
+            .build()
+            .unwrap(),
+    );
+    let clone = (*dclient).clone();
+
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    let res = runtime.block_on(clone.get_tags(name, None).collect::<Vec<_>>());
+    assert_eq!(
+        res.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+        vec!["t1"]
+    );
+
+    authorized.assert();
+}
+
+#[test]
+fn test_auth_preobtained_bearer_token() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let tags_mock = server
+        .mock("GET", "/v2/repo/tags/list")
+        .match_header("Authorization", "Bearer external-token")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"name": "repo", "tags": ["t1"]}"#)
+        .expect(1)
+        .create();
+    let token_mock = server
+        .mock("GET", mockito::Matcher::Regex("^/token".to_string()))
+        .expect(0)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .bearer_token("external-token")
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let tags: Vec<_> = runtime.block_on(dclient.get_tags("repo", None).collect());
+    assert_eq!(
+        tags.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+        vec!["t1"]
+    );
+
+    tags_mock.assert();
+    token_mock.assert();
+}
+
+#[test]
+fn test_auth_kind() {
+    use dkregistry::v2::AuthKind;
+
+    let runtime = Runtime::new().unwrap();
+    let client_for = |addr: &str| {
+        dkregistry::v2::Client::configure()
+            .registry(addr)
+            .insecure_registry(true)
+            .username(Some("user".into()))
+            .password(Some("pass".into()))
+            .build()
+            .unwrap()
+    };
+
+    let mut open = mockito::Server::new();
+    let _open = open.mock("GET", "/v2/").with_status(200).create();
+    let dclient = client_for(&open.host_with_port());
+    assert_eq!(dclient.auth_kind(), AuthKind::Anonymous);
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    assert_eq!(dclient.auth_kind(), AuthKind::Anonymous);
+    assert!(!dclient.is_authenticated());
+
+    let mut basic = mockito::Server::new();
+    let _basic = basic
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header("WWW-Authenticate", r#"Basic realm="registry""#)
+        .create();
+    let dclient = client_for(&basic.host_with_port());
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    assert_eq!(dclient.auth_kind(), AuthKind::Basic);
+    assert!(dclient.is_authenticated());
+
+    let mut bearer = mockito::Server::new();
+    let _bearer = bearer
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                bearer.url()
+            ),
+        )
+        .create();
+    let _token = bearer
+        .mock("GET", "/token?service=registry&scope=repository:repo:pull")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "abcdef"}"#)
+        .create();
+    let dclient = client_for(&bearer.host_with_port());
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    assert_eq!(dclient.auth_kind(), AuthKind::Bearer);
+    assert!(dclient.is_authenticated());
+}
+
+#[test]
+fn test_auth_granted_scopes() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                server.url()
+            ),
+        )
+        .create();
+    let _token = server
+        .mock(
+            "GET",
+            "/token?service=registry&scope=repository:repo:pull,push",
+        )
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "abcdef", "scope": "repository:repo:pull"}"#)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let requested = vec!["repository:repo:pull,push".to_string()];
+    let granted = runtime.block_on(dclient.authenticate(&requested)).unwrap();
+    assert_eq!(granted, vec!["repository:repo:pull"]);
+
+    // Cached tokens report the same scopes.
+    let granted = runtime.block_on(dclient.authenticate(requested)).unwrap();
+    assert_eq!(granted, vec!["repository:repo:pull"]);
+}
+
+#[test]
+fn test_auth_token_granting_request_action() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                server.url()
+            ),
+        )
+        .create();
+    let _pull_token = server
+        .mock("GET", "/token?service=registry&scope=repository:repo:pull")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "pull-token", "expires_in": 300}"#)
+        .create();
+    let _push_token = server
+        .mock(
+            "GET",
+            "/token?service=registry&scope=repository:repo:pull,push",
+        )
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "push-token", "expires_in": 300}"#)
+        .create();
+    let start = server
+        .mock("POST", "/v2/repo/blobs/uploads/")
+        .match_header("Authorization", "Bearer push-token")
+        .with_status(202)
+        .with_header("Location", "/v2/repo/blobs/uploads/uuid")
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull,push"]))
+        .unwrap();
+    // The pull-only token is also cached, but does not grant the push.
+    runtime.block_on(dclient.start_upload("repo")).unwrap();
+
+    start.assert();
+}
+
+#[test]
+fn test_auth_token_endpoint_guards() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                server.url()
+            ),
+        )
+        .create();
+    let _slow = server
+        .mock("GET", "/token?service=registry&scope=repository:slow:pull")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_chunked_body(|w| {
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            w.write_all(br#"{"token": "abcdef"}"#)
+        })
+        .create();
+    let _large = server
+        .mock("GET", "/token?service=registry&scope=repository:large:pull")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(format!(r#"{{"token": "{}"}}"#, "a".repeat(2 * 1024 * 1024)))
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .auth_timeout(std::time::Duration::from_millis(100))
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.authenticate(&["repository:slow:pull"])) {
+        Err(e @ dkregistry::errors::Error::AuthEndpointError { .. }) => assert!(e.is_timeout()),
+        res => panic!("expected AuthEndpointError, got {:?}", res),
+    }
+    match runtime.block_on(dclient.authenticate(&["repository:large:pull"])) {
+        Err(e @ dkregistry::errors::Error::AuthEndpointError { .. }) => assert!(!e.is_timeout()),
+        res => panic!("expected AuthEndpointError, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_auth_no_auth() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let challenge = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header("WWW-Authenticate", r#"Basic realm="registry""#)
+        .expect(0)
+        .create();
+    let tags = server
+        .mock("GET", "/v2/repo/tags/list")
+        .match_header("authorization", mockito::Matcher::Missing)
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"name": "repo", "tags": [ "t1" ]}"#)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(Some("user".into()))
+        .password(Some("password".into()))
+        .bearer_token("token")
+        .auto_reauth(true)
+        .no_auth()
+        .build()
+        .unwrap();
+
+    let scopes = runtime
+        .block_on(dclient.authenticate(["repository:repo:pull"]))
+        .unwrap();
+    assert_eq!(scopes, ["repository:repo:pull"]);
+    assert!(!dclient.is_authenticated());
+
+    let fetched: Vec<String> = runtime
+        .block_on(dclient.get_tags("repo", None).collect::<Vec<_>>())
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(fetched, ["t1"]);
+
+    challenge.assert();
+    tags.assert();
+}
+
+#[test]
+fn test_auth_clock_expires_cached_token() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                server.url()
+            ),
+        )
+        .create();
+    let token_mock = server
+        .mock("GET", "/token?service=registry&scope=repository:repo:pull")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "abcdef", "expires_in": 300}"#)
+        .expect(2)
+        .create();
+
+    let elapsed = Arc::new(AtomicU64::new(0));
+    let clock = {
+        let elapsed = elapsed.clone();
+        move || SystemTime::now() + Duration::from_secs(elapsed.load(Ordering::SeqCst))
+    };
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .clock(clock)
+        .build()
+        .unwrap();
+
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    elapsed.store(290, Ordering::SeqCst);
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    elapsed.store(600, Ordering::SeqCst);
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+
+    token_mock.assert();
+}
+
+#[test]
+fn test_auth_prefer_basic() {
+    use dkregistry::v2::AuthKind;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                server.url()
+            ),
+        )
+        .with_header("WWW-Authenticate", r#"Basic realm="registry""#)
+        .create();
+    let token_mock = server
+        .mock("GET", "/token?service=registry&scope=repository:repo:pull")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "abcdef"}"#)
+        .expect(2)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let client = |prefer_basic, username: Option<&str>| {
+        dkregistry::v2::Client::configure()
+            .registry(&addr)
+            .insecure_registry(true)
+            .username(username.map(Into::into))
+            .password(username.map(|_| "secret".into()))
+            .prefer_basic(prefer_basic)
+            .build()
+            .unwrap()
+    };
+
+    let dclient = client(false, Some("robot$ci"));
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    assert_eq!(dclient.auth_kind(), AuthKind::Bearer);
+
+    let dclient = client(true, Some("robot$ci"));
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    assert_eq!(dclient.auth_kind(), AuthKind::Basic);
+
+    // Without credentials, Basic can't be used.
+    let dclient = client(true, None);
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    assert_eq!(dclient.auth_kind(), AuthKind::Bearer);
+
+    token_mock.assert();
+}
+
+#[test]
+fn test_auth_credential_provider() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="registry""#,
+                server.url()
+            ),
+        )
+        .create();
+    let token_mocks = ["secret-1", "secret-2"].map(|secret| {
+        server
+            .mock("POST", "/token")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("username".into(), "user".into()),
+                mockito::Matcher::UrlEncoded("password".into(), secret.into()),
+            ]))
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(r#"{"access_token": "abcdef", "expires_in": 300}"#)
+            .expect(1)
+            .create()
+    });
+
+    let calls = Arc::new(AtomicU64::new(0));
+    let provider = {
+        let calls = calls.clone();
+        move || {
+            let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            async move { Ok(("user".to_string(), format!("secret-{}", call))) }
+        }
+    };
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .oauth2(true)
+        .username(Some("ignored".into()))
+        .password(Some("ignored".into()))
+        .credential_provider(provider)
+        .build()
+        .unwrap();
+
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    // Served from the token cache, without asking for credentials.
+    runtime
+        .block_on(dclient.authenticate(&["repository:repo:pull"]))
+        .unwrap();
+    runtime
+        .block_on(dclient.authenticate(&["repository:other:pull"]))
+        .unwrap();
+
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    for token_mock in token_mocks {
+        token_mock.assert();
+    }
+}
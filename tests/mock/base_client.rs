@@ -2,17 +2,19 @@ extern crate dkregistry;
 extern crate mockito;
 extern crate tokio;
 
-use self::mockito::mock;
 use self::tokio::runtime::Runtime;
+use futures::StreamExt;
 
-static API_VERSION_K: &'static str = "Docker-Distribution-API-Version";
-static API_VERSION_V: &'static str = "registry/2.0";
+static API_VERSION_K: &str = "Docker-Distribution-API-Version";
+static API_VERSION_V: &str = "registry/2.0";
 
 #[test]
 #[ignore]
 fn test_base_no_insecure() {
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", "/v2/")
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
         .with_status(200)
         .with_header(API_VERSION_K, API_VERSION_V)
         .create();
@@ -31,16 +33,89 @@ fn test_base_no_insecure() {
     // This relies on the fact that mockito is HTTP-only and
     // trying to speak TLS to it results in garbage/errors.
     runtime.block_on(futcheck).unwrap_err();
+}
+
+#[test]
+fn test_base_registry_with_scheme() {
+    let mut server = mockito::Server::new();
+    let _m = server
+        .mock("GET", "/v2/")
+        .with_status(200)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .create();
 
-    mockito::reset();
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&server.url())
+        .insecure_registry(false)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    assert!(runtime.block_on(dclient.is_v2_supported()).unwrap());
+}
+
+#[test]
+fn test_base_connection_tuning() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let m = server
+        .mock("GET", "/v2/")
+        .with_status(200)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .expect(2)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .http2_prior_knowledge(true)
+        .pool_max_idle_per_host(4)
+        .pool_idle_timeout(std::time::Duration::from_secs(30))
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    assert!(runtime.block_on(dclient.is_v2_supported()).unwrap());
+    assert!(runtime.block_on(dclient.is_v2_supported()).unwrap());
+    m.assert();
+}
+
+#[test]
+fn test_base_http1_only() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let m = server
+        .mock("GET", "/v2/")
+        .with_status(200)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .http2_prior_knowledge(true)
+        .http1_only(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    assert!(runtime.block_on(dclient.is_v2_supported()).unwrap());
+    m.assert();
 }
 
 #[test]
-#[ignore]
 fn test_base_useragent() {
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", "/v2/")
-        .match_header("user-agent", dkregistry::USER_AGENT)
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .match_header("user-agent", dkregistry::USER_AGENT.as_str())
         .with_status(200)
         .with_header(API_VERSION_K, API_VERSION_V)
         .create();
@@ -57,17 +132,17 @@ fn test_base_useragent() {
     let futcheck = dclient.is_v2_supported();
 
     let res = runtime.block_on(futcheck).unwrap();
-    assert_eq!(res, true);
-
-    mockito::reset();
+    assert!(res);
 }
 
 #[test]
 fn test_base_custom_useragent() {
     let ua = "custom-ua/1.0";
 
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", "/v2/")
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
         .match_header("user-agent", ua)
         .with_status(200)
         .with_header(API_VERSION_K, API_VERSION_V)
@@ -77,7 +152,41 @@ fn test_base_custom_useragent() {
     let dclient = dkregistry::v2::Client::configure()
         .registry(&addr)
         .insecure_registry(true)
-        .user_agent(Some(ua.to_string()))
+        .user_agent(Some(ua.into()))
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.is_v2_supported();
+
+    let res = runtime.block_on(futcheck).unwrap();
+    assert!(res);
+}
+
+#[test]
+fn test_base_custom_http_client() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/")
+        .match_header("x-custom", "shared-client")
+        .with_status(200)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .create();
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("x-custom", "shared-client".parse().unwrap());
+    let http_client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .http_client(http_client)
         .username(None)
         .password(None)
         .build()
@@ -86,7 +195,236 @@ fn test_base_custom_useragent() {
     let futcheck = dclient.is_v2_supported();
 
     let res = runtime.block_on(futcheck).unwrap();
-    assert_eq!(res, true);
+    assert!(res);
+}
+
+#[test]
+fn test_base_retry_transient_failure() {
+    let name = "repo";
+    let ep = format!("/v2/{}/tags/list", name);
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let unavailable = server
+        .mock("GET", ep.as_str())
+        .with_status(503)
+        .with_header("Retry-After", "0")
+        .expect(2)
+        .create();
+    let ok = server
+        .mock("GET", ep.as_str())
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"name": "repo", "tags": [ "t1" ]}"#)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .retry(dkregistry::v2::RetryPolicy {
+            max_attempts: 3,
+            base_delay: std::time::Duration::from_millis(10),
+            max_delay: std::time::Duration::from_millis(10),
+            jitter: false,
+        })
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let tags: Vec<String> = runtime
+        .block_on(dclient.get_tags(name, None).collect::<Vec<_>>())
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(tags, vec!["t1".to_string()]);
+
+    unavailable.assert();
+    ok.assert();
+}
+
+#[test]
+fn test_base_retry_gives_up() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let unavailable = server
+        .mock("HEAD", "/v2/repo/blobs/sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")
+        .with_status(503)
+        .expect(2)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .retry(dkregistry::v2::RetryPolicy {
+            max_attempts: 2,
+            base_delay: std::time::Duration::from_millis(10),
+            max_delay: std::time::Duration::from_millis(10),
+            jitter: false,
+        })
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime
+        .block_on(dclient.has_blob(
+            "repo",
+            "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            None,
+        ))
+        .unwrap();
+    assert!(!res);
+
+    unavailable.assert();
+}
+
+#[test]
+fn test_base_last_rate_limit() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("HEAD", "/v2/repo/blobs/sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")
+        .with_status(200)
+        .with_header("RateLimit-Limit", "100;w=21600")
+        .with_header("RateLimit-Remaining", "76;w=21600")
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+    assert_eq!(dclient.last_rate_limit(), None);
+
+    let res = runtime
+        .block_on(dclient.has_blob(
+            "repo",
+            "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            None,
+        ))
+        .unwrap();
+    assert!(res);
+
+    let rate_limit = dclient.last_rate_limit().unwrap();
+    assert_eq!(rate_limit.limit(), 100);
+    assert_eq!(rate_limit.remaining(), 76);
+    assert_eq!(
+        rate_limit.window(),
+        Some(std::time::Duration::from_secs(21600))
+    );
+}
+
+#[test]
+fn test_base_path_prefix() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("HEAD", "/myregistry/v2/repo/blobs/sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")
+        .with_status(200)
+        .create();
+    let _tags = server
+        .mock("GET", "/myregistry/v2/repo/tags/list")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"name": "repo", "tags": [ "t1" ]}"#)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .path_prefix("/myregistry/")
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime
+        .block_on(dclient.has_blob(
+            "repo",
+            "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            None,
+        ))
+        .unwrap();
+    assert!(res);
+
+    let tags = runtime.block_on(
+        dclient
+            .get_tags("repo", None)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(tags, vec!["t1".to_string()]);
+}
+
+#[test]
+fn test_base_default_namespace() {
+    let digest = "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let manifest = server
+        .mock("HEAD", "/v2/library/alpine/manifests/latest?ns=docker.io")
+        .with_status(200)
+        .with_header("Docker-Content-Digest", digest)
+        .expect(1)
+        .create();
+    let overridden = server
+        .mock("HEAD", "/v2/library/alpine/manifests/latest?ns=quay.io")
+        .with_status(200)
+        .with_header("Docker-Content-Digest", digest)
+        .expect(1)
+        .create();
+    let blob = server
+        .mock(
+            "HEAD",
+            format!("/v2/library/alpine/blobs/{}?ns=docker.io", digest).as_str(),
+        )
+        .with_status(200)
+        .expect(1)
+        .create();
+    let tags = server
+        .mock("GET", "/v2/library/alpine/tags/list?ns=docker.io")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"name": "library/alpine", "tags": ["latest"]}"#)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .default_namespace(Some("docker.io"))
+        .build()
+        .unwrap();
+
+    let res = runtime
+        .block_on(dclient.get_manifestref("library/alpine", "latest", None))
+        .unwrap();
+    assert_eq!(res.as_deref(), Some(digest));
+    let res = runtime
+        .block_on(dclient.get_manifestref("library/alpine", "latest", Some("quay.io")))
+        .unwrap();
+    assert_eq!(res.as_deref(), Some(digest));
+    assert!(runtime
+        .block_on(dclient.has_blob("library/alpine", digest, None))
+        .unwrap());
+    let res = runtime.block_on(dclient.get_tags("library/alpine", None).collect::<Vec<_>>());
+    assert_eq!(
+        res.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+        vec!["latest"]
+    );
 
-    mockito::reset();
+    manifest.assert();
+    overridden.assert();
+    blob.assert();
+    tags.assert();
 }
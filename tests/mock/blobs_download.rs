@@ -3,23 +3,23 @@ extern crate mockito;
 extern crate sha2;
 extern crate tokio;
 
-use self::mockito::mock;
 use self::tokio::runtime::Runtime;
 use crate::mock::blobs_download::sha2::Digest;
 use futures::stream::StreamExt;
-use futures::FutureExt;
 
 type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
 
 #[test]
 fn test_blobs_has_layer() {
     let name = "my-repo/my-image";
-    let digest = "fakedigest";
+    let digest = "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
     let binary_digest = "binarydigest";
 
     let ep = format!("/v2/{}/blobs/{}", name, digest);
-    let addr = mockito::server_address().to_string();
-    let _m = mock("HEAD", ep.as_str())
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("HEAD", ep.as_str())
         .with_status(200)
         .with_header("Content-Length", "0")
         .with_header("Docker-Content-Digest", binary_digest)
@@ -34,22 +34,128 @@ fn test_blobs_has_layer() {
         .build()
         .unwrap();
 
-    let futcheck = dclient.has_blob(name, digest);
+    let futcheck = dclient.has_blob(name, digest, None);
 
     let res = runtime.block_on(futcheck).unwrap();
-    assert_eq!(res, true);
+    assert!(res);
+}
+
+#[test]
+fn test_blobs_has_layer_ranged_get_fallback() {
+    let name = "my-repo/my-image";
+    let digest = "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    let missing = "sha256:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _head = server
+        .mock("HEAD", mockito::Matcher::Any)
+        .with_status(405)
+        .create();
+    let get = server
+        .mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+        .match_header("Range", "bytes=0-0")
+        .with_status(206)
+        .with_body("h")
+        .expect(1)
+        .create();
+    let _get_missing = server
+        .mock("GET", format!("/v2/{}/blobs/{}", name, missing).as_str())
+        .with_status(404)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    assert!(runtime
+        .block_on(dclient.has_blob(name, digest, None))
+        .unwrap());
+    assert!(!runtime
+        .block_on(dclient.has_blob(name, missing, None))
+        .unwrap());
+    get.assert();
+}
+
+#[test]
+fn test_blobs_rate_limited() {
+    let name = "my-repo/my-image";
+    let digest = "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("HEAD", mockito::Matcher::Any)
+        .with_status(429)
+        .create();
+    let _get = server
+        .mock("GET", mockito::Matcher::Any)
+        .with_status(429)
+        .with_header("Retry-After", "3")
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.has_blob(name, digest, None)) {
+        Err(dkregistry::errors::Error::RateLimited { retry_after: None }) => {}
+        res => panic!("expected RateLimited, got {:?}", res),
+    }
+    match runtime.block_on(dclient.get_blob(name, digest, None)) {
+        Err(dkregistry::errors::Error::RateLimited { retry_after }) => {
+            assert_eq!(retry_after, Some(std::time::Duration::from_secs(3)))
+        }
+        res => panic!("expected RateLimited, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_blobs_invalid_digest() {
+    let name = "my-repo/my-image";
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let mock = server
+        .mock("HEAD", mockito::Matcher::Any)
+        .expect(0)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
 
-    mockito::reset();
+    match runtime.block_on(dclient.has_blob(name, "fakedigest", None)) {
+        Err(dkregistry::errors::Error::VersionParse(_)) => {}
+        res => panic!("expected a digest parse error, got {:?}", res),
+    }
+    mock.assert();
 }
 
 #[test]
 fn test_blobs_hasnot_layer() {
     let name = "my-repo/my-image";
-    let digest = "fakedigest";
+    let digest = "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
 
     let ep = format!("/v2/{}/blobs/{}", name, digest);
-    let addr = mockito::server_address().to_string();
-    let _m = mock("HEAD", ep.as_str()).with_status(404).create();
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server.mock("HEAD", ep.as_str()).with_status(404).create();
 
     let runtime = Runtime::new().unwrap();
     let dclient = dkregistry::v2::Client::configure()
@@ -60,24 +166,80 @@ fn test_blobs_hasnot_layer() {
         .build()
         .unwrap();
 
-    let futcheck = dclient.has_blob(name, digest);
+    let futcheck = dclient.has_blob(name, digest, None);
 
     let res = runtime.block_on(futcheck).unwrap();
-    assert_eq!(res, false);
+    assert!(!res);
+}
+
+#[test]
+fn test_blobs_metadata() {
+    let name = "my-repo/my-image";
+    let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("HEAD", ep.as_str())
+        .with_status(200)
+        .with_header("Content-Length", "5")
+        .with_header("Content-Type", "application/octet-stream")
+        .with_header("Docker-Content-Digest", digest)
+        .with_header("Accept-Ranges", "bytes")
+        .create();
+    let _no_ranges = server
+        .mock("HEAD", "/v2/no-ranges/blobs/sha256:abcd")
+        .with_status(200)
+        .with_header("Accept-Ranges", "none")
+        .create();
+    let _missing = server
+        .mock("HEAD", "/v2/my-repo/my-image/blobs/sha256:abcd")
+        .with_status(404)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let meta = runtime
+        .block_on(dclient.blob_metadata(name, digest, None))
+        .unwrap()
+        .unwrap();
+    assert_eq!(meta.size(), Some(5));
+    assert_eq!(meta.digest(), digest);
+    assert_eq!(meta.media_type(), Some("application/octet-stream"));
+    assert!(meta.supports_ranges());
 
-    mockito::reset();
+    let meta = runtime
+        .block_on(dclient.blob_metadata("no-ranges", "sha256:abcd", None))
+        .unwrap()
+        .unwrap();
+    assert!(!meta.supports_ranges());
+
+    let missing = runtime
+        .block_on(dclient.blob_metadata(name, "sha256:abcd", None))
+        .unwrap();
+    assert_eq!(missing, None);
 }
 
 #[test]
 fn get_blobs_succeeds_with_consistent_layer() -> Fallible<()> {
-    let addr = mockito::server_address().to_string();
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
 
     let name = "my-repo/my-image";
     let blob = b"hello";
     let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
 
     let ep = format!("/v2/{}/blobs/{}", &name, &digest);
-    let _m = mock("GET", ep.as_str())
+    let _m = server
+        .mock("GET", ep.as_str())
         .with_status(200)
         .with_body(blob)
         .create();
@@ -91,18 +253,18 @@ fn get_blobs_succeeds_with_consistent_layer() -> Fallible<()> {
         .build()
         .unwrap();
 
-    let futcheck = dclient.get_blob(&name, &digest);
+    let futcheck = dclient.get_blob(name, &digest, None);
 
     let result = runtime.block_on(futcheck)?;
     assert_eq!(blob, result.as_slice());
 
-    mockito::reset();
     Ok(())
 }
 
 #[test]
 fn get_blobs_fails_with_inconsistent_layer() -> Fallible<()> {
-    let addr = mockito::server_address().to_string();
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
 
     let name = "my-repo/my-image";
     let blob = b"hello";
@@ -110,7 +272,8 @@ fn get_blobs_fails_with_inconsistent_layer() -> Fallible<()> {
     let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
 
     let ep = format!("/v2/{}/blobs/{}", &name, &digest);
-    let _m = mock("GET", ep.as_str())
+    let _m = server
+        .mock("GET", ep.as_str())
         .with_status(200)
         .with_body(blob2)
         .create();
@@ -124,27 +287,65 @@ fn get_blobs_fails_with_inconsistent_layer() -> Fallible<()> {
         .build()
         .unwrap();
 
-    let futcheck = dclient.get_blob(&name, &digest);
+    let futcheck = dclient.get_blob(name, &digest, None);
 
-    if runtime.block_on(futcheck).is_ok() {
-        return Err("expected get_blob to fail with an inconsistent blob".into());
+    match runtime.block_on(futcheck) {
+        Err(dkregistry::errors::Error::DigestMismatch { expected, actual }) => {
+            assert_eq!(expected, digest);
+            assert_eq!(actual, format!("sha256:{:x}", sha2::Sha256::digest(blob2)));
+        }
+        res => return Err(format!("expected DigestMismatch, got {:?}", res).into()),
     };
 
-    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blobs_unverified_with_inconsistent_layer() -> Fallible<()> {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = server
+        .mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(b"hello2")
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .verify_digests(false)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let blob = runtime.block_on(dclient.get_blob(name, &digest, None))?;
+    assert_eq!(blob, b"hello2");
+
     Ok(())
 }
 
 #[test]
 fn get_blobs_stream() -> Fallible<()> {
-    let addr = mockito::server_address().to_string();
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
 
     let name = "my-repo/my-image";
     let blob = b"hello";
     let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
 
     let ep = format!("/v2/{}/blobs/{}", &name, &digest);
-    let _m = mock("GET", ep.as_str())
+    let _m = server
+        .mock("GET", ep.as_str())
         .with_status(200)
+        .with_header("Accept-Ranges", "bytes")
+        .with_header("X-Vendor-Cache", "hit")
         .with_body(blob)
         .create();
 
@@ -157,14 +358,1203 @@ fn get_blobs_stream() -> Fallible<()> {
         .build()
         .unwrap();
 
-    let futcheck = dclient.get_blob_response(&name, &digest);
+    let futcheck = dclient.get_blob_response(name, &digest, None);
 
     let blob_resp = runtime.block_on(futcheck)?;
     assert_eq!(blob_resp.size(), Some(5));
-    let stream_output = blob_resp.stream().next().now_or_never();
-    let output = stream_output.unwrap_or_else(|| panic!("No stream output"));
+    assert!(blob_resp.supports_ranges());
+    assert_eq!(blob_resp.headers()["x-vendor-cache"], "hit");
+    let mut stream = blob_resp.stream();
+    assert_eq!(stream.digest(), None);
+    let output = runtime.block_on(stream.next());
     let received_blob = output.unwrap_or_else(|| panic!("No blob data"))?;
     assert_eq!(blob.to_vec(), received_blob);
-    mockito::reset();
+    assert!(runtime.block_on(stream.next()).is_none());
+    assert_eq!(stream.digest(), Some(digest.as_str()));
+    Ok(())
+}
+
+#[test]
+fn get_blobs_sha512() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha512:{:x}", sha2::Sha512::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .create();
+    let ep_corrupted = format!("/v2/{}/blobs/{}", "my-repo/corrupted", &digest);
+    let _m_corrupted = server
+        .mock("GET", ep_corrupted.as_str())
+        .with_status(200)
+        .with_body(b"hello2")
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let result = runtime.block_on(dclient.get_blob(name, &digest, None))?;
+    assert_eq!(blob, result.as_slice());
+
+    if runtime
+        .block_on(dclient.get_blob("my-repo/corrupted", &digest, None))
+        .is_ok()
+    {
+        return Err("expected get_blob to fail with an inconsistent blob".into());
+    };
+
+    Ok(())
+}
+
+#[test]
+fn get_blobs_stream_fails_with_inconsistent_layer() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let blob2 = b"hello2";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob2)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let stream = runtime.block_on(dclient.get_blob_stream(name, &digest, None))?;
+    let chunks = runtime.block_on(stream.collect::<Vec<_>>());
+
+    match chunks.last() {
+        Some(Err(dkregistry::errors::Error::DigestMismatch { expected, actual })) => {
+            assert_eq!(expected, &digest);
+            assert_eq!(actual, &format!("sha256:{:x}", sha2::Sha256::digest(blob2)));
+        }
+        res => return Err(format!("expected DigestMismatch, got {:?}", res).into()),
+    };
+
+    Ok(())
+}
+
+#[test]
+fn get_blobs_stream_chunked() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello world, in chunks";
+    let blob2 = b"hello world, in chunks!";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .create();
+    let _m2 = server
+        .mock(
+            "GET",
+            format!("/v2/my-repo/corrupted/blobs/{}", &digest).as_str(),
+        )
+        .with_status(200)
+        .with_body(blob2)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let mut stream = runtime.block_on(dclient.get_blob_stream_chunked(name, &digest, 8, None))?;
+    assert_eq!(stream.bytes_downloaded(), 0);
+    let mut sizes = vec![];
+    let mut data = vec![];
+    while let Some(chunk) = runtime.block_on(stream.next()) {
+        let chunk = chunk?;
+        sizes.push(chunk.len());
+        data.extend_from_slice(&chunk);
+    }
+    assert_eq!(sizes, vec![8, 8, 6]);
+    assert_eq!(data, blob);
+    assert_eq!(stream.digest(), Some(digest.as_str()));
+    assert_eq!(stream.bytes_downloaded(), blob.len() as u64);
+
+    let stream =
+        runtime.block_on(dclient.get_blob_stream_chunked("my-repo/corrupted", &digest, 8, None))?;
+    let chunks = runtime.block_on(stream.collect::<Vec<_>>());
+    assert_eq!(chunks.len(), 3);
+    match chunks.last() {
+        Some(Err(dkregistry::errors::Error::DigestMismatch { .. })) => {}
+        res => return Err(format!("expected DigestMismatch, got {:?}", res).into()),
+    };
+
+    Ok(())
+}
+
+#[test]
+fn download_blob_to() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello world, to a writer";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", format!("/v2/{}/blobs/{}", &name, &digest).as_str())
+        .with_status(200)
+        .with_body(blob)
+        .create();
+    let _m2 = server
+        .mock(
+            "GET",
+            format!("/v2/my-repo/corrupted/blobs/{}", &digest).as_str(),
+        )
+        .with_status(200)
+        .with_body("tampered")
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .verify_digests(false)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let mut data = vec![];
+    let written = runtime.block_on(dclient.download_blob_to(name, &digest, &mut data, None))?;
+    assert_eq!(written, blob.len() as u64);
+    assert_eq!(data, blob);
+
+    let mut data = vec![];
+    let res =
+        runtime.block_on(dclient.download_blob_to("my-repo/corrupted", &digest, &mut data, None));
+    match res {
+        Err(dkregistry::errors::Error::DigestMismatch { .. }) => {}
+        res => return Err(format!("expected DigestMismatch, got {:?}", res).into()),
+    };
+    assert_eq!(data, b"tampered");
+
+    Ok(())
+}
+
+#[test]
+fn get_blobs_sized_fails_with_truncated_layer() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let result = runtime.block_on(dclient.get_blob_sized(name, &digest, 5, None))?;
+    assert_eq!(blob, result.as_slice());
+
+    match runtime.block_on(dclient.get_blob_response_sized(name, &digest, 10, None)) {
+        Err(dkregistry::errors::Error::SizeMismatch { expected, actual }) => {
+            assert_eq!(expected, 10);
+            assert_eq!(actual, 5);
+        }
+        res => return Err(format!("expected SizeMismatch, got {:?}", res).into()),
+    };
+
+    Ok(())
+}
+
+#[test]
+fn get_blobs_timeout() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
+        .with_status(200)
+        .with_chunked_body(|w| {
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            w.write_all(b"hello")
+        })
+        .expect(2)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let configure = || {
+        dkregistry::v2::Client::configure()
+            .registry(&addr)
+            .insecure_registry(true)
+            .username(None)
+            .password(None)
+            .timeout(std::time::Duration::from_millis(100))
+    };
+
+    let dclient = configure().build().unwrap();
+    match runtime.block_on(dclient.get_blob(name, &digest, None)) {
+        Err(e) if e.is_timeout() => {}
+        res => return Err(format!("expected a timeout, got {:?}", res).into()),
+    };
+
+    let dclient = configure()
+        .blob_timeout(std::time::Duration::from_secs(10))
+        .build()
+        .unwrap();
+    let result = runtime.block_on(dclient.get_blob(name, &digest, None))?;
+    assert_eq!(blob, result.as_slice());
+
     Ok(())
 }
+
+#[test]
+fn get_blobs_concurrent() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blobs: [&[u8]; 3] = [b"hello", b"hello2", b"hello3"];
+    let digests: Vec<String> = blobs
+        .iter()
+        .map(|blob| format!("sha256:{:x}", sha2::Sha256::digest(blob)))
+        .collect();
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let mocks: Vec<_> = blobs
+        .iter()
+        .zip(&digests)
+        .map(|(blob, digest)| {
+            server
+                .mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+                .with_status(200)
+                .with_body(blob)
+                .expect(1)
+                .create()
+        })
+        .collect();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let digest_refs: Vec<&str> = digests.iter().map(String::as_str).collect();
+    let mut results = runtime
+        .block_on(
+            dclient
+                .get_blobs_concurrent(name, &digest_refs, 2, None)
+                .collect::<Vec<_>>(),
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    results.sort();
+
+    let mut expected: Vec<_> = digests
+        .iter()
+        .cloned()
+        .zip(blobs.iter().map(|blob| blob.to_vec()))
+        .collect();
+    expected.sort();
+    assert_eq!(results, expected);
+
+    for m in mocks {
+        m.assert();
+    }
+
+    Ok(())
+}
+
+#[test]
+fn get_blobs_range() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello world";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _partial = server
+        .mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+        .match_header("Range", "bytes=6-10")
+        .with_status(206)
+        .with_header("Content-Range", "bytes 6-10/11")
+        .with_body(&blob[6..])
+        .create();
+    let _bad_range = server
+        .mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+        .match_header("Range", "bytes=0-4")
+        .with_status(206)
+        .with_header("Content-Range", "bytes 6-10/11")
+        .with_body(&blob[6..])
+        .create();
+    let _ignored = server
+        .mock("GET", format!("/v2/ignored/blobs/{}", digest).as_str())
+        .with_status(200)
+        .with_body(blob)
+        .create();
+    let _unknown = server
+        .mock("GET", format!("/v2/unknown/blobs/{}", digest).as_str())
+        .with_status(404)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"errors":[{"code":"BLOB_UNKNOWN","message":"blob unknown to registry"}]}"#)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let range = runtime.block_on(dclient.get_blob_range(name, &digest, 6..11, None))?;
+    assert_eq!(&range[..], b"world");
+
+    let range = runtime.block_on(dclient.get_blob_range("ignored", &digest, 0..5, None))?;
+    assert_eq!(&range[..], b"hello");
+
+    match runtime.block_on(dclient.get_blob_range(name, &digest, 0..5, None)) {
+        Err(dkregistry::errors::Error::UnexpectedContentRange(Some(content_range))) => {
+            assert_eq!(content_range, "bytes 6-10/11");
+        }
+        res => return Err(format!("expected UnexpectedContentRange, got {:?}", res).into()),
+    };
+
+    match runtime.block_on(dclient.get_blob_range("unknown", &digest, 0..5, None)) {
+        Err(dkregistry::errors::Error::Registry { status, errors }) => {
+            assert_eq!(status, 404);
+            assert_eq!(errors[0].code(), "BLOB_UNKNOWN");
+        }
+        res => return Err(format!("expected Registry error, got {:?}", res).into()),
+    };
+
+    Ok(())
+}
+
+#[test]
+fn get_blobs_cached() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let m = server
+        .mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .expect(1)
+        .create();
+
+    let cache_dir =
+        std::env::temp_dir().join(format!("dkregistry-mock-cache-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&cache_dir);
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .blob_cache(dkregistry::v2::FsBlobCache::new(&cache_dir))
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    for _ in 0..2 {
+        let result = runtime.block_on(dclient.get_blob(name, &digest, None))?;
+        assert_eq!(blob, result.as_slice());
+    }
+    m.assert();
+
+    std::fs::remove_dir_all(&cache_dir)?;
+    Ok(())
+}
+
+#[test]
+fn get_foreign_blob_from_urls() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _missing = server
+        .mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+        .with_status(404)
+        .create();
+    let _broken = server
+        .mock("GET", "/foreign/broken.tar.gz")
+        .with_status(500)
+        .create();
+    let foreign = server
+        .mock("GET", "/foreign/layer.tar.gz")
+        .match_header("Authorization", mockito::Matcher::Missing)
+        .with_status(200)
+        .with_body(blob)
+        .expect(1)
+        .create();
+
+    let layer: dkregistry::v2::manifest::Layer = serde_json::from_value(serde_json::json!({
+        "mediaType": "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip",
+        "size": 5,
+        "digest": digest,
+        "urls": [
+            format!("{}/foreign/broken.tar.gz", server.url()),
+            format!("{}/foreign/layer.tar.gz", server.url()),
+        ],
+    }))?;
+    assert!(layer.is_foreign());
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(Some("user".into()))
+        .password(Some("password".into()))
+        .build()
+        .unwrap();
+
+    let result = runtime.block_on(dclient.get_foreign_blob(name, &layer, None))?;
+    assert_eq!(blob, result.as_slice());
+    foreign.assert();
+
+    Ok(())
+}
+
+#[test]
+fn get_foreign_blob_with_mirrors() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+
+    let mut mirror = mockito::Server::new();
+    let mirror_missing = mirror
+        .mock("GET", ep.as_str())
+        .with_status(404)
+        .expect(1)
+        .create();
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _missing = server.mock("GET", ep.as_str()).with_status(404).create();
+    let foreign = server
+        .mock("GET", "/foreign/layer.tar.gz")
+        .with_status(200)
+        .with_body(blob)
+        .expect(1)
+        .create();
+
+    let layer: dkregistry::v2::manifest::Layer = serde_json::from_value(serde_json::json!({
+        "mediaType": "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip",
+        "size": 5,
+        "digest": digest,
+        "urls": [format!("{}/foreign/layer.tar.gz", server.url())],
+    }))?;
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .mirrors(vec![mirror.url().parse()?])
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let result = runtime.block_on(dclient.get_foreign_blob(name, &layer, None))?;
+    assert_eq!(blob, result.as_slice());
+    mirror_missing.assert();
+    foreign.assert();
+
+    Ok(())
+}
+
+#[test]
+fn get_foreign_blob_never_reauthenticates() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mut foreign = mockito::Server::new();
+    let realm = foreign
+        .mock("GET", "/token")
+        .match_query(mockito::Matcher::Any)
+        .with_status(200)
+        .with_body(r#"{"token": "stolen"}"#)
+        .expect(0)
+        .create();
+    let challenge = format!(
+        r#"Bearer realm="{}/token",scope="repository:x:pull""#,
+        foreign.url()
+    );
+    let layer_mock = foreign
+        .mock("GET", "/layer.tar.gz")
+        .with_status(401)
+        .with_header("WWW-Authenticate", &challenge)
+        .expect(1)
+        .create();
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _missing = server
+        .mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+        .with_status(404)
+        .create();
+
+    let layer: dkregistry::v2::manifest::Layer = serde_json::from_value(serde_json::json!({
+        "mediaType": "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip",
+        "size": 5,
+        "digest": digest,
+        "urls": [format!("{}/layer.tar.gz", foreign.url())],
+    }))?;
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .auto_reauth(true)
+        .username(Some("user".into()))
+        .password(Some("password".into()))
+        .build()
+        .unwrap();
+
+    let result = runtime.block_on(dclient.get_foreign_blob(name, &layer, None));
+    assert!(result.is_err());
+    layer_mock.assert();
+    realm.assert();
+
+    Ok(())
+}
+
+fn redirected_blob(policy: dkregistry::v2::BlobRedirectPolicy, storage_auth: mockito::Matcher) {
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mut storage = mockito::Server::new();
+    let storage_mock = storage
+        .mock("GET", "/bucket/blob")
+        .match_header("Authorization", storage_auth)
+        .with_status(200)
+        .with_body(blob)
+        .expect(1)
+        .create();
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _redirect = server
+        .mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+        .match_header("Authorization", "Bearer secret")
+        .with_status(307)
+        .with_header("Location", &format!("{}/bucket/blob", storage.url()))
+        .create();
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(
+        reqwest::header::AUTHORIZATION,
+        reqwest::header::HeaderValue::from_static("Bearer secret"),
+    );
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .default_headers(headers)
+        .blob_redirect_policy(policy)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let resp = runtime
+        .block_on(dclient.get_blob_response(name, &digest, None))
+        .unwrap();
+    assert_eq!(
+        resp.final_url().as_str(),
+        format!("{}/bucket/blob", storage.url())
+    );
+    let res = runtime.block_on(resp.bytes()).unwrap();
+    assert_eq!(res, blob);
+    storage_mock.assert();
+}
+
+#[test]
+fn get_blobs_redirect_strips_auth() {
+    redirected_blob(
+        dkregistry::v2::BlobRedirectPolicy::StripAuth,
+        mockito::Matcher::Missing,
+    );
+}
+
+#[test]
+fn get_blobs_redirect_keeps_auth() {
+    redirected_blob(
+        dkregistry::v2::BlobRedirectPolicy::KeepAuth,
+        mockito::Matcher::Exact("Bearer secret".to_string()),
+    );
+}
+
+#[cfg(feature = "decompress")]
+#[test]
+fn test_blobs_decompressed_zstd() {
+    // "hello\n", compressed with `zstd`.
+    let blob: &[u8] = &[
+        0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x58, 0x31, 0x00, 0x00, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x0a,
+        0x53, 0x88, 0xbd, 0x91,
+    ];
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.oci.image.layer.v1.tar+zstd",
+        )
+        .with_body(blob)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime
+        .block_on(dclient.get_blob_decompressed(name, &digest, None))
+        .unwrap();
+    assert_eq!(res, b"hello\n");
+}
+
+#[test]
+fn test_blobs_registry_error() {
+    let name = "my-repo/my-image";
+    let digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
+        .with_status(404)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"errors":[{"code":"BLOB_UNKNOWN","message":"blob unknown to registry"}]}"#)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let err = runtime
+        .block_on(dclient.get_blob_response(name, digest, None))
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "registry error with status 404 Not Found: BLOB_UNKNOWN: blob unknown to registry"
+    );
+    match err {
+        dkregistry::errors::Error::Registry { status, errors } => {
+            assert_eq!(status, 404);
+            assert_eq!(errors[0].code(), "BLOB_UNKNOWN");
+            assert_eq!(errors[0].detail(), None);
+        }
+        e => panic!("expected Registry error, got {:?}", e),
+    }
+}
+
+#[test]
+fn test_blobs_which_exist() {
+    let name = "my-repo/my-image";
+    let digests = [
+        "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        "sha256:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb",
+        "sha256:cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc",
+    ];
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _present = server
+        .mock(
+            "HEAD",
+            mockito::Matcher::Regex("/blobs/sha256:(a+|c+)$".to_string()),
+        )
+        .with_status(200)
+        .with_header("Content-Length", "0")
+        .expect(2)
+        .create();
+    let _missing = server
+        .mock("HEAD", format!("/v2/{}/blobs/sha256:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb", name).as_str())
+        .with_status(404)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime
+        .block_on(dclient.which_blobs_exist(name, &digests, 2, None))
+        .unwrap();
+    assert_eq!(
+        res,
+        vec![
+            (
+                "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+                    .to_string(),
+                true
+            ),
+            (
+                "sha256:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"
+                    .to_string(),
+                false
+            ),
+            (
+                "sha256:cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc"
+                    .to_string(),
+                true
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_blobs_from_mirror() {
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+
+    let mut mirror = mockito::Server::new();
+    let mirror_get = mirror
+        .mock("GET", ep.as_str())
+        .match_header("Authorization", mockito::Matcher::Missing)
+        .with_status(200)
+        .with_body(blob)
+        .expect(1)
+        .create();
+    let mirror_head = mirror
+        .mock("HEAD", ep.as_str())
+        .with_status(200)
+        .expect(1)
+        .create();
+    let mut server = mockito::Server::new();
+    let canonical = server.mock("GET", mockito::Matcher::Any).expect(0).create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&server.host_with_port())
+        .insecure_registry(true)
+        .mirrors(vec![mirror.url().parse().unwrap()])
+        .username(Some("user".into()))
+        .password(Some("pass".into()))
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.get_blob(name, digest.as_str(), None));
+    assert_eq!(res.unwrap(), blob.to_vec());
+    let found = runtime.block_on(dclient.blob_exists_any(name, &digest, None));
+    assert_eq!(found.unwrap(), Some(mirror.url()));
+
+    mirror_get.assert();
+    mirror_head.assert();
+    canonical.assert();
+}
+
+#[test]
+fn test_blobs_mirror_fallback() {
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+
+    let mut mirror = mockito::Server::new();
+    let _mirror_get = mirror
+        .mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body("tampered")
+        .create();
+    let _mirror_head = mirror.mock("HEAD", ep.as_str()).with_status(500).create();
+    let mut server = mockito::Server::new();
+    let get = server
+        .mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .expect(1)
+        .create();
+    let _head = server.mock("HEAD", ep.as_str()).with_status(404).create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&server.host_with_port())
+        .insecure_registry(true)
+        .mirrors(vec![mirror.url().parse().unwrap()])
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.get_blob(name, digest.as_str(), None));
+    assert_eq!(res.unwrap(), blob.to_vec());
+    assert!(!runtime
+        .block_on(dclient.has_blob(name, digest.as_str(), None))
+        .unwrap());
+    get.assert();
+}
+
+#[test]
+fn test_blobs_mirrors_all_fail() {
+    let name = "my-repo/my-image";
+    let digest = "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+
+    let mut mirror = mockito::Server::new();
+    let _mirror = mirror
+        .mock("GET", mockito::Matcher::Any)
+        .with_status(500)
+        .create();
+    let mut server = mockito::Server::new();
+    let _server = server
+        .mock("GET", mockito::Matcher::Any)
+        .with_status(404)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&server.host_with_port())
+        .insecure_registry(true)
+        .mirrors(vec![mirror.url().parse().unwrap()])
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.get_blob(name, digest, None)) {
+        Err(dkregistry::errors::Error::Mirrors(errors)) => {
+            let registries: Vec<_> = errors.iter().map(|(r, _)| r.clone()).collect();
+            assert_eq!(registries, vec![mirror.url(), server.url()]);
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+
+    let res = dkregistry::v2::Client::configure()
+        .registry(&server.host_with_port())
+        .mirrors(vec!["ftp://mirror.example.com".parse().unwrap()])
+        .build();
+    assert!(matches!(
+        res,
+        Err(dkregistry::errors::Error::InvalidRegistry(_))
+    ));
+}
+
+#[test]
+fn get_blobs_max_blob_bytes() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+        .with_status(200)
+        .with_body(blob)
+        .create();
+    let _chunked = server
+        .mock(
+            "GET",
+            format!("/v2/my-repo/chunked/blobs/{}", digest).as_str(),
+        )
+        .with_status(200)
+        .with_chunked_body(|w| w.write_all(b"hello"))
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let client_with_limit = |limit| {
+        dkregistry::v2::Client::configure()
+            .registry(&addr)
+            .insecure_registry(true)
+            .username(None)
+            .password(None)
+            .max_blob_bytes(limit)
+            .build()
+            .unwrap()
+    };
+
+    let result = runtime.block_on(client_with_limit(5).get_blob(name, &digest, None))?;
+    assert_eq!(blob, result.as_slice());
+
+    for repo in &[name, "my-repo/chunked"] {
+        match runtime.block_on(client_with_limit(4).get_blob(repo, &digest, None)) {
+            Err(dkregistry::errors::Error::BlobTooLarge { size: 5, limit: 4 }) => {}
+            res => return Err(format!("expected BlobTooLarge, got {:?}", res).into()),
+        }
+    }
+    match runtime.block_on(client_with_limit(4).get_blob_sized(name, &digest, 5, None)) {
+        Err(dkregistry::errors::Error::BlobTooLarge { size: 5, limit: 4 }) => {}
+        res => return Err(format!("expected BlobTooLarge, got {:?}", res).into()),
+    }
+    let result = runtime.block_on(client_with_limit(5).get_blob_sized(name, &digest, 5, None))?;
+    assert_eq!(blob, result.as_slice());
+
+    let mut stream = runtime.block_on(client_with_limit(4).get_blob_stream(name, &digest, None))?;
+    let mut data = vec![];
+    while let Some(chunk) = runtime.block_on(stream.next()) {
+        data.extend_from_slice(&chunk?);
+    }
+    assert_eq!(data, blob);
+
+    Ok(())
+}
+
+#[test]
+fn test_blobs_cancellation() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello world";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+        .with_status(200)
+        .with_chunked_body(|w| {
+            w.write_all(b"hello")?;
+            w.flush()?;
+            std::thread::sleep(std::time::Duration::from_secs(1));
+            w.write_all(b" world")
+        })
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let token = dkregistry::v2::CancellationToken::new();
+    let cancellable = dclient.with_cancellation_token(token.clone());
+    let (first, rest, elapsed) = runtime.block_on(async {
+        let mut stream = cancellable.get_blob_stream(name, &digest, None).await?;
+        let first = stream.next().await.transpose()?;
+        let start = std::time::Instant::now();
+        token.cancel();
+        let rest = stream.next().await;
+        assert!(stream.next().await.is_none());
+        Ok::<_, dkregistry::errors::Error>((first, rest, start.elapsed()))
+    })?;
+    assert_eq!(first.as_deref(), Some(&b"hello"[..]));
+    assert!(matches!(
+        rest,
+        Some(Err(dkregistry::errors::Error::Cancelled))
+    ));
+    assert!(elapsed < std::time::Duration::from_millis(500));
+
+    assert!(matches!(
+        runtime.block_on(cancellable.get_blob(name, &digest, None)),
+        Err(dkregistry::errors::Error::Cancelled)
+    ));
+    // Other clients are not affected.
+    assert_eq!(
+        runtime.block_on(dclient.get_blob(name, &digest, None))?,
+        blob
+    );
+    Ok(())
+}
+
+#[test]
+fn prefetch_layers() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let layers: [&[u8]; 3] = [b"base", b"middle", b"top"];
+    let digests: Vec<String> = layers
+        .iter()
+        .map(|blob| format!("sha256:{:x}", sha2::Sha256::digest(blob)))
+        .collect();
+    let manifest = format!(
+        r#"{{"schemaVersion":2,"mediaType":"application/vnd.docker.distribution.manifest.v2+json","config":{{"mediaType":"application/vnd.docker.container.image.v1+json","size":0,"digest":"sha256:0000000000000000000000000000000000000000000000000000000000000000"}},"layers":[{}]}}"#,
+        layers
+            .iter()
+            .zip(&digests)
+            .map(|(blob, digest)| format!(
+                r#"{{"mediaType":"application/vnd.docker.image.rootfs.diff.tar.gzip","size":{},"digest":"{}"}}"#,
+                blob.len(),
+                digest
+            ))
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    let manifest: dkregistry::v2::manifest::Manifest = serde_json::from_str(&manifest)?;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    for (i, (blob, digest)) in layers.iter().zip(&digests).enumerate() {
+        // The base layer is the slowest, yet comes out first.
+        let delay = std::time::Duration::from_millis(if i == 0 { 200 } else { 0 });
+        let blob = blob.to_vec();
+        server
+            .mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+            .with_status(200)
+            .with_chunked_body(move |w| {
+                std::thread::sleep(delay);
+                w.write_all(&blob)
+            })
+            .expect(1)
+            .create();
+    }
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let results = runtime
+        .block_on(
+            dclient
+                .prefetch_layers(name, &manifest, None, 2, None)
+                .collect::<Vec<_>>(),
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    let expected: Vec<_> = digests
+        .iter()
+        .cloned()
+        .zip(layers.iter().map(|blob| blob.to_vec()))
+        .collect();
+    assert_eq!(results, expected);
+
+    let list: dkregistry::v2::manifest::Manifest =
+        serde_json::from_slice(&std::fs::read("tests/fixtures/manifest_list_v2.json")?)?;
+    let results = runtime.block_on(
+        dclient
+            .prefetch_layers(name, &list, None, 2, None)
+            .collect::<Vec<_>>(),
+    );
+    assert!(matches!(
+        results.as_slice(),
+        [Err(dkregistry::errors::Error::Manifest(_))]
+    ));
+    Ok(())
+}
+
+fn redirect_chain(policy: dkregistry::v2::BlobRedirectPolicy, max_redirects: usize) -> bool {
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _redirect = server
+        .mock("GET", format!("/v2/{}/blobs/{}", name, digest).as_str())
+        .with_status(307)
+        .with_header("Location", "/cdn/blob")
+        .create();
+    let _cdn = server
+        .mock("GET", "/cdn/blob")
+        .with_status(302)
+        .with_header("Location", "/bucket/blob?signature=secret")
+        .create();
+    let _storage = server
+        .mock("GET", "/bucket/blob")
+        .match_query(mockito::Matcher::Any)
+        .with_status(200)
+        .with_body(blob)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .blob_redirect_policy(policy)
+        .max_redirects(max_redirects)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.get_blob(name, &digest, None)) {
+        Ok(res) => {
+            assert_eq!(res, blob);
+            true
+        }
+        Err(dkregistry::errors::Error::TooManyRedirects(max)) => {
+            assert_eq!(max, max_redirects);
+            false
+        }
+        Err(e) => panic!("expected TooManyRedirects, got {:?}", e),
+    }
+}
+
+#[test]
+fn get_blobs_max_redirects() {
+    for policy in [
+        dkregistry::v2::BlobRedirectPolicy::StripAuth,
+        dkregistry::v2::BlobRedirectPolicy::KeepAuth,
+    ] {
+        assert!(!redirect_chain(policy, 0));
+        assert!(redirect_chain(policy, 2));
+        assert!(!redirect_chain(policy, 1));
+    }
+}
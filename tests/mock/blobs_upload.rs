@@ -0,0 +1,395 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate sha2;
+extern crate tokio;
+
+use self::tokio::runtime::Runtime;
+use crate::mock::blobs_upload::sha2::Digest;
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[test]
+fn upload_blob_stream() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello world";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let start = server
+        .mock("POST", format!("/v2/{}/blobs/uploads/", name).as_str())
+        .with_status(202)
+        .with_header(
+            "Location",
+            &format!("/v2/{}/blobs/uploads/uuid1?_state=abc", name),
+        )
+        .expect(1)
+        .create();
+    let finish = server
+        .mock("PUT", format!("/v2/{}/blobs/uploads/uuid1", name).as_str())
+        .match_query(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("_state".into(), "abc".into()),
+            mockito::Matcher::UrlEncoded("digest".into(), digest.clone()),
+        ]))
+        .match_body("hello world")
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &digest)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let chunks: Vec<dkregistry::errors::Result<bytes::Bytes>> = vec![
+        Ok(bytes::Bytes::from_static(b"hello ")),
+        Ok(bytes::Bytes::from_static(b"world")),
+    ];
+    let uploaded = runtime.block_on(dclient.upload_blob_stream(
+        name,
+        &digest,
+        futures::stream::iter(chunks),
+    ))?;
+    assert_eq!(uploaded, digest);
+
+    start.assert();
+    finish.assert();
+    Ok(())
+}
+
+#[test]
+fn upload_blob_chunked() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello world";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _start = server
+        .mock("POST", format!("/v2/{}/blobs/uploads/", name).as_str())
+        .with_status(202)
+        .with_header("Location", &format!("/v2/{}/blobs/uploads/uuid1", name))
+        .with_header("Docker-Upload-UUID", "uuid1")
+        .create();
+    let chunk = server
+        .mock(
+            "PATCH",
+            format!("/v2/{}/blobs/uploads/uuid1", name).as_str(),
+        )
+        .match_body("hello ")
+        .with_status(202)
+        .with_header("Location", &format!("/v2/{}/blobs/uploads/uuid2", name))
+        .expect(1)
+        .create();
+    let finish = server
+        .mock("PUT", format!("/v2/{}/blobs/uploads/uuid2", name).as_str())
+        .match_query(mockito::Matcher::UrlEncoded(
+            "digest".into(),
+            digest.clone(),
+        ))
+        .match_body("world")
+        .with_status(201)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let mut session = runtime.block_on(dclient.start_upload(name))?;
+    assert_eq!(session.uuid(), Some("uuid1"));
+    runtime.block_on(dclient.upload_chunk(&mut session, "hello "))?;
+    assert_eq!(session.uuid(), Some("uuid1"));
+    assert_eq!(
+        session.location().path(),
+        format!("/v2/{}/blobs/uploads/uuid2", name)
+    );
+    let uploaded = runtime.block_on(dclient.finish_upload(session, &digest, "world"))?;
+    assert_eq!(uploaded, digest);
+
+    chunk.assert();
+    finish.assert();
+    Ok(())
+}
+
+#[test]
+fn upload_blob_quota_exceeded() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello world";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _start = server
+        .mock("POST", format!("/v2/{}/blobs/uploads/", name).as_str())
+        .with_status(202)
+        .with_header("Location", &format!("/v2/{}/blobs/uploads/uuid1", name))
+        .create();
+    let _finish = server
+        .mock("PUT", format!("/v2/{}/blobs/uploads/uuid1", name).as_str())
+        .match_query(mockito::Matcher::Any)
+        .with_status(413)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.upload_blob(name, &digest, blob.to_vec())) {
+        Err(dkregistry::errors::Error::QuotaExceeded) => {}
+        res => return Err(format!("expected QuotaExceeded, got {:?}", res).into()),
+    }
+    Ok(())
+}
+
+#[test]
+fn upload_blob_registry_error() -> Fallible<()> {
+    let name = "my-repo/my-image";
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _start = server
+        .mock("POST", format!("/v2/{}/blobs/uploads/", name).as_str())
+        .with_status(403)
+        .with_header("Content-Type", "application/json")
+        .with_body(
+            r#"{"errors":[{"code":"DENIED","message":"requested access to the resource is denied","detail":{"reason":"read-only"}}]}"#,
+        )
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.start_upload(name)) {
+        Err(dkregistry::errors::Error::Registry { status, errors }) => {
+            assert_eq!(status, 403);
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].code(), "DENIED");
+            assert_eq!(
+                errors[0].message(),
+                "requested access to the resource is denied"
+            );
+            assert_eq!(
+                errors[0].detail().unwrap().to_string(),
+                r#"{"reason":"read-only"}"#
+            );
+        }
+        res => return Err(format!("expected Registry error, got {:?}", res).into()),
+    }
+    Ok(())
+}
+
+#[test]
+fn upload_blob_chunked_verify_digest() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello world"));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _start = server
+        .mock("POST", format!("/v2/{}/blobs/uploads/", name).as_str())
+        .with_status(202)
+        .with_header("Location", &format!("/v2/{}/blobs/uploads/uuid1", name))
+        .create();
+    let _chunk = server
+        .mock(
+            "PATCH",
+            format!("/v2/{}/blobs/uploads/uuid1", name).as_str(),
+        )
+        .with_status(202)
+        .with_header("Location", &format!("/v2/{}/blobs/uploads/uuid2", name))
+        .create();
+    let finish = server
+        .mock("PUT", format!("/v2/{}/blobs/uploads/uuid2", name).as_str())
+        .match_query(mockito::Matcher::Any)
+        .with_status(201)
+        .expect(0)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let mut session = runtime
+        .block_on(dclient.start_upload(name))?
+        .verify_digest(&digest)?;
+    runtime.block_on(dclient.upload_chunk(&mut session, "hello "))?;
+    match runtime.block_on(dclient.finish_upload(session, &digest, "there")) {
+        Err(dkregistry::errors::Error::DigestMismatch { expected, actual }) => {
+            assert_eq!(expected, digest);
+            assert_eq!(
+                actual,
+                format!("sha256:{:x}", sha2::Sha256::digest(b"hello there"))
+            );
+        }
+        res => return Err(format!("expected DigestMismatch, got {:?}", res).into()),
+    }
+
+    finish.assert();
+    Ok(())
+}
+
+#[test]
+fn push_blob_sha512() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello world";
+    let digest = format!("sha512:{:x}", sha2::Sha512::digest(blob));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _start = server
+        .mock("POST", format!("/v2/{}/blobs/uploads/", name).as_str())
+        .with_status(202)
+        .with_header("Location", &format!("/v2/{}/blobs/uploads/uuid1", name))
+        .create();
+    let finish = server
+        .mock("PUT", format!("/v2/{}/blobs/uploads/uuid1", name).as_str())
+        .match_query(mockito::Matcher::UrlEncoded(
+            "digest".into(),
+            digest.clone(),
+        ))
+        .with_status(201)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .digest_algorithm(dkregistry::v2::Algorithm::Sha512)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let uploaded = runtime.block_on(dclient.push_blob(name, blob.to_vec()))?;
+    assert_eq!(uploaded, digest);
+
+    finish.assert();
+    Ok(())
+}
+
+#[test]
+fn push_blob_sha512_rejected() -> Fallible<()> {
+    let name = "my-repo/my-image";
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _start = server
+        .mock("POST", format!("/v2/{}/blobs/uploads/", name).as_str())
+        .with_status(202)
+        .with_header("Location", &format!("/v2/{}/blobs/uploads/uuid1", name))
+        .create();
+    let _finish = server
+        .mock("PUT", format!("/v2/{}/blobs/uploads/uuid1", name).as_str())
+        .match_query(mockito::Matcher::Any)
+        .with_status(400)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"errors":[{"code":"DIGEST_INVALID","message":"provided digest did not match uploaded content"}]}"#)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .digest_algorithm(dkregistry::v2::Algorithm::Sha512)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.push_blob(name, b"hello world".to_vec())) {
+        Err(dkregistry::errors::Error::UnsupportedDigestAlgorithm(algorithm)) => {
+            assert_eq!(algorithm, "sha512")
+        }
+        res => return Err(format!("expected UnsupportedDigestAlgorithm, got {:?}", res).into()),
+    }
+    Ok(())
+}
+
+#[test]
+fn resume_upload_from_location() -> Fallible<()> {
+    let name = "my-repo/my-image";
+    let blob = b"hello world";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let status = server
+        .mock("GET", format!("/v2/{}/blobs/uploads/uuid1", name).as_str())
+        .with_status(204)
+        .with_header("Location", &format!("/v2/{}/blobs/uploads/uuid2", name))
+        .with_header("Range", "0-5")
+        .with_header("Docker-Upload-UUID", "uuid")
+        .expect(1)
+        .create();
+    let finish = server
+        .mock("PUT", format!("/v2/{}/blobs/uploads/uuid2", name).as_str())
+        .match_query(mockito::Matcher::UrlEncoded(
+            "digest".into(),
+            digest.clone(),
+        ))
+        .match_body("world")
+        .with_status(201)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let location = format!("http://{}/v2/{}/blobs/uploads/uuid1", addr, name).parse()?;
+    let mut session = dkregistry::v2::UploadSession::from_location(&dclient, location, 3)?;
+    assert_eq!(session.offset(), 3);
+    assert_eq!(runtime.block_on(dclient.upload_status(&mut session))?, 6);
+    assert_eq!(session.offset(), 6);
+    assert_eq!(session.uuid(), Some("uuid"));
+    let uploaded = runtime.block_on(dclient.finish_upload(session, &digest, "world"))?;
+    assert_eq!(uploaded, digest);
+
+    let foreign = format!(
+        "http://elsewhere.example.com/v2/{}/blobs/uploads/uuid1",
+        name
+    );
+    match dkregistry::v2::UploadSession::from_location(&dclient, foreign.parse()?, 0) {
+        Err(dkregistry::errors::Error::ForeignUploadLocation(location)) => {
+            assert_eq!(location, foreign)
+        }
+        res => panic!("expected ForeignUploadLocation, got {:?}", res),
+    }
+
+    status.assert();
+    finish.assert();
+    Ok(())
+}
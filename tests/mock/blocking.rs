@@ -0,0 +1,58 @@
+extern crate dkregistry;
+extern crate mockito;
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[test]
+fn test_blocking_client() -> Fallible<()> {
+    let manifest = std::fs::read("tests/fixtures/manifest_list_v2.json")?;
+    let blob = b"hello";
+    let digest = dkregistry::v2::digest_of(blob);
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    server.mock("GET", "/v2/").with_status(200).create();
+    server
+        .mock("GET", "/v2/repo/tags/list")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"name":"repo","tags":["latest","v1"]}"#)
+        .create();
+    server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+    server
+        .mock("GET", format!("/v2/repo/blobs/{}", digest).as_str())
+        .with_status(200)
+        .with_body(blob)
+        .expect(2)
+        .create();
+
+    let client = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()?;
+    let client = dkregistry::blocking::Client::new(client)?;
+
+    client.authenticate(["repository:repo:pull"])?;
+    assert_eq!(client.get_tags("repo", None)?, vec!["latest", "v1"]);
+    let fetched = client.get_manifest("repo", "latest", None)?;
+    assert_eq!(fetched.raw_bytes(), manifest.as_slice());
+    assert_eq!(client.get_blob("repo", &digest, None)?, blob);
+
+    let mut written = Vec::new();
+    assert_eq!(
+        client.download_blob_to("repo", &digest, &mut written, None)?,
+        5
+    );
+    assert_eq!(written, blob);
+    Ok(())
+}
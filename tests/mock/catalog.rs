@@ -4,16 +4,17 @@ extern crate mockito;
 extern crate tokio;
 
 use self::futures::StreamExt;
-use self::mockito::mock;
 use self::tokio::runtime::Runtime;
 
 #[test]
 fn test_catalog_simple() {
     let repos = r#"{"repositories": ["r1/i1", "r2"]}"#;
 
-    let ep = format!("/v2/_catalog");
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", ep.as_str())
+    let ep = "/v2/_catalog".to_string();
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
         .with_status(200)
         .with_body(repos)
         .create();
@@ -31,8 +32,6 @@ fn test_catalog_simple() {
 
     let res = runtime.block_on(futcheck.map(Result::unwrap).collect::<Vec<_>>());
     assert_eq!(res, vec!["r1/i1", "r2"]);
-
-    mockito::reset();
 }
 
 #[test]
@@ -40,20 +39,23 @@ fn test_catalog_paginate() {
     let repos_p1 = r#"{"repositories": ["r1/i1"]}"#;
     let repos_p2 = r#"{"repositories": ["r2"]}"#;
 
-    let addr = mockito::server_address().to_string();
-    let _m1 = mock("GET", "/v2/_catalog?n=1")
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m1 = server
+        .mock("GET", "/v2/_catalog?n=1")
         .with_status(200)
         .with_header(
             "Link",
             &format!(
                 r#"<{}/v2/_catalog?n=21&last=r1/i1>; rel="next""#,
-                mockito::server_url()
+                server.url()
             ),
         )
         .with_header("Content-Type", "application/json")
         .with_body(repos_p1)
         .create();
-    let _m2 = mock("GET", "/v2/_catalog?n=1&last=r1")
+    let _m2 = server
+        .mock("GET", "/v2/_catalog?n=1&last=r1")
         .with_status(200)
         .with_header("Content-Type", "application/json")
         .with_body(repos_p2)
@@ -83,6 +85,4 @@ fn test_catalog_paginate() {
     if end.is_some() {
         panic!("end is some: {:?}", end);
     }
-
-    mockito::reset();
 }
@@ -0,0 +1,199 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate tokio;
+
+use self::tokio::runtime::Runtime;
+use crate::mock::{client, digest, manifest};
+use dkregistry::reference::Reference;
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[test]
+fn copy_image_across_registries() -> Fallible<()> {
+    let config = br#"{"architecture":"amd64"}"#;
+    let layer = b"layer content";
+    let manifest = manifest(config, layer);
+
+    let mut src_server = mockito::Server::new();
+    let src_addr = src_server.host_with_port();
+    let _manifest = src_server
+        .mock("GET", "/v2/src/manifests/latest")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+    let _layer = src_server
+        .mock("GET", format!("/v2/src/blobs/{}", digest(layer)).as_str())
+        .with_status(200)
+        .with_body(layer)
+        .create();
+
+    let mut dst_server = mockito::Server::new();
+    let dst_addr = dst_server.host_with_port();
+    let _config_present = dst_server
+        .mock("HEAD", format!("/v2/dst/blobs/{}", digest(config)).as_str())
+        .with_status(200)
+        .create();
+    let _layer_missing = dst_server
+        .mock("HEAD", format!("/v2/dst/blobs/{}", digest(layer)).as_str())
+        .with_status(404)
+        .create();
+    let start = dst_server
+        .mock("POST", "/v2/dst/blobs/uploads/")
+        .with_status(202)
+        .with_header("Location", "/v2/dst/blobs/uploads/uuid1")
+        .expect(1)
+        .create();
+    let finish = dst_server
+        .mock("PUT", "/v2/dst/blobs/uploads/uuid1")
+        .match_query(mockito::Matcher::UrlEncoded("digest".into(), digest(layer)))
+        .match_body("layer content")
+        .with_status(201)
+        .expect(1)
+        .create();
+    let put_manifest = dst_server
+        .mock("PUT", "/v2/dst/manifests/v1")
+        .match_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .match_body(manifest.as_str())
+        .with_status(201)
+        .expect(1)
+        .create();
+
+    let src: Reference = format!("{}/src:latest", src_addr).parse()?;
+    let dst: Reference = format!("{}/dst:v1", dst_addr).parse()?;
+
+    let runtime = Runtime::new().unwrap();
+    let copied = runtime.block_on(client(&src_addr).copy_image(&src, &dst, &client(&dst_addr)))?;
+    assert_eq!(copied, digest(manifest.as_bytes()));
+
+    start.assert();
+    finish.assert();
+    put_manifest.assert();
+    Ok(())
+}
+
+#[test]
+fn copy_image_mounts_within_registry() -> Fallible<()> {
+    let config = br#"{"architecture":"amd64"}"#;
+    let layer = b"layer content";
+    let manifest = manifest(config, layer);
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _manifest = server
+        .mock("GET", "/v2/src/manifests/latest")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+    let _missing = server
+        .mock(
+            "HEAD",
+            mockito::Matcher::Regex("^/v2/dst/blobs/".to_string()),
+        )
+        .with_status(404)
+        .create();
+    let mount = server
+        .mock("POST", "/v2/dst/blobs/uploads/")
+        .match_query(mockito::Matcher::UrlEncoded("from".into(), "src".into()))
+        .with_status(201)
+        .expect(2)
+        .create();
+    let put_manifest = server
+        .mock("PUT", "/v2/dst/manifests/latest")
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &digest(manifest.as_bytes()))
+        .expect(1)
+        .create();
+
+    let src: Reference = format!("{}/src:latest", addr).parse()?;
+    let dst: Reference = format!("{}/dst:latest", addr).parse()?;
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = client(&addr);
+    let copied = runtime.block_on(dclient.copy_image(&src, &dst, &dclient))?;
+    assert_eq!(copied, digest(manifest.as_bytes()));
+
+    mount.assert();
+    put_manifest.assert();
+    Ok(())
+}
+
+#[test]
+fn plan_copy_writes_nothing() -> Fallible<()> {
+    let config = br#"{"architecture":"amd64"}"#;
+    let layer = b"layer content";
+    let manifest = manifest(config, layer);
+
+    let mut src_server = mockito::Server::new();
+    let src_addr = src_server.host_with_port();
+    let _manifest = src_server
+        .mock("GET", "/v2/src/manifests/latest")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+    let layer_download = src_server
+        .mock("GET", format!("/v2/src/blobs/{}", digest(layer)).as_str())
+        .with_status(200)
+        .with_body(layer)
+        .expect(0)
+        .create();
+
+    let mut dst_server = mockito::Server::new();
+    let dst_addr = dst_server.host_with_port();
+    let _config_present = dst_server
+        .mock("HEAD", format!("/v2/dst/blobs/{}", digest(config)).as_str())
+        .with_status(200)
+        .create();
+    let _layer_missing = dst_server
+        .mock("HEAD", format!("/v2/dst/blobs/{}", digest(layer)).as_str())
+        .with_status(404)
+        .create();
+    let writes: Vec<_> = ["POST", "PATCH", "PUT"]
+        .iter()
+        .map(|method| {
+            dst_server
+                .mock(method, mockito::Matcher::Any)
+                .with_status(500)
+                .expect(0)
+                .create()
+        })
+        .collect();
+
+    let src: Reference = format!("{}/src:latest", src_addr).parse()?;
+    let dst: Reference = format!("{}/dst:v1", dst_addr).parse()?;
+
+    let runtime = Runtime::new().unwrap();
+    let plan = runtime.block_on(client(&src_addr).plan_copy(&src, &dst, &client(&dst_addr)))?;
+    assert_eq!(plan.digest(), digest(manifest.as_bytes()));
+    assert_eq!(plan.to_upload(), [digest(layer)]);
+    assert!(plan.to_mount().is_empty());
+    assert_eq!(plan.already_present(), [digest(config)]);
+
+    let dry_client = dkregistry::v2::Client::configure()
+        .registry(&dst_addr)
+        .insecure_registry(true)
+        .dry_run(true)
+        .build()?;
+    let copied = runtime.block_on(client(&src_addr).copy_image(&src, &dst, &dry_client))?;
+    assert_eq!(copied, digest(manifest.as_bytes()));
+
+    layer_download.assert();
+    for write in writes {
+        write.assert();
+    }
+    Ok(())
+}
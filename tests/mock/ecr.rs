@@ -0,0 +1,88 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate tokio;
+
+use self::tokio::runtime::Runtime;
+use dkregistry::ecr::{self, AwsCredentials};
+
+#[test]
+fn test_ecr_authorization_token() {
+    let mut server = mockito::Server::new();
+    let m = server
+        .mock("POST", "/")
+        .match_header(
+            "x-amz-target",
+            "AmazonEC2ContainerRegistry_V20150921.GetAuthorizationToken",
+        )
+        .match_header("content-type", "application/x-amz-json-1.1")
+        .match_header("x-amz-security-token", "session")
+        .match_header("user-agent", "ecr-test")
+        .match_header(
+            "authorization",
+            mockito::Matcher::Regex(
+                "^AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/[0-9]{8}/us-east-1/ecr/aws4_request, \
+                 SignedHeaders=content-type;host;x-amz-date;x-amz-security-token;x-amz-target, \
+                 Signature=[0-9a-f]{64}$"
+                    .to_string(),
+            ),
+        )
+        .match_body("{}")
+        .with_status(200)
+        .with_header("Content-Type", "application/x-amz-json-1.1")
+        .with_body(
+            r#"{"authorizationData": [{
+                "authorizationToken": "QVdTOnNlY3JldC1wYXNzd29yZA==",
+                "expiresAt": 1700000000.5,
+                "proxyEndpoint": "https://123456789012.dkr.ecr.us-east-1.amazonaws.com"
+            }]}"#,
+        )
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let credentials = AwsCredentials::new("AKIDEXAMPLE", "secret", Some("session"));
+    let endpoint = server.url().parse().unwrap();
+    let authorization = runtime
+        .block_on(ecr::get_authorization_token_from(
+            &dkregistry::v2::Client::configure().user_agent(Some("ecr-test".into())),
+            &endpoint,
+            "us-east-1",
+            &credentials,
+        ))
+        .unwrap();
+
+    assert_eq!(authorization.username(), "AWS");
+    assert_eq!(authorization.password(), "secret-password");
+    assert_eq!(
+        authorization.proxy_endpoint(),
+        Some("https://123456789012.dkr.ecr.us-east-1.amazonaws.com")
+    );
+    assert!(authorization.expires_at().is_some());
+    assert!(!format!("{:?}", authorization).contains("secret-password"));
+    m.assert();
+}
+
+#[test]
+fn test_ecr_authorization_error() {
+    let mut server = mockito::Server::new();
+    let _m = server
+        .mock("POST", "/")
+        .with_status(400)
+        .with_body(r#"{"__type": "UnrecognizedClientException", "message": "The security token included in the request is invalid."}"#)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let credentials = AwsCredentials::new("AKIDEXAMPLE", "secret", None);
+    let endpoint = server.url().parse().unwrap();
+    match runtime.block_on(ecr::get_authorization_token_from(
+        &dkregistry::v2::Client::configure(),
+        &endpoint,
+        "us-east-1",
+        &credentials,
+    )) {
+        Err(dkregistry::errors::Error::Ecr(message)) => {
+            assert!(message.starts_with("UnrecognizedClientException"))
+        }
+        res => panic!("expected Ecr error, got {:?}", res),
+    }
+}
@@ -0,0 +1,128 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate tokio;
+
+use self::tokio::runtime::Runtime;
+
+#[test]
+fn test_gcr_access_token_from_file() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let token = server
+        .mock("POST", "/token")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("grant_type".into(), "refresh_token".into()),
+            mockito::Matcher::UrlEncoded("client_id".into(), "id".into()),
+            mockito::Matcher::UrlEncoded("client_secret".into(), "secret".into()),
+            mockito::Matcher::UrlEncoded("refresh_token".into(), "refresh".into()),
+        ]))
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"access_token": "ya29.token", "expires_in": 3599, "token_type": "Bearer"}"#)
+        .expect(1)
+        .create();
+    let _challenge = server
+        .mock("GET", "/v2/")
+        .with_status(401)
+        .with_header("WWW-Authenticate", r#"Basic realm="registry""#)
+        .create();
+    let tags = server
+        .mock("GET", "/v2/project/image/tags/list")
+        .match_header(
+            "Authorization",
+            "Basic b2F1dGgyYWNjZXNzdG9rZW46eWEyOS50b2tlbg==",
+        )
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"name": "project/image", "tags": []}"#)
+        .expect(1)
+        .create();
+
+    let path = std::env::temp_dir().join(format!("dkregistry-gcr-{}.json", std::process::id()));
+    std::fs::write(
+        &path,
+        format!(
+            r#"{{"type": "authorized_user", "client_id": "id", "client_secret": "secret",
+                "refresh_token": "refresh", "token_uri": "{}/token"}}"#,
+            server.url()
+        ),
+    )
+    .unwrap();
+
+    let runtime = Runtime::new().unwrap();
+    let access_token = runtime
+        .block_on(dkregistry::gcr::access_token_from_file(
+            &dkregistry::v2::Client::configure(),
+            &path,
+        ))
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(access_token.token(), "ya29.token");
+    assert!(access_token.expires_at().is_some());
+
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .gcr_access_token(access_token.token())
+        .build()
+        .unwrap();
+    runtime
+        .block_on(dclient.authenticate(&["repository:project/image:pull"]))
+        .unwrap();
+    let page = runtime.block_on(dclient.get_tags_page("project/image", None, None, None));
+    assert!(page.unwrap().0.is_empty());
+
+    token.assert();
+    tags.assert();
+}
+
+#[test]
+fn test_gcr_service_account_unsupported() {
+    let path = std::env::temp_dir().join(format!("dkregistry-gcr-sa-{}.json", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"{"type": "service_account", "private_key": "..."}"#,
+    )
+    .unwrap();
+
+    let runtime = Runtime::new().unwrap();
+    let res = runtime.block_on(dkregistry::gcr::access_token_from_file(
+        &dkregistry::v2::Client::configure(),
+        &path,
+    ));
+    std::fs::remove_file(&path).unwrap();
+    match res {
+        Err(dkregistry::errors::Error::Gcr(message)) => {
+            assert!(message.contains("service_account"))
+        }
+        res => panic!("expected Gcr error, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_gcr_access_token_from_metadata_server() {
+    let mut server = mockito::Server::new();
+    let token = server
+        .mock(
+            "GET",
+            "/computeMetadata/v1/instance/service-accounts/default/token",
+        )
+        .match_header("Metadata-Flavor", "Google")
+        .match_header("user-agent", "gcr-test")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(
+            r#"{"access_token": "ya29.metadata", "expires_in": 3599, "token_type": "Bearer"}"#,
+        )
+        .expect(1)
+        .create();
+    std::env::set_var("GCE_METADATA_HOST", server.host_with_port());
+
+    let runtime = Runtime::new().unwrap();
+    let config = dkregistry::v2::Client::configure().user_agent(Some("gcr-test".into()));
+    let access_token = runtime
+        .block_on(dkregistry::gcr::access_token_from_metadata_server(&config))
+        .unwrap();
+    assert_eq!(access_token.token(), "ya29.metadata");
+    token.assert();
+}
@@ -0,0 +1,1294 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate tokio;
+
+use self::tokio::runtime::Runtime;
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[test]
+fn test_manifest_spec_without_config_blob() -> Fallible<()> {
+    let name = "repo";
+    let reference = "latest";
+    let manifest = std::fs::read("tests/fixtures/manifest_v2_s2.json")?;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock(
+            "GET",
+            format!("/v2/{}/manifests/{}", name, reference).as_str(),
+        )
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_header("Docker-Content-Digest", "sha256:abcd")
+        .with_body(manifest)
+        .create();
+    let config_blob = server
+        .mock("GET", mockito::Matcher::Regex("/blobs/".to_string()))
+        .expect(0)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let (spec, digest) = runtime.block_on(dclient.get_manifest_spec(name, reference, None))?;
+    assert_eq!(digest.as_deref(), Some("sha256:abcd"));
+    assert_eq!(spec.layers().count(), 3);
+
+    config_blob.assert();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_raw_config_blob() -> Fallible<()> {
+    let name = "repo";
+    let reference = "latest";
+    // Not as `serde_json` would serialize it.
+    let config = br#"{ "os": "linux",  "architecture": "amd64" }"#;
+    let config_digest = dkregistry::v2::digest_of(config);
+    let manifest = std::fs::read_to_string("tests/fixtures/manifest_v2_s2.json")?
+        .replace(
+            "sha256:b5b2b2c507a0944348e0303114d8d93aaaa081732b86451d9bce1f432a537bc7",
+            &config_digest,
+        )
+        .into_bytes();
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock(
+            "GET",
+            format!("/v2/{}/manifests/{}", name, reference).as_str(),
+        )
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+    let _config = server
+        .mock("GET", format!("/v2/repo/blobs/{}", config_digest).as_str())
+        .with_status(200)
+        .with_body(config)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let fetched = runtime.block_on(dclient.get_manifest(name, reference, None))?;
+    assert_eq!(fetched.raw_bytes(), manifest.as_slice());
+    match fetched {
+        dkregistry::v2::manifest::Manifest::S2(m) => {
+            assert_eq!(m.architecture(), "amd64");
+            assert_eq!(m.raw_config_blob, config);
+        }
+        m => return Err(format!("expected a schema 2 manifest, got {:?}", m).into()),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_manifest_config_blob_digest_mismatch() -> Fallible<()> {
+    let manifest = std::fs::read("tests/fixtures/manifest_v2_s2.json")?;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+    let _config = server
+        .mock("GET", mockito::Matcher::Regex("/blobs/".to_string()))
+        .with_status(200)
+        .with_body(r#"{"os": "linux", "architecture": "amd64"}"#)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.get_manifest("repo", "latest", None)) {
+        Err(dkregistry::errors::Error::DigestMismatch { expected, .. }) => assert_eq!(
+            expected,
+            "sha256:b5b2b2c507a0944348e0303114d8d93aaaa081732b86451d9bce1f432a537bc7"
+        ),
+        res => return Err(format!("expected DigestMismatch, got {:?}", res).into()),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_manifest_resolve_platform() -> Fallible<()> {
+    let list = std::fs::read("tests/fixtures/manifest_list_v2.json")?;
+    let manifest = std::fs::read("tests/fixtures/manifest_v2_s2.json")?;
+    let config = br#"{"os": "linux", "architecture": "amd64"}"#;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _list = server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_body(list)
+        .expect(2)
+        .create();
+    let child = server
+        .mock(
+            "GET",
+            "/v2/repo/manifests/sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270",
+        )
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(manifest)
+        .expect(1)
+        .create();
+    let _config = server
+        .mock("GET", mockito::Matcher::Regex("/blobs/".to_string()))
+        .with_status(200)
+        .with_body(config)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    // The config blob served does not match the digest of the fixture.
+    let configure = || {
+        dkregistry::v2::Client::configure()
+            .registry(&addr)
+            .insecure_registry(true)
+            .verify_digests(false)
+            .username(None)
+            .password(None)
+    };
+
+    let dclient = configure().build()?;
+    match runtime.block_on(dclient.get_manifest("repo", "latest", None))? {
+        dkregistry::v2::manifest::Manifest::ML(_) => {}
+        m => return Err(format!("expected a manifest list, got {:?}", m).into()),
+    }
+
+    let dclient = configure()
+        .resolve_platform(Some(("linux", "x86_64")))
+        .build()?;
+    match runtime.block_on(dclient.get_manifest("repo", "latest", None))? {
+        dkregistry::v2::manifest::Manifest::S2(m) => assert_eq!(m.architecture(), "amd64"),
+        m => return Err(format!("expected a schema 2 manifest, got {:?}", m).into()),
+    }
+
+    child.assert();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_spec_with_unknown_content_type() -> Fallible<()> {
+    let name = "repo";
+    let reference = "latest";
+    let manifest = std::fs::read("tests/fixtures/manifest_v2_s2.json")?;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock(
+            "GET",
+            format!("/v2/{}/manifests/{}", name, reference).as_str(),
+        )
+        .with_status(200)
+        .with_header("Content-Type", "application/octet-stream")
+        .with_body(manifest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let (spec, _) = runtime.block_on(dclient.get_manifest_spec(name, reference, None))?;
+    assert_eq!(spec.layers().count(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_manifest_without_content_type() -> Fallible<()> {
+    let manifest = std::fs::read("tests/fixtures/manifest_list_v2.json")?;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .with_status(200)
+        .with_body(manifest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let (_, media_type, _) =
+        runtime.block_on(dclient.get_raw_manifest_and_metadata("repo", "latest", None))?;
+    assert_eq!(media_type, dkregistry::mediatypes::MediaTypes::ManifestList);
+    Ok(())
+}
+
+#[test]
+fn test_manifest_digest_computed_without_header() -> Fallible<()> {
+    use sha2::Digest;
+
+    let name = "repo";
+    let reference = "latest";
+    let manifest = std::fs::read("tests/fixtures/manifest_v2_s2.json")?;
+    let expected_digest = format!("sha256:{:x}", sha2::Sha256::digest(&manifest));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock(
+            "GET",
+            format!("/v2/{}/manifests/{}", name, reference).as_str(),
+        )
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(manifest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let (_, digest) = runtime.block_on(dclient.get_manifest_spec(name, reference, None))?;
+    assert_eq!(digest, Some(expected_digest));
+    Ok(())
+}
+
+#[test]
+fn test_put_manifest_digest() -> Fallible<()> {
+    use sha2::Digest;
+
+    let manifest = std::fs::read("tests/fixtures/manifest_v2_s2.json")?;
+    let expected_digest = format!("sha256:{:x}", sha2::Sha256::digest(&manifest));
+    let mangled_digest = format!("sha256:{:x}", sha2::Sha256::digest(b"mangled"));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _reported = server
+        .mock("PUT", "/v2/repo/manifests/reported")
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &expected_digest)
+        .create();
+    let _omitted = server
+        .mock("PUT", "/v2/repo/manifests/omitted")
+        .with_status(201)
+        .create();
+    let _mangled = server
+        .mock("PUT", "/v2/repo/manifests/mangled")
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &mangled_digest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+    let media_type = dkregistry::mediatypes::MediaTypes::ManifestV2S2;
+    let put = |reference| {
+        runtime.block_on(dclient.put_manifest(
+            "repo",
+            reference,
+            &media_type,
+            manifest.clone().into(),
+        ))
+    };
+
+    assert_eq!(put("reported")?, expected_digest);
+    assert_eq!(put("omitted")?, expected_digest);
+    match put("mangled") {
+        Err(dkregistry::errors::Error::DigestMismatch { expected, actual }) => {
+            assert_eq!(expected, mangled_digest);
+            assert_eq!(actual, expected_digest);
+        }
+        res => return Err(format!("expected DigestMismatch, got {:?}", res).into()),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_has_manifest_unauthorized() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("HEAD", "/v2/repo/manifests/latest")
+        .with_status(401)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.has_manifest("repo", "latest", None, None)) {
+        Err(dkregistry::errors::Error::Unauthorized(status)) => assert_eq!(status, 401),
+        res => panic!("expected Unauthorized, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_has_manifest_any_type() -> Fallible<()> {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let m = server
+        .mock("HEAD", "/v2/repo/manifests/latest")
+        .match_request(|request| {
+            let accept = request.header("Accept");
+            let types = dkregistry::mediatypes::MediaTypes::all_manifest_types();
+            accept.len() == 1
+                && accept[0].to_str().is_ok_and(|accept| {
+                    let accepted = accept.split(',').collect::<Vec<_>>();
+                    accepted.len() == types.len()
+                        && types
+                            .iter()
+                            .all(|t| accepted.contains(&t.to_string().as_str()))
+                })
+        })
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let types = dkregistry::mediatypes::MediaTypes::all_manifest_types()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    let types = types.iter().map(String::as_str).collect::<Vec<_>>();
+    let media_type =
+        runtime.block_on(dclient.has_manifest("repo", "latest", None, Some(&types)))?;
+    assert_eq!(
+        media_type,
+        Some(dkregistry::mediatypes::MediaTypes::OciV1ManifestList)
+    );
+
+    match runtime.block_on(dclient.has_manifest("repo", "latest", None, Some(&["not a type"]))) {
+        Err(dkregistry::errors::Error::InvalidMediaType(t)) => assert_eq!(t, "not a type"),
+        res => panic!("expected InvalidMediaType, got {:?}", res),
+    }
+
+    m.assert();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_head() -> Fallible<()> {
+    let manifest = std::fs::read("tests/fixtures/manifest_v2_s2.json")?;
+    let digest = dkregistry::v2::digest_of(&manifest);
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    server
+        .mock("HEAD", "/v2/repo/manifests/latest")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_header("Docker-Content-Digest", &digest)
+        .with_header("Content-Length", &manifest.len().to_string())
+        .create();
+    server
+        .mock("HEAD", "/v2/repo/manifests/missing")
+        .with_status(404)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let head = runtime
+        .block_on(dclient.manifest_head("repo", "latest", None, None))?
+        .ok_or("manifest not found")?;
+    assert_eq!(
+        head.media_type(),
+        dkregistry::mediatypes::MediaTypes::ManifestV2S2
+    );
+    assert_eq!(head.digest(), Some(digest.as_str()));
+    assert_eq!(head.size(), Some(manifest.len() as u64));
+
+    assert_eq!(
+        runtime.block_on(dclient.manifest_head("repo", "missing", None, None))?,
+        None
+    );
+    Ok(())
+}
+
+#[test]
+fn test_manifest_rate_limited() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .with_status(429)
+        .with_header("Retry-After", "42")
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.get_manifest("repo", "latest", None)) {
+        Err(dkregistry::errors::Error::RateLimited { retry_after }) => {
+            assert_eq!(retry_after, Some(std::time::Duration::from_secs(42)))
+        }
+        res => panic!("expected RateLimited, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_manifest_unknown() -> Fallible<()> {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/repo/manifests/missing")
+        .with_status(404)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"errors":[{"code":"MANIFEST_UNKNOWN","message":"manifest unknown","detail":"missing"}]}"#)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.get_manifest("repo", "missing", None)) {
+        Err(e @ dkregistry::errors::Error::ManifestUnknown { .. }) => {
+            assert!(e.is_not_found());
+            assert_eq!(
+                e.to_string(),
+                "manifest 'missing' unknown in repository 'repo'"
+            );
+        }
+        res => return Err(format!("expected ManifestUnknown, got {:?}", res).into()),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_manifest_not_found_without_error_body() -> Fallible<()> {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .with_status(404)
+        .with_header("Content-Type", "text/html")
+        .with_body("<html>Not Found</html>")
+        .create();
+    let _unknown_name = server
+        .mock("GET", "/v2/other/manifests/latest")
+        .with_status(404)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"errors":[{"code":"NAME_UNKNOWN","message":"repository name not known to registry"}]}"#)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.get_manifest("repo", "latest", None)) {
+        Err(dkregistry::errors::Error::UnexpectedHttpStatus(status)) => assert_eq!(status, 404),
+        res => return Err(format!("expected UnexpectedHttpStatus, got {:?}", res).into()),
+    }
+    match runtime.block_on(dclient.get_manifest("other", "latest", None)) {
+        Err(dkregistry::errors::Error::Registry { status, errors }) => {
+            assert_eq!(status, 404);
+            assert_eq!(errors[0].code(), "NAME_UNKNOWN");
+        }
+        res => return Err(format!("expected Registry error, got {:?}", res).into()),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_manifest_too_large() -> Fallible<()> {
+    let manifest = std::fs::read("tests/fixtures/manifest_v2_s2.json")?;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(manifest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .max_manifest_bytes(64)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.get_manifest_spec("repo", "latest", None)) {
+        Err(dkregistry::errors::Error::ManifestTooLarge(limit)) => assert_eq!(limit, 64),
+        res => return Err(format!("expected ManifestTooLarge, got {:?}", res).into()),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_verify_manifest_digest() -> Fallible<()> {
+    use sha2::Digest;
+
+    let manifest = std::fs::read("tests/fixtures/manifest_v2_s2.json")?;
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(&manifest));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _good = server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_header("Docker-Content-Digest", &digest)
+        .with_body(&manifest)
+        .create();
+    let _forged = server
+        .mock("GET", "/v2/repo/manifests/forged")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_header("Docker-Content-Digest", "sha256:abcd")
+        .with_body(&manifest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let verified = runtime.block_on(dclient.verify_manifest_digest("repo", "latest", None))?;
+    assert_eq!(verified, digest);
+
+    match runtime.block_on(dclient.verify_manifest_digest("repo", "forged", None)) {
+        Err(dkregistry::errors::Error::DigestMismatch { expected, actual }) => {
+            assert_eq!(expected, "sha256:abcd");
+            assert_eq!(actual, digest);
+        }
+        res => return Err(format!("expected DigestMismatch, got {:?}", res).into()),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_resolve_digest() -> Fallible<()> {
+    use sha2::Digest;
+
+    let manifest = std::fs::read("tests/fixtures/manifest_v2_s2.json")?;
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(&manifest));
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _head = server
+        .mock("HEAD", "/v2/repo/manifests/latest")
+        .with_status(200)
+        .create();
+    let _get = server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let reference: dkregistry::reference::Reference = format!("{}/repo:latest", addr).parse()?;
+    let pinned = runtime.block_on(dclient.resolve_digest(&reference, None))?;
+    assert_eq!(pinned.registry(), addr);
+    assert_eq!(pinned.repository(), "repo");
+    assert_eq!(pinned.digest(), Some(digest));
+    Ok(())
+}
+
+#[test]
+fn test_resolve_digest_default_tag() -> Fallible<()> {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let head = server
+        .mock("HEAD", "/v2/repo/manifests/stable")
+        .with_status(200)
+        .with_header(
+            "Docker-Content-Digest",
+            &format!("sha256:{}", "a".repeat(64)),
+        )
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .default_tag("stable")
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let reference: dkregistry::reference::Reference = format!("{}/repo", addr).parse()?;
+    let pinned = runtime.block_on(dclient.resolve_digest(&reference, None))?;
+    assert_eq!(pinned.digest(), Some(format!("sha256:{}", "a".repeat(64))));
+    head.assert();
+    Ok(())
+}
+
+#[test]
+fn test_resolve_short_digest() {
+    let digests = [
+        ("v1", format!("sha256:abc1{}", "0".repeat(60))),
+        ("v2", format!("sha256:abc2{}", "0".repeat(60))),
+        ("latest", format!("sha256:abc2{}", "0".repeat(60))),
+        ("old", format!("sha256:ff{}", "0".repeat(62))),
+    ];
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _tags = server
+        .mock("GET", "/v2/repo/tags/list")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"name": "repo", "tags": ["v1", "v2", "latest", "old"]}"#)
+        .create();
+    let _heads = digests
+        .iter()
+        .map(|(tag, digest)| {
+            server
+                .mock("HEAD", format!("/v2/repo/manifests/{}", tag).as_str())
+                .with_status(200)
+                .with_header("Docker-Content-Digest", digest)
+                .create()
+        })
+        .collect::<Vec<_>>();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let resolve = |prefix| runtime.block_on(dclient.resolve_short_digest("repo", prefix, None));
+    assert_eq!(resolve("ff").unwrap(), digests[3].1);
+    assert_eq!(resolve("sha256:abc2").unwrap(), digests[1].1);
+    match resolve("abc") {
+        Err(dkregistry::errors::Error::AmbiguousDigest { candidates, .. }) => {
+            assert_eq!(candidates, vec![digests[0].1.clone(), digests[1].1.clone()])
+        }
+        res => panic!("expected AmbiguousDigest, got {:?}", res),
+    }
+    assert!(matches!(
+        resolve("sha512:ff"),
+        Err(dkregistry::errors::Error::NotFound(_))
+    ));
+}
+
+#[test]
+fn test_get_all_child_manifests_partial() -> Fallible<()> {
+    let list: dkregistry::v2::manifest::ManifestList =
+        serde_json::from_slice(&std::fs::read("tests/fixtures/manifest_list_v2.json")?)?;
+    let available = "sha256:e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f";
+    let missing = "sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270";
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _available = server
+        .mock("GET", format!("/v2/repo/manifests/{}", available).as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v1+prettyjws",
+        )
+        .with_body(std::fs::read("tests/fixtures/manifest_v2_s1.json")?)
+        .create();
+    let _missing = server
+        .mock("GET", format!("/v2/repo/manifests/{}", missing).as_str())
+        .with_status(404)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let results = runtime.block_on(dclient.get_all_child_manifests("repo", &list, None));
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].0, available);
+    assert!(matches!(
+        results[0].1,
+        Ok(dkregistry::v2::manifest::Manifest::S1Signed(_))
+    ));
+    assert_eq!(
+        results[0].1.as_ref().unwrap().raw_bytes(),
+        std::fs::read("tests/fixtures/manifest_v2_s1.json")?.as_slice()
+    );
+    assert_eq!(results[1].0, missing);
+    assert!(results[1].1.is_err());
+    Ok(())
+}
+
+#[test]
+fn test_stream_manifest_list() -> Fallible<()> {
+    use futures::stream::StreamExt;
+
+    let list: dkregistry::v2::manifest::ManifestList =
+        serde_json::from_slice(&std::fs::read("tests/fixtures/manifest_list_v2.json")?)?;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let children = server
+        .mock(
+            "GET",
+            mockito::Matcher::Regex("/v2/repo/manifests/sha256:".to_string()),
+        )
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v1+prettyjws",
+        )
+        .with_body(std::fs::read("tests/fixtures/manifest_v2_s1.json")?)
+        .expect(2)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let results = runtime.block_on(
+        dclient
+            .stream_manifest_list("repo", &list, 2, None)
+            .collect::<Vec<_>>(),
+    );
+    let platforms = results
+        .into_iter()
+        .map(|res| res.map(|(platform, _)| platform.architecture.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(platforms, vec!["ppc64le", "amd64"]);
+
+    children.assert();
+    Ok(())
+}
+
+#[test]
+fn test_manifest_with_accept() -> Fallible<()> {
+    let manifest_list = std::fs::read("tests/fixtures/manifest_list_v2.json")?;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let m = server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .match_header(
+            "Accept",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_body(manifest_list)
+        .expect(1)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let manifest = runtime.block_on(dclient.get_manifest_with_accept(
+        "repo",
+        "latest",
+        &[dkregistry::mediatypes::MediaTypes::ManifestList],
+        None,
+    ))?;
+    assert!(matches!(
+        manifest,
+        dkregistry::v2::manifest::Manifest::ML(_)
+    ));
+
+    m.assert();
+    Ok(())
+}
+
+#[cfg(feature = "schema1-signatures")]
+#[test]
+fn test_manifest_verify_schema1_signatures() -> Fallible<()> {
+    let raw = std::fs::read_to_string("tests/fixtures/quayio_coreos_etcd_latest.json")?;
+    let tampered = raw.replacen("sha256:a3ed", "sha256:b3ed", 1);
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    for (reference, body) in &[("signed", &raw), ("tampered", &tampered)] {
+        server
+            .mock("GET", format!("/v2/repo/manifests/{}", reference).as_str())
+            .with_status(200)
+            .with_header(
+                "Content-Type",
+                "application/vnd.docker.distribution.manifest.v1+prettyjws",
+            )
+            .with_body(body.as_str())
+            .create();
+    }
+
+    let runtime = Runtime::new().unwrap();
+    let client = |verify| {
+        dkregistry::v2::Client::configure()
+            .registry(&addr)
+            .insecure_registry(true)
+            .verify_schema1_signatures(verify)
+            .build()
+            .unwrap()
+    };
+
+    runtime.block_on(client(true).get_manifest("repo", "signed", None))?;
+    runtime.block_on(client(false).get_manifest("repo", "tampered", None))?;
+    match runtime.block_on(client(true).get_manifest("repo", "tampered", None)) {
+        Err(dkregistry::errors::Error::InvalidManifestSignature(_)) => {}
+        res => return Err(format!("expected InvalidManifestSignature, got {:?}", res).into()),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_get_config_blob() -> Fallible<()> {
+    use dkregistry::v2::manifest::Config;
+
+    let config_blob = br#"{"architecture":"amd64","os":"linux"}"#;
+    let digest = dkregistry::v2::digest_of(config_blob);
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    server
+        .mock("GET", format!("/v2/repo/blobs/{}", digest).as_str())
+        .with_status(200)
+        .with_body(config_blob)
+        .create();
+    let tampered = format!("sha256:{}", "0".repeat(64));
+    server
+        .mock("GET", format!("/v2/repo/blobs/{}", tampered).as_str())
+        .with_status(200)
+        .with_body(config_blob)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    // Verification of the config blob doesn't depend on `verify_digests`.
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .verify_digests(false)
+        .build()
+        .unwrap();
+
+    let config = Config {
+        media_type: "application/vnd.docker.container.image.v1+json".into(),
+        size: config_blob.len() as u64,
+        digest,
+        ..Default::default()
+    };
+    let blob = runtime.block_on(dclient.get_config_blob("repo", &config, None))?;
+    assert_eq!(blob, config_blob);
+    let parsed = runtime.block_on(dclient.get_config("repo", &config, None))?;
+    assert_eq!(parsed.architecture(), "amd64");
+    assert_eq!(parsed.os(), Some("linux"));
+
+    let config = Config {
+        digest: tampered,
+        ..config
+    };
+    assert!(matches!(
+        runtime.block_on(dclient.get_config_blob("repo", &config, None)),
+        Err(dkregistry::errors::Error::DigestMismatch { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_manifest_if_changed() -> Fallible<()> {
+    let manifest = std::fs::read("tests/fixtures/manifest_list_v2.json")?;
+    let digest = dkregistry::v2::digest_of(&manifest);
+    let etag = format!("\"{}\"", digest);
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let not_modified = server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .match_header("If-None-Match", etag.as_str())
+        .with_status(304)
+        .expect(2)
+        .create();
+    let full = server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .match_header("If-None-Match", mockito::Matcher::Missing)
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_header("ETag", &etag)
+        .with_body(&manifest)
+        .create();
+    // Ignores If-None-Match, and sends no ETag.
+    server
+        .mock("GET", "/v2/repo/manifests/ignoring")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let (fetched, new_etag) = runtime
+        .block_on(dclient.get_manifest_if_changed("repo", "latest", None, None))?
+        .ok_or("manifest not returned")?;
+    assert_eq!(fetched.raw_bytes(), manifest.as_slice());
+    assert_eq!(new_etag, etag);
+    full.assert();
+
+    assert!(runtime
+        .block_on(dclient.get_manifest_if_changed("repo", "latest", Some(&new_etag), None))?
+        .is_none());
+    // A bare digest is quoted into an entity tag.
+    assert!(runtime
+        .block_on(dclient.get_manifest_if_changed("repo", "latest", Some(&digest), None))?
+        .is_none());
+    not_modified.assert();
+
+    let (_, new_etag) = runtime
+        .block_on(dclient.get_manifest_if_changed("repo", "ignoring", None, None))?
+        .ok_or("manifest not returned")?;
+    assert_eq!(new_etag, digest);
+    assert!(runtime
+        .block_on(dclient.get_manifest_if_changed("repo", "ignoring", Some(&new_etag), None))?
+        .is_none());
+    Ok(())
+}
+
+#[test]
+fn test_manifest_and_headers() -> Fallible<()> {
+    let name = "repo";
+    let reference = "latest";
+    let manifest = std::fs::read("tests/fixtures/manifest_v2_s2.json")?;
+    let config = br#"{ "os": "linux",  "architecture": "amd64" }"#;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock(
+            "GET",
+            format!("/v2/{}/manifests/{}", name, reference).as_str(),
+        )
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_header("X-Registry-Supports-Signatures", "1")
+        .with_body(&manifest)
+        .create();
+    let _config = server
+        .mock("GET", mockito::Matcher::Regex("/blobs/".to_string()))
+        .with_status(200)
+        .with_header("X-Config-Only", "1")
+        .with_body(config)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    // The config blob served does not match the digest of the fixture.
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .verify_digests(false)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let (fetched, headers) =
+        runtime.block_on(dclient.get_manifest_and_headers(name, reference, None))?;
+    assert_eq!(fetched.raw_bytes(), manifest.as_slice());
+    assert_eq!(headers["x-registry-supports-signatures"], "1");
+    assert!(headers.get("x-config-only").is_none());
+    Ok(())
+}
+
+#[test]
+fn test_get_manifest_with_oci_config() -> Fallible<()> {
+    use dkregistry::mediatypes::MediaTypes;
+    use dkregistry::v2::manifest::Manifest;
+
+    let config_blob = br#"{"architecture":"arm","os":"linux","variant":"v7"}"#;
+    let manifest = |media_type: &str, config_media_type: &str| {
+        format!(
+            r#"{{"schemaVersion":2,"mediaType":"{}","config":{{"mediaType":"{}","size":{},"digest":"{}"}},"layers":[]}}"#,
+            media_type,
+            config_media_type,
+            config_blob.len(),
+            dkregistry::v2::digest_of(config_blob)
+        )
+    };
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    for (reference, media_type, config_media_type) in [
+        (
+            "oci",
+            "application/vnd.docker.distribution.manifest.v2+json",
+            "application/vnd.oci.image.config.v1+json",
+        ),
+        (
+            "oci-image",
+            "application/vnd.oci.image.manifest.v1+json",
+            "application/vnd.oci.image.config.v1+json",
+        ),
+        (
+            "sbom",
+            "application/vnd.docker.distribution.manifest.v2+json",
+            "application/vnd.example.sbom.v1+json",
+        ),
+    ] {
+        server
+            .mock("GET", format!("/v2/repo/manifests/{}", reference).as_str())
+            .with_status(200)
+            .with_header("Content-Type", media_type)
+            .with_body(manifest(media_type, config_media_type))
+            .create();
+    }
+    server
+        .mock(
+            "GET",
+            format!("/v2/repo/blobs/{}", dkregistry::v2::digest_of(config_blob)).as_str(),
+        )
+        .with_status(200)
+        .with_body(config_blob)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .build()
+        .unwrap();
+
+    let m = match runtime.block_on(dclient.get_manifest("repo", "oci", None))? {
+        Manifest::S2(m) => m,
+        m => panic!("unexpected manifest: {:?}", m),
+    };
+    assert_eq!(m.config_media_type(), MediaTypes::OciV1ManifestConfig);
+    assert_eq!(m.architecture(), "arm");
+    assert_eq!(m.os(), Some("linux"));
+    let platform = m.config_blob.platform();
+    assert_eq!(platform.variant.as_deref(), Some("v7"));
+
+    let m = match runtime.block_on(dclient.get_manifest("repo", "oci-image", None))? {
+        Manifest::S2(m) => m,
+        m => panic!("unexpected manifest: {:?}", m),
+    };
+    assert_eq!(m.manifest_spec.media_type(), &MediaTypes::OciV1Manifest);
+    assert!(m.config_blob.platform().matches("linux", "arm", Some("v7")));
+
+    assert!(matches!(
+        runtime.block_on(dclient.get_manifest("repo", "sbom", None)),
+        Err(dkregistry::errors::Error::UnsupportedMediaType(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_get_manifest_oci_only() -> Fallible<()> {
+    use dkregistry::mediatypes::MediaTypes;
+    use dkregistry::v2::manifest::Manifest;
+
+    let config_blob = br#"{"architecture":"arm64","os":"linux"}"#;
+    let config_digest = dkregistry::v2::digest_of(config_blob);
+    let manifest = format!(
+        r#"{{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json","config":{{"mediaType":"application/vnd.oci.image.config.v1+json","size":{},"digest":"{}"}},"layers":[]}}"#,
+        config_blob.len(),
+        config_digest
+    );
+    let manifest_digest = dkregistry::v2::digest_of(manifest.as_bytes());
+    let index = format!(
+        r#"{{"schemaVersion":2,"mediaType":"application/vnd.oci.image.index.v1+json","manifests":[{{"mediaType":"application/vnd.oci.image.manifest.v1+json","size":{},"digest":"{}","platform":{{"architecture":"arm64","os":"linux"}}}}]}}"#,
+        manifest.len(),
+        manifest_digest
+    );
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let accept = mockito::Matcher::Regex("^application/vnd\\.oci\\.".to_string());
+    server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .match_header("Accept", accept.clone())
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+        .with_body(&index)
+        .create();
+    server
+        .mock(
+            "GET",
+            format!("/v2/repo/manifests/{}", manifest_digest).as_str(),
+        )
+        .match_header("Accept", accept)
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .with_body(&manifest)
+        .create();
+    server
+        .mock("GET", format!("/v2/repo/blobs/{}", config_digest).as_str())
+        .with_status(200)
+        .with_body(config_blob)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .oci_only()
+        .build()
+        .unwrap();
+
+    let list = runtime.block_on(dclient.get_manifest("repo", "latest", None))?;
+    assert_eq!(list.media_type(), MediaTypes::OciV1ManifestList);
+    assert!(matches!(list, Manifest::ML(_)));
+
+    let image = runtime.block_on(dclient.get_manifest("repo", &manifest_digest, None))?;
+    assert_eq!(image.media_type(), MediaTypes::OciV1Manifest);
+    assert_eq!(image.architectures()?.collect::<Vec<_>>(), ["arm64"]);
+
+    let (spec, _) = runtime.block_on(dclient.get_manifest_spec("repo", &manifest_digest, None))?;
+    assert_eq!(spec.media_type(), &MediaTypes::OciV1Manifest);
+    Ok(())
+}
@@ -1,6 +1,47 @@
 mod api_version;
+mod artifact;
+mod auth;
 mod base_client;
 mod blobs_download;
+mod blobs_upload;
+#[cfg(feature = "blocking")]
+mod blocking;
 mod catalog;
+mod copy_image;
+#[cfg(feature = "ecr")]
+mod ecr;
+#[cfg(feature = "gcr")]
+mod gcr;
+mod manifest;
+mod oci_layout;
+mod pull;
+mod referrers;
 mod tags_dockerv2;
 mod tags_quay;
+mod verify_image;
+
+use dkregistry::v2::{digest_of as digest, Client, Config};
+
+/// A Docker image manifest with a single layer.
+fn manifest(config: &[u8], layer: &[u8]) -> String {
+    format!(
+        r#"{{"schemaVersion":2,"mediaType":"application/vnd.docker.distribution.manifest.v2+json","config":{{"mediaType":"application/vnd.docker.container.image.v1+json","size":{},"digest":"{}"}},"layers":[{{"mediaType":"application/vnd.docker.image.rootfs.diff.tar.gzip","size":{},"digest":"{}"}}]}}"#,
+        config.len(),
+        digest(config),
+        layer.len(),
+        digest(layer)
+    )
+}
+
+/// Configure an anonymous client of the mock registry at `addr`.
+fn configure(addr: &str) -> Config {
+    Client::configure()
+        .registry(addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+}
+
+fn client(addr: &str) -> Client {
+    configure(addr).build().unwrap()
+}
@@ -0,0 +1,302 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate serde_json;
+extern crate tokio;
+
+use self::tokio::runtime::Runtime;
+use crate::mock::{client, digest, manifest};
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+const CONFIG: &[u8] = br#"{"architecture":"amd64","os":"linux"}"#;
+const LAYER: &[u8] = b"layer content";
+
+#[test]
+fn export_oci_layout() -> Fallible<()> {
+    let manifest = manifest(CONFIG, LAYER);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    server.mock("GET", "/v2/").with_status(200).create();
+    server
+        .mock("GET", "/v2/repo/manifests/v1")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+    server
+        .mock("GET", format!("/v2/repo/blobs/{}", digest(CONFIG)).as_str())
+        .with_status(200)
+        .with_body(CONFIG)
+        .create();
+    let layer = server
+        .mock("GET", format!("/v2/repo/blobs/{}", digest(LAYER)).as_str())
+        .with_status(200)
+        .with_body(LAYER)
+        .expect(1)
+        .create();
+
+    let dir = std::env::temp_dir().join(format!("dkregistry-oci-layout-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let reference = format!("{}/repo:v1", addr).parse()?;
+    let runtime = Runtime::new().unwrap();
+    let dclient = client(&addr);
+    runtime.block_on(dclient.export_oci_layout(&reference, &dir, None))?;
+    // Exporting again only rewrites the manifest and config, and keeps a single entry.
+    runtime.block_on(dclient.export_oci_layout(&reference, &dir, None))?;
+
+    let blob = |d: &str| std::fs::read(dir.join("blobs/sha256").join(&d[7..]));
+    assert_eq!(blob(&digest(CONFIG))?, CONFIG);
+    assert_eq!(blob(&digest(LAYER))?, LAYER);
+    assert_eq!(blob(&digest(manifest.as_bytes()))?, manifest.as_bytes());
+    let layout: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(dir.join("oci-layout"))?)?;
+    assert_eq!(layout["imageLayoutVersion"], "1.0.0");
+
+    let index: serde_json::Value = serde_json::from_slice(&std::fs::read(dir.join("index.json"))?)?;
+    assert_eq!(index["schemaVersion"], 2);
+    let manifests = index["manifests"].as_array().unwrap();
+    assert_eq!(manifests.len(), 1);
+    assert_eq!(manifests[0]["digest"], digest(manifest.as_bytes()));
+    assert_eq!(manifests[0]["size"], manifest.len());
+    assert_eq!(
+        manifests[0]["mediaType"],
+        "application/vnd.docker.distribution.manifest.v2+json"
+    );
+    assert_eq!(
+        manifests[0]["annotations"]["org.opencontainers.image.ref.name"],
+        "v1"
+    );
+
+    layer.assert();
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn export_oci_layout_oci_manifest() -> Fallible<()> {
+    let manifest = format!(
+        r#"{{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json","config":{{"mediaType":"application/vnd.oci.image.config.v1+json","size":{},"digest":"{}"}},"layers":[{{"mediaType":"application/vnd.oci.image.layer.v1.tar+gzip","size":{},"digest":"{}"}}]}}"#,
+        CONFIG.len(),
+        digest(CONFIG),
+        LAYER.len(),
+        digest(LAYER)
+    );
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    server.mock("GET", "/v2/").with_status(200).create();
+    server
+        .mock("GET", "/v2/repo/manifests/v1")
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .with_body(&manifest)
+        .create();
+    server
+        .mock("GET", format!("/v2/repo/blobs/{}", digest(CONFIG)).as_str())
+        .with_status(200)
+        .with_body(CONFIG)
+        .create();
+    server
+        .mock("GET", format!("/v2/repo/blobs/{}", digest(LAYER)).as_str())
+        .with_status(200)
+        .with_body(LAYER)
+        .create();
+
+    let dir =
+        std::env::temp_dir().join(format!("dkregistry-oci-layout-oci-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let reference = format!("{}/repo:v1", addr).parse()?;
+    let runtime = Runtime::new().unwrap();
+    runtime.block_on(client(&addr).export_oci_layout(&reference, &dir, None))?;
+
+    let blob = |d: &str| std::fs::read(dir.join("blobs/sha256").join(&d[7..]));
+    assert_eq!(blob(&digest(LAYER))?, LAYER);
+    assert_eq!(blob(&digest(manifest.as_bytes()))?, manifest.as_bytes());
+    let index: serde_json::Value = serde_json::from_slice(&std::fs::read(dir.join("index.json"))?)?;
+    assert_eq!(
+        index["manifests"][0]["mediaType"],
+        "application/vnd.oci.image.manifest.v1+json"
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn export_oci_layout_rejects_corrupt_layer() -> Fallible<()> {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    server.mock("GET", "/v2/").with_status(200).create();
+    server
+        .mock("GET", "/v2/repo/manifests/v1")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(manifest(CONFIG, LAYER))
+        .create();
+    server
+        .mock("GET", format!("/v2/repo/blobs/{}", digest(CONFIG)).as_str())
+        .with_status(200)
+        .with_body(CONFIG)
+        .create();
+    server
+        .mock("GET", format!("/v2/repo/blobs/{}", digest(LAYER)).as_str())
+        .with_status(200)
+        .with_body("tampered layer")
+        .create();
+
+    let dir = std::env::temp_dir().join(format!(
+        "dkregistry-oci-layout-corrupt-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    let reference = format!("{}/repo:v1", addr).parse()?;
+    let runtime = Runtime::new().unwrap();
+    let res = runtime.block_on(client(&addr).export_oci_layout(&reference, &dir, None));
+    assert!(matches!(
+        res,
+        Err(dkregistry::errors::Error::DigestMismatch { .. })
+    ));
+    assert!(!dir.join("index.json").exists());
+    assert_eq!(std::fs::read_dir(dir.join("blobs/sha256"))?.count(), 1);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+/// Write a layout holding the image of `CONFIG` and `LAYER`, with `layer` stored as its layer.
+fn write_layout(dir: &std::path::Path, layer: &[u8]) -> Fallible<()> {
+    let manifest = manifest(CONFIG, LAYER);
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir.join("blobs/sha256"))?;
+    std::fs::write(dir.join("oci-layout"), r#"{"imageLayoutVersion":"1.0.0"}"#)?;
+    for (d, blob) in [
+        (digest(CONFIG), CONFIG),
+        (digest(LAYER), layer),
+        (digest(manifest.as_bytes()), manifest.as_bytes()),
+    ] {
+        std::fs::write(dir.join("blobs/sha256").join(&d[7..]), blob)?;
+    }
+    std::fs::write(
+        dir.join("index.json"),
+        format!(
+            r#"{{"schemaVersion":2,"manifests":[{{"mediaType":"application/vnd.docker.distribution.manifest.v2+json","size":{},"digest":"{}","annotations":{{"org.opencontainers.image.ref.name":"v1"}}}}]}}"#,
+            manifest.len(),
+            digest(manifest.as_bytes())
+        ),
+    )?;
+    Ok(())
+}
+
+#[test]
+fn import_oci_layout() -> Fallible<()> {
+    let manifest = manifest(CONFIG, LAYER);
+    let dir = std::env::temp_dir().join(format!(
+        "dkregistry-oci-layout-import-{}",
+        std::process::id()
+    ));
+    write_layout(&dir, LAYER)?;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    server.mock("GET", "/v2/").with_status(200).create();
+    server
+        .mock(
+            "HEAD",
+            format!("/v2/repo/blobs/{}", digest(CONFIG)).as_str(),
+        )
+        .with_status(200)
+        .create();
+    server
+        .mock("HEAD", format!("/v2/repo/blobs/{}", digest(LAYER)).as_str())
+        .with_status(404)
+        .create();
+    let start = server
+        .mock("POST", "/v2/repo/blobs/uploads/")
+        .match_query(mockito::Matcher::UrlEncoded("mount".into(), digest(LAYER)))
+        .with_status(202)
+        .with_header("Location", "/v2/repo/blobs/uploads/uuid1")
+        .expect(1)
+        .create();
+    let finish = server
+        .mock("PUT", "/v2/repo/blobs/uploads/uuid1")
+        .match_query(mockito::Matcher::UrlEncoded("digest".into(), digest(LAYER)))
+        .match_body(LAYER.to_vec())
+        .with_status(201)
+        .expect(1)
+        .create();
+    let put_manifest = server
+        .mock("PUT", "/v2/repo/manifests/latest")
+        .match_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .match_body(manifest.as_str())
+        .with_status(201)
+        .expect(1)
+        .create();
+
+    // The only image of the layout is imported, whatever its tag.
+    let reference = format!("{}/repo:latest", addr).parse()?;
+    let runtime = Runtime::new().unwrap();
+    let pushed = runtime.block_on(client(&addr).import_oci_layout(&dir, &reference))?;
+    assert_eq!(pushed, digest(manifest.as_bytes()));
+
+    start.assert();
+    finish.assert();
+    put_manifest.assert();
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn import_oci_layout_rejects_corrupt_blob() -> Fallible<()> {
+    let dir = std::env::temp_dir().join(format!(
+        "dkregistry-oci-layout-import-corrupt-{}",
+        std::process::id()
+    ));
+    write_layout(&dir, b"tampered layer")?;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    server.mock("GET", "/v2/").with_status(200).create();
+    server
+        .mock(
+            "HEAD",
+            format!("/v2/repo/blobs/{}", digest(CONFIG)).as_str(),
+        )
+        .with_status(200)
+        .create();
+    server
+        .mock("HEAD", format!("/v2/repo/blobs/{}", digest(LAYER)).as_str())
+        .with_status(404)
+        .create();
+    let writes: Vec<_> = ["POST", "PUT"]
+        .iter()
+        .map(|method| {
+            server
+                .mock(method, mockito::Matcher::Any)
+                .with_status(500)
+                .expect(0)
+                .create()
+        })
+        .collect();
+
+    let reference = format!("{}/repo:v1", addr).parse()?;
+    let runtime = Runtime::new().unwrap();
+    let res = runtime.block_on(client(&addr).import_oci_layout(&dir, &reference));
+    assert!(matches!(
+        res,
+        Err(dkregistry::errors::Error::DigestMismatch { .. })
+    ));
+
+    for write in writes {
+        write.assert();
+    }
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
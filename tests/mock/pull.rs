@@ -0,0 +1,157 @@
+extern crate dkregistry;
+extern crate futures;
+extern crate mockito;
+extern crate tokio;
+
+use self::futures::TryStreamExt;
+use self::tokio::runtime::Runtime;
+use crate::mock::{client, digest};
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+const CONFIG: &[u8] = br#"{"architecture":"arm64","os":"linux"}"#;
+const LAYERS: [&[u8]; 2] = [b"base layer", b"top layer"];
+
+/// Serve a manifest list with a single arm64 image, returning the digest of the image.
+fn serve_image(server: &mut mockito::ServerGuard) -> String {
+    let manifest = format!(
+        r#"{{"schemaVersion":2,"mediaType":"application/vnd.docker.distribution.manifest.v2+json","config":{{"mediaType":"application/vnd.docker.container.image.v1+json","size":{},"digest":"{}"}},"layers":[{}]}}"#,
+        CONFIG.len(),
+        digest(CONFIG),
+        LAYERS
+            .iter()
+            .map(|l| format!(
+                r#"{{"mediaType":"application/vnd.docker.image.rootfs.diff.tar.gzip","size":{},"digest":"{}"}}"#,
+                l.len(),
+                digest(l)
+            ))
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    let manifest_digest = digest(manifest.as_bytes());
+    let list = format!(
+        r#"{{"schemaVersion":2,"mediaType":"application/vnd.docker.distribution.manifest.list.v2+json","manifests":[{{"mediaType":"application/vnd.docker.distribution.manifest.v2+json","size":{},"digest":"{}","platform":{{"architecture":"arm64","os":"linux"}}}}]}}"#,
+        manifest.len(),
+        manifest_digest
+    );
+
+    server.mock("GET", "/v2/").with_status(200).create();
+    server
+        .mock("GET", "/v2/repo/manifests/latest")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_body(&list)
+        .create();
+    server
+        .mock(
+            "GET",
+            format!("/v2/repo/manifests/{}", manifest_digest).as_str(),
+        )
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+    for blob in std::iter::once(CONFIG).chain(LAYERS) {
+        server
+            .mock("GET", format!("/v2/repo/blobs/{}", digest(blob)).as_str())
+            .with_status(200)
+            .with_body(blob)
+            .create();
+    }
+
+    manifest_digest
+}
+
+#[test]
+fn test_pull() -> Fallible<()> {
+    let mut server = mockito::Server::new();
+    let manifest_digest = serve_image(&mut server);
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = client(&server.host_with_port());
+    let reference = format!("{}/repo", server.host_with_port()).parse()?;
+    let image = runtime.block_on(dclient.pull(&reference, Some(("linux", "aarch64"))))?;
+
+    assert_eq!(digest(image.manifest().raw_bytes()), manifest_digest);
+    assert_eq!(image.config(), Some(CONFIG));
+    let layers = runtime.block_on(async {
+        let mut layers = Vec::new();
+        for (descriptor, stream) in image.layers() {
+            let chunks: Vec<_> = stream.try_collect().await?;
+            layers.push((descriptor.digest().to_string(), chunks.concat()));
+        }
+        Ok::<_, dkregistry::errors::Error>(layers)
+    })?;
+    assert_eq!(
+        layers,
+        LAYERS
+            .iter()
+            .map(|l| (digest(l), l.to_vec()))
+            .collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_pull_manifest_list_needs_platform() -> Fallible<()> {
+    let mut server = mockito::Server::new();
+    serve_image(&mut server);
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = client(&server.host_with_port());
+    let reference = format!("{}/repo:latest", server.host_with_port()).parse()?;
+    assert!(matches!(
+        runtime.block_on(dclient.pull(&reference, None)),
+        Err(dkregistry::errors::Error::UnsupportedMediaType(_))
+    ));
+    assert!(matches!(
+        runtime.block_on(dclient.pull(&reference, Some(("linux", "amd64")))),
+        Err(dkregistry::errors::Error::Manifest(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_image_download_size() -> Fallible<()> {
+    let mut server = mockito::Server::new();
+    serve_image(&mut server);
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = client(&server.host_with_port());
+    let reference = format!("{}/repo", server.host_with_port()).parse()?;
+    let size = runtime.block_on(dclient.image_download_size(
+        &reference,
+        Some(("linux", "arm64")),
+        None,
+    ))?;
+    let expected = CONFIG.len() + LAYERS.iter().map(|l| l.len()).sum::<usize>();
+    assert_eq!(size, expected as u64);
+
+    assert!(matches!(
+        runtime.block_on(dclient.image_download_size(&reference, None, None)),
+        Err(dkregistry::errors::Error::UnsupportedMediaType(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_image_platforms() -> Fallible<()> {
+    let mut server = mockito::Server::new();
+    let manifest_digest = serve_image(&mut server);
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = client(&server.host_with_port());
+    for reference in ["latest", manifest_digest.as_str()] {
+        let platforms = runtime.block_on(dclient.get_image_platforms("repo", reference, None))?;
+        assert_eq!(platforms.len(), 1);
+        assert_eq!(platforms[0].os, "linux");
+        assert_eq!(platforms[0].architecture, "arm64");
+    }
+    Ok(())
+}
@@ -0,0 +1,154 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate tokio;
+
+use self::tokio::runtime::Runtime;
+
+static SUBJECT: &str = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+#[test]
+fn test_referrers_simple() {
+    let name = "repo";
+    let index = r#"{
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.index.v1+json",
+        "manifests": [
+            {
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "size": 1234,
+                "digest": "sha256:1111111111111111111111111111111111111111111111111111111111111111",
+                "artifactType": "application/vnd.dev.cosign.artifact.sig.v1+json",
+                "annotations": {"org.opencontainers.image.created": "2023-01-01T00:00:00Z"}
+            }
+        ]
+    }"#;
+
+    let ep = format!(
+        "/v2/{}/referrers/{}?artifactType=application%2Fvnd.dev.cosign.artifact.sig.v1%2Bjson",
+        name, SUBJECT
+    );
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+        .with_body(index)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_referrers(
+        name,
+        SUBJECT,
+        Some("application/vnd.dev.cosign.artifact.sig.v1+json"),
+        None,
+    );
+
+    let res = runtime.block_on(futcheck).unwrap();
+    assert_eq!(res.manifests.len(), 1);
+    let referrer = &res.manifests[0];
+    assert_eq!(
+        referrer.artifact_type(),
+        Some("application/vnd.dev.cosign.artifact.sig.v1+json")
+    );
+    assert_eq!(
+        referrer
+            .annotations()
+            .and_then(|a| a.get("org.opencontainers.image.created"))
+            .map(String::as_str),
+        Some("2023-01-01T00:00:00Z")
+    );
+}
+
+#[test]
+fn test_referrers_unsupported() {
+    let name = "repo";
+
+    let ep = format!("/v2/{}/referrers/{}", name, SUBJECT);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server.mock("GET", ep.as_str()).with_status(404).create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_referrers(name, SUBJECT, None, None);
+
+    match runtime.block_on(futcheck) {
+        Err(dkregistry::errors::Error::ReferrersUnsupported) => {}
+        res => panic!("expected ReferrersUnsupported, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_referrers_manifest_unknown() {
+    let name = "repo";
+
+    let ep = format!("/v2/{}/referrers/{}", name, SUBJECT);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
+        .with_status(404)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"errors":[{"code":"NAME_UNKNOWN","message":"repository name not known to registry"}]}"#)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.get_referrers(name, SUBJECT, None, None)) {
+        Err(e @ dkregistry::errors::Error::Registry { .. }) => assert!(e.is_not_found()),
+        res => panic!("expected Registry error, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_referrers_max_manifest_bytes() {
+    let name = "repo";
+
+    let ep = format!("/v2/{}/referrers/{}", name, SUBJECT);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+        .with_body(r#"{"schemaVersion":2,"mediaType":"application/vnd.oci.image.index.v1+json","manifests":[]}"#)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .max_manifest_bytes(16)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.get_referrers(name, SUBJECT, None, None)) {
+        Err(dkregistry::errors::Error::ManifestTooLarge(16)) => {}
+        res => panic!("expected ManifestTooLarge, got {:?}", res),
+    }
+}
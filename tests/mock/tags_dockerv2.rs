@@ -4,7 +4,6 @@ extern crate mockito;
 extern crate tokio;
 
 use self::futures::StreamExt;
-use self::mockito::mock;
 use self::tokio::runtime::Runtime;
 
 #[test]
@@ -13,8 +12,10 @@ fn test_dockerv2_tags_simple() {
     let tags = r#"{"name": "repo", "tags": [ "t1", "t2" ]}"#;
 
     let ep = format!("/v2/{}/tags/list", name);
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", ep.as_str())
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
         .with_status(200)
         .with_header("Content-Type", "application/json")
         .with_body(tags)
@@ -32,10 +33,8 @@ fn test_dockerv2_tags_simple() {
     let futcheck = dclient.get_tags(name, None);
 
     let res = runtime.block_on(futcheck.map(Result::unwrap).collect::<Vec<_>>());
-    assert_eq!(res.get(0).unwrap(), &String::from("t1"));
+    assert_eq!(res.first().unwrap(), &String::from("t1"));
     assert_eq!(res.get(1).unwrap(), &String::from("t2"));
-
-    mockito::reset();
 }
 
 #[test]
@@ -46,20 +45,20 @@ fn test_dockerv2_tags_paginate() {
 
     let ep1 = format!("/v2/{}/tags/list?n=1", name);
     let ep2 = format!("/v2/{}/tags/list?n=1&last=t1", name);
-    let addr = mockito::server_address().to_string();
-    let _m1 = mock("GET", ep1.as_str())
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m1 = server
+        .mock("GET", ep1.as_str())
         .with_status(200)
         .with_header(
             "Link",
-            &format!(
-                r#"<{}/v2/_tags?n=1&last=t1>; rel="next""#,
-                mockito::server_url()
-            ),
+            &format!(r#"<{}/v2/_tags?n=1&last=t1>; rel="next""#, server.url()),
         )
         .with_header("Content-Type", "application/json")
         .with_body(tags_p1)
         .create();
-    let _m2 = mock("GET", ep2.as_str())
+    let _m2 = server
+        .mock("GET", ep2.as_str())
         .with_status(200)
         .with_header("Content-Type", "application/json")
         .with_body(tags_p2)
@@ -86,16 +85,16 @@ fn test_dockerv2_tags_paginate() {
     if end.is_some() {
         panic!("end is some: {:?}", end);
     }
-
-    mockito::reset();
 }
 
 #[test]
 fn test_dockerv2_tags_404() {
     let name = "repo";
     let ep = format!("/v2/{}/tags/list", name);
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", ep.as_str())
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
         .with_status(404)
         .with_header("Content-Type", "application/json")
         .create();
@@ -112,9 +111,7 @@ fn test_dockerv2_tags_404() {
     let futcheck = dclient.get_tags(name, None);
 
     let res = runtime.block_on(futcheck.collect::<Vec<_>>());
-    assert!(res.get(0).unwrap().is_err());
-
-    mockito::reset();
+    assert!(res.first().unwrap().is_err());
 }
 
 #[test]
@@ -123,8 +120,10 @@ fn test_dockerv2_tags_missing_header() {
     let tags = r#"{"name": "repo", "tags": [ "t1", "t2" ]}"#;
     let ep = format!("/v2/{}/tags/list", name);
 
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", ep.as_str())
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
         .with_status(200)
         .with_body(tags)
         .create();
@@ -142,6 +141,310 @@ fn test_dockerv2_tags_missing_header() {
 
     let res = runtime.block_on(futcheck.map(Result::unwrap).collect::<Vec<_>>());
     assert_eq!(vec!["t1", "t2"], res);
+}
+
+#[test]
+fn test_dockerv2_tags_with_digests() {
+    let name = "repo";
+    let tags_p1 = r#"{"name": "repo", "tags": [ "t1" ]}"#;
+    let tags_p2 = r#"{"name": "repo", "tags": [ "t2" ]}"#;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m1 = server
+        .mock("GET", "/v2/repo/tags/list")
+        .with_status(200)
+        .with_header(
+            "Link",
+            &format!(r#"<{}/v2/_tags?n=1&last=t1>; rel="next""#, server.url()),
+        )
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_p1)
+        .create();
+    let _m2 = server
+        .mock("GET", "/v2/repo/tags/list?n=1&last=t1")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_p2)
+        .create();
+    let _t1 = server
+        .mock("HEAD", "/v2/repo/manifests/t1")
+        .with_status(200)
+        .with_header("Docker-Content-Digest", "sha256:1111")
+        .create();
+    let _t2 = server
+        .mock("HEAD", "/v2/repo/manifests/t2")
+        .with_status(200)
+        .with_header("Docker-Content-Digest", "sha256:2222")
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let mut res = runtime.block_on(
+        dclient
+            .stream_tags_with_digests(name, 2, None)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>(),
+    );
+    res.sort();
+    assert_eq!(
+        res,
+        vec![
+            ("t1".to_string(), Some("sha256:1111".to_string())),
+            ("t2".to_string(), Some("sha256:2222".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn test_dockerv2_tags_with_digests_namespace() {
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _tags = server
+        .mock("GET", "/v2/repo/tags/list?ns=docker.io")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"name": "repo", "tags": [ "t1" ]}"#)
+        .create();
+    let _t1 = server
+        .mock("HEAD", "/v2/repo/manifests/t1?ns=docker.io")
+        .with_status(200)
+        .with_header("Docker-Content-Digest", "sha256:1111")
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(
+        dclient
+            .stream_tags_with_digests("repo", 2, Some("docker.io"))
+            .map(Result::unwrap)
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(
+        res,
+        vec![("t1".to_string(), Some("sha256:1111".to_string()))]
+    );
+}
+
+#[test]
+fn test_dockerv2_tags_page() {
+    let name = "repo";
+    let tags_p1 = r#"{"name": "repo", "tags": [ "t1", "t2" ]}"#;
+    let tags_p2 = r#"{"name": "repo", "tags": [ "t3" ]}"#;
+
+    let ep1 = format!("/v2/{}/tags/list?n=2", name);
+    let ep2 = format!("/v2/{}/tags/list?n=2&last=t2", name);
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m1 = server
+        .mock("GET", ep1.as_str())
+        .with_status(200)
+        .with_header(
+            "Link",
+            &format!(r#"<{}/v2/_tags?n=2&last=t2>; rel="next""#, server.url()),
+        )
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_p1)
+        .create();
+    let _m2 = server
+        .mock("GET", ep2.as_str())
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_p2)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let (tags, last) = runtime
+        .block_on(dclient.get_tags_page(name, Some(2), None, None))
+        .unwrap();
+    assert_eq!(tags, vec!["t1", "t2"]);
+    assert_eq!(last.as_deref(), Some("t2"));
+
+    let (tags, last) = runtime
+        .block_on(dclient.get_tags_page(name, Some(2), last.as_deref(), None))
+        .unwrap();
+    assert_eq!(tags, vec!["t3"]);
+    assert_eq!(last, None);
+}
+
+#[test]
+fn test_dockerv2_tags_with_manifests() {
+    let name = "repo";
+    let tags_p1 = r#"{"name": "repo", "tags": [ "t1" ]}"#;
+    let tags_p2 = r#"{"name": "repo", "tags": [ "t2" ]}"#;
+    let list = std::fs::read("tests/fixtures/manifest_list_v2.json").unwrap();
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m1 = server
+        .mock("GET", "/v2/repo/tags/list")
+        .with_status(200)
+        .with_header(
+            "Link",
+            &format!(r#"<{}/v2/_tags?n=1&last=t1>; rel="next""#, server.url()),
+        )
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_p1)
+        .create();
+    let _m2 = server
+        .mock("GET", "/v2/repo/tags/list?n=1&last=t1")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_p2)
+        .create();
+    let _t1 = server
+        .mock("GET", "/v2/repo/manifests/t1")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_body(&list)
+        .create();
+    let _t2 = server
+        .mock("GET", "/v2/repo/manifests/t2")
+        .with_status(500)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(
+        dclient
+            .stream_tags_with_manifests(name, 2, None)
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(res.len(), 2);
+    let (ok, err): (Vec<_>, Vec<_>) = res.into_iter().partition(Result::is_ok);
+    assert_eq!(err.len(), 1);
+    match &ok[..] {
+        [Ok((tag, dkregistry::v2::manifest::Manifest::ML(_)))] => assert_eq!(tag, "t1"),
+        other => panic!("unexpected results: {:?}", other),
+    }
+}
+
+#[test]
+fn test_dockerv2_tags_with_manifests_namespace() {
+    let list = std::fs::read("tests/fixtures/manifest_list_v2.json").unwrap();
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _tags = server
+        .mock("GET", "/v2/repo/tags/list?ns=docker.io")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"name": "repo", "tags": [ "t1" ]}"#)
+        .create();
+    let _t1 = server
+        .mock("GET", "/v2/repo/manifests/t1?ns=docker.io")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_body(&list)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(
+        dclient
+            .stream_tags_with_manifests("repo", 2, Some("docker.io"))
+            .collect::<Vec<_>>(),
+    );
+    match &res[..] {
+        [Ok((tag, dkregistry::v2::manifest::Manifest::ML(_)))] => assert_eq!(tag, "t1"),
+        other => panic!("unexpected results: {:?}", other),
+    }
+}
+
+#[test]
+fn test_dockerv2_tags_max_pagination_pages() {
+    let name = "repo";
+    let tags_p1 = r#"{"name": "repo", "tags": [ "t1" ]}"#;
+    let tags_p2 = r#"{"name": "repo", "tags": [ "t2" ]}"#;
+
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m1 = server
+        .mock("GET", "/v2/repo/tags/list")
+        .with_status(200)
+        .with_header(
+            "Link",
+            &format!(r#"<{}/v2/_tags?n=1&last=t1>; rel="next""#, server.url()),
+        )
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_p1)
+        .create();
+    let _m2 = server
+        .mock("GET", "/v2/repo/tags/list?n=1&last=t1")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_p2)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let client = |max_pages| {
+        dkregistry::v2::Client::configure()
+            .registry(&addr)
+            .insecure_registry(true)
+            .username(None)
+            .password(None)
+            .max_pagination_pages(max_pages)
+            .build()
+            .unwrap()
+    };
+
+    let dclient = client(1);
+    let res = runtime.block_on(dclient.get_tags(name, None).collect::<Vec<_>>());
+    assert_eq!(res.len(), 2);
+    assert_eq!(res[0].as_ref().unwrap(), "t1");
+    assert!(matches!(
+        res[1],
+        Err(dkregistry::errors::Error::PaginationTruncated(1))
+    ));
 
-    mockito::reset();
+    let dclient = client(2);
+    let res = runtime.block_on(
+        dclient
+            .get_tags(name, None)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(res, vec!["t1", "t2"]);
 }
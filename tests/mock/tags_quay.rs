@@ -4,7 +4,6 @@ extern crate mockito;
 extern crate tokio;
 
 use self::futures::StreamExt;
-use self::mockito::mock;
 use self::tokio::runtime::Runtime;
 
 #[test]
@@ -13,8 +12,10 @@ fn test_quay_tags_simple() {
     let tags = r#"{"name": "repo", "tags": [ "t1", "t2" ]}"#;
 
     let ep = format!("/v2/{}/tags/list", name);
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", ep.as_str())
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
         .with_status(200)
         .with_header("Content-Type", "application/json")
         .with_body(tags)
@@ -32,10 +33,8 @@ fn test_quay_tags_simple() {
     let futcheck = dclient.get_tags(name, None);
 
     let res = runtime.block_on(futcheck.map(Result::unwrap).collect::<Vec<_>>());
-    assert_eq!(res.get(0).unwrap(), &String::from("t1"));
+    assert_eq!(res.first().unwrap(), &String::from("t1"));
     assert_eq!(res.get(1).unwrap(), &String::from("t2"));
-
-    mockito::reset();
 }
 
 #[test]
@@ -46,20 +45,23 @@ fn test_quay_tags_paginate() {
 
     let ep1 = format!("/v2/{}/tags/list?n=1", name);
     let ep2 = format!("/v2/{}/tags/list?n=1&next_page=t1", name);
-    let addr = mockito::server_address().to_string();
-    let _m1 = mock("GET", ep1.as_str())
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m1 = server
+        .mock("GET", ep1.as_str())
         .with_status(200)
         .with_header(
             "Link",
             &format!(
                 r#"<{}/v2/_tags?n=1&next_page=t1>; rel="next""#,
-                mockito::server_url()
+                server.url()
             ),
         )
         .with_header("Content-Type", "application/json")
         .with_body(tags_p1)
         .create();
-    let _m2 = mock("GET", ep2.as_str())
+    let _m2 = server
+        .mock("GET", ep2.as_str())
         .with_status(200)
         .with_header("Content-Type", "application/json")
         .with_body(tags_p2)
@@ -86,16 +88,16 @@ fn test_quay_tags_paginate() {
     if end.is_some() {
         panic!("end is some: {:?}", end);
     }
-
-    mockito::reset();
 }
 
 #[test]
 fn test_quay_tags_404() {
     let name = "repo";
     let ep = format!("/v2/{}/tags/list", name);
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", ep.as_str())
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
         .with_status(404)
         .with_header("Content-Type", "application/json")
         .create();
@@ -112,9 +114,7 @@ fn test_quay_tags_404() {
     let futcheck = dclient.get_tags(name, None);
 
     let res = runtime.block_on(futcheck.collect::<Vec<_>>());
-    assert!(res.get(0).unwrap().is_err());
-
-    mockito::reset();
+    assert!(res.first().unwrap().is_err());
 }
 
 #[test]
@@ -123,8 +123,10 @@ fn test_quay_tags_missing_header() {
     let tags = r#"{"name": "repo", "tags": [ "t1", "t2" ]}"#;
     let ep = format!("/v2/{}/tags/list", name);
 
-    let addr = mockito::server_address().to_string();
-    let _m = mock("GET", ep.as_str())
+    let mut server = mockito::Server::new();
+    let addr = server.host_with_port();
+    let _m = server
+        .mock("GET", ep.as_str())
         .with_status(200)
         .with_body(tags)
         .create();
@@ -142,6 +144,4 @@ fn test_quay_tags_missing_header() {
 
     let res = runtime.block_on(futcheck.map(Result::unwrap).collect::<Vec<_>>());
     assert_eq!(vec!["t1", "t2"], res);
-
-    mockito::reset();
 }
@@ -0,0 +1,138 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate tokio;
+
+use self::tokio::runtime::Runtime;
+use crate::mock::{configure, digest, manifest};
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+/// Serve an image whose layer is served as `served_layer`.
+fn serve_image(server: &mut mockito::ServerGuard, served_layer: &[u8]) -> String {
+    let config = br#"{"architecture":"amd64"}"#;
+    let layer = b"layer content";
+    let manifest = manifest(config, layer);
+    let manifest_digest = digest(manifest.as_bytes());
+
+    server
+        .mock(
+            "GET",
+            format!("/v2/repo/manifests/{}", manifest_digest).as_str(),
+        )
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+    server
+        .mock("GET", format!("/v2/repo/blobs/{}", digest(config)).as_str())
+        .with_status(200)
+        .with_body(config)
+        .create();
+    server
+        .mock("GET", format!("/v2/repo/blobs/{}", digest(layer)).as_str())
+        .with_status(200)
+        .with_body(served_layer)
+        .create();
+
+    manifest_digest
+}
+
+fn client(addr: &str) -> dkregistry::v2::Client {
+    configure(addr).verify_digests(false).build().unwrap()
+}
+
+#[test]
+fn verify_image() -> Fallible<()> {
+    let mut server = mockito::Server::new();
+    let manifest_digest = serve_image(&mut server, b"layer content");
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = client(&server.host_with_port());
+    let report = runtime.block_on(dclient.verify_image("repo", &manifest_digest, None))?;
+    assert_eq!(report.digest(), manifest_digest);
+    assert_eq!(
+        report.config(),
+        Some(digest(br#"{"architecture":"amd64"}"#).as_str())
+    );
+    assert_eq!(report.layers(), &[digest(b"layer content")]);
+    assert!(report.foreign_layers().is_empty());
+    assert_eq!(report.total_size(), 24 + 13);
+    Ok(())
+}
+
+#[test]
+fn verify_image_foreign_layer() -> Fallible<()> {
+    let config = br#"{"architecture":"amd64","os":"windows"}"#;
+    let layer = b"windows base layer";
+    let manifest = format!(
+        r#"{{"schemaVersion":2,"mediaType":"application/vnd.docker.distribution.manifest.v2+json","config":{{"mediaType":"application/vnd.docker.container.image.v1+json","size":{},"digest":"{}"}},"layers":[{{"mediaType":"application/vnd.docker.image.rootfs.foreign.diff.tar.gzip","size":{},"digest":"{}","urls":["https://mcr.microsoft.com/v2/windows/blobs/{}"]}}]}}"#,
+        config.len(),
+        digest(config),
+        layer.len(),
+        digest(layer),
+        digest(layer)
+    );
+    let manifest_digest = digest(manifest.as_bytes());
+
+    let mut server = mockito::Server::new();
+    server
+        .mock(
+            "GET",
+            format!("/v2/repo/manifests/{}", manifest_digest).as_str(),
+        )
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+    server
+        .mock("GET", format!("/v2/repo/blobs/{}", digest(config)).as_str())
+        .with_status(200)
+        .with_body(config)
+        .create();
+    let foreign = server
+        .mock("GET", format!("/v2/repo/blobs/{}", digest(layer)).as_str())
+        .with_status(404)
+        .expect(0)
+        .create();
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = client(&server.host_with_port());
+    let report = runtime.block_on(dclient.verify_image("repo", &manifest_digest, None))?;
+    assert_eq!(report.layers(), &[digest(layer)]);
+    assert_eq!(report.foreign_layers(), &[digest(layer)]);
+    assert_eq!(report.total_size(), config.len() as u64);
+    foreign.assert();
+    Ok(())
+}
+
+#[test]
+fn verify_image_corrupted_layer() -> Fallible<()> {
+    let mut server = mockito::Server::new();
+    let manifest_digest = serve_image(&mut server, b"tampered content");
+
+    let runtime = Runtime::new().unwrap();
+    let dclient = client(&server.host_with_port());
+    match runtime.block_on(dclient.verify_image("repo", &manifest_digest, None)) {
+        Err(dkregistry::errors::Error::DigestMismatch { expected, .. }) => {
+            assert_eq!(expected, digest(b"layer content"))
+        }
+        res => return Err(format!("expected DigestMismatch, got {:?}", res).into()),
+    }
+    Ok(())
+}
+
+#[test]
+fn verify_image_requires_digest() {
+    let runtime = Runtime::new().unwrap();
+    let dclient = client("localhost:1");
+    assert!(matches!(
+        runtime.block_on(dclient.verify_image("repo", "latest", None)),
+        Err(dkregistry::errors::Error::VersionParse(_))
+    ));
+}
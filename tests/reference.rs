@@ -1,7 +1,8 @@
 extern crate spectral;
 
-use dkregistry::reference::Reference;
+use dkregistry::reference::{Digest, Reference, Version};
 use spectral::prelude::*;
+use std::convert::TryFrom;
 use std::str::FromStr;
 
 #[test]
@@ -91,7 +92,7 @@ fn valid_references() {
 
 #[test]
 fn invalid_references() {
-    let tcases = vec!["".into(), "L".repeat(128), ":justatag".into()];
+    let tcases = ["".into(), "L".repeat(128), ":justatag".into()];
 
     for t in tcases.iter() {
         let r = Reference::from_str(t);
@@ -133,3 +134,155 @@ fn ipv4_registry_and_library_with_tag() -> Result<(), Box<dyn std::error::Error>
 
     Ok(())
 }
+
+#[test]
+fn digest_references() -> Result<(), Box<dyn std::error::Error>> {
+    let digest = "sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+
+    let dkr_ref = Reference::from_str(&format!("quay.io/coreos/etcd@{}", digest))?;
+    assert_eq!(dkr_ref.repository(), "coreos/etcd");
+    assert_eq!(dkr_ref.version(), digest);
+    assert_eq!(dkr_ref.digest().as_deref(), Some(digest));
+    assert_eq!(dkr_ref.tag(), None);
+
+    let dkr_ref = Reference::from_str(&format!("quay.io/coreos/etcd:v3.1.0@{}", digest))?;
+    assert_eq!(dkr_ref.repository(), "coreos/etcd");
+    assert_eq!(dkr_ref.version(), digest);
+    assert_eq!(dkr_ref.digest().as_deref(), Some(digest));
+    assert_eq!(dkr_ref.tag(), Some("v3.1.0"));
+    assert_eq!(
+        dkr_ref.to_string(),
+        format!("quay.io/coreos/etcd:v3.1.0@{}", digest)
+    );
+
+    let dkr_ref = Reference::from_str("quay.io/coreos/etcd:v3.1.0")?;
+    assert_eq!(dkr_ref.digest(), None);
+    assert_eq!(dkr_ref.tag(), Some("v3.1.0"));
+
+    Ok(())
+}
+
+#[test]
+fn reference_mutators() -> Result<(), Box<dyn std::error::Error>> {
+    let digest = "sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+
+    let dkr_ref = Reference::from_str("quay.io/coreos/etcd:v3.1.0")?.with_digest(digest)?;
+    assert_eq!(dkr_ref.registry(), "quay.io");
+    assert_eq!(dkr_ref.repository(), "coreos/etcd");
+    assert_eq!(dkr_ref.version(), digest);
+    assert_eq!(dkr_ref.tag(), Some("v3.1.0"));
+    assert_eq!(
+        dkr_ref.to_raw_string(),
+        format!("quay.io/coreos/etcd:v3.1.0@{}", digest)
+    );
+
+    let dkr_ref = dkr_ref.with_tag("v3.2.0")?;
+    assert_eq!(dkr_ref.version(), "v3.2.0");
+    assert_eq!(dkr_ref.digest(), None);
+    assert_eq!(dkr_ref.to_string(), "quay.io/coreos/etcd:v3.2.0");
+
+    let dkr_ref = Reference::from_str("quay.io/coreos/etcd")?;
+    asserting("bad digest")
+        .that(&dkr_ref.clone().with_digest("sha256:ffff"))
+        .is_err();
+    for t in &["", ":v1", "-v1", "v1@sha256", &"v".repeat(129)] {
+        asserting(t).that(&dkr_ref.clone().with_tag(t)).is_err();
+    }
+
+    Ok(())
+}
+
+#[test]
+fn invalid_digest_references() {
+    for t in &[
+        "busybox@sha256:",
+        "busybox@sha256:ffff",
+        "busybox@sha256:FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
+        "busybox:tag@sha512:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+        "busybox@sha256",
+    ] {
+        let r = Reference::from_str(t);
+        asserting(t).that(&r).is_err();
+    }
+}
+
+#[test]
+fn typed_digests() -> Result<(), Box<dyn std::error::Error>> {
+    let input = "sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+
+    let digest = Digest::from_str(input)?;
+    assert_eq!(digest.algorithm(), "sha256");
+    assert_eq!(digest.encoded(), &input[7..]);
+    assert_eq!(digest.to_string(), input);
+
+    for t in &["", "sha256", "sha256:ffff", "sha512:ffff", "fakedigest"] {
+        asserting(t).that(&Digest::from_str(t)).is_err();
+    }
+
+    Ok(())
+}
+
+#[test]
+fn bare_versions() -> Result<(), Box<dyn std::error::Error>> {
+    let digest = "sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+
+    assert!(matches!(Version::try_from("latest")?, Version::Tag(t) if t == "latest"));
+    assert!(matches!(Version::try_from(digest)?, Version::Digest(..)));
+    assert_eq!(Version::try_from(digest)?.to_string(), digest);
+    assert_eq!(Version::from(Digest::from_str(digest)?).to_string(), digest);
+    assert!(Version::try_from("").is_err());
+    assert!(Version::try_from("sha256:ffff").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn default_tag() -> Result<(), Box<dyn std::error::Error>> {
+    let dkr_ref = Reference::from_str("quay.io/coreos/etcd")?;
+    assert!(!dkr_ref.has_version());
+    assert_eq!(dkr_ref.version(), "latest");
+    assert_eq!(dkr_ref.version_or("stable"), "stable");
+    assert_eq!(dkr_ref.tag(), Some("latest"));
+    assert_eq!(dkr_ref.to_string(), "quay.io/coreos/etcd:latest");
+
+    let dkr_ref = Reference::from_str("quay.io/coreos/etcd:v3.1.0")?;
+    assert!(dkr_ref.has_version());
+    assert_eq!(dkr_ref.version_or("stable"), "v3.1.0");
+
+    Ok(())
+}
+
+#[test]
+fn docker_hub_normalization() -> Result<(), Box<dyn std::error::Error>> {
+    for input in &[
+        "ubuntu",
+        "library/ubuntu",
+        "docker.io/ubuntu",
+        "docker.io/library/ubuntu:latest",
+        "index.docker.io/ubuntu",
+        "docker://ubuntu",
+    ] {
+        let dkr_ref = Reference::from_str(input)?;
+        asserting(input)
+            .that(&dkr_ref.registry())
+            .is_equal_to(dkregistry::reference::DEFAULT_REGISTRY);
+        asserting(input)
+            .that(&dkr_ref.repository())
+            .is_equal_to("library/ubuntu");
+        asserting(input)
+            .that(&dkr_ref.version().as_str())
+            .is_equal_to("latest");
+        asserting(input)
+            .that(&dkr_ref.to_raw_string())
+            .is_equal_to(*input);
+    }
+
+    let dkr_ref = Reference::from_str("docker.io/steveej/cincinnati-test")?;
+    assert_eq!(dkr_ref.repository(), "steveej/cincinnati-test");
+
+    let dkr_ref = Reference::from_str("localhost:5000/busybox")?;
+    assert_eq!(dkr_ref.registry(), "localhost:5000");
+    assert_eq!(dkr_ref.repository(), "busybox");
+
+    Ok(())
+}